@@ -3,6 +3,8 @@ use std::{collections::HashMap, env, fs, path::PathBuf, process::Command};
 
 const TARGET_ARCH: &str = "riscv64gc-unknown-none-elf";
 const TG_USER_VERSION: &str = "0.2.0-preview.1";
+#[cfg(feature = "fs")]
+const BLOCK_SZ: usize = 512;
 
 #[derive(Deserialize, Default)]
 struct Cases {
@@ -16,6 +18,7 @@ fn main() {
     println!("cargo:rerun-if-env-changed=LOG");
     println!("cargo:rerun-if-env-changed=TG_USER_DIR");
     println!("cargo:rerun-if-env-changed=TG_USER_VERSION");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_FS");
 
     let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
 
@@ -24,9 +27,15 @@ fn main() {
         write_linker();
         if should_skip_build_apps() {
             write_dummy_app_asm();
+            #[cfg(feature = "fs")]
+            write_app_names(&[]);
         } else {
             build_apps();
         }
+    } else {
+        // 宿主机上只做类型检查，不构建用户程序，但 fs.rs 仍需要 APP_NAMES
+        #[cfg(feature = "fs")]
+        write_app_names(&[]);
     }
 }
 
@@ -72,24 +81,40 @@ fn build_apps() {
     }
 
     let target_dir = tg_user_root.join("target").join(TARGET_ARCH).join("debug");
-    let mut bins: Vec<PathBuf> = Vec::with_capacity(names.len());
 
     for (i, name) in names.iter().enumerate() {
-        let base_address = base + i as u64 * step;
-        build_user_app(&tg_user_root, name, base_address);
-        let elf = target_dir.join(name);
-        let app_path = if base_address != 0 {
-            objcopy_to_bin(&elf)
-        } else {
-            elf
-        };
-        bins.push(app_path);
+        build_user_app(&tg_user_root, name, base + i as u64 * step);
     }
 
-    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    let app_asm = out_dir.join("app.asm");
-    write_app_asm(&app_asm, base, step, &bins);
-    println!("cargo:rustc-env=APP_ASM={}", app_asm.display());
+    // fs feature：用户程序以 ELF 形式写入磁盘镜像，内核运行时按名加载
+    #[cfg(feature = "fs")]
+    {
+        let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let fs_target_dir = manifest_dir.join("target").join(TARGET_ARCH).join("debug");
+        easy_fs_pack(&names, &target_dir, &fs_target_dir).unwrap_or_else(|err| {
+            panic!("failed to pack easy-fs image in {}: {err}", fs_target_dir.display())
+        });
+        write_app_names(&names);
+    }
+
+    #[cfg(not(feature = "fs"))]
+    {
+        let mut bins: Vec<PathBuf> = Vec::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            let elf = target_dir.join(name);
+            let app_path = if base + i as u64 * step != 0 {
+                objcopy_to_bin(&elf)
+            } else {
+                elf
+            };
+            bins.push(app_path);
+        }
+
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        let app_asm = out_dir.join("app.asm");
+        write_app_asm(&app_asm, base, step, &bins);
+        println!("cargo:rustc-env=APP_ASM={}", app_asm.display());
+    }
 }
 
 fn build_user_app(tg_user_root: &PathBuf, name: &str, base_address: u64) {
@@ -114,6 +139,7 @@ fn build_user_app(tg_user_root: &PathBuf, name: &str, base_address: u64) {
     }
 }
 
+#[cfg(not(feature = "fs"))]
 fn objcopy_to_bin(elf: &PathBuf) -> PathBuf {
     let bin = elf.with_extension("bin");
     let status = Command::new("rust-objcopy")
@@ -132,6 +158,7 @@ fn objcopy_to_bin(elf: &PathBuf) -> PathBuf {
     bin
 }
 
+#[cfg(not(feature = "fs"))]
 fn write_app_asm(path: &PathBuf, base: u64, step: u64, bins: &[PathBuf]) {
     use std::io::Write;
     let mut asm = fs::File::create(path)
@@ -169,6 +196,82 @@ app_{i}_end:",
     }
 }
 
+/// 把用户程序名按批处理顺序逐行写入 `APP_NAMES`，内核据此依次从镜像中打开
+#[cfg(feature = "fs")]
+fn write_app_names(names: &[String]) {
+    let path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("app_names.txt");
+    let mut content = String::new();
+    for name in names {
+        content.push_str(name);
+        content.push('\n');
+    }
+    fs::write(&path, content)
+        .unwrap_or_else(|err| panic!("failed to write {}: {}", path.display(), err));
+    println!("cargo:rustc-env=APP_NAMES={}", path.display());
+}
+
+#[cfg(feature = "fs")]
+/// 用宿主机上的镜像文件模拟块设备，第二个字段记录文件是否以只读方式打开
+struct BlockFile(std::sync::Mutex<std::fs::File>, bool);
+
+#[cfg(feature = "fs")]
+impl tg_easy_fs::BlockDevice for BlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
+            .expect("Error when seeking!");
+        assert_eq!(file.read(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        use std::io::{Seek, SeekFrom, Write};
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
+            .expect("Error when seeking!");
+        assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.1
+    }
+}
+
+/// 与第六章相同的镜像格式，内核可以直接用 easy-fs 打开
+#[cfg(feature = "fs")]
+fn easy_fs_pack(cases: &[String], app_target: &std::path::Path, fs_target: &std::path::Path) -> std::io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::sync::Arc;
+    use tg_easy_fs::EasyFileSystem;
+
+    fs::create_dir_all(fs_target)?;
+    let fs_file = fs_target.join("fs.img");
+    println!("cargo:rerun-if-changed={}", fs_file.display());
+    // 打包要写入镜像，文件以读写方式打开，设备报告为可写
+    let block_file = Arc::new(BlockFile(std::sync::Mutex::new({
+        let f = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(fs_file)?;
+        f.set_len(16 * 2048 * BLOCK_SZ as u64)?;
+        f
+    }), false));
+
+    let efs = EasyFileSystem::create(block_file, 16 * 2048, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    for case in cases {
+        let data = fs::read(app_target.join(case))?;
+        let inode = root_inode.create(case.as_str()).unwrap();
+        assert!(
+            data.len() <= tg_easy_fs::MAX_FILE_SIZE,
+            "{case} exceeds the easy-fs file size limit of {} bytes",
+            tg_easy_fs::MAX_FILE_SIZE
+        );
+        let written = inode.write_at(0, &data);
+        assert_eq!(written, data.len(), "fs.img is too small for {case}");
+    }
+    // 逐个文件的写入只同步了各自的块，收尾时把剩下的脏块全部写回
+    assert!(efs.lock().sync_all(), "failed to write back fs.img");
+    Ok(())
+}
+
 fn write_dummy_app_asm() {
     use std::io::Write;
 
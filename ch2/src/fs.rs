@@ -0,0 +1,185 @@
+//! 从 easy-fs 镜像加载用户程序（`fs` feature）
+//!
+//! 默认情况下 ch2 把所有用户程序内嵌进内核的 `.data` 段；启用 `fs` feature 后，
+//! build.rs 改为把用户程序的 ELF 写入 `fs.img`，内核运行时通过 VirtIO 块设备读取镜像，
+//! 按 `APP_NAMES` 中的顺序逐个打开并加载，批处理的其余流程不变。
+//!
+//! 本章没有开启分页，ELF 的 LOAD 段直接按虚拟地址（即物理地址）拷贝到内存中，
+//! 因此用户程序仍需像内嵌方式一样链接到固定的基地址。
+//!
+//! 教程阅读建议：
+//!
+//! - 先看 `init`：理解为什么引入文件系统就需要内核堆；
+//! - 再看 `load_app`：对照第六章的 `read_all` + `from_elf`，这里直接从 inode 读段，
+//!   不需要把整个文件读进内存。
+
+use alloc::sync::Arc;
+use core::ptr::NonNull;
+use spin::{Lazy, Mutex};
+use tg_console::log;
+use tg_easy_fs::{BlockDevice, EasyFileSystem, Inode};
+use virtio_drivers::{Hal, MmioTransport, Transport, VirtIOBlk, VirtIOHeader};
+
+/// VirtIO 块设备的 MMIO 基地址（QEMU virt 平台）
+const VIRTIO0: usize = 0x1000_1000;
+/// 设备特性位 VIRTIO_BLK_F_RO：设备只读
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+/// 内核堆大小：只需容纳块缓存、inode 等少量数据结构
+const HEAP_SIZE: usize = 256 << 10;
+/// QEMU virt 平台默认 128 MiB 内存的结束地址，用户程序不能加载到它之外
+const MEMORY_END: usize = 0x8800_0000;
+/// VirtIO DMA 以页为单位分配
+const PAGE_SIZE: usize = 4096;
+
+/// 内核堆空间，位于内核镜像的 `.bss` 段中
+static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+/// 根目录 inode，首次访问时通过 VirtIO 块设备打开文件系统
+static ROOT: Lazy<Inode> = Lazy::new(|| {
+    let mut transport = unsafe { MmioTransport::new(NonNull::new(VIRTIO0 as *mut VirtIOHeader).unwrap()) }
+        .expect("Error when creating MmioTransport");
+    // virtio-drivers 0.1.0 不协商也不保存 VIRTIO_BLK_F_RO，交给驱动之前先从设备特性中读出
+    let read_only = transport.read_device_features() & VIRTIO_BLK_F_RO != 0;
+    let device: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock(
+        Mutex::new(VirtIOBlk::new(transport).expect("Error when creating VirtIOBlk")),
+        read_only,
+    ));
+    EasyFileSystem::root_inode(&EasyFileSystem::open(device))
+});
+
+/// 初始化内核堆，必须在访问文件系统之前调用
+pub fn init() {
+    let heap = core::ptr::addr_of_mut!(HEAP);
+    tg_kernel_alloc::init(heap as usize);
+    unsafe { tg_kernel_alloc::transfer(&mut (&mut *heap)[..]) };
+}
+
+/// 按批处理顺序排列的用户程序名，由 build.rs 生成
+pub fn app_names() -> impl Iterator<Item = &'static str> {
+    include_str!(env!("APP_NAMES")).lines()
+}
+
+/// 从镜像中加载名为 `name` 的用户程序，返回其入口地址
+///
+/// 文件不存在、不是 RISC-V 64 位可执行文件，或 LOAD 段会覆盖内核时返回 `None`。
+pub fn load_app(name: &str) -> Option<usize> {
+    let inode = ROOT.find(name)?;
+    // ELF64 文件头：魔数、64 位、小端，机器类型为 RISC-V
+    let mut ehdr = [0u8; 64];
+    if inode.read_at(0, &mut ehdr) != ehdr.len()
+        || ehdr[..4] != *b"\x7fELF"
+        || ehdr[4] != 2
+        || ehdr[5] != 1
+        || u16_at(&ehdr, 0x12) != 243
+    {
+        log::error!("{name}: not a RISC-V ELF64 file");
+        return None;
+    }
+    let entry = u64_at(&ehdr, 0x18);
+    let phoff = u64_at(&ehdr, 0x20);
+    let phentsize = u16_at(&ehdr, 0x36);
+    let phnum = u16_at(&ehdr, 0x38);
+
+    let kernel_end = tg_linker::KernelLayout::locate().end();
+    for i in 0..phnum {
+        let mut phdr = [0u8; 56];
+        inode.read_at(phoff + i * phentsize, &mut phdr);
+        // 只加载 PT_LOAD 段
+        if u32_at(&phdr, 0) != 1 {
+            continue;
+        }
+        let offset = u64_at(&phdr, 0x08);
+        let vaddr = u64_at(&phdr, 0x10);
+        let filesz = u64_at(&phdr, 0x20);
+        let memsz = u64_at(&phdr, 0x28);
+        let in_range = vaddr >= kernel_end && vaddr.checked_add(memsz).is_some_and(|end| end <= MEMORY_END);
+        if filesz > memsz || !in_range {
+            log::error!("{name}: bad segment at {vaddr:#x} (size {memsz:#x})");
+            return None;
+        }
+        let segment = unsafe { core::slice::from_raw_parts_mut(vaddr as *mut u8, memsz) };
+        let (data, bss) = segment.split_at_mut(filesz);
+        if inode.read_at(offset, data) != filesz {
+            log::error!("{name}: truncated segment");
+            return None;
+        }
+        bss.fill(0);
+    }
+    // 段内容是以数据写入的，执行前要让指令缓存看到它们
+    unsafe { core::arch::asm!("fence.i") };
+    Some(entry)
+}
+
+/// 读取小端 `u16`
+fn u16_at(buf: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes(buf[offset..][..2].try_into().unwrap()) as usize
+}
+
+/// 读取小端 `u32`
+fn u32_at(buf: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(buf[offset..][..4].try_into().unwrap()) as usize
+}
+
+/// 读取小端 `u64`
+fn u64_at(buf: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(buf[offset..][..8].try_into().unwrap()) as usize
+}
+
+/// VirtIO 块设备封装，第二个字段记录设备是否只读
+struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>, bool);
+
+// Safety: VirtIOBlock 内部使用 Mutex 保护，确保线程安全访问
+unsafe impl Send for VirtIOBlock {}
+unsafe impl Sync for VirtIOBlock {}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.0
+            .lock()
+            .read_block(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0
+            .lock()
+            .write_block(block_id, buf)
+            .expect("Error when writing VirtIOBlk");
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.1
+    }
+}
+
+/// VirtIO HAL 实现：本章未开启分页，物理地址与虚拟地址相同
+struct VirtioHal;
+
+impl Hal for VirtioHal {
+    fn dma_alloc(pages: usize) -> usize {
+        unsafe {
+            alloc::alloc::alloc_zeroed(core::alloc::Layout::from_size_align_unchecked(
+                pages * PAGE_SIZE,
+                PAGE_SIZE,
+            )) as _
+        }
+    }
+
+    fn dma_dealloc(paddr: usize, pages: usize) -> i32 {
+        unsafe {
+            alloc::alloc::dealloc(
+                paddr as _,
+                core::alloc::Layout::from_size_align_unchecked(pages * PAGE_SIZE, PAGE_SIZE),
+            )
+        }
+        0
+    }
+
+    fn phys_to_virt(paddr: usize) -> usize {
+        paddr
+    }
+
+    fn virt_to_phys(vaddr: usize) -> usize {
+        vaddr
+    }
+}
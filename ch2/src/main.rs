@@ -10,6 +10,8 @@
 //! - **Trap 处理**：用户程序通过 `ecall` 触发系统调用，或因异常陷入内核
 //! - **上下文保存与恢复**：进入/退出 Trap 时保存/恢复用户寄存器状态
 //! - **系统调用**：`write`（输出）和 `exit`（退出）
+//!
+//! 启用 `fs` feature 时，用户程序不再内嵌进内核，而是在运行时从 easy-fs 镜像按名加载（见 `fs` 模块）。
 
 // 不使用标准库，裸机环境没有操作系统提供系统调用支持
 #![no_std]
@@ -24,6 +26,15 @@
 #[macro_use]
 extern crate tg_console;
 
+// fs feature：从 easy-fs 镜像加载用户程序，需要内核堆
+#[cfg(feature = "fs")]
+extern crate alloc;
+#[cfg(feature = "fs")]
+mod fs;
+
+// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
 // 本地模块：Console 和 SyscallContext 的实现
 use impls::{Console, SyscallContext};
 // riscv 库：访问 RISC-V 控制状态寄存器（CSR），如 scause
@@ -41,7 +52,7 @@ use tg_syscall::{Caller, SyscallId};
 
 // 将用户程序的二进制数据内联到内核镜像的 .data 段中
 // APP_ASM 由 build.rs 在编译时生成，包含所有用户程序的二进制数据
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(feature = "fs")))]
 core::arch::global_asm!(include_str!(env!("APP_ASM")));
 
 // 定义内核入口点：设置 8 页（32 KiB）的内核栈，然后跳转到 rust_main
@@ -65,8 +76,19 @@ extern "C" fn rust_main() -> ! {
     tg_syscall::init_process(&SyscallContext);
 
     // 第四步：批处理——依次加载并运行每个用户程序
-    for (i, app) in tg_linker::AppMeta::locate().iter().enumerate() {
-        let app_base = app.as_ptr() as usize;
+    // 默认从内核镜像中的内嵌数据加载；启用 fs feature 时从磁盘镜像按名加载
+    #[cfg(not(feature = "fs"))]
+    let apps = tg_linker::AppMeta::locate().iter().map(|app| Some(app.as_ptr() as usize));
+    #[cfg(feature = "fs")]
+    let apps = {
+        fs::init();
+        fs::app_names().map(fs::load_app)
+    };
+    for (i, app_base) in apps.enumerate() {
+        let Some(app_base) = app_base else {
+            log::error!("app{i} failed to load");
+            continue;
+        };
         log::info!("load app{i} to {app_base:#x}");
 
         // 创建用户态上下文，入口地址为 app_base
@@ -106,6 +128,8 @@ extern "C" fn rust_main() -> ! {
                 // 其他异常（如非法指令、页错误等）：杀死应用
                 trap => log::error!("app{i} was killed because of {trap:?}"),
             }
+            // 应用可能留下不以换行结尾的输出
+            console::flush();
             // 清除指令缓存：因为下一个用户程序会被加载到相同的内存区域，
             // 需要确保 i-cache 中不会残留旧的指令
             unsafe { core::arch::asm!("fence.i") };
@@ -117,6 +141,7 @@ extern "C" fn rust_main() -> ! {
     }
 
     // 所有用户程序执行完毕，关机
+    console::flush();
     tg_sbi::shutdown(false)
 }
 
@@ -126,6 +151,7 @@ extern "C" fn rust_main() -> ! {
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
     tg_sbi::shutdown(true)
 }
 
@@ -174,13 +200,13 @@ fn handle_syscall(ctx: &mut LocalContext) -> SyscallResult {
 mod impls {
     use tg_syscall::{STDDEBUG, STDOUT};
 
-    /// 控制台实现：通过 SBI 逐字符输出
+    /// 控制台实现：经 `console` 模块的行缓冲输出
     pub struct Console;
 
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
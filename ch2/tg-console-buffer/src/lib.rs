@@ -0,0 +1,120 @@
+//! 控制台输出缓冲
+//!
+//! `print!` 最终逐字节调用 `Console::put_char`，若每个字节都直接 `console_putchar`，
+//! 每个字符都是一次陷入 M 态的 SBI 调用。这里先把字节写入行缓冲，遇到换行或缓冲满时才刷新：
+//! SBI 实现了 DBCN（Debug Console）扩展时用一次 `console_write` 写出整段，否则退回逐字节输出。
+//!
+//! 逐字节输出的函数由调用者传入（各章使用的 tg-sbi 版本不同），本 crate 不依赖 tg-sbi。
+//! 缓冲中可能留有不以换行结尾的内容（例如 shell 提示符），因此读控制台输入、进程退出、
+//! 关机与 panic 前都要调用 [`flush`]。
+
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+use core::cell::UnsafeCell;
+
+/// 行缓冲大小（字节）
+const CAPACITY: usize = 256;
+
+/// 行缓冲
+struct LineBuffer {
+    buf: [u8; CAPACITY],
+    len: usize,
+    /// SBI 是否实现了 DBCN：第一次刷新时探测，之后 console_write 出错时改为 `false`
+    dbcn: Option<bool>,
+}
+
+impl LineBuffer {
+    /// 输出缓冲中的全部内容并清空
+    fn flush(&mut self, putchar: fn(u8)) {
+        let mut written = 0;
+        if *self.dbcn.get_or_insert_with(dbcn::probe) {
+            while written < self.len {
+                match dbcn::write(&self.buf[written..self.len]) {
+                    Some(n) if n > 0 => written += n,
+                    Some(_) => break,
+                    // 有的 SBI 对任何扩展都报告“已实现”（如 tg-sbi 的 nobios），
+                    // 调用失败一次后不再尝试，避免每次刷新都多一次注定失败的 SBI 调用
+                    None => {
+                        self.dbcn = Some(false);
+                        break;
+                    }
+                }
+            }
+        }
+        // DBCN 不可用或写出出错：剩余部分逐字节输出
+        for &c in &self.buf[written..self.len] {
+            putchar(c);
+        }
+        self.len = 0;
+    }
+}
+
+/// 内核单核运行，输出过程中不会被中断打断，用 `UnsafeCell` 即可
+struct Buffer(UnsafeCell<LineBuffer>);
+
+unsafe impl Sync for Buffer {}
+
+static BUFFER: Buffer = Buffer(UnsafeCell::new(LineBuffer { buf: [0; CAPACITY], len: 0, dbcn: None }));
+
+/// 把一个字节写入缓冲，遇到换行或缓冲满时刷新；DBCN 不可用时用 `putchar` 逐字节输出
+pub fn put_char(c: u8, putchar: fn(u8)) {
+    let buffer = unsafe { &mut *BUFFER.0.get() };
+    buffer.buf[buffer.len] = c;
+    buffer.len += 1;
+    if c == b'\n' || buffer.len == CAPACITY {
+        buffer.flush(putchar);
+    }
+}
+
+/// 立即输出缓冲中的全部内容；DBCN 不可用时用 `putchar` 逐字节输出
+pub fn flush(putchar: fn(u8)) {
+    unsafe { &mut *BUFFER.0.get() }.flush(putchar);
+}
+
+/// SBI Debug Console 扩展（DBCN，SBI 2.0 起提供）
+mod dbcn {
+    /// Base 扩展
+    const EID_BASE: usize = 0x10;
+    /// Base 扩展：sbi_probe_extension
+    const FID_PROBE_EXTENSION: usize = 3;
+    /// Debug Console 扩展（"DBCN"）
+    const EID_DBCN: usize = 0x4442_434E;
+    /// Debug Console 扩展：sbi_debug_console_write
+    const FID_CONSOLE_WRITE: usize = 0;
+
+    /// 探测 SBI 是否实现了 DBCN
+    pub fn probe() -> bool {
+        matches!(ecall(EID_BASE, FID_PROBE_EXTENSION, [EID_DBCN, 0, 0]), Ok(available) if available != 0)
+    }
+
+    /// console_write：返回实际写出的字节数，出错时返回 `None`
+    ///
+    /// 参数是物理地址。内核未开启分页或运行在恒等映射的地址空间中，缓冲区的虚拟地址就是物理地址。
+    pub fn write(bytes: &[u8]) -> Option<usize> {
+        ecall(EID_DBCN, FID_CONSOLE_WRITE, [bytes.len(), bytes.as_ptr() as usize, 0]).ok()
+    }
+
+    /// 发起一次 SBI 调用，返回 `(error, value)` 中的 value 或错误码
+    #[cfg(target_arch = "riscv64")]
+    fn ecall(eid: usize, fid: usize, args: [usize; 3]) -> Result<usize, isize> {
+        let (error, value): (usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") args[0] => error,
+                inlateout("a1") args[1] => value,
+                in("a2") args[2],
+                in("a6") fid,
+                in("a7") eid,
+            );
+        }
+        if error == 0 { Ok(value) } else { Err(error as isize) }
+    }
+
+    /// 非 RISC-V64 架构的占位实现：视为不支持
+    #[cfg(not(target_arch = "riscv64"))]
+    fn ecall(_eid: usize, _fid: usize, _args: [usize; 3]) -> Result<usize, isize> {
+        Err(-2)
+    }
+}
@@ -26,6 +26,9 @@
 // 非 RISC-V64 架构允许死代码（用于 cargo publish --dry-run 在主机上通过编译）
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code))]
 
+// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
 // 任务管理模块：定义任务控制块（TCB）和调度事件
 mod task;
 
@@ -124,13 +127,15 @@ extern "C" fn rust_main() -> ! {
     unsafe { sie::set_stimer() };
 
     // ========== 多道程序主循环 ==========
-    // 使用轮转调度算法（Round-Robin），依次执行各任务
+    // 使用加权轮转调度算法（Weighted Round-Robin），依次执行各任务：
+    // 每轮中任务最多连续运行 priority 个时间片，主动 yield 或退出则提前轮转
     let mut remain = index_mod; // 剩余未完成的任务数
     let mut i = 0usize; // 当前任务索引
     while remain > 0 {
         let tcb = unsafe { &mut TCBS[i] };
         unsafe { CURRENT_TASK = i; } // 更新当前任务索引
         if !tcb.finish {
+            let mut slices = 0; // 本轮已用完的时间片数
             loop {
                 // 【抢占式调度】设置时钟中断：12500 个时钟周期后触发
                 // 当 coop feature 启用时，跳过此步（协作式调度，不使用时钟中断）
@@ -150,6 +155,11 @@ extern "C" fn rust_main() -> ! {
                         // 清除时钟中断（设置为最大值，避免立即再次触发）
                         tg_sbi::set_timer(u64::MAX);
                         log::trace!("app{i} timeout");
+                        // 权重未用完时继续运行当前任务
+                        slices += 1;
+                        if slices < tcb.priority {
+                            continue;
+                        }
                         false // 不结束任务，切换到下一个
                     }
                     // ─── 系统调用：用户程序执行了 ecall 指令 ───
@@ -189,6 +199,8 @@ extern "C" fn rust_main() -> ! {
 
                 // 如果任务结束（退出或被杀死），标记为已完成
                 if finish {
+                    // 任务可能留下不以换行结尾的输出
+                    console::flush();
                     tcb.finish = true;
                     remain -= 1;
                 }
@@ -200,6 +212,7 @@ extern "C" fn rust_main() -> ! {
     }
 
     // 所有用户程序执行完毕，关机
+    console::flush();
     tg_sbi::shutdown(false)
 }
 
@@ -209,6 +222,7 @@ extern "C" fn rust_main() -> ! {
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
     tg_sbi::shutdown(true)
 }
 
@@ -218,14 +232,15 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 mod impls {
     use tg_syscall::*;
     use crate::{TCBS, CURRENT_TASK};
+    use crate::task::MAX_PRIORITY;
 
-    /// 控制台实现：通过 SBI 逐字符输出
+    /// 控制台实现：经 `console` 模块的行缓冲输出
     pub struct Console;
 
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
@@ -263,7 +278,7 @@ mod impls {
         }
     }
 
-    /// Scheduling 系统调用实现：处理 yield 系统调用
+    /// Scheduling 系统调用实现：处理 yield 和 set_priority 系统调用
     ///
     /// `sched_yield` 允许任务主动让出 CPU，是协作式调度的基础。
     /// 内核在收到 yield 后会切换到下一个就绪任务。
@@ -272,6 +287,17 @@ mod impls {
         fn sched_yield(&self, _caller: Caller) -> isize {
             0
         }
+
+        /// 设置当前任务的调度权重，合法范围为 `1..=MAX_PRIORITY`，返回设置的值，失败返回 -1
+        ///
+        /// 权重只在抢占式调度下生效：协作式调度没有时钟中断，任务总是运行到 yield 为止。
+        fn set_priority(&self, _caller: Caller, prio: isize) -> isize {
+            if !(1..=MAX_PRIORITY as isize).contains(&prio) {
+                return -1;
+            }
+            unsafe { TCBS[CURRENT_TASK].priority = prio as usize };
+            prio
+        }
     }
 
     /// Clock 系统调用实现：处理 clock_gettime 系统调用
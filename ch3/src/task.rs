@@ -25,6 +25,7 @@ use tg_syscall::{Caller, SyscallId};
 /// - `finish`：任务是否已完成（退出或被杀死）
 /// - `stack`：用户栈空间（8 KiB），每个任务有独立的栈
 /// - `syscall_count`：系统调用计数器数组，用于统计各系统调用的调用次数
+/// - `priority`：调度权重，每轮轮转中连续获得的时间片数
 pub struct TaskControlBlock {
     /// 用户态上下文：保存 Trap 时的所有寄存器状态
     ctx: LocalContext,
@@ -35,8 +36,15 @@ pub struct TaskControlBlock {
     stack: [usize; 1024],
     /// 系统调用计数器：索引为系统调用号，值为调用次数（最多支持 512 个系统调用）
     pub syscall_count: [usize; 512],
+    /// 调度权重：每轮最多连续运行 `priority` 个时间片，默认为 1（等权轮转）
+    pub priority: usize,
 }
 
+/// 默认优先级
+pub const DEFAULT_PRIORITY: usize = 1;
+/// 最大优先级，避免单个任务一轮内长时间独占 CPU
+pub const MAX_PRIORITY: usize = 16;
+
 /// 调度事件
 ///
 /// `handle_syscall` 处理完系统调用后返回此枚举，
@@ -59,6 +67,7 @@ impl TaskControlBlock {
         finish: false,
         stack: [0; 1024],
         syscall_count: [0; 512],
+        priority: DEFAULT_PRIORITY,
     };
 
     /// 初始化一个任务
@@ -69,6 +78,7 @@ impl TaskControlBlock {
     pub fn init(&mut self, entry: usize) {
         self.stack.fill(0);
         self.finish = false;
+        self.priority = DEFAULT_PRIORITY;
         self.ctx = LocalContext::user(entry);
         // 栈从高地址向低地址增长，所以 sp 指向栈顶（数组末尾之后的地址）
         *self.ctx.sp_mut() = self.stack.as_ptr() as usize + core::mem::size_of_val(&self.stack);
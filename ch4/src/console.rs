@@ -0,0 +1,14 @@
+//! 控制台输出缓冲
+//!
+//! 行缓冲本身在 `tg-console-buffer` 中实现（ch2~ch8 共用），这里只提供本章的逐字节输出函数。
+//! 缓冲中可能留有不以换行结尾的内容，因此进程退出、关机与 panic 前都要调用 [`flush`]。
+
+/// 把一个字节写入缓冲，遇到换行或缓冲满时刷新
+pub fn put_char(c: u8) {
+    tg_console_buffer::put_char(c, tg_sbi::console_putchar);
+}
+
+/// 立即输出缓冲中的全部内容
+pub fn flush() {
+    tg_console_buffer::flush(tg_sbi::console_putchar);
+}
@@ -27,6 +27,9 @@
 // 非 RISC-V64 架构允许死代码和未使用导入（用于 cargo publish --dry-run）
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code, unused_imports))]
 
+// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
 // 进程管理模块：定义 Process 结构体，包含地址空间和上下文
 mod process;
 
@@ -44,7 +47,11 @@ use crate::{
     process::Process,
 };
 use alloc::{alloc::alloc, vec::Vec};
-use core::{alloc::Layout, cell::UnsafeCell};
+use core::{
+    alloc::Layout,
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use impls::Console;
 use riscv::register::*;
 // 非 RISC-V64 使用占位 Sv39 类型
@@ -140,6 +147,12 @@ impl ProcessList {
 /// 全局进程列表实例。
 static PROCESSES: ProcessList = ProcessList::new();
 
+/// 调度线程是否正在处理 `PROCESSES[0]` 的 Trap。
+///
+/// 此时调度线程发生的异常可以归咎于该进程的数据（例如系统调用参数），
+/// `rust_main` 据此决定是杀死该进程后重启调度线程，还是视为内核 bug 而 panic。
+static HANDLING_TRAP: AtomicBool = AtomicBool::new(false);
+
 // ========== 内核主函数 ==========
 
 /// 内核主函数：初始化各子系统，建立内核地址空间，加载用户进程。
@@ -202,12 +215,48 @@ extern "C" fn rust_main() -> ! {
     );
     // 第八步：建立调度线程
     // 调度线程在独立的异常域运行，内核异常不会导致整个系统崩溃
-    let mut scheduling = LocalContext::thread(schedule as *const () as _, false);
-    *scheduling.sp_mut() = 1 << 38;
-    unsafe { scheduling.execute() };
-    // 如果从 execute() 返回，说明调度线程发生了异常
-    log::error!("stval = {:#x}", stval::read());
-    panic!("trap from scheduling thread: {:?}", scause::read().cause());
+    loop {
+        let mut scheduling = LocalContext::thread(schedule as *const () as _, false);
+        *scheduling.sp_mut() = 1 << 38;
+        unsafe { scheduling.execute() };
+        // 如果从 execute() 返回，说明调度线程发生了异常
+        let cause = scause::read().cause();
+        let stval = stval::read();
+        let in_portal = scheduling.pc() >= PROTAL_TRANSIT.base().val();
+        if !HANDLING_TRAP.swap(false, Ordering::Relaxed) || in_portal || !recoverable(cause) {
+            log::error!("stval = {stval:#x}, sepc = {:#x}", scheduling.pc());
+            panic!("trap from scheduling thread: {cause:?}");
+        }
+        // 可恢复：跳过出问题的进程，用新的栈重新进入调度线程
+        log::error!(
+            "trap from scheduling thread: {cause:?}, stval = {stval:#x}, sepc = {:#x}; app killed",
+            scheduling.pc()
+        );
+        unsafe { PROCESSES.get_mut().remove(0) };
+    }
+}
+
+/// 调度线程的异常是否可能由进程数据引起（访存错误），其余异常与中断视为内核 bug。
+///
+/// riscv 0.10.1 没有 load 地址不对齐的变体（scause 4 解析为 `Unknown`），这类异常无法识别，按内核 bug 处理。
+fn recoverable(cause: scause::Trap) -> bool {
+    use scause::{Exception as E, Trap};
+    matches!(
+        cause,
+        Trap::Exception(
+            E::LoadFault
+                | E::StoreFault
+                | E::LoadPageFault
+                | E::StorePageFault
+                | E::StoreMisaligned
+        )
+    )
+}
+
+/// 移除 `PROCESSES[0]`：先结束对它的 Trap 处理，移除过程中的异常不再归咎于它。
+fn remove_current() {
+    HANDLING_TRAP.store(false, Ordering::Relaxed);
+    unsafe { PROCESSES.get_mut().remove(0) };
 }
 
 // ========== 调度函数 ==========
@@ -219,6 +268,9 @@ extern "C" fn rust_main() -> ! {
 /// 2. 取出第一个进程，通过传送门切换到其地址空间并执行
 /// 3. Trap 返回后处理系统调用或异常
 /// 4. 进程退出后从列表中移除，继续下一个
+///
+/// 处理 Trap 期间调度线程发生访存异常时，`rust_main` 会杀死当前进程并重新进入本函数，
+/// 因此这里的初始化必须可以重复执行。
 extern "C" fn schedule() -> ! {
     // 初始化异界传送门（设置传送门页面的虚拟地址和 slot 数量）
     let portal = unsafe { MultislotPortal::init_transit(PROTAL_TRANSIT.base().val(), 1) };
@@ -242,6 +294,7 @@ extern "C" fn schedule() -> ! {
         unsafe { ctx.execute(portal, ()) };
 
         // 处理 Trap
+        HANDLING_TRAP.store(true, Ordering::Relaxed);
         match scause::read().cause() {
             // ─── 系统调用 ───
             scause::Trap::Exception(scause::Exception::UserEnvCall) => {
@@ -261,9 +314,11 @@ extern "C" fn schedule() -> ! {
                 match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
                     Ret::Done(ret) => match id {
                         // exit：移除进程
-                        Id::EXIT => unsafe {
-                            PROCESSES.get_mut().remove(0);
-                        },
+                        Id::EXIT => {
+                            // 进程可能留下不以换行结尾的输出
+                            console::flush();
+                            remove_current()
+                        }
                         // 其他系统调用：写回返回值，sepc += 4
                         _ => {
                             *ctx.a_mut(0) = ret as _;
@@ -273,7 +328,7 @@ extern "C" fn schedule() -> ! {
                     // 不支持的系统调用：杀死进程
                     Ret::Unsupported(_) => {
                         log::info!("id = {id:?}");
-                        unsafe { PROCESSES.get_mut().remove(0) };
+                        remove_current();
                     }
                 }
             }
@@ -284,11 +339,13 @@ extern "C" fn schedule() -> ! {
                     stval::read(),
                     ctx.context.pc()
                 );
-                unsafe { PROCESSES.get_mut().remove(0) };
+                remove_current();
             }
         }
+        HANDLING_TRAP.store(false, Ordering::Relaxed);
     }
     // 所有进程执行完毕，关机
+    console::flush();
     tg_sbi::shutdown(false)
 }
 
@@ -298,6 +355,7 @@ extern "C" fn schedule() -> ! {
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     log::error!("{info}");
+    console::flush();
     tg_sbi::shutdown(true)
 }
 
@@ -366,7 +424,7 @@ fn kernel_space(
 /// 用户传入的指针是虚拟地址，内核需要通过页表将其翻译为物理地址才能访问。
 mod impls {
     use crate::{build_flags, Sv39, PROCESSES};
-    use alloc::alloc::alloc_zeroed;
+    use alloc::alloc::{alloc_zeroed, dealloc};
     use core::{alloc::Layout, ptr::NonNull};
     use tg_console::log;
     use tg_kernel_vm::{
@@ -381,7 +439,9 @@ mod impls {
 
     impl Sv39Manager {
         /// 自定义标志位：标记该页面由内核分配（用于 deallocate 时判断）
-        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
+        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(Self::OWNED_BIT) };
+        /// OWNED 标志在页表项中的位（释放页表时直接按位判断）
+        const OWNED_BIT: usize = 1 << 8;
 
         /// 分配物理页面并清零
         #[inline]
@@ -394,6 +454,17 @@ mod impls {
             }
             .cast()
         }
+
+        /// 归还 `count` 个从 `ptr` 开始的物理页面（与 `page_alloc` 的布局一致）
+        #[inline]
+        fn page_dealloc(ptr: *mut u8, count: usize) {
+            unsafe {
+                dealloc(
+                    ptr,
+                    Layout::from_size_align_unchecked(count << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
     }
 
     /// 实现 PageManager trait：为地址空间提供页表操作能力
@@ -441,22 +512,61 @@ mod impls {
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
 
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        /// 释放 `pte` 指向的 `len` 个物理页面，返回释放的页数
+        ///
+        /// 只回收带 OWNED 标志、由本地址空间分配的页；外部映射的页（恒等映射、异界传送门）不归它所有。
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            if !self.check_owned(pte) {
+                return 0;
+            }
+            Self::page_dealloc(self.p_to_v::<u8>(pte.ppn()).as_ptr(), len);
+            len
         }
 
+        /// 释放整棵页表：自有的叶子页面、全部中间页表，最后是根页表本身
+        ///
+        /// 用户地址只落在根页表的低半部分；高半部分的异界传送门项是从内核地址空间复制来的，
+        /// 指向的子树属于内核，不能释放。叶子页按 4 KiB 逐页归还。
         fn drop_root(&mut self) {
-            todo!()
+            const PTE_V: usize = 1 << 0;
+            const PTE_RWX: usize = 0b111 << 1;
+            fn free_table(table: *mut usize, level: usize, entries: usize) {
+                for index in 0..entries {
+                    let pte = unsafe { *table.add(index) };
+                    if pte & PTE_V == 0 {
+                        continue;
+                    }
+                    let ppn = (pte >> 10) & ((1 << 44) - 1);
+                    let page = (ppn << Sv39::PAGE_BITS) as *mut u8;
+                    if pte & PTE_RWX == 0 {
+                        if level > 0 {
+                            free_table(page.cast(), level - 1, 512);
+                        }
+                    } else if pte & Sv39Manager::OWNED_BIT != 0 {
+                        // level 级的叶子覆盖 512^level 个页面
+                        Sv39Manager::page_dealloc(page, 1 << (9 * level));
+                    }
+                }
+                Sv39Manager::page_dealloc(table.cast(), 1);
+            }
+            free_table(self.0.as_ptr().cast(), 2, 256);
+        }
+    }
+
+    impl Drop for Sv39Manager {
+        /// 地址空间被释放（进程退出、exec 换掉旧地址空间）时回收它的页表和页面
+        fn drop(&mut self) {
+            self.drop_root();
         }
     }
 
-    /// 控制台实现：通过 SBI 逐字符输出
+    /// 控制台实现：经 `console` 模块的行缓冲输出
     pub struct Console;
 
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
@@ -586,7 +696,9 @@ mod impls {
                     if id >= (1 << 38) {
                         return -1;
                     }
-                    const READABLE: VmFlags<Sv39> = build_flags("R_V");
+                    // 标志串中每个字母对应一位（`_` 只作占位），"U__RV" 即 U|R|V；
+                    // 要求 U 位，避免读到未设置 U 位的内核页（如异界传送门）
+                    const READABLE: VmFlags<Sv39> = build_flags("U__RV");
                     if let Some(ptr) = unsafe { PROCESSES.get_mut() }
                         .get_mut(caller.entity)
                         .unwrap()
@@ -604,7 +716,8 @@ mod impls {
                     if id >= (1 << 38) {
                         return -1;
                     }
-                    const WRITABLE: VmFlags<Sv39> = build_flags("W_V");
+                    // 只读代码段、rodata 没有 W 位，写入会失败
+                    const WRITABLE: VmFlags<Sv39> = build_flags("U_W_V");
                     if let Some(mut ptr) = unsafe { PROCESSES.get_mut() }
                         .get_mut(caller.entity)
                         .unwrap()
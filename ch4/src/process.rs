@@ -20,13 +20,11 @@
 //! - 最后结合 `ch4/src/main.rs`：对齐“进程对象创建”和“调度执行”两条路径。
 
 use crate::{build_flags, parse_flags, Sv39, Sv39Manager};
-use alloc::alloc::alloc_zeroed;
 use alloc::boxed::Box;
-use core::alloc::Layout;
 use tg_console::log;
 use tg_kernel_context::{foreign::ForeignContext, LocalContext};
 use tg_kernel_vm::{
-    page_table::{MmuMeta, VAddr, PPN, VPN},
+    page_table::{MmuMeta, VAddr, VPN},
     AddressSpace,
 };
 use xmas_elf::{
@@ -124,16 +122,12 @@ impl Process {
         let heap_bottom = VAddr::<Sv39>::new(max_end_va).ceil().base().val();
 
         // 分配用户栈：2 页 = 8 KiB，映射到虚拟地址空间的高地址区域
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        // 用户栈映射到 VPN [(1<<26)-2, 1<<26)，即虚拟地址空间的高区域
-        address_space.map_extern(
+        // 用户栈映射到 VPN [(1<<26)-2, 1<<26)，即虚拟地址空间的高区域；
+        // 由 map 分配清零的物理页并标记 OWNED，进程退出时随地址空间一起回收
+        address_space.map(
             VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
+            &[],
+            0,
             build_flags("U_WRV"), // 用户态可读写
         );
 
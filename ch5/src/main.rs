@@ -39,6 +39,9 @@
 // 在非 RISC-V 架构上允许未使用的代码（用于 IDE 开发体验）
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code, unused_imports))]
 
+/// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
 /// 进程模块：定义 Process 结构体及其方法（from_elf、fork、exec 等）
 mod process;
 /// 处理器模块：定义 PROCESSOR 全局变量和进程管理器 ProcManager
@@ -228,14 +231,17 @@ extern "C" fn rust_main() -> ! {
     tg_syscall::init_memory(&SyscallContext);
     // 步骤 8：加载初始进程 initproc
     // initproc 是所有用户进程的祖先，它会 fork 出 shell 进程
-    let initproc_data = APPS.get("initproc").unwrap();
-    if let Some(process) = Process::from_elf(ElfFile::new(initproc_data).unwrap()) {
-        // 初始化进程管理器并添加 initproc
-        PROCESSOR.get_mut().set_manager(ProcManager::new());
-        PROCESSOR
-            .get_mut()
-            .add(process.pid, process, ProcId::from_usize(usize::MAX));
-    }
+    let Some(initproc_data) = APPS.get("initproc") else {
+        no_initproc("not found")
+    };
+    let Some(process) = ElfFile::new(initproc_data).ok().and_then(Process::from_elf) else {
+        no_initproc("not a valid RISC-V executable")
+    };
+    // 初始化进程管理器并添加 initproc
+    PROCESSOR.get_mut().set_manager(ProcManager::new());
+    PROCESSOR
+        .get_mut()
+        .add(process.pid, process, ProcId::from_usize(usize::MAX));
 
     // ─── 主调度循环 ───
     // 不断从进程管理器中取出就绪进程执行，直到所有进程结束
@@ -265,25 +271,40 @@ extern "C" fn rust_main() -> ! {
                     match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
                         Ret::Done(ret) => match id {
                             // exit 系统调用：标记当前进程为已退出
-                            Id::EXIT => unsafe { (*processor).make_current_exited(ret) },
+                            Id::EXIT => processor::exit_current(ret),
                             _ => {
                                 // 其他系统调用：将返回值写入 a0 寄存器，暂停当前进程
                                 let ctx = &mut task.context.context;
-                                *ctx.a_mut(0) = ret as _;
+                                if processor::is_wait_blocked(task.pid) {
+                                    // wait 需要阻塞：回退 sepc，被唤醒后重新执行这次 ecall
+                                    *ctx.pc_mut() -= 4;
+                                } else {
+                                    *ctx.a_mut(0) = ret as _;
+                                }
                                 unsafe { (*processor).make_current_suspend() };
                             }
                         },
                         Ret::Unsupported(_) => {
                             // 不支持的系统调用：终止进程
                             log::info!("id = {id:?}");
-                            unsafe { (*processor).make_current_exited(-2) };
+                            processor::exit_current(-2);
                         }
                     }
                 }
+                // ─── 写 fork 后写时复制的页：复制出私有页，重新执行出错的指令 ───
+                scause::Trap::Exception(scause::Exception::StorePageFault) => {
+                    let addr = stval::read();
+                    if process::resolve_cow(&task.address_space, addr, 1) {
+                        unsafe { (*processor).make_current_suspend() };
+                    } else {
+                        log::error!("page fault at {addr:#x}, pc = {:#x}", task.context.context.pc());
+                        processor::exit_current(-3);
+                    }
+                }
                 // ─── 其他异常/中断：杀死进程 ───
                 e => {
                     log::error!("unsupported trap: {e:?}");
-                    unsafe { (*processor).make_current_exited(-3) };
+                    processor::exit_current(-3);
                 }
             }
         } else {
@@ -293,13 +314,24 @@ extern "C" fn rust_main() -> ! {
         }
     }
     // 所有进程执行完毕，关机
+    console::flush();
     tg_sbi::shutdown(false)
 }
 
+/// initproc 缺失或无法加载：打印原因和镜像中可用的程序后关机，而不是 panic
+fn no_initproc(reason: &str) -> ! {
+    log::error!("cannot load initproc: {reason}");
+    println!("available apps:");
+    APPS.keys().for_each(|app| println!("{app}"));
+    console::flush();
+    tg_sbi::shutdown(true)
+}
+
 /// Rust panic 处理函数，打印错误信息并以异常方式关机
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
     tg_sbi::shutdown(true)
 }
 
@@ -370,9 +402,12 @@ fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
 /// 包括 IO、Process、Scheduling、Clock、Memory 等系统调用接口。
 mod impls {
     use crate::{
-        build_flags, process::Process as ProcStruct, processor::ProcManager, Sv39, APPS, PROCESSOR,
+        build_flags,
+        process::{resolve_cow, unshare_page, Process as ProcStruct, WriteTranslate},
+        processor::{has_live_child, reap, record_child, set_wait_blocked, ProcManager},
+        Sv39, APPS, PROCESSOR,
     };
-    use alloc::alloc::alloc_zeroed;
+    use alloc::alloc::{alloc_zeroed, dealloc};
     use core::{alloc::Layout, ptr::NonNull};
     use tg_console::log;
     use tg_kernel_vm::{
@@ -396,7 +431,9 @@ mod impls {
 
     impl Sv39Manager {
         /// 自定义标志位：标记此页面由内核分配（用于区分恒等映射的外部页面）
-        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
+        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(Self::OWNED_BIT) };
+        /// OWNED 标志在页表项中的位（释放页表时直接按位判断）
+        pub(crate) const OWNED_BIT: usize = 1 << 8;
 
         /// 分配对齐的物理页面（已清零）
         #[inline]
@@ -409,6 +446,17 @@ mod impls {
             }
             .cast()
         }
+
+        /// 归还 `count` 个从 `ptr` 开始的物理页面（与 `page_alloc` 的布局一致）
+        #[inline]
+        fn page_dealloc(ptr: *mut u8, count: usize) {
+            unsafe {
+                dealloc(
+                    ptr,
+                    Layout::from_size_align_unchecked(count << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
@@ -455,12 +503,61 @@ mod impls {
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
 
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        /// 释放 `pte` 指向的 `len` 个物理页面，返回释放的页数
+        ///
+        /// 只回收带 OWNED 标志、由本地址空间分配的页；外部映射的页（恒等映射、异界传送门）不归它所有。
+        /// fork 共享的页与 `drop_root` 一样，由最后一个映射它的地址空间释放。
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            if let Some(last) = unshare_page(pte.ppn().val()) {
+                if !last {
+                    return 0;
+                }
+            } else if !self.check_owned(pte) {
+                return 0;
+            }
+            Self::page_dealloc(self.p_to_v::<u8>(pte.ppn()).as_ptr(), len);
+            len
         }
 
+        /// 释放整棵页表：自有的叶子页面、全部中间页表，最后是根页表本身
+        ///
+        /// 用户地址只落在根页表的低半部分；高半部分的异界传送门项是从内核地址空间复制来的，
+        /// 指向的子树属于内核，不能释放。叶子页按 4 KiB 逐页归还。
         fn drop_root(&mut self) {
-            todo!()
+            const PTE_V: usize = 1 << 0;
+            const PTE_RWX: usize = 0b111 << 1;
+            fn free_table(table: *mut usize, level: usize, entries: usize) {
+                for index in 0..entries {
+                    let pte = unsafe { *table.add(index) };
+                    if pte & PTE_V == 0 {
+                        continue;
+                    }
+                    let ppn = (pte >> 10) & ((1 << 44) - 1);
+                    let page = (ppn << Sv39::PAGE_BITS) as *mut u8;
+                    if pte & PTE_RWX == 0 {
+                        if level > 0 {
+                            free_table(page.cast(), level - 1, 512);
+                        }
+                    } else if let Some(last) = unshare_page(ppn) {
+                        // fork 共享的页：最后一个映射它的地址空间负责释放，不论它是否为原主
+                        if last {
+                            Sv39Manager::page_dealloc(page, 1);
+                        }
+                    } else if pte & Sv39Manager::OWNED_BIT != 0 {
+                        // level 级的叶子覆盖 512^level 个页面
+                        Sv39Manager::page_dealloc(page, 1 << (9 * level));
+                    }
+                }
+                Sv39Manager::page_dealloc(table.cast(), 1);
+            }
+            free_table(self.0.as_ptr().cast(), 2, 256);
+        }
+    }
+
+    impl Drop for Sv39Manager {
+        /// 地址空间被释放（进程退出、exec 换掉旧地址空间）时回收它的页表和页面
+        fn drop(&mut self) {
+            self.drop_root();
         }
     }
 
@@ -472,7 +569,7 @@ mod impls {
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
@@ -525,16 +622,15 @@ mod impls {
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             if fd == STDIN {
                 const WRITEABLE: VmFlags<Sv39> = build_flags("W_V");
-                if let Some(mut ptr) = PROCESSOR
-                    .get_mut()
-                    .current()
-                    .unwrap()
+                let current = PROCESSOR.get_mut().current().unwrap();
+                resolve_cow(&current.address_space, buf, count);
+                if let Some(mut ptr) = current
                     .address_space
                     .translate::<u8>(VAddr::new(buf), WRITEABLE)
                 {
                     let mut ptr = unsafe { ptr.as_mut() } as *mut u8;
                     for _ in 0..count {
-                        let c = tg_sbi::console_getchar() as u8;
+                        let c = crate::console::getchar();
                         unsafe {
                             *ptr = c;
                             ptr = ptr.add(1);
@@ -578,6 +674,7 @@ mod impls {
             *context.a_mut(0) = 0 as _;
             // 将子进程加入进程管理器，父进程 PID 用于维护进程树
             unsafe { (*processor).add(pid, child_proc, parent_pid) };
+            record_child(parent_pid, pid);
             // 父进程返回子进程 PID
             pid.get_usize() as isize
         }
@@ -615,7 +712,9 @@ mod impls {
         ///
         /// - pid == -1：等待任意子进程
         /// - pid > 0：等待指定 PID 的子进程
+        ///
         /// 返回值：成功返回子进程 PID，无子进程返回 -1
+        /// 子进程都还在运行时阻塞当前进程，直到有子进程退出
         fn wait(&self, _caller: Caller, pid: isize, exit_code_ptr: usize) -> isize {
             let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
             let current = unsafe { (*processor).current().unwrap() };
@@ -623,16 +722,20 @@ mod impls {
             if let Some((dead_pid, exit_code)) =
                 unsafe { (*processor).wait(ProcId::from_usize(pid as usize)) }
             {
+                set_wait_blocked(current.pid, false);
+                reap(dead_pid);
                 // 将退出码写入用户空间指针（需地址翻译）
                 if let Some(mut ptr) = current
                     .address_space
-                    .translate::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
+                    .translate_for_write::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
                 {
                     unsafe { *ptr.as_mut() = exit_code as i32 };
                 }
                 return dead_pid.get_usize() as isize;
             } else {
-                // 等待的子进程不存在
+                // 子进程尚未退出时阻塞，被唤醒后重新执行本次 wait；没有可等待的子进程才返回 -1
+                let target = (pid != -1).then(|| ProcId::from_usize(pid as usize));
+                set_wait_blocked(current.pid, has_live_child(current.pid, target));
                 return -1;
             }
         }
@@ -668,6 +771,7 @@ mod impls {
                     let child_pid = child_proc.pid;
                     // 将子进程加入进程管理器
                     unsafe { (*processor).add(child_pid, child_proc, parent_pid) };
+                    record_child(parent_pid, child_pid);
                     child_pid.get_usize() as isize
                 });
 
@@ -726,7 +830,7 @@ mod impls {
                         .current()
                         .unwrap()
                         .address_space
-                        .translate::<TimeSpec>(VAddr::new(tp), WRITABLE)
+                        .translate_for_write::<TimeSpec>(VAddr::new(tp), WRITABLE)
                     {
                         let time = riscv::register::time::read() * 10000 / 125;
                         *unsafe { ptr.as_mut() } = TimeSpec {
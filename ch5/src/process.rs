@@ -17,15 +17,19 @@
 //! 教程阅读建议：
 //!
 //! - 先看 `from_elf`：理解“进程初始化”与“程序装载”基础路径；
-//! - 再看 `fork`：重点理解地址空间深拷贝与上下文复制；
+//! - 再看 `fork`：重点理解地址空间的写时复制共享与上下文复制；
 //! - 最后看 `exec`：对比“保留 PID、替换执行映像”的设计含义。
 
 use crate::{build_flags, map_portal, parse_flags, Sv39, Sv39Manager};
-use alloc::alloc::alloc_zeroed;
-use core::alloc::Layout;
+use alloc::{
+    alloc::alloc,
+    collections::BTreeMap,
+};
+use core::{alloc::Layout, ptr::NonNull};
+use spin::Mutex;
 use tg_kernel_context::{foreign::ForeignContext, LocalContext};
 use tg_kernel_vm::{
-    page_table::{MmuMeta, VAddr, PPN, VPN},
+    page_table::{MmuMeta, VAddr, VmFlags, PPN, VPN},
     AddressSpace,
 };
 use tg_task_manage::ProcId;
@@ -56,6 +60,169 @@ pub struct Process {
     pub priority: usize,
 }
 
+/// 页表项标志位：有效
+const PTE_V: usize = 1 << 0;
+/// 页表项标志位：R/W/X 任一置位即为叶子页表项
+const PTE_RWX: usize = 0b111 << 1;
+/// 页表项标志位：可写
+const PTE_W: usize = 1 << 2;
+/// 页表项标志位：用户可访问
+const PTE_U: usize = 1 << 4;
+/// 页表项标志位：已被访问（由硬件置位）
+const PTE_A: usize = 1 << 6;
+/// 页表项标志位：已被写过（由硬件置位）
+const PTE_D: usize = 1 << 7;
+/// 页表项标志位：写时复制（RSW 的第二位，第一位是 `Sv39Manager::OWNED_BIT`）
+///
+/// fork 后父子共享的可写页清除 W 位并置上该位，对它的写入由 [`resolve_cow`] 处理。
+const PTE_COW: usize = 1 << 9;
+/// 复制页表项时保留的标志位：V R W X U
+const PTE_PERM: usize = 0x1f;
+
+/// fork 时父子共享的物理页（PPN）-> 映射它的地址空间个数
+///
+/// 地址空间释放时逐页减少计数（见 [`unshare_page`]），降到 0 时才真正释放该页；
+/// 写时复制页被复制走一份时计数也减一。
+static SHARED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// 释放地址空间时归还一个页的共享引用
+///
+/// 该页不是共享页时返回 `None`，由调用者按 OWNED 标志处理；
+/// 否则返回 `Some(是否为最后一个引用)`，最后一个引用者负责释放该页。
+pub fn unshare_page(ppn: usize) -> Option<bool> {
+    let mut shared = SHARED_PAGES.lock();
+    let count = shared.get_mut(&ppn)?;
+    *count -= 1;
+    if *count == 0 {
+        shared.remove(&ppn);
+        Some(true)
+    } else {
+        Some(false)
+    }
+}
+
+/// 页表项中的物理页号
+fn pte_ppn(pte: usize) -> usize {
+    (pte >> 10) & ((1 << 44) - 1)
+}
+
+/// fork：让 `dst` 与 `src` 共享全部用户页，不复制任何物理页
+///
+/// 只沿有效（V 位置位）的页表项向下递归，整段未映射的区域不会被访问；
+/// `dst` 只为共享的页建立所需的下级页表，稀疏地址空间的子进程页表页数与父进程相当。
+/// 可写页在 `src` 和 `dst` 中都改为写时复制；没有 U 位的页（异界传送门）不共享，由调用者重新映射。
+/// 修改了 `src` 的页表项，调用者需要刷新 TLB。
+fn share_user_pages(src: &AddressSpace<Sv39, Sv39Manager>, dst: &mut AddressSpace<Sv39, Sv39Manager>) {
+    fn walk(
+        table: usize,
+        level: usize,
+        vpn: usize,
+        dst: &mut AddressSpace<Sv39, Sv39Manager>,
+        shared: &mut BTreeMap<usize, usize>,
+    ) {
+        for index in 0..512 {
+            let pte = unsafe { &mut *(table as *mut usize).add(index) };
+            if *pte & PTE_V == 0 {
+                continue;
+            }
+            let vpn = (vpn << 9) | index;
+            let ppn = pte_ppn(*pte);
+            if *pte & PTE_RWX == 0 {
+                if level > 0 {
+                    walk(ppn << Sv39::PAGE_BITS, level - 1, vpn, dst, shared);
+                }
+            } else if level == 0 && *pte & PTE_U != 0 {
+                if *pte & (PTE_W | PTE_COW) != 0 {
+                    *pte = (*pte & !PTE_W) | PTE_COW;
+                }
+                let flags = unsafe { VmFlags::from_raw(*pte & (PTE_PERM | PTE_COW)) };
+                // 以外部页映射，子进程的地址空间不会把它当作自己分配的页
+                dst.map_extern(VPN::new(vpn)..VPN::new(vpn + 1), PPN::new(ppn), flags);
+                *shared.entry(ppn).or_insert(1) += 1;
+            }
+        }
+    }
+    let mut shared = SHARED_PAGES.lock();
+    walk(src.root_ppn().val() << Sv39::PAGE_BITS, 2, 0, dst, &mut shared);
+}
+
+/// 找到虚拟页号 `vpn` 对应的 4 KiB 用户页的页表项
+fn user_leaf_pte(space: &AddressSpace<Sv39, Sv39Manager>, vpn: usize) -> Option<*mut usize> {
+    let mut table = space.root_ppn().val() << Sv39::PAGE_BITS;
+    for level in (0..3).rev() {
+        let pte = unsafe { (table as *mut usize).add((vpn >> (9 * level)) & 0x1ff) };
+        let value = unsafe { *pte };
+        if value & PTE_V == 0 {
+            return None;
+        }
+        if value & PTE_RWX != 0 {
+            return (level == 0 && value & PTE_U != 0).then_some(pte);
+        }
+        table = pte_ppn(value) << Sv39::PAGE_BITS;
+    }
+    None
+}
+
+/// 把 `[addr, addr + len)` 中的写时复制页换成本地址空间私有的可写页
+///
+/// 用户写写时复制页引发 StorePageFault 时由 trap 处理调用；内核代用户写内存之前也要先调用它
+/// （见 [`WriteTranslate`]），否则翻译会因缺少 W 位失败。仍有其他地址空间映射的页复制一份，
+/// 只剩自己映射的页直接收回所有权，不必复制。
+/// 返回是否有页被转为可写；分配不到物理页时停在该页，它仍然不可写。
+/// `len` 往往直接来自系统调用参数，遇到第一个未映射的页就停止，遍历不会越过从 `addr` 起连续映射的部分。
+pub fn resolve_cow(space: &AddressSpace<Sv39, Sv39Manager>, addr: usize, len: usize) -> bool {
+    const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+    let first = addr >> Sv39::PAGE_BITS;
+    let last = addr.saturating_add(len.max(1) - 1) >> Sv39::PAGE_BITS;
+    let mut resolved = false;
+    for vpn in first..=last {
+        let Some(pte) = user_leaf_pte(space, vpn) else {
+            break;
+        };
+        let pte = unsafe { &mut *pte };
+        if *pte & PTE_COW == 0 {
+            continue;
+        }
+        let ppn = pte_ppn(*pte);
+        let mut shared = SHARED_PAGES.lock();
+        let new_ppn = match shared.get_mut(&ppn) {
+            Some(count) if *count > 1 => {
+                let page = unsafe { alloc(Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE)) };
+                if page.is_null() {
+                    break;
+                }
+                unsafe { core::ptr::copy_nonoverlapping((ppn << Sv39::PAGE_BITS) as *const u8, page, PAGE_SIZE) };
+                *count -= 1;
+                page as usize >> Sv39::PAGE_BITS
+            }
+            _ => {
+                shared.remove(&ppn);
+                ppn
+            }
+        };
+        // 新页归本地址空间所有；马上就要被写入，直接置上 A、D 位
+        *pte = (new_ppn << 10) | (*pte & PTE_PERM) | PTE_W | Sv39Manager::OWNED_BIT | PTE_A | PTE_D;
+        resolved = true;
+    }
+    if resolved {
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+    resolved
+}
+
+/// 内核代用户写内存时使用的地址翻译
+pub trait WriteTranslate {
+    /// 与 `translate` 相同，但先把 `T` 占用的写时复制页换成私有页（见 [`resolve_cow`]）
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>>;
+}
+
+impl WriteTranslate for AddressSpace<Sv39, Sv39Manager> {
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>> {
+        resolve_cow(self, addr.val(), core::mem::size_of::<T>());
+        self.translate(addr, flags)
+    }
+}
+
 impl Process {
     /// exec 系统调用的核心实现：用新程序替换当前进程
     ///
@@ -72,15 +239,17 @@ impl Process {
 
     /// fork 系统调用的核心实现：复制当前进程创建子进程
     ///
-    /// 深拷贝父进程的地址空间（包括所有映射的物理页面），
+    /// 子进程与父进程写时复制地共享全部用户页：fork 本身不复制物理页，
+    /// 之后父子谁先写某一页，谁就得到该页的一份私有副本（见 [`resolve_cow`]）。
     /// 子进程获得独立的 PID 和地址空间，但初始上下文与父进程相同。
     pub fn fork(&mut self) -> Option<Process> {
         // 分配新的 PID
         let pid = ProcId::new();
-        // 复制父进程的完整地址空间（深拷贝所有页表和物理页面数据）
-        let parent_addr_space = &self.address_space;
+        // 共享父进程地址空间中的全部用户页（只建立用到的页表）
         let mut address_space: AddressSpace<Sv39, Sv39Manager> = AddressSpace::new();
-        parent_addr_space.cloneself(&mut address_space);
+        share_user_pages(&self.address_space, &mut address_space);
+        // 父进程的可写页刚被改为只读，清掉 TLB 中仍带 W 位的旧表项
+        unsafe { riscv::asm::sfence_vma_all() };
         // 在子进程地址空间中映射异界传送门
         map_portal(&address_space);
         // 复制父进程的用户态上下文（通用寄存器状态）
@@ -171,17 +340,8 @@ impl Process {
 
         // 映射用户栈：2 页 = 8 KiB，位于虚拟地址空间高位
         // 栈顶为 1 << 38（256 GiB），栈底为 (1<<38) - 8KiB
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
-            VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
-            build_flags("U_WRV"),
-        );
+        // 物理页由 map 分配并标记 OWNED，进程退出时随地址空间一起回收
+        address_space.map(VPN::new((1 << 26) - 2)..VPN::new(1 << 26), &[], 0, build_flags("U_WRV"));
 
         // 映射异界传送门（与内核地址空间共享同一物理页面）
         map_portal(&address_space);
@@ -23,8 +23,10 @@
 //! - 最后结合 `ch5/src/main.rs` 中对 `PROCESSOR` 的调用观察状态流转。
 
 use crate::process::Process;
-use alloc::collections::{BTreeMap, VecDeque};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
 use core::cell::UnsafeCell;
+use spin::Mutex;
+use tg_console::log;
 use tg_task_manage::{Manage, PManager, ProcId, Schedule};
 
 /// 处理器全局管理器
@@ -55,6 +57,112 @@ impl Processor {
 /// 全局处理器管理器实例
 pub static PROCESSOR: Processor = Processor::new();
 
+/// wait 阻塞所需的进程关系
+///
+/// `PManager` 只在 wait 时报告已退出的子进程，这里额外记录存活的父子关系，
+/// 用于判断 wait 是否需要阻塞，以及子进程退出时该唤醒谁。
+struct WaitState {
+    /// 存活子进程 -> 父进程
+    parent: BTreeMap<ProcId, ProcId>,
+    /// 阻塞在 wait 上的进程
+    waiting: BTreeSet<ProcId>,
+    /// 已退出、尚未被 wait 回收的进程
+    zombies: BTreeMap<ProcId, Zombie>,
+    /// 调度轮次，每次 `fetch` 加一
+    round: usize,
+}
+
+/// 僵尸进程记录，只用于发现可能的 wait 泄漏
+struct Zombie {
+    /// 退出码
+    exit_code: isize,
+    /// 退出时的调度轮次
+    since: usize,
+    /// 是否已经告警过
+    warned: bool,
+}
+
+/// 僵尸进程超过这么多调度轮次仍无人回收时告警
+const ZOMBIE_WARN_ROUNDS: usize = 1000;
+
+static WAIT_STATE: Mutex<WaitState> = Mutex::new(WaitState {
+    parent: BTreeMap::new(),
+    waiting: BTreeSet::new(),
+    zombies: BTreeMap::new(),
+    round: 0,
+});
+
+/// 记录父子关系（fork/spawn 把子进程加入管理器后调用）
+pub fn record_child(parent: ProcId, child: ProcId) {
+    WAIT_STATE.lock().parent.insert(child, parent);
+}
+
+/// `parent` 是否有可等待的存活子进程，`target` 为 `None` 表示任意子进程
+pub fn has_live_child(parent: ProcId, target: Option<ProcId>) -> bool {
+    let state = WAIT_STATE.lock();
+    match target {
+        Some(child) => state.parent.get(&child) == Some(&parent),
+        None => state.parent.values().any(|&p| p == parent),
+    }
+}
+
+/// 设置 `pid` 是否阻塞在 wait 上；阻塞的进程不会被调度，直到有子进程退出
+pub fn set_wait_blocked(pid: ProcId, blocked: bool) {
+    let mut state = WAIT_STATE.lock();
+    if blocked {
+        state.waiting.insert(pid);
+    } else {
+        state.waiting.remove(&pid);
+    }
+}
+
+/// `pid` 是否阻塞在 wait 上
+pub fn is_wait_blocked(pid: ProcId) -> bool {
+    WAIT_STATE.lock().waiting.contains(&pid)
+}
+
+/// `pid` 的退出码已被 wait 取走，不再是僵尸进程
+pub fn reap(pid: ProcId) {
+    WAIT_STATE.lock().zombies.remove(&pid);
+}
+
+/// 推进一个调度轮次，对滞留过久的僵尸进程各告警一次
+fn tick_zombies() {
+    let mut state = WAIT_STATE.lock();
+    state.round += 1;
+    let round = state.round;
+    for (pid, zombie) in state.zombies.iter_mut() {
+        if !zombie.warned && round - zombie.since > ZOMBIE_WARN_ROUNDS {
+            zombie.warned = true;
+            log::warn!(
+                "process {} exited with code {} but has not been waited for \
+                 after {ZOMBIE_WARN_ROUNDS} rounds, possible wait leak",
+                pid.get_usize(),
+                zombie.exit_code,
+            );
+        }
+    }
+}
+
+/// 让当前进程退出，并唤醒阻塞在 wait 上的父进程
+pub fn exit_current(exit_code: isize) {
+    // 进程可能留下不以换行结尾的输出
+    crate::console::flush();
+    let manager = PROCESSOR.get_mut();
+    if let Some(pid) = manager.current().map(|p| p.pid) {
+        let mut state = WAIT_STATE.lock();
+        let since = state.round;
+        state.zombies.insert(pid, Zombie { exit_code, since, warned: false });
+        if let Some(parent) = state.parent.remove(&pid) {
+            state.waiting.remove(&parent);
+        }
+        // 子进程由 PManager 托付给 initproc，这里不再跟踪
+        state.parent.retain(|_, p| *p != pid);
+        state.waiting.remove(&pid);
+    }
+    manager.make_current_exited(exit_code);
+}
+
 /// 进程管理器
 ///
 /// 负责管理所有进程实体和调度队列：
@@ -109,6 +217,7 @@ impl Schedule<ProcId> for ProcManager {
 
     /// 从就绪队列中选择 stride 最小的进程（stride 调度算法）
     fn fetch(&mut self) -> Option<ProcId> {
+        tick_zombies();
         if self.ready_queue.is_empty() {
             return None;
         }
@@ -118,6 +227,10 @@ impl Schedule<ProcId> for ProcManager {
         let mut min_index = 0;
 
         for (index, &pid) in self.ready_queue.iter().enumerate() {
+            // 阻塞在 wait 上的进程不参与调度；若全部阻塞则退化为选第一个，让它重新检查
+            if is_wait_blocked(pid) {
+                continue;
+            }
             if let Some(process) = self.tasks.get(&pid) {
                 if process.stride < min_stride {
                     min_stride = process.stride;
@@ -22,24 +22,52 @@
 use crate::virtio_block::BLOCK_DEVICE;
 use alloc::{string::String, sync::Arc, vec::Vec};
 use spin::Lazy;
-use tg_easy_fs::{EasyFileSystem, FSManager, FileHandle, Inode, OpenFlags};
+use tg_easy_fs::{
+    block_cache_sync_all, EasyFileSystem, FSManager, FileHandle, Inode, LinkError, OpenFlags,
+};
+use tg_syscall::StatMode;
 
 /// 全局文件系统实例
 ///
 /// 在首次访问时初始化：
 /// 1. 通过 `BLOCK_DEVICE`（VirtIO 块设备）打开 easy-fs 文件系统
 /// 2. 获取根目录 inode
-pub static FS: Lazy<FileSystem> = Lazy::new(|| FileSystem {
-    root: EasyFileSystem::root_inode(&EasyFileSystem::open(BLOCK_DEVICE.clone())),
+///
+/// 同时把 `time` 寄存器设为 easy-fs 的时钟，文件时间戳与 clock_gettime 一样是开机以来的纳秒数。
+pub static FS: Lazy<FileSystem> = Lazy::new(|| {
+    tg_easy_fs::set_clock(|| riscv::register::time::read64() * 10000 / 125);
+    FileSystem {
+        root: Arc::new(EasyFileSystem::root_inode(&EasyFileSystem::open(BLOCK_DEVICE.clone()))),
+    }
 });
 
+/// 把块缓存中的脏块写回磁盘并冲刷块设备
+///
+/// 关机前调用，避免尚未落盘的修改丢失。
+///
+/// 返回是否所有脏块都已写回；失败的块仍留在缓存中，下次同步时重试。
+pub fn sync_all() -> bool {
+    let ok = block_cache_sync_all();
+    BLOCK_DEVICE.flush();
+    ok
+}
+
+/// 只写回 `inode` 自己的数据块与文件系统元数据，再冲刷块设备（fsync）
+///
+/// 返回是否这些块都已写回。
+pub fn sync_inode(inode: &Inode) -> bool {
+    let ok = inode.sync();
+    BLOCK_DEVICE.flush();
+    ok
+}
+
 /// 文件系统管理器
 ///
 /// 封装 easy-fs 的根目录 inode，提供文件操作接口。
 /// 当前仅支持**单级目录**（所有文件在根目录下）。
 pub struct FileSystem {
     /// 根目录 inode
-    root: Inode,
+    root: Arc<Inode>,
 }
 
 impl FSManager for FileSystem {
@@ -49,64 +77,148 @@ impl FSManager for FileSystem {
     /// - `CREATE`：文件存在则清空，不存在则创建
     /// - `TRUNC`：清空文件内容
     /// - `RDONLY`/`WRONLY`/`RDWR`：设置读写权限
+    ///
+    /// 目录（目前只有根目录 `/`）只能以只读方式打开，用于 getdents。
     fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>> {
-        let (readable, writable) = flags.read_write();
-        if flags.contains(OpenFlags::CREATE) {
-            if let Some(inode) = self.find(path) {
-                // 文件已存在，清空内容
-                inode.clear();
-                Some(Arc::new(FileHandle::new(readable, writable, inode)))
-            } else {
-                // 文件不存在，创建新文件
-                self.root
-                    .create(path)
-                    .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
-            }
-        } else {
-            self.find(path).map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(FileHandle::new(readable, writable, inode))
-            })
-        }
+        self.open_errno(path, flags).ok()
     }
 
-    /// 在根目录中查找文件
+    /// 在根目录中查找文件，`/` 表示根目录自身
     fn find(&self, path: &str) -> Option<Arc<Inode>> {
+        if path == "/" {
+            return Some(self.root.clone());
+        }
         self.root.find(path)
     }
 
     /// 列出根目录下所有文件名
     fn readdir(&self, _path: &str) -> Option<alloc::vec::Vec<String>> {
-        Some(self.root.readdir())
+        let entries = self.root.readdir().ok()?;
+        Some(entries.into_iter().map(|(name, _)| name).collect())
     }
 
-    /// 创建硬链接
+    /// 创建硬链接，失败时返回负的错误码
     fn link(&self, src: &str, dst: &str) -> isize {
-        if let Some(inode) = self.find(src) {
-            // 文件存在，创建硬链接
-            if self.root.link(dst, inode).is_ok() {
-                0
-            } else {
-                -1
-            }
-        } else {
+        let Some(inode) = self.find(src) else {
             // 源文件不存在
-            -1
+            return ENOENT;
+        };
+        match self.root.link(dst, inode) {
+            Ok(()) => 0,
+            Err(err) => link_errno(err),
         }
     }
 
-    /// 删除硬链接
+    /// 删除硬链接，失败时返回负的错误码
     fn unlink(&self, path: &str) -> isize {
-        if self.root.unlink(path).is_ok() {
-            0
+        match self.root.unlink(path) {
+            Ok(()) => 0,
+            Err(err) => link_errno(err),
+        }
+    }
+}
+
+impl FileSystem {
+    /// 与 [`FSManager::open`] 相同，失败时返回负的错误码
+    ///
+    /// 创建新文件失败时按原因返回错误码（inode 或数据块用尽为 ENOSPC），
+    /// 文件系统只读挂载时以写方式打开、截断或创建文件返回 EROFS，
+    /// 其余失败（文件不存在、以写方式打开目录）仍返回 -1。
+    pub fn open_errno(&self, path: &str, flags: OpenFlags) -> Result<Arc<FileHandle>, isize> {
+        let (readable, writable) = flags.read_write();
+        if let Some(inode) = self.find(path).filter(|inode| inode.is_dir()) {
+            if writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+                return Err(-1);
+            }
+            return Ok(Arc::new(FileHandle::new(readable, writable, inode)));
+        }
+        if (writable || flags.contains(OpenFlags::TRUNC)) && self.root.is_read_only() {
+            return Err(EROFS);
+        }
+        if let Some(inode) = self.find(path) {
+            // 文件已存在：CREATE 不影响已有内容，只有 TRUNC 才清空
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.truncate(0);
+            }
+            Ok(Arc::new(FileHandle::new(readable, writable, inode)))
+        } else if flags.contains(OpenFlags::CREATE) {
+            // 文件不存在且带 CREATE：创建新文件
+            self.root
+                .create(path)
+                .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
+                .map_err(link_errno)
         } else {
-            -1
+            Err(-1)
+        }
+    }
+
+    /// 删除空目录，失败时返回负的错误码
+    pub fn rmdir(&self, path: &str) -> isize {
+        match self.root.rmdir(path) {
+            Ok(()) => 0,
+            Err(err) => link_errno(err),
         }
     }
 }
 
+/// 操作不被允许（如非 root 修改 owner）
+pub const EPERM: isize = -1;
+/// 文件不存在
+const ENOENT: isize = -2;
+/// I/O 错误（目录项损坏、脏块写回失败）
+pub const EIO: isize = -5;
+/// 权限位不允许该访问
+pub const EACCES: isize = -13;
+/// 文件已存在
+const EEXIST: isize = -17;
+/// 目标不是目录（rmdir 普通文件、dirfd 指向普通文件）
+pub const ENOTDIR: isize = -20;
+/// 目标是目录
+const EISDIR: isize = -21;
+/// 参数非法（如链接到同名文件）
+pub const EINVAL: isize = -22;
+/// 磁盘空间不足（inode 或数据块用尽）
+const ENOSPC: isize = -28;
+/// 文件系统只读挂载
+pub const EROFS: isize = -30;
+/// 硬链接数达到上限
+const EMLINK: isize = -31;
+/// 目录非空
+const ENOTEMPTY: isize = -39;
+
+/// 把 easy-fs 的目录项操作错误转换为负的错误码
+fn link_errno(err: LinkError) -> isize {
+    match err {
+        LinkError::NotFound => ENOENT,
+        LinkError::AlreadyExists => EEXIST,
+        LinkError::IsDirectory => EISDIR,
+        LinkError::NotPermitted => EPERM,
+        LinkError::NotDirectory => ENOTDIR,
+        LinkError::NotEmpty => ENOTEMPTY,
+        LinkError::Corrupted => EIO,
+        LinkError::NoSpace => ENOSPC,
+        LinkError::InvalidName => EINVAL,
+        LinkError::ReadOnly => EROFS,
+        LinkError::TooManyLinks => EMLINK,
+    }
+}
+
+/// 按 owner/group/other 权限位判断 `uid`/`gid` 能否读、写 `inode`，root 不受限制
+pub fn may_access(inode: &Inode, uid: u32, gid: u32, read: bool, write: bool) -> bool {
+    if uid == 0 {
+        return true;
+    }
+    let (owner, group, mode) = inode.owner_and_mode();
+    let bits = if uid == owner {
+        mode >> 6
+    } else if gid == group {
+        mode >> 3
+    } else {
+        mode
+    };
+    (!read || bits & 0o4 != 0) && (!write || bits & 0o2 != 0)
+}
+
 /// 读取文件的全部内容到 Vec<u8>
 ///
 /// 通过文件句柄的 inode，从偏移 0 开始逐块读取，
@@ -127,3 +239,65 @@ pub fn read_all(fd: Arc<FileHandle>) -> Vec<u8> {
     }
     v
 }
+
+/// 字符设备的文件类型（Linux 的 `S_IFCHR`），`tg_syscall::StatMode` 没有定义这一位
+const S_IFCHR: StatMode = unsafe { StatMode::from_bits_unchecked(0o020000) };
+
+/// fstat 写回用户空间的文件状态
+///
+/// 前缀字段（dev/ino/mode/nlink）与 `tg_syscall::Stat` 的布局一致，
+/// 之后的字段占用原先保留的填充区，旧的用户程序读取时不受影响。
+#[repr(C)]
+pub struct Stat {
+    /// 设备号
+    pub dev: u64,
+    /// inode 编号
+    pub ino: u64,
+    /// 文件类型
+    pub mode: StatMode,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 文件大小
+    pub size: u64,
+    /// 最后访问时间（纳秒）
+    pub atime: u64,
+    /// 最后修改时间（纳秒）
+    pub mtime: u64,
+    /// 创建时间（纳秒）
+    pub ctime: u64,
+    /// 保留填充
+    pad: [u64; 3],
+}
+
+impl Stat {
+    /// 由 inode 的状态信息构造普通文件的 Stat
+    pub fn from_inode(inode: &Inode) -> Self {
+        let info = inode.get_stat_info();
+        Self {
+            dev: 0,
+            ino: info.ino as u64,
+            mode: StatMode::FILE,
+            nlink: info.nlink,
+            size: inode.size() as u64,
+            atime: info.atime,
+            mtime: info.mtime,
+            ctime: info.ctime,
+            pad: [0; 3],
+        }
+    }
+
+    /// 标准输入/输出（没有 inode 的句柄）对应的 Stat：字符设备，nlink 为 1
+    pub fn char_device() -> Self {
+        Self {
+            dev: 0,
+            ino: 0,
+            mode: S_IFCHR,
+            nlink: 1,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            pad: [0; 3],
+        }
+    }
+}
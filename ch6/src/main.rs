@@ -36,6 +36,9 @@
 // 在非 RISC-V 架构上允许未使用的代码（用于 IDE 开发体验）
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code, unused_imports))]
 
+/// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
 /// 文件系统模块：easy-fs 文件系统管理器
 mod fs;
 /// 进程模块：定义 Process 结构体（含文件描述符表）
@@ -197,13 +200,17 @@ extern "C" fn rust_main() -> ! {
     tg_syscall::init_memory(&SyscallContext);
     // 步骤 8：从文件系统加载初始进程 initproc
     // 与第五章不同：程序从磁盘镜像（fs.img）中读取，而非内核内嵌
-    let initproc = read_all(FS.open("initproc", OpenFlags::RDONLY).unwrap());
-    if let Some(process) = Process::from_elf(ElfFile::new(initproc.as_slice()).unwrap()) {
-        PROCESSOR.get_mut().set_manager(ProcManager::new());
-        PROCESSOR
-            .get_mut()
-            .add(process.pid, process, ProcId::from_usize(usize::MAX));
-    }
+    let Some(fd) = FS.open("initproc", OpenFlags::RDONLY) else {
+        no_initproc("not found")
+    };
+    let initproc = read_all(fd);
+    let Some(process) = ElfFile::new(initproc.as_slice()).ok().and_then(Process::from_elf) else {
+        no_initproc("not a valid RISC-V executable")
+    };
+    PROCESSOR.get_mut().set_manager(ProcManager::new());
+    PROCESSOR
+        .get_mut()
+        .add(process.pid, process, ProcId::from_usize(usize::MAX));
 
     // ─── 主调度循环 ───
     loop {
@@ -226,25 +233,53 @@ extern "C" fn rust_main() -> ! {
                     ctx.move_next();
                     let id: Id = ctx.a(7).into();
                     let args = [ctx.a(0), ctx.a(1), ctx.a(2), ctx.a(3), ctx.a(4), ctx.a(5)];
-                    match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
+                    let syscall_ret = match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
+                        // tg-syscall 未覆盖的调用交给内核扩展分发
+                        Ret::Unsupported(_) => match impls::handle_extra(ctx.a(7), args) {
+                            Some(ret) => Ret::Done(ret),
+                            None => Ret::Unsupported(id),
+                        },
+                        ret => ret,
+                    };
+                    match syscall_ret {
                         Ret::Done(ret) => match id {
-                            Id::EXIT => unsafe { (*processor).make_current_exited(ret) },
+                            Id::EXIT => processor::exit_current(ret),
                             _ => {
                                 let ctx = &mut task.context.context;
-                                *ctx.a_mut(0) = ret as _;
+                                if processor::is_wait_blocked(task.pid) {
+                                    // wait 需要阻塞：回退 sepc，被唤醒后重新执行这次 ecall
+                                    *ctx.pc_mut() -= 4;
+                                } else {
+                                    *ctx.a_mut(0) = ret as _;
+                                }
                                 unsafe { (*processor).make_current_suspend() };
                             }
                         },
                         Ret::Unsupported(_) => {
                             log::info!("id = {id:?}");
-                            unsafe { (*processor).make_current_exited(-2) };
+                            processor::exit_current(-2);
                         }
                     }
                 }
+                // ─── 缺页：写的是写时复制页则复制出私有页，落在 MAP_GROWSDOWN 区间的 guard 区时
+                //     向下扩展，否则杀死进程 ───
+                scause::Trap::Exception(
+                    cause @ (scause::Exception::LoadPageFault | scause::Exception::StorePageFault),
+                ) => {
+                    let addr = stval::read();
+                    let cow = matches!(cause, scause::Exception::StorePageFault)
+                        && process::resolve_cow(&task.address_space, addr, 1);
+                    if cow || task.grow_mmap_area(addr) {
+                        unsafe { (*processor).make_current_suspend() };
+                    } else {
+                        log::error!("page fault at {addr:#x}, pc = {:#x}", task.context.context.pc());
+                        processor::exit_current(-3);
+                    }
+                }
                 // ─── 其他异常/中断：杀死进程 ───
                 e => {
                     log::error!("unsupported trap: {e:?}");
-                    unsafe { (*processor).make_current_exited(-3) };
+                    processor::exit_current(-3);
                 }
             }
         } else {
@@ -253,13 +288,34 @@ extern "C" fn rust_main() -> ! {
         }
     }
 
-    tg_sbi::shutdown(false)
+    shutdown(false)
+}
+
+/// 关机：先同步文件系统，再通过 SBI 关机
+///
+/// 除 panic 外的关机路径都应经过这里。
+fn shutdown(failure: bool) -> ! {
+    if !fs::sync_all() {
+        log::error!("some dirty blocks could not be written back, data may be lost");
+    }
+    console::flush();
+    tg_sbi::shutdown(failure)
+}
+
+/// initproc 缺失或无法加载：打印原因和镜像中可用的程序后关机，而不是 panic
+fn no_initproc(reason: &str) -> ! {
+    log::error!("cannot load initproc: {reason}");
+    println!("available apps:");
+    FS.readdir("").into_iter().flatten().for_each(|app| println!("{app}"));
+    shutdown(true)
 }
 
 /// Rust panic 处理函数，打印错误信息并以异常方式关机
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
+    // panic 时可能正持有块缓存的锁，不做同步，直接关机
     tg_sbi::shutdown(true)
 }
 
@@ -346,21 +402,29 @@ fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
 mod impls {
     use crate::{
         build_flags,
-        fs::{read_all, FS},
-        process::Process as ProcStruct,
-        processor::ProcManager,
+        fs::{may_access, read_all, sync_all, sync_inode, Stat, EACCES, EINVAL, EIO, ENOTDIR, EPERM, EROFS, FS},
+        process::{
+            resolve_cow, unshare_page, FileMapping, MmapArea, Process as ProcStruct, WriteTranslate,
+            MAX_MAP_COUNT,
+        },
+        processor::{
+            can_create_proc, has_live_child, nproc_limit, record_child, set_nproc_limit,
+            set_wait_blocked, ProcManager,
+        },
         Sv39, PROCESSOR,
     };
     use alloc::vec::Vec;
-    use alloc::{alloc::alloc_zeroed, string::String};
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        string::String,
+    };
     use core::{alloc::Layout, ptr::NonNull};
-    use spin::Mutex;
     use tg_console::log;
     use tg_easy_fs::UserBuffer;
-    use tg_easy_fs::{FSManager, OpenFlags};
+    use tg_easy_fs::{FSManager, OpenFlags, SeekFrom};
     use tg_kernel_vm::{
         page_table::{MmuMeta, Pte, VAddr, VmFlags, PPN, VPN},
-        PageManager,
+        AddressSpace, PageManager,
     };
     use tg_syscall::*;
     use tg_task_manage::{PManager, ProcId};
@@ -374,7 +438,9 @@ mod impls {
 
     impl Sv39Manager {
         /// 自定义标志位：标记此页面由内核分配
-        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
+        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(Self::OWNED_BIT) };
+        /// OWNED 标志在页表项中的位（释放页表时直接按位判断）
+        pub(crate) const OWNED_BIT: usize = 1 << 8;
 
         /// 分配对齐的物理页面（已清零）
         #[inline]
@@ -387,6 +453,17 @@ mod impls {
             }
             .cast()
         }
+
+        /// 归还 `count` 个从 `ptr` 开始的物理页面（与 `page_alloc` 的布局一致）
+        #[inline]
+        fn page_dealloc(ptr: *mut u8, count: usize) {
+            unsafe {
+                dealloc(
+                    ptr,
+                    Layout::from_size_align_unchecked(count << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
@@ -419,11 +496,61 @@ mod impls {
             *flags |= Self::OWNED;
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        /// 释放 `pte` 指向的 `len` 个物理页面，返回释放的页数
+        ///
+        /// 只回收带 OWNED 标志、由本地址空间分配的页；外部映射的页（恒等映射、异界传送门）不归它所有。
+        /// fork 共享的页与 `drop_root` 一样，由最后一个映射它的地址空间释放。
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            if let Some(last) = unshare_page(pte.ppn().val()) {
+                if !last {
+                    return 0;
+                }
+            } else if !self.check_owned(pte) {
+                return 0;
+            }
+            Self::page_dealloc(self.p_to_v::<u8>(pte.ppn()).as_ptr(), len);
+            len
         }
+
+        /// 释放整棵页表：自有的叶子页面、全部中间页表，最后是根页表本身
+        ///
+        /// 用户地址只落在根页表的低半部分；高半部分的异界传送门项是从内核地址空间复制来的，
+        /// 指向的子树属于内核，不能释放。叶子页按 4 KiB 逐页归还。
         fn drop_root(&mut self) {
-            todo!()
+            const PTE_V: usize = 1 << 0;
+            const PTE_RWX: usize = 0b111 << 1;
+            fn free_table(table: *mut usize, level: usize, entries: usize) {
+                for index in 0..entries {
+                    let pte = unsafe { *table.add(index) };
+                    if pte & PTE_V == 0 {
+                        continue;
+                    }
+                    let ppn = (pte >> 10) & ((1 << 44) - 1);
+                    let page = (ppn << Sv39::PAGE_BITS) as *mut u8;
+                    if pte & PTE_RWX == 0 {
+                        if level > 0 {
+                            free_table(page.cast(), level - 1, 512);
+                        }
+                    } else if let Some(last) = unshare_page(ppn) {
+                        // fork 共享的页（只读页或写时复制页）：最后一个映射它的地址空间负责释放，不论它是否为原主
+                        if last {
+                            Sv39Manager::page_dealloc(page, 1);
+                        }
+                    } else if pte & Sv39Manager::OWNED_BIT != 0 {
+                        // level 级的叶子覆盖 512^level 个页面
+                        Sv39Manager::page_dealloc(page, 1 << (9 * level));
+                    }
+                }
+                Sv39Manager::page_dealloc(table.cast(), 1);
+            }
+            free_table(self.0.as_ptr().cast(), 2, 256);
+        }
+    }
+
+    impl Drop for Sv39Manager {
+        /// 地址空间被释放（进程退出、exec 换掉旧地址空间）时回收它的页表和页面
+        fn drop(&mut self) {
+            self.drop_root();
         }
     }
 
@@ -435,7 +562,7 @@ mod impls {
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
@@ -448,6 +575,29 @@ mod impls {
     const READABLE: VmFlags<Sv39> = build_flags("RV");
     /// 可写权限标志
     const WRITEABLE: VmFlags<Sv39> = build_flags("W_V");
+    /// 路径的最大长度（含结尾的 `\0`），与 Linux 的 `PATH_MAX` 相同
+    const PATH_MAX: usize = 4096;
+
+    /// 从用户空间读取以 `\0` 结尾的字符串，最多读 `max` 字节
+    ///
+    /// 字符串可以跨页，每到新的一页重新翻译地址。地址未映射，或读满 `max` 字节仍没有 `\0`
+    /// （ENAMETOOLONG）时返回 `None`，调用方返回 -1。
+    fn read_user_cstr(space: &AddressSpace<Sv39, Sv39Manager>, ptr: usize, max: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut raw_ptr: *const u8 = core::ptr::null();
+        for addr in ptr..ptr.checked_add(max)? {
+            if addr == ptr || addr & ((1 << Sv39::PAGE_BITS) - 1) == 0 {
+                raw_ptr = space.translate::<u8>(VAddr::new(addr), READABLE)?.as_ptr();
+            }
+            let ch = unsafe { *raw_ptr };
+            if ch == 0 {
+                return Some(string);
+            }
+            string.push(ch as char);
+            raw_ptr = unsafe { raw_ptr.add(1) };
+        }
+        None
+    }
 
     /// IO 系统调用实现：read、write、open、close
     ///
@@ -457,7 +607,7 @@ mod impls {
     impl IO for SyscallContext {
         /// write 系统调用：写入文件或标准输出
         ///
-        /// - fd == STDOUT/STDDEBUG：直接通过控制台输出
+        /// - 指向控制台的 fd（stdout/stderr 及其 dup 出的副本）：直接通过控制台输出
         /// - 其他 fd：通过文件描述符表查找文件句柄，写入文件
         fn write(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
@@ -465,7 +615,7 @@ mod impls {
                 .address_space
                 .translate::<u8>(VAddr::new(buf), READABLE)
             {
-                if fd == STDOUT || fd == STDDEBUG {
+                if current.is_console(fd, true) {
                     // 标准输出：直接打印到控制台
                     print!("{}", unsafe {
                         core::str::from_utf8_unchecked(core::slice::from_raw_parts(
@@ -474,7 +624,7 @@ mod impls {
                         ))
                     });
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     // 普通文件：通过文件句柄写入
                     let file = file.lock();
                     if file.writable() {
@@ -497,25 +647,38 @@ mod impls {
 
         /// read 系统调用：从文件或标准输入读取
         ///
-        /// - fd == STDIN：通过 SBI console_getchar 逐字符读取
+        /// - 指向控制台的 fd（stdin 及其 dup 出的副本）：通过 SBI console_getchar 逐字符读取
         /// - 其他 fd：通过文件句柄从磁盘文件读取
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
+            // 先确认 fd 可读，再替用户缓冲区解除写时复制：无效 fd 不应触发按用户长度的页表遍历
+            match current.fd_table.get(fd) {
+                Some(Some(file)) if file.lock().readable() => {}
+                Some(Some(_)) => {
+                    log::error!("file not readable");
+                    return -1;
+                }
+                _ => {
+                    log::error!("unsupported fd: {fd}");
+                    return -1;
+                }
+            }
+            resolve_cow(&current.address_space, buf, count);
             if let Some(ptr) = current
                 .address_space
                 .translate::<u8>(VAddr::new(buf), WRITEABLE)
             {
-                if fd == STDIN {
+                if current.is_console(fd, false) {
                     // 标准输入：通过 SBI 逐字符读取
                     let mut ptr = ptr.as_ptr();
                     for _ in 0..count {
                         unsafe {
-                            *ptr = tg_sbi::console_getchar() as u8;
+                            *ptr = crate::console::getchar();
                             ptr = ptr.add(1);
                         }
                     }
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     // 普通文件：通过文件句柄读取
                     let file = file.lock();
                     if file.readable() {
@@ -542,30 +705,30 @@ mod impls {
         /// 通过 easy-fs 文件系统打开文件，分配新的文件描述符。
         fn open(&self, _caller: Caller, path: usize, flags: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
-            if let Some(ptr) = current.address_space.translate(VAddr::new(path), READABLE) {
-                // 从用户空间逐字符读取文件路径（需要地址翻译）
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
-                        }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
+            if let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) {
+                let flags = OpenFlags::from_bits(flags as u32).unwrap();
+                let (read, write) = flags.read_write();
+                // 已存在的文件按权限位检查，TRUNC 会清空文件，同样需要写权限
+                let existing = FS.find(&string);
+                if let Some(inode) = &existing {
+                    let clears = flags.contains(OpenFlags::TRUNC);
+                    if !may_access(inode, current.uid, current.gid, read, write || clears) {
+                        return EACCES;
                     }
                 }
-
-                // 通过文件系统打开文件，分配新的文件描述符
-                if let Some(fd) =
-                    FS.open(string.as_str(), OpenFlags::from_bits(flags as u32).unwrap())
-                {
-                    let new_fd = current.fd_table.len();
-                    current.fd_table.push(Some(Mutex::new(fd.as_ref().clone())));
-                    new_fd as isize
-                } else {
-                    -1
+                // 通过文件系统打开文件，分配新的文件描述符；创建失败时返回对应错误码（如 ENOSPC）
+                match FS.open_errno(string.as_str(), flags) {
+                    Ok(fd) => {
+                        // 新建的文件归创建者所有（easy-fs 只有根目录，创建时不检查目录权限）
+                        if existing.is_none()
+                            && (current.uid, current.gid) != (0, 0)
+                            && let Some(inode) = &fd.inode
+                        {
+                            inode.chown(current.uid, current.gid);
+                        }
+                        current.alloc_fd(fd.as_ref().clone()) as isize
+                    }
+                    Err(errno) => errno,
                 }
             } else {
                 log::error!("ptr not writeable");
@@ -585,6 +748,9 @@ mod impls {
         }
 
         /// linkat 系统调用：创建硬链接
+        ///
+        /// 失败时返回细分的错误码：源不存在 -2（ENOENT）、新名字已存在 -17（EEXIST）、
+        /// 源是目录 -21（EISDIR）、新旧名字相同 -22（EINVAL）。
         fn linkat(
             &self,
             _caller: Caller,
@@ -597,121 +763,70 @@ mod impls {
             let current = PROCESSOR.get_mut().current().unwrap();
 
             // 读取旧路径
-            let old_name = if let Some(ptr) = current.address_space.translate(VAddr::new(oldpath), READABLE) {
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
-                        }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
-                    }
-                }
-                string
-            } else {
+            let Some(old_name) = read_user_cstr(&current.address_space, oldpath, PATH_MAX) else {
                 return -1;
             };
 
             // 读取新路径
-            let new_name = if let Some(ptr) = current.address_space.translate(VAddr::new(newpath), READABLE) {
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
-                        }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
-                    }
-                }
-                string
-            } else {
+            let Some(new_name) = read_user_cstr(&current.address_space, newpath, PATH_MAX) else {
                 return -1;
             };
 
             // 检查是否链接同名文件
             if old_name == new_name {
-                return -1;
+                return EINVAL;
             }
 
             // 创建硬链接
             FS.link(&old_name, &new_name)
         }
 
-        /// unlinkat 系统调用：删除硬链接
-        fn unlinkat(&self, _caller: Caller, _dirfd: i32, path: usize, _flags: u32) -> isize {
+        /// unlinkat 系统调用：删除硬链接，`flags` 含 `AT_REMOVEDIR` 时删除空目录（rmdir）
+        ///
+        /// 失败时返回细分的错误码：不存在 -2（ENOENT）、是目录 -21（EISDIR）；
+        /// rmdir 时不是目录 -20（ENOTDIR）、目录非空 -39（ENOTEMPTY）、删除 `.` 或 `..` -1（EPERM）。
+        fn unlinkat(&self, _caller: Caller, _dirfd: i32, path: usize, flags: u32) -> isize {
+            /// 删除目录而不是文件
+            const AT_REMOVEDIR: u32 = 0x200;
             let current = PROCESSOR.get_mut().current().unwrap();
 
             // 读取文件路径
-            let filename = if let Some(ptr) = current.address_space.translate(VAddr::new(path), READABLE) {
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
-                        }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
-                    }
-                }
-                string
-            } else {
+            let Some(filename) = read_user_cstr(&current.address_space, path, PATH_MAX) else {
                 return -1;
             };
 
+            if flags & AT_REMOVEDIR != 0 {
+                return FS.rmdir(&filename);
+            }
             // 删除硬链接
             FS.unlink(&filename)
         }
 
         /// fstat 系统调用：获取文件状态
+        ///
+        /// 除 inode 编号与硬链接数外，还返回文件大小和 atime/mtime/ctime（见 `fs::Stat`）。
+        /// 没有 inode 的句柄是标准输入/输出，报告为字符设备。
         fn fstat(&self, _caller: Caller, fd: usize, st: usize) -> isize {
-            use tg_syscall::{Stat, StatMode};
             const WRITABLE: VmFlags<Sv39> = build_flags("W_V");
 
             let current = PROCESSOR.get_mut().current().unwrap();
 
             // 检查 fd 是否有效
-            if fd >= current.fd_table.len() || current.fd_table[fd].is_none() {
+            let Some(Some(file_mutex)) = current.fd_table.get(fd) else {
                 return -1;
-            }
-
-            // 获取文件句柄
-            if let Some(file_mutex) = &current.fd_table[fd] {
-                let file = file_mutex.lock();
-
-                // 获取 inode 信息
-                let (inode_id, nlink) = match file.get_stat_info() {
-                    Some(info) => info,
-                    None => return -1,
-                };
-
-                // 判断文件类型（目前只支持普通文件）
-                let mode = StatMode::FILE;
-
-                // 构造 Stat 结构体
-                let mut stat = Stat::new();
-                stat.dev = 0;
-                stat.ino = inode_id as u64;
-                stat.mode = mode;
-                stat.nlink = nlink;
+            };
+            let stat = match file_mutex.lock().inode.as_deref() {
+                Some(inode) => Stat::from_inode(inode),
+                None => Stat::char_device(),
+            };
 
-                // 将 Stat 写入用户空间
-                if let Some(mut ptr) = current
-                    .address_space
-                    .translate::<Stat>(VAddr::new(st), WRITABLE)
-                {
-                    unsafe { *ptr.as_mut() = stat };
-                    0
-                } else {
-                    -1
-                }
+            // 将 Stat 写入用户空间
+            if let Some(mut ptr) = current
+                .address_space
+                .translate_for_write::<Stat>(VAddr::new(st), WRITABLE)
+            {
+                unsafe { *ptr.as_mut() = stat };
+                0
             } else {
                 -1
             }
@@ -729,6 +844,10 @@ mod impls {
         /// fork 系统调用：创建子进程（包含复制文件描述符表）
         fn fork(&self, _caller: Caller) -> isize {
             let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
+            if !can_create_proc() {
+                log::warn!("fork: RLIMIT_NPROC ({}) reached", nproc_limit());
+                return -1;
+            }
             let current = unsafe { (*processor).current().unwrap() };
             let parent_pid = current.pid;
             let mut child_proc = current.fork().unwrap();
@@ -738,6 +857,7 @@ mod impls {
             unsafe {
                 (*processor).add(pid, child_proc, parent_pid);
             }
+            record_child(parent_pid, pid);
             pid.get_usize() as isize
         }
 
@@ -746,6 +866,9 @@ mod impls {
         /// 与第五章不同：程序从 easy-fs 文件系统中读取，而非内存中的 APPS 表
         fn exec(&self, _caller: Caller, path: usize, count: usize) -> isize {
             const READABLE: VmFlags<Sv39> = build_flags("RV");
+            if count >= PATH_MAX {
+                return -1;
+            }
             let current = PROCESSOR.get_mut().current().unwrap();
             current
                 .address_space
@@ -759,21 +882,28 @@ mod impls {
                         log::error!("unknown app, select one in the list: ");
                         // 列出文件系统中所有可用程序
                         FS.readdir("")
-                            .unwrap()
                             .into_iter()
+                            .flatten()
                             .for_each(|app| println!("{app}"));
                         println!();
                         -1
                     },
                     |fd| {
-                        // 从文件系统读取完整 ELF 数据并加载
-                        current.exec(ElfFile::new(&read_all(fd)).unwrap());
-                        0
+                        // 从文件系统读取完整 ELF 数据并加载；文件不是合法的 ELF 或加载失败时
+                        // 返回 -1，当前进程不受影响
+                        let data = read_all(fd);
+                        match ElfFile::new(&data) {
+                            Ok(elf) => if current.exec(elf) { 0 } else { -1 },
+                            Err(err) => {
+                                log::error!("exec: bad ELF: {err}");
+                                -1
+                            }
+                        }
                     },
                 )
         }
 
-        /// wait 系统调用：等待子进程退出
+        /// wait 系统调用：等待子进程退出，子进程都还在运行时阻塞
         fn wait(&self, _caller: Caller, pid: isize, exit_code_ptr: usize) -> isize {
             let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
             let current = unsafe { (*processor).current().unwrap() };
@@ -781,14 +911,18 @@ mod impls {
             if let Some((dead_pid, exit_code)) =
                 unsafe { (*processor).wait(ProcId::from_usize(pid as usize)) }
             {
+                set_wait_blocked(current.pid, false);
                 if let Some(mut ptr) = current
                     .address_space
-                    .translate::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
+                    .translate_for_write::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
                 {
                     unsafe { *ptr.as_mut() = exit_code as i32 };
                 }
                 return dead_pid.get_usize() as isize;
             } else {
+                // 子进程尚未退出时阻塞，被唤醒后重新执行本次 wait；没有可等待的子进程才返回 -1
+                let target = (pid != -1).then(|| ProcId::from_usize(pid as usize));
+                set_wait_blocked(current.pid, has_live_child(current.pid, target));
                 return -1;
             }
         }
@@ -804,9 +938,17 @@ mod impls {
         /// 与 fork+exec 不同，spawn 直接从文件系统加载 ELF 创建新进程
         fn spawn(&self, _caller: Caller, path: usize, count: usize) -> isize {
             const READABLE: VmFlags<Sv39> = build_flags("RV");
+            if count >= PATH_MAX {
+                return -1;
+            }
             let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
+            if !can_create_proc() {
+                log::warn!("spawn: RLIMIT_NPROC ({}) reached", nproc_limit());
+                return -1;
+            }
             let current = unsafe { (*processor).current().unwrap() };
             let parent_pid = current.pid;
+            let (uid, gid) = (current.uid, current.gid);
 
             // 翻译用户空间的路径字符串并从文件系统加载
             let result = current
@@ -816,14 +958,17 @@ mod impls {
                     core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr.as_ptr(), count))
                 })
                 .and_then(|name| FS.open(name, OpenFlags::RDONLY))
-                .map(|fd| {
-                    // 从文件系统读取 ELF 数据并创建新进程
+                .and_then(|fd| {
+                    // 从文件系统读取 ELF 数据并创建新进程，文件不是合法的 ELF 时返回 -1
                     let elf_data = read_all(fd);
-                    let child_proc = ProcStruct::from_elf(ElfFile::new(&elf_data).unwrap()).unwrap();
+                    let mut child_proc = ElfFile::new(&elf_data).ok().and_then(ProcStruct::from_elf)?;
+                    child_proc.uid = uid;
+                    child_proc.gid = gid;
                     let child_pid = child_proc.pid;
                     // 将子进程加入进程管理器
                     unsafe { (*processor).add(child_pid, child_proc, parent_pid) };
-                    child_pid.get_usize() as isize
+                    record_child(parent_pid, child_pid);
+                    Some(child_pid.get_usize() as isize)
                 });
 
             result.unwrap_or(-1)
@@ -872,7 +1017,7 @@ mod impls {
                         .current()
                         .unwrap()
                         .address_space
-                        .translate::<TimeSpec>(VAddr::new(tp), WRITABLE)
+                        .translate_for_write::<TimeSpec>(VAddr::new(tp), WRITABLE)
                     {
                         let time = riscv::register::time::read() * 10000 / 125;
                         *unsafe { ptr.as_mut() } = TimeSpec {
@@ -890,18 +1035,23 @@ mod impls {
         }
     }
 
+    /// 内存不足，或 mmap 区间数达到 `MAX_MAP_COUNT`
+    const ENOMEM: isize = -12;
+
     /// 内存管理系统调用实现
     impl Memory for SyscallContext {
         /// mmap 系统调用：映射内存区域
+        ///
+        /// 每次成功的 mmap 登记一个区间，进程的区间数达到 `MAX_MAP_COUNT` 时返回 -12（ENOMEM）。
         fn mmap(
             &self,
             _caller: Caller,
             addr: usize,
             len: usize,
             prot: i32,
-            _flags: i32,
-            _fd: i32,
-            _offset: usize,
+            flags: i32,
+            fd: i32,
+            offset: usize,
         ) -> isize {
             const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
 
@@ -910,6 +1060,34 @@ mod impls {
                 return -1;
             }
 
+            // 向下增长的区间只能是匿名映射（用作线程栈）
+            let growsdown = flags & MAP_GROWSDOWN != 0;
+            if growsdown && flags & MAP_ANONYMOUS == 0 {
+                return EINVAL;
+            }
+
+            // 共享文件映射：记下被映射的文件，建立映射后把文件内容读入页面
+            let file = if flags & MAP_SHARED != 0 && flags & MAP_ANONYMOUS == 0 && fd >= 0 {
+                if offset & (PAGE_SIZE - 1) != 0 {
+                    return EINVAL;
+                }
+                let current = PROCESSOR.get_mut().current().unwrap();
+                let Some(Some(handle)) = current.fd_table.get(fd as usize) else {
+                    return -1;
+                };
+                let handle = handle.lock();
+                let Some(inode) = handle.inode.clone() else {
+                    return -1;
+                };
+                // 与 Linux 一致：文件必须可读，可写映射还要求文件以可写方式打开
+                if !handle.read || (prot & 0x2 != 0 && !handle.write) {
+                    return EACCES;
+                }
+                Some(inode)
+            } else {
+                None
+            };
+
             // 检查 prot 参数（只能有 bit 0-2，且不能为 0）
             if prot & !0x7 != 0 || prot == 0 {
                 return -1;
@@ -943,6 +1121,17 @@ mod impls {
                 }
             }
 
+            // 先登记区间，到达上限时不建立任何映射
+            let area = MmapArea {
+                range: addr..addr + page_count * PAGE_SIZE,
+                flags,
+                growsdown,
+            };
+            if !current.add_mmap_area(area) {
+                log::warn!("mmap: max_map_count ({MAX_MAP_COUNT}) reached");
+                return ENOMEM;
+            }
+
             // 计算虚拟页号范围
             let start_vpn = VAddr::new(addr).floor();
             let end_vpn = VAddr::new(addr + page_count * PAGE_SIZE).ceil();
@@ -956,10 +1145,29 @@ mod impls {
                 flags,
             );
 
+            if let Some(inode) = file {
+                for i in 0..page_count {
+                    let page = current
+                        .address_space
+                        .translate::<u8>(VAddr::new(addr + i * PAGE_SIZE), CHECK_FLAGS)
+                        .unwrap();
+                    let page = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), PAGE_SIZE) };
+                    inode.read_at(offset + i * PAGE_SIZE, page);
+                }
+                current.file_maps.push(FileMapping {
+                    start: addr,
+                    len: page_count * PAGE_SIZE,
+                    inode,
+                    offset,
+                });
+            }
+
             0
         }
 
         /// munmap 系统调用：取消内存映射
+        ///
+        /// 从区间中间取消映射会把它一分为二，区间数因此超过上限时返回 -12（ENOMEM）。
         fn munmap(&self, _caller: Caller, addr: usize, len: usize) -> isize {
             const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
 
@@ -993,12 +1201,505 @@ mod impls {
             let start_vpn = VAddr::new(addr).floor();
             let end_vpn = VAddr::new(addr + page_count * PAGE_SIZE).ceil();
 
+            let end = addr + page_count * PAGE_SIZE;
+            if !current.remove_mmap_area(addr, end) {
+                return ENOMEM;
+            }
+
+            // 先把共享文件映射的修改写回文件，完全被取消的映射不再记录
+            current.sync_file_maps(addr, end);
+            current.file_maps.retain(|m| m.start < addr || m.start + m.len > end);
+
             // 取消所有页面的映射
             current.address_space.unmap(start_vpn..end_vpn);
 
             0
         }
     }
+
+    // ─── 扩展系统调用（tg-syscall 未提供的调用） ───
+
+    /// 扩展系统调用号（与 Linux RISC-V 保持一致）
+    mod extra_id {
+        pub const DUP: usize = 23;
+        pub const FTRUNCATE: usize = 46;
+        pub const FACCESSAT: usize = 48;
+        pub const FCHOWNAT: usize = 54;
+        pub const GETDENTS64: usize = 61;
+        pub const LSEEK: usize = 62;
+        pub const SYNC: usize = 81;
+        pub const FSYNC: usize = 82;
+        pub const READAHEAD: usize = 213;
+        pub const SETUID: usize = 146;
+        pub const GETUID: usize = 174;
+        pub const MSYNC: usize = 227;
+        pub const MADVISE: usize = 233;
+        pub const PRLIMIT64: usize = 261;
+        pub const COPY_FILE_RANGE: usize = 285;
+        // Linux 的 telldir/seekdir 由 libc 借助 lseek 实现；本章的 lseek 只定位普通文件，目录的读位置以自定义调用号提供
+        pub const TELLDIR: usize = 2010;
+        pub const SEEKDIR: usize = 2011;
+        // RISC-V Linux 没有 dup2（libc 用 dup3 实现），本内核以自定义编号提供
+        pub const DUP2: usize = 2020;
+    }
+
+    /// 分发扩展系统调用，未知的调用号返回 `None`
+    pub fn handle_extra(id: usize, args: [usize; 6]) -> Option<isize> {
+        let ctx = SyscallContext;
+        let ret = match id {
+            extra_id::DUP => ctx.dup(args[0]),
+            extra_id::DUP2 => ctx.dup2(args[0], args[1]),
+            extra_id::FTRUNCATE => ctx.ftruncate(args[0], args[1] as isize),
+            extra_id::FACCESSAT => ctx.faccessat(args[0] as i32, args[1], args[2], args[3]),
+            extra_id::FCHOWNAT => ctx.fchownat(args[0] as i32, args[1], args[2] as u32, args[3] as u32),
+            extra_id::GETDENTS64 => ctx.getdents64(args[0], args[1], args[2]),
+            extra_id::LSEEK => ctx.lseek(args[0], args[1] as isize, args[2]),
+            extra_id::SYNC => if sync_all() { 0 } else { EIO },
+            extra_id::FSYNC => ctx.fsync(args[0]),
+            extra_id::SETUID => ctx.setuid(args[0] as u32),
+            extra_id::GETUID => PROCESSOR.get_mut().current().unwrap().uid as isize,
+            extra_id::READAHEAD => ctx.readahead(args[0], args[1], args[2]),
+            extra_id::MSYNC => ctx.msync(args[0], args[1], args[2]),
+            extra_id::MADVISE => ctx.madvise(args[0], args[1], args[2]),
+            extra_id::PRLIMIT64 => ctx.prlimit64(args[0], args[1], args[2], args[3]),
+            extra_id::COPY_FILE_RANGE => {
+                ctx.copy_file_range(args[0], args[1], args[2], args[3], args[4], args[5])
+            }
+            extra_id::TELLDIR => ctx.telldir(args[0]),
+            extra_id::SEEKDIR => ctx.seekdir(args[0], args[1]),
+            _ => return None,
+        };
+        Some(ret)
+    }
+
+    /// mmap：共享映射，修改经 msync/munmap 写回文件
+    const MAP_SHARED: i32 = 0x01;
+    /// mmap：匿名映射，忽略 fd 和 offset
+    const MAP_ANONYMOUS: i32 = 0x20;
+    /// mmap：区间像栈一样向下增长，访问其下方的 guard 区时自动扩展
+    const MAP_GROWSDOWN: i32 = 0x100;
+    /// msync 标志：MS_ASYNC | MS_INVALIDATE | MS_SYNC
+    const MS_FLAGS_MASK: usize = 0x7;
+    /// madvise：MADV_NORMAL、MADV_RANDOM、MADV_SEQUENTIAL 只是访问模式提示，直接接受
+    const MADV_SEQUENTIAL: usize = 2;
+    /// madvise：即将访问，提前建立映射
+    const MADV_WILLNEED: usize = 3;
+
+    /// 以当前工作目录解析相对路径（与 Linux 保持一致）
+    const AT_FDCWD: i32 = -100;
+    /// fd 无效
+    const EBADF: isize = -9;
+    /// faccessat：检查符号链接自身而非其目标
+    const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+    /// faccessat：用有效用户 ID 检查
+    const AT_EACCESS: usize = 0x200;
+    /// access 模式：R_OK | W_OK | X_OK（F_OK 为 0）
+    const ACCESS_MODE_MASK: usize = 0x7;
+    /// access 模式：可读
+    const R_OK: usize = 0x4;
+    /// access 模式：可写
+    const W_OK: usize = 0x2;
+
+    /// `struct linux_dirent64` 中 `d_name` 之前的定长部分：d_ino、d_off、d_reclen、d_type
+    const DIRENT64_HEADER: usize = 8 + 8 + 2 + 1;
+
+    /// 资源编号：进程数上限（与 Linux 保持一致）
+    const RLIMIT_NPROC: usize = 6;
+
+    /// 用户态的 `struct rlimit`
+    #[repr(C)]
+    struct RLimit {
+        /// 软限制
+        cur: u64,
+        /// 硬限制
+        max: u64,
+    }
+
+    impl SyscallContext {
+        /// faccessat 系统调用：检查文件是否存在/可访问
+        ///
+        /// 以 `/` 开头的路径从根目录查找；相对路径在 `AT_FDCWD`（当前目录即根目录）时从根目录查找，
+        /// 否则从 `dirfd` 打开的目录查找，`dirfd` 无效返回 -9（EBADF），不是目录返回 -20（ENOTDIR）。
+        /// R/W 按 inode 的 owner 与权限位检查，不检查 X；进程只有一个 uid，`AT_EACCESS` 与不带时结果相同；
+        /// easy-fs 没有符号链接，`AT_SYMLINK_NOFOLLOW` 不影响结果。未知的 `mode` 或 `flags` 位返回 -22（EINVAL）。
+        fn faccessat(&self, dirfd: i32, path: usize, mode: usize, flags: usize) -> isize {
+            if mode & !ACCESS_MODE_MASK != 0 || flags & !(AT_SYMLINK_NOFOLLOW | AT_EACCESS) != 0 {
+                return EINVAL;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) else {
+                return -1;
+            };
+            let found = if string.starts_with('/') || dirfd == AT_FDCWD {
+                FS.find(&string)
+            } else {
+                let Some(Some(dir)) = usize::try_from(dirfd).ok().and_then(|fd| current.fd_table.get(fd)) else {
+                    return EBADF;
+                };
+                match dir.lock().inode.clone() {
+                    Some(dir) if dir.is_dir() => dir.find(&string),
+                    _ => return ENOTDIR,
+                }
+            };
+            match found {
+                Some(inode) if may_access(&inode, current.uid, current.gid, mode & R_OK != 0, mode & W_OK != 0) => 0,
+                Some(_) => EACCES,
+                None => -1,
+            }
+        }
+
+        /// fchownat 系统调用：修改文件的 owner，`uid`/`gid` 为 -1 的一项保持不变
+        ///
+        /// 只有 root 可以调用；`dirfd` 只支持 `AT_FDCWD`，没有符号链接，因此不区分 flags。
+        fn fchownat(&self, dirfd: i32, path: usize, uid: u32, gid: u32) -> isize {
+            if dirfd != AT_FDCWD {
+                return -1;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if current.uid != 0 {
+                return EPERM;
+            }
+            let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) else {
+                return -1;
+            };
+            let Some(inode) = FS.find(&string) else {
+                return -1;
+            };
+            let (old_uid, old_gid, _) = inode.owner_and_mode();
+            let uid = if uid == u32::MAX { old_uid } else { uid };
+            let gid = if gid == u32::MAX { old_gid } else { gid };
+            if inode.chown(uid, gid) { 0 } else { EROFS }
+        }
+
+        /// setuid 系统调用：root 可以切换到任意 uid（降权后不能再升回），其他进程只能设为自身
+        fn setuid(&self, uid: u32) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if current.uid != 0 && uid != current.uid {
+                return EPERM;
+            }
+            current.uid = uid;
+            0
+        }
+
+        /// msync 系统调用：把 `[addr, addr + len)` 中共享文件映射的脏页写回文件
+        ///
+        /// 写回总是同步完成，`MS_ASYNC` 与 `MS_SYNC` 效果相同；
+        /// 范围内的匿名映射和未映射的页被忽略。
+        fn msync(&self, addr: usize, len: usize, flags: usize) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+            if addr & (PAGE_SIZE - 1) != 0 || flags & !MS_FLAGS_MASK != 0 {
+                return EINVAL;
+            }
+            let Some(end) = addr.checked_add(len) else {
+                return EINVAL;
+            };
+            PROCESSOR.get_mut().current().unwrap().sync_file_maps(addr, end);
+            0
+        }
+
+        /// madvise 系统调用：对 `[addr, addr + len)` 给出访问提示
+        ///
+        /// MADV_WILLNEED 为区间内还没有映射的页提前分配页面，文件映射的页同时读入文件内容（见
+        /// `Process::populate`）；MADV_NORMAL/RANDOM/SEQUENTIAL 被接受但不做任何事。
+        /// `addr` 未页对齐或提示不支持返回 EINVAL，区间中有不属于 mmap 区间的页返回 -12（ENOMEM）。
+        fn madvise(&self, addr: usize, len: usize, advice: usize) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+            if addr & (PAGE_SIZE - 1) != 0 {
+                return EINVAL;
+            }
+            let Some(end) = addr.checked_add(len).and_then(|end| end.checked_next_multiple_of(PAGE_SIZE)) else {
+                return EINVAL;
+            };
+            match advice {
+                0..=MADV_SEQUENTIAL => 0,
+                MADV_WILLNEED => {
+                    if PROCESSOR.get_mut().current().unwrap().populate(addr, end) { 0 } else { ENOMEM }
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// prlimit64 系统调用：读取/设置资源限制
+        ///
+        /// 目前只支持 `RLIMIT_NPROC`（简化为全局进程数上限），`pid` 须为 0 或当前进程。
+        /// `new_limit`/`old_limit` 为 0 表示不设置/不读取。
+        fn prlimit64(&self, pid: usize, resource: usize, new_limit: usize, old_limit: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if pid != 0 && pid != current.pid.get_usize() {
+                return -1;
+            }
+            if resource != RLIMIT_NPROC {
+                log::warn!("prlimit64: unsupported resource {resource}");
+                return -1;
+            }
+            if old_limit != 0 {
+                let limit = nproc_limit() as u64;
+                match current.address_space.translate_for_write::<RLimit>(VAddr::new(old_limit), WRITEABLE) {
+                    Some(mut ptr) => unsafe { *ptr.as_mut() = RLimit { cur: limit, max: limit } },
+                    None => return -1,
+                }
+            }
+            if new_limit != 0 {
+                match current.address_space.translate::<RLimit>(VAddr::new(new_limit), READABLE) {
+                    Some(ptr) => {
+                        // 软限制不能超过硬限制
+                        let limit = unsafe { ptr.as_ref() };
+                        set_nproc_limit(limit.cur.min(limit.max) as usize);
+                    }
+                    None => return -1,
+                }
+            }
+            0
+        }
+
+        /// copy_file_range 系统调用：在两个文件之间复制数据，不经过用户缓冲区
+        ///
+        /// `off_in`/`off_out` 为 0 时使用并推进文件自身的偏移，否则读写用户提供的偏移，
+        /// 文件偏移保持不变。复制在源文件末尾截止，返回实际复制的字节数。
+        fn copy_file_range(
+            &self,
+            fd_in: usize,
+            off_in: usize,
+            fd_out: usize,
+            off_out: usize,
+            len: usize,
+            flags: usize,
+        ) -> isize {
+            if flags != 0 {
+                return -1;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            // fd_in 与 fd_out 可以相同，先分别取出 inode 与偏移，避免同时持有两把锁
+            let endpoint = |fd: usize, read: bool| {
+                let file = current.fd_table.get(fd)?.as_ref()?.lock();
+                let allowed = if read { file.readable() } else { file.writable() };
+                if !allowed {
+                    return None;
+                }
+                Some((file.inode.clone()?, file.offset.get()))
+            };
+            let (Some((src, src_pos)), Some((dst, dst_pos))) =
+                (endpoint(fd_in, true), endpoint(fd_out, false))
+            else {
+                return -1;
+            };
+            // 用户提供了偏移指针时以它为准，不能是负数
+            let start = |off_ptr: usize, file_pos: usize| {
+                if off_ptr == 0 {
+                    return Some(file_pos);
+                }
+                let ptr = current.address_space.translate::<i64>(VAddr::new(off_ptr), READABLE)?;
+                usize::try_from(unsafe { *ptr.as_ref() }).ok()
+            };
+            let (Some(src_start), Some(dst_start)) = (start(off_in, src_pos), start(off_out, dst_pos))
+            else {
+                return -1;
+            };
+            let copied = src.copy_range(src_start, &dst, dst_start, len);
+            let ends = [(fd_in, off_in, src_start + copied), (fd_out, off_out, dst_start + copied)];
+            for (fd, off_ptr, end) in ends {
+                if off_ptr != 0 {
+                    match current.address_space.translate_for_write::<i64>(VAddr::new(off_ptr), WRITEABLE) {
+                        Some(mut ptr) => unsafe { *ptr.as_mut() = end as i64 },
+                        None => return -1,
+                    }
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
+                    file.lock().offset.set(end);
+                }
+            }
+            copied as isize
+        }
+
+        /// getdents64 系统调用：从目录 fd 的当前位置起读出尽量多的目录项
+        ///
+        /// 目录 fd 的偏移是目录项序号而不是字节数，每条记录的 `d_off` 为下一项的序号，
+        /// 可以直接交给 seekdir。读到末尾返回 0，缓冲区连一条记录都放不下返回 EINVAL。
+        /// `d_type` 取自目录项中缓存的类型，不需要读取各个子 inode。
+        fn getdents64(&self, fd: usize, dirp: usize, count: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            let Some(inode) = file.inode.as_ref().filter(|inode| inode.is_dir()) else {
+                return EINVAL;
+            };
+            resolve_cow(&current.address_space, dirp, count);
+            let Some(ptr) = current.address_space.translate::<u8>(VAddr::new(dirp), WRITEABLE) else {
+                return -1;
+            };
+            let buf = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), count) };
+            let mut written = 0;
+            loop {
+                let index = file.offset.get();
+                let (name, inode_id, type_) = match inode.dirent_at(index) {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => break,
+                    Err(_) => return -1,
+                };
+                // 名字以 '\0' 结尾，整条记录按 8 字节对齐
+                let reclen = (DIRENT64_HEADER + name.len() + 1).next_multiple_of(8);
+                if written + reclen > count {
+                    // 一条都放不下时报错，否则留到下次调用
+                    if written == 0 {
+                        return EINVAL;
+                    }
+                    break;
+                }
+                let record = &mut buf[written..written + reclen];
+                record.fill(0);
+                record[0..8].copy_from_slice(&(inode_id as u64).to_ne_bytes());
+                record[8..16].copy_from_slice(&((index + 1) as i64).to_ne_bytes());
+                record[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+                record[18] = type_.d_type();
+                record[DIRENT64_HEADER..][..name.len()].copy_from_slice(name.as_bytes());
+                file.offset.set(index + 1);
+                written += reclen;
+            }
+            written as isize
+        }
+
+        /// fsync 系统调用：把文件的修改写回磁盘
+        ///
+        /// 只同步该文件的数据块、索引块和文件系统元数据，其他文件的脏块不受影响。
+        /// 任一块写回失败都返回 EIO，失败的块仍留在缓存中，之后的 fsync/sync 会重试。
+        fn fsync(&self, fd: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let Some(inode) = file.lock().inode.clone() else {
+                return EINVAL;
+            };
+            if sync_inode(&inode) { 0 } else { EIO }
+        }
+
+        /// readahead 系统调用：把文件 `[offset, offset + count)` 区间提前载入块缓存
+        ///
+        /// 只是提示，不拷贝数据到用户空间；区间超出文件末尾的部分被忽略，
+        /// 单次载入的块数受块缓存容量限制。fd 不可读返回 -1，不是普通文件返回 EINVAL。
+        fn readahead(&self, fd: usize, offset: usize, count: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            if !file.readable() {
+                return -1;
+            }
+            match &file.inode {
+                Some(inode) if !inode.is_dir() && !inode.is_fifo() => {
+                    inode.readahead(offset, count);
+                    0
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// lseek 系统调用：按 `whence` 移动 `fd` 的读写偏移，返回新的绝对偏移
+        ///
+        /// `whence` 为 SEEK_SET(0)/SEEK_CUR(1)/SEEK_END(2)，SEEK_END 以文件当前大小为基准。
+        /// 管道、标准输入输出等不能定位的 fd、非法的 `whence` 与结果为负的偏移都返回 -1。
+        fn lseek(&self, fd: usize, offset: isize, whence: usize) -> isize {
+            let from = match whence {
+                0 => SeekFrom::Start,
+                1 => SeekFrom::Current,
+                2 => SeekFrom::End,
+                _ => return -1,
+            };
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            file.lock().seek(offset, from).map_or(-1, |pos| pos as isize)
+        }
+
+        /// telldir：返回目录 fd 的当前读位置（下一个要读的目录项序号）
+        fn telldir(&self, fd: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            match &file.inode {
+                Some(inode) if inode.is_dir() => file.offset.get() as isize,
+                _ => EINVAL,
+            }
+        }
+
+        /// seekdir：把目录 fd 的读位置设为 `off`
+        ///
+        /// `off` 应来自 telldir 或某个目录项的 d_off；越过末尾的位置合法，之后的 getdents 返回 0。
+        fn seekdir(&self, fd: usize, off: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            match &file.inode {
+                Some(inode) if inode.is_dir() && off <= isize::MAX as usize => {
+                    file.offset.set(off);
+                    0
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// dup 系统调用：复制 `fd`，返回编号最小的空闲 fd
+        ///
+        /// 新 fd 与 `fd` 共享同一个句柄：控制台的副本仍读写控制台，管道副本共享同一个缓冲区，
+        /// 普通文件的偏移也是共享的，通过任一 fd 读写或 lseek 都会移动另一个 fd 的偏移。
+        fn dup(&self, fd: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.clone();
+            current.install_fd(file) as isize
+        }
+
+        /// dup2 系统调用：把 `oldfd` 复制到 `newfd`，`newfd` 已打开时先将其关闭
+        ///
+        /// `oldfd == newfd` 时不做任何事，`oldfd` 有效则返回 `newfd`。
+        fn dup2(&self, oldfd: usize, newfd: usize) -> isize {
+            /// `newfd` 的上限，防止用户用一个很大的 fd 把 fd_table 撑大
+            const NOFILE: usize = 1024;
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(oldfd) else {
+                return -1;
+            };
+            if oldfd == newfd {
+                return newfd as isize;
+            }
+            if newfd >= NOFILE {
+                return -1;
+            }
+            let file = file.clone();
+            if newfd >= current.fd_table.len() {
+                current.fd_table.resize_with(newfd + 1, || None);
+            }
+            current.fd_table[newfd] = Some(file);
+            newfd as isize
+        }
+
+        /// ftruncate 系统调用：把可写文件的大小设为 `length`
+        ///
+        /// 扩大时只改大小，不分配中间的全零块；缩小时回收新末尾之后的数据块。
+        fn ftruncate(&self, fd: usize, length: isize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            let (Some(inode), true) = (&file.inode, file.writable()) else {
+                return -1;
+            };
+            let Ok(length) = usize::try_from(length) else {
+                return -1;
+            };
+            if inode.truncate(length) { 0 } else { -1 }
+        }
+    }
 }
 
 /// 非 RISC-V64 架构的占位实现
@@ -19,13 +19,14 @@
 //! - 最后看 `change_program_brk`：理解用户堆扩缩时的页映射变化。
 
 use crate::{build_flags, map_portal, parse_flags, Sv39, Sv39Manager};
-use alloc::{alloc::alloc_zeroed, vec::Vec};
-use core::alloc::Layout;
+use alloc::{alloc::alloc, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{alloc::Layout, ops::Range, ptr::NonNull};
 use spin::Mutex;
-use tg_easy_fs::FileHandle;
+use tg_console::log;
+use tg_easy_fs::{FileHandle, Inode};
 use tg_kernel_context::{foreign::ForeignContext, LocalContext};
 use tg_kernel_vm::{
-    page_table::{MmuMeta, VAddr, PPN, VPN},
+    page_table::{MmuMeta, VAddr, VmFlags, PPN, VPN},
     AddressSpace,
 };
 use tg_task_manage::ProcId;
@@ -51,7 +52,7 @@ pub struct Process {
     /// - `None`: 该 fd 已关闭或未使用
     ///
     /// 预留 fd 0/1/2 分别为 stdin/stdout/stderr。
-    pub fd_table: Vec<Option<Mutex<FileHandle>>>,
+    pub fd_table: Vec<Option<Arc<Mutex<FileHandle>>>>,
     /// 堆底地址
     pub heap_bottom: usize,
     /// 当前程序 break 位置（堆顶）
@@ -60,29 +61,271 @@ pub struct Process {
     pub stride: usize,
     /// 进程的优先级（用于 stride 调度算法，值越大优先级越高）
     pub priority: usize,
+    /// 用户 ID（initproc 为 0，即 root；fork/spawn/exec 后保留）
+    pub uid: u32,
+    /// 组 ID
+    pub gid: u32,
+    /// 以 `MAP_SHARED` 映射的文件区域
+    pub file_maps: Vec<FileMapping>,
+    /// mmap 建立的区间，数量不超过 `MAX_MAP_COUNT`
+    pub mmap_areas: Vec<MmapArea>,
+    /// 当前统计窗口内成功 exec 的次数
+    exec_count: usize,
+    /// 当前统计窗口的起始时间（时钟周期）
+    exec_window_start: u64,
+}
+
+/// 页表项标志位：有效
+const PTE_V: usize = 1 << 0;
+/// 页表项标志位：可写
+const PTE_W: usize = 1 << 2;
+/// 页表项标志位：R/W/X 任一置位即为叶子页表项
+const PTE_RWX: usize = 0b111 << 1;
+/// 页表项标志位：用户可访问
+const PTE_U: usize = 1 << 4;
+/// 页表项标志位：已被访问（由硬件置位）
+const PTE_A: usize = 1 << 6;
+/// 页表项标志位：已被写过（由硬件置位）
+const PTE_D: usize = 1 << 7;
+/// 页表项标志位：写时复制（RSW 的第二位，第一位是 `Sv39Manager::OWNED_BIT`）
+///
+/// fork 后父子共享的可写页清除 W 位并置上该位，对它的写入由 [`resolve_cow`] 处理。
+const PTE_COW: usize = 1 << 9;
+/// 复制页表项时保留的标志位：V R W X U
+const PTE_PERM: usize = 0x1f;
+
+/// fork 时父子共享的物理页（PPN）-> 映射它的地址空间个数
+///
+/// 只读页和写时复制页都登记在这里。地址空间释放时逐页减少计数（见 [`unshare_page`]），
+/// 降到 0 时才真正释放该页；写时复制页被复制走一份时计数也减一。
+static SHARED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// 释放地址空间时归还一个页的共享引用
+///
+/// 该页不是共享页时返回 `None`，由调用者按 OWNED 标志处理；
+/// 否则返回 `Some(是否为最后一个引用)`，最后一个引用者负责释放该页。
+pub fn unshare_page(ppn: usize) -> Option<bool> {
+    let mut shared = SHARED_PAGES.lock();
+    let count = shared.get_mut(&ppn)?;
+    *count -= 1;
+    if *count == 0 {
+        shared.remove(&ppn);
+        Some(true)
+    } else {
+        Some(false)
+    }
+}
+
+/// 页表项中的物理页号
+fn pte_ppn(pte: usize) -> usize {
+    (pte >> 10) & ((1 << 44) - 1)
+}
+
+/// 按 Sv39 格式遍历用户地址空间，对每个 4 KiB 的用户页调用 `f(虚拟页号, 页表项)`
+///
+/// 没有 U 位的页（如异界传送门）不会被访问到。`f` 可以直接修改页表项，修改后由调用者刷新 TLB。
+fn for_each_user_leaf(space: &AddressSpace<Sv39, Sv39Manager>, f: &mut dyn FnMut(usize, &mut usize)) {
+    fn walk(table: usize, level: usize, vpn: usize, f: &mut dyn FnMut(usize, &mut usize)) {
+        for index in 0..512 {
+            let pte = unsafe { &mut *(table as *mut usize).add(index) };
+            if *pte & PTE_V == 0 {
+                continue;
+            }
+            let vpn = (vpn << 9) | index;
+            if *pte & PTE_RWX == 0 {
+                if level > 0 {
+                    walk(pte_ppn(*pte) << Sv39::PAGE_BITS, level - 1, vpn, f);
+                }
+            } else if level == 0 && *pte & PTE_U != 0 {
+                f(vpn, pte);
+            }
+        }
+    }
+    walk(space.root_ppn().val() << Sv39::PAGE_BITS, 2, 0, f);
+}
+
+/// 找到虚拟页号 `vpn` 对应的 4 KiB 用户页的页表项
+fn user_leaf_pte(space: &AddressSpace<Sv39, Sv39Manager>, vpn: usize) -> Option<*mut usize> {
+    let mut table = space.root_ppn().val() << Sv39::PAGE_BITS;
+    for level in (0..3).rev() {
+        let pte = unsafe { (table as *mut usize).add((vpn >> (9 * level)) & 0x1ff) };
+        let value = unsafe { *pte };
+        if value & PTE_V == 0 {
+            return None;
+        }
+        if value & PTE_RWX != 0 {
+            return (level == 0 && value & PTE_U != 0).then_some(pte);
+        }
+        table = pte_ppn(value) << Sv39::PAGE_BITS;
+    }
+    None
+}
+
+/// 把 `[addr, addr + len)` 中的写时复制页换成本地址空间私有的可写页
+///
+/// 用户写写时复制页引发 StorePageFault 时由 trap 处理调用；内核代用户写内存之前也要先调用它
+/// （见 [`WriteTranslate`]），否则翻译会因缺少 W 位失败。仍有其他地址空间映射的页复制一份，
+/// 只剩自己映射的页直接收回所有权，不必复制。
+/// 返回是否有页被转为可写；分配不到物理页时停在该页，它仍然不可写。
+/// `len` 往往直接来自系统调用参数，遇到第一个未映射的页就停止，遍历不会越过从 `addr` 起连续映射的部分。
+pub fn resolve_cow(space: &AddressSpace<Sv39, Sv39Manager>, addr: usize, len: usize) -> bool {
+    const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+    let first = addr >> Sv39::PAGE_BITS;
+    let last = addr.saturating_add(len.max(1) - 1) >> Sv39::PAGE_BITS;
+    let mut resolved = false;
+    for vpn in first..=last {
+        let Some(pte) = user_leaf_pte(space, vpn) else {
+            break;
+        };
+        let pte = unsafe { &mut *pte };
+        if *pte & PTE_COW == 0 {
+            continue;
+        }
+        let ppn = pte_ppn(*pte);
+        let mut shared = SHARED_PAGES.lock();
+        let new_ppn = match shared.get_mut(&ppn) {
+            Some(count) if *count > 1 => {
+                let page = unsafe { alloc(Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE)) };
+                if page.is_null() {
+                    break;
+                }
+                unsafe { core::ptr::copy_nonoverlapping((ppn << Sv39::PAGE_BITS) as *const u8, page, PAGE_SIZE) };
+                *count -= 1;
+                page as usize >> Sv39::PAGE_BITS
+            }
+            _ => {
+                shared.remove(&ppn);
+                ppn
+            }
+        };
+        // 新页归本地址空间所有；马上就要被写入，直接置上 A、D 位
+        *pte = (new_ppn << 10) | (*pte & PTE_PERM) | PTE_W | Sv39Manager::OWNED_BIT | PTE_A | PTE_D;
+        resolved = true;
+    }
+    if resolved {
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+    resolved
+}
+
+/// 内核代用户写内存时使用的地址翻译
+pub trait WriteTranslate {
+    /// 与 `translate` 相同，但先把 `T` 占用的写时复制页换成私有页（见 [`resolve_cow`]）
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>>;
+}
+
+impl WriteTranslate for AddressSpace<Sv39, Sv39Manager> {
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>> {
+        resolve_cow(self, addr.val(), core::mem::size_of::<T>());
+        self.translate(addr, flags)
+    }
+}
+
+/// exec 计数的统计窗口：1 秒（QEMU virt 时钟频率 12.5 MHz）
+const EXEC_WINDOW: u64 = 12_500_000;
+/// 一个统计窗口内 exec 达到该次数时打印告警，提示进程可能在循环 exec
+const EXEC_WARN_THRESHOLD: usize = 64;
+
+/// 每个进程 mmap 区间数的上限，对应 Linux 的 `vm.max_map_count`
+///
+/// 限制的是内核为登记区间分配的管理结构，而不是映射的总大小。
+pub const MAX_MAP_COUNT: usize = 256;
+
+/// `MAP_GROWSDOWN` 区间最多能增长到的大小，与 Linux 默认的 `RLIMIT_STACK` 相同
+const GROWSDOWN_LIMIT: usize = 8 << 20;
+/// `MAP_GROWSDOWN` 区间下方的 guard 区页数：缺页地址落在其中时区间向下扩展到该页
+///
+/// 比一页大一些，函数一次分配较大的栈帧、跳过紧邻的那一页时也能正常增长。
+const GROWSDOWN_GUARD_PAGES: usize = 16;
+
+/// mmap 建立的一段区间（简化的 VMA）
+#[derive(Clone)]
+pub struct MmapArea {
+    /// 页对齐的虚拟地址范围
+    pub range: Range<usize>,
+    /// 映射时使用的页表权限，向下扩展的新页沿用它
+    pub flags: VmFlags<Sv39>,
+    /// 以 `MAP_GROWSDOWN` 映射：访问区间下方的 guard 区时自动向下扩展
+    pub growsdown: bool,
+}
+
+/// 一段共享文件映射
+///
+/// 映射建立时把文件内容读入新分配的页，之后用户对这些页的修改由硬件记在 PTE 的 D 位上，
+/// `msync`/`munmap` 时只把 D 位为 1 的页写回文件。
+pub struct FileMapping {
+    /// 起始虚拟地址（页对齐）
+    pub start: usize,
+    /// 映射长度（页对齐）
+    pub len: usize,
+    /// 被映射的文件
+    pub inode: Arc<Inode>,
+    /// `start` 对应的文件偏移（页对齐）
+    pub offset: usize,
 }
 
 impl Process {
     /// exec：用新程序替换当前进程（保留 PID、fd_table、stride 和 priority）
-    pub fn exec(&mut self, elf: ElfFile) {
-        let proc = Process::from_elf(elf).unwrap();
+    ///
+    /// 新地址空间在 `from_elf` 中完整建好之后才替换当前进程的状态；
+    /// ELF 非法时返回 `false`，当前进程保持原样，可以继续运行。
+    pub fn exec(&mut self, elf: ElfFile) -> bool {
+        let Some(proc) = Process::from_elf(elf) else {
+            return false;
+        };
+        // 旧地址空间在这里被释放，其中的页面随之回收
         self.address_space = proc.address_space;
         self.context = proc.context;
         self.heap_bottom = proc.heap_bottom;
         self.program_brk = proc.program_brk;
-        // 保留原进程的 stride 和 priority
+        // 旧地址空间已被替换，其中未 msync 的修改随之丢弃
+        self.file_maps.clear();
+        self.mmap_areas.clear();
+        self.count_exec();
+        // 保留原进程的 stride、priority 和 uid/gid
+        true
+    }
+
+    /// 记录一次成功的 exec，短时间内 exec 过于频繁时打印告警（只告警，不杀死进程）
+    fn count_exec(&mut self) {
+        let now = riscv::register::time::read64();
+        if now - self.exec_window_start > EXEC_WINDOW {
+            self.exec_window_start = now;
+            self.exec_count = 0;
+        }
+        self.exec_count += 1;
+        if self.exec_count == EXEC_WARN_THRESHOLD {
+            log::warn!(
+                "process {} exec'd {EXEC_WARN_THRESHOLD} times within 1s, possibly an exec loop",
+                self.pid.get_usize()
+            );
+        }
     }
 
     /// fork：复制当前进程创建子进程
     ///
-    /// 深拷贝地址空间和文件描述符表。
-    /// 子进程继承父进程的所有已打开文件。
+    /// 用户页全部与父进程共享（见 `SHARED_PAGES`），不复制任何物理页：
+    /// 可写页在父子两边都改为写时复制，谁先写谁得到一份私有副本（见 [`resolve_cow`]）。
+    /// 复制文件描述符表，子进程继承父进程的所有已打开文件。
     pub fn fork(&mut self) -> Option<Process> {
         let pid = ProcId::new();
-        // 复制父进程的完整地址空间
-        let parent_addr_space = &self.address_space;
+        // 共享父进程的地址空间：只读页（代码段、rodata）直接共享，可写页改为写时复制
         let mut address_space: AddressSpace<Sv39, Sv39Manager> = AddressSpace::new();
-        parent_addr_space.cloneself(&mut address_space);
+        let mut shared = SHARED_PAGES.lock();
+        for_each_user_leaf(&self.address_space, &mut |vpn, pte| {
+            let range = VPN::new(vpn)..VPN::new(vpn + 1);
+            let ppn = pte_ppn(*pte);
+            if *pte & (PTE_W | PTE_COW) != 0 {
+                *pte = (*pte & !PTE_W) | PTE_COW;
+            }
+            let flags = unsafe { VmFlags::from_raw(*pte & (PTE_PERM | PTE_COW)) };
+            // 以外部页映射，子进程的地址空间不会把它当作自己分配的页
+            address_space.map_extern(range, PPN::new(ppn), flags);
+            *shared.entry(ppn).or_insert(1) += 1;
+        });
+        drop(shared);
+        // 父进程的可写页刚被改为只读，清掉 TLB 中仍带 W 位的旧表项
+        unsafe { riscv::asm::sfence_vma_all() };
         map_portal(&address_space);
         // 复制父进程上下文
         let context = self.context.context.clone();
@@ -90,10 +333,10 @@ impl Process {
         let foreign_ctx = ForeignContext { context, satp };
         // 复制父进程的文件描述符表
         // 子进程继承父进程所有已打开的文件
-        let mut new_fd_table: Vec<Option<Mutex<FileHandle>>> = Vec::new();
+        let mut new_fd_table: Vec<Option<Arc<Mutex<FileHandle>>>> = Vec::new();
         for fd in self.fd_table.iter_mut() {
             if let Some(file) = fd {
-                new_fd_table.push(Some(Mutex::new(file.get_mut().clone())));
+                new_fd_table.push(Some(Arc::new(Mutex::new(file.lock().clone()))));
             } else {
                 new_fd_table.push(None);
             }
@@ -107,6 +350,17 @@ impl Process {
             program_brk: self.program_brk,
             stride: 0,  // 子进程 stride 初始化为 0
             priority: self.priority,  // 继承父进程的优先级
+            uid: self.uid,
+            gid: self.gid,
+            // 页面写时复制，子进程的修改对父进程不可见，但 msync 仍写回同一个文件
+            file_maps: self
+                .file_maps
+                .iter()
+                .map(|m| FileMapping { inode: m.inode.clone(), ..*m })
+                .collect(),
+            mmap_areas: self.mmap_areas.clone(),
+            exec_count: 0,
+            exec_window_start: 0,
         })
     }
 
@@ -126,6 +380,9 @@ impl Process {
             }
             _ => None?,
         };
+        if !check_load_segments(&elf) {
+            return None;
+        }
 
         const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
         const PAGE_MASK: usize = PAGE_SIZE - 1;
@@ -170,17 +427,8 @@ impl Process {
         let heap_bottom = VAddr::<Sv39>::new(max_end_va).ceil().base().val();
 
         // 映射用户栈（2 页 = 8 KiB）
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
-            VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
-            build_flags("U_WRV"),
-        );
+        // 物理页由 map 分配并标记 OWNED，进程退出时随地址空间一起回收
+        address_space.map(VPN::new((1 << 26) - 2)..VPN::new(1 << 26), &[], 0, build_flags("U_WRV"));
         // 映射异界传送门
         map_portal(&address_space);
 
@@ -194,17 +442,209 @@ impl Process {
             address_space,
             // 初始化文件描述符表：预留 stdin(0)、stdout(1)、stderr(2)
             fd_table: vec![
-                Some(Mutex::new(FileHandle::empty(true, false))),  // fd 0: stdin（可读）
-                Some(Mutex::new(FileHandle::empty(false, true))),  // fd 1: stdout（可写）
-                Some(Mutex::new(FileHandle::empty(false, true))),  // fd 2: stderr（可写）
+                Some(Arc::new(Mutex::new(FileHandle::empty(true, false)))),  // fd 0: stdin（可读）
+                Some(Arc::new(Mutex::new(FileHandle::empty(false, true)))),  // fd 1: stdout（可写）
+                Some(Arc::new(Mutex::new(FileHandle::empty(false, true)))),  // fd 2: stderr（可写）
             ],
             heap_bottom,
             program_brk: heap_bottom,
             stride: 0,        // 初始 stride 为 0
             priority: 16,     // 初始优先级为 16
+            uid: 0,
+            gid: 0,
+            file_maps: Vec::new(),
+            mmap_areas: Vec::new(),
+            exec_count: 0,
+            exec_window_start: 0,
         })
     }
 
+    /// 分配文件描述符：优先复用编号最小的空闲槽位，没有空闲槽位时追加到末尾
+    ///
+    /// open 等所有需要新 fd 的系统调用都应通过这里分配，保证 fd 号不会无限增长。
+    pub fn alloc_fd(&mut self, file: FileHandle) -> usize {
+        self.install_fd(Arc::new(Mutex::new(file)))
+    }
+
+    /// 把一个已有的句柄装入编号最小的空闲槽位，dup 出的 fd 与原 fd 共享同一个句柄
+    pub fn install_fd(&mut self, file: Arc<Mutex<FileHandle>>) -> usize {
+        let file = Some(file);
+        if let Some(fd) = self.fd_table.iter().position(Option::is_none) {
+            self.fd_table[fd] = file;
+            fd
+        } else {
+            self.fd_table.push(file);
+            self.fd_table.len() - 1
+        }
+    }
+
+    /// `fd` 是否指向控制台且允许按 `write` 指定的方向访问
+    ///
+    /// 标准输入输出没有 inode，dup 出的副本也一样；stdin 不可写、stdout 不可读，
+    /// 方向不符时返回 `false`，由调用者按普通句柄的读写权限报错。
+    pub fn is_console(&self, fd: usize, write: bool) -> bool {
+        matches!(self.fd_table.get(fd), Some(Some(file)) if {
+            let file = file.lock();
+            file.inode.is_none() && if write { file.writable() } else { file.readable() }
+        })
+    }
+
+    /// 登记一段新的 mmap 区间，已达到 `MAX_MAP_COUNT` 时返回 `false`
+    pub fn add_mmap_area(&mut self, area: MmapArea) -> bool {
+        if self.mmap_areas.len() >= MAX_MAP_COUNT {
+            return false;
+        }
+        self.mmap_areas.push(area);
+        true
+    }
+
+    /// 从登记的 mmap 区间中挖去 `[start, end)`
+    ///
+    /// 从中间挖开的区间一分为二，区间数因此超过 `MAX_MAP_COUNT` 时不做修改并返回 `false`。
+    pub fn remove_mmap_area(&mut self, start: usize, end: usize) -> bool {
+        let mut areas = Vec::with_capacity(self.mmap_areas.len() + 1);
+        for area in &self.mmap_areas {
+            let range = &area.range;
+            if range.end <= start || range.start >= end {
+                areas.push(area.clone());
+                continue;
+            }
+            // 挖开后的两段都保留原区间的属性（与 Linux 拆分 VMA 一致）
+            if range.start < start {
+                areas.push(MmapArea { range: range.start..start, ..area.clone() });
+            }
+            if range.end > end {
+                areas.push(MmapArea { range: end..range.end, ..area.clone() });
+            }
+        }
+        if areas.len() > MAX_MAP_COUNT {
+            return false;
+        }
+        self.mmap_areas = areas;
+        true
+    }
+
+    /// 缺页地址 `addr` 落在某个 `MAP_GROWSDOWN` 区间下方的 guard 区时，把区间向下扩展到该页
+    ///
+    /// 新页与区间原有的页权限相同。扩展后超过 `GROWSDOWN_LIMIT`、会碰到堆或其他已映射的页时
+    /// 不做修改并返回 `false`，由调用者按非法访问处理。
+    pub fn grow_mmap_area(&mut self, addr: usize) -> bool {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        const CHECK_FLAGS: VmFlags<Sv39> = build_flags("__V");
+        let page = addr & !(PAGE_SIZE - 1);
+        let Some(index) = self.mmap_areas.iter().position(|area| {
+            area.growsdown
+                && page < area.range.start
+                && area.range.start - page <= GROWSDOWN_GUARD_PAGES * PAGE_SIZE
+        }) else {
+            return false;
+        };
+        let area = &self.mmap_areas[index];
+        if area.range.end - page > GROWSDOWN_LIMIT || page < self.program_brk {
+            return false;
+        }
+        let start = area.range.start;
+        let taken = self.mmap_areas.iter().any(|other| other.range.start < start && other.range.end > page)
+            || (page..start)
+                .step_by(PAGE_SIZE)
+                .any(|addr| self.address_space.translate::<u8>(VAddr::new(addr), CHECK_FLAGS).is_some());
+        if taken {
+            return false;
+        }
+        let flags = area.flags;
+        self.address_space
+            .map(VAddr::<Sv39>::new(page).floor()..VAddr::<Sv39>::new(start).floor(), &[], 0, flags);
+        self.mmap_areas[index].range.start = page;
+        true
+    }
+
+    /// MADV_WILLNEED：为 `[start, end)` 中还没有建立映射的页分配页面并映射
+    ///
+    /// 区间必须完全落在 mmap 区间内，否则不做修改并返回 `false`。文件映射的页先对整段调用
+    /// `readahead` 把块批量载入块缓存，再逐页读入文件内容；匿名映射的页保持全零。
+    /// 目前 mmap 在映射时就读入了全部页，对它们这里什么也不做，只有缺失的页才会被补上。
+    pub fn populate(&mut self, start: usize, end: usize) -> bool {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        const CHECK_FLAGS: VmFlags<Sv39> = build_flags("__V");
+        let area_of = |addr: usize| self.mmap_areas.iter().find(|area| area.range.contains(&addr));
+        if (start..end).step_by(PAGE_SIZE).any(|addr| area_of(addr).is_none()) {
+            return false;
+        }
+        for map in &self.file_maps {
+            let from = start.max(map.start);
+            let to = end.min(map.start + map.len);
+            if from < to {
+                map.inode.readahead(map.offset + (from - map.start), to - from);
+            }
+        }
+        for addr in (start..end).step_by(PAGE_SIZE) {
+            if self.address_space.translate::<u8>(VAddr::new(addr), CHECK_FLAGS).is_some() {
+                continue;
+            }
+            let flags = area_of(addr).unwrap().flags;
+            self.address_space
+                .map(VAddr::<Sv39>::new(addr).floor()..VAddr::<Sv39>::new(addr + PAGE_SIZE).floor(), &[], 0, flags);
+            let Some(map) = self.file_maps.iter().find(|map| (map.start..map.start + map.len).contains(&addr)) else {
+                continue;
+            };
+            let page = self.address_space.translate::<u8>(VAddr::new(addr), CHECK_FLAGS).unwrap();
+            let page = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), PAGE_SIZE) };
+            map.inode.read_at(map.offset + (addr - map.start), page);
+        }
+        true
+    }
+
+    /// 把 `[start, end)` 中属于共享文件映射的脏页写回文件
+    ///
+    /// 写回后清除 D 位，下次只有再被修改过的页才会写回。
+    /// 文件末尾所在的页只写回文件范围内的部分，不会因为映射按页对齐而把文件变长。
+    pub fn sync_file_maps(&self, start: usize, end: usize) {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        for map in &self.file_maps {
+            let from = start.max(map.start);
+            let to = end.min(map.start + map.len);
+            for addr in (from..to).step_by(PAGE_SIZE) {
+                let Some(page) = self.take_dirty(addr) else {
+                    continue;
+                };
+                let offset = map.offset + (addr - map.start);
+                let len = PAGE_SIZE.min(map.inode.size().saturating_sub(offset));
+                if len > 0 {
+                    let data = unsafe { core::slice::from_raw_parts(page as *const u8, len) };
+                    map.inode.write_at(offset, data);
+                }
+            }
+        }
+    }
+
+    /// 若 `addr` 所在的用户页已映射且 D 位为 1，清除 D 位并返回该页的物理地址
+    ///
+    /// `tg-kernel-vm` 不提供读写 PTE 标志位的接口，这里直接按 Sv39 格式遍历页表；
+    /// 内核恒等映射了全部物理内存，页表页的物理地址可以直接访问。
+    fn take_dirty(&self, addr: usize) -> Option<usize> {
+        let mut table = self.address_space.root_ppn().val() << Sv39::PAGE_BITS;
+        for level in (0..3).rev() {
+            let index = (addr >> (Sv39::PAGE_BITS + 9 * level)) & 0x1ff;
+            let pte = unsafe { &mut *(table as *mut usize).add(index) };
+            if *pte & PTE_V == 0 {
+                return None;
+            }
+            let base = pte_ppn(*pte) << Sv39::PAGE_BITS;
+            if *pte & PTE_RWX != 0 {
+                if *pte & PTE_D == 0 {
+                    return None;
+                }
+                *pte &= !PTE_D;
+                unsafe { riscv::asm::sfence_vma_all() };
+                // 大页内按 addr 定位到具体的 4 KiB 页
+                let huge_mask = (1 << (Sv39::PAGE_BITS + 9 * level)) - 1;
+                return Some(base + (addr & huge_mask & !((1 << Sv39::PAGE_BITS) - 1)));
+            }
+            table = base;
+        }
+        None
+    }
+
     /// 修改程序 break 位置（实现 sbrk 系统调用）
     pub fn change_program_brk(&mut self, size: isize) -> Option<usize> {
         let old_brk = self.program_brk;
@@ -232,3 +672,23 @@ impl Process {
         Some(old_brk)
     }
 }
+
+/// 校验 ELF 的所有 LOAD 段（在分配任何页面之前调用）
+///
+/// 检查文件内偏移不越界、文件大小不超过内存大小、页内偏移一致、段落在用户地址空间内。
+/// 全部通过才返回 `true`，这样 `from_elf` 失败时不会留下映射了一半的地址空间。
+fn check_load_segments(elf: &ElfFile) -> bool {
+    const PAGE_MASK: usize = (1 << Sv39::PAGE_BITS) - 1;
+    elf.program_iter()
+        .filter(|program| matches!(program.get_type(), Ok(program::Type::Load)))
+        .all(|program| {
+            let off_file = program.offset() as usize;
+            let len_file = program.file_size() as usize;
+            let off_mem = program.virtual_addr() as usize;
+            let len_mem = program.mem_size() as usize;
+            off_file & PAGE_MASK == off_mem & PAGE_MASK
+                && len_file <= len_mem
+                && off_file.checked_add(len_file).is_some_and(|end| end <= elf.input.len())
+                && off_mem.checked_add(len_mem).is_some_and(|end| end <= 1 << 38)
+        })
+}
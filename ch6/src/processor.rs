@@ -9,8 +9,10 @@
 //! - 再看 `ProcManager`：把握“实体管理(Manage) + 调度队列(Schedule)”分层。
 
 use crate::process::Process;
-use alloc::collections::{BTreeMap, VecDeque};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
 use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
 use tg_task_manage::{Manage, PManager, ProcId, Schedule};
 
 /// 处理器全局管理器
@@ -38,6 +40,96 @@ impl Processor {
 /// 全局处理器管理器实例
 pub static PROCESSOR: Processor = Processor::new();
 
+/// wait 阻塞所需的进程关系
+///
+/// `PManager` 只在 wait 时报告已退出的子进程，这里额外记录存活的父子关系，
+/// 用于判断 wait 是否需要阻塞，以及子进程退出时该唤醒谁。
+struct WaitState {
+    /// 存活子进程 -> 父进程
+    parent: BTreeMap<ProcId, ProcId>,
+    /// 阻塞在 wait 上的进程
+    waiting: BTreeSet<ProcId>,
+}
+
+static WAIT_STATE: Mutex<WaitState> = Mutex::new(WaitState {
+    parent: BTreeMap::new(),
+    waiting: BTreeSet::new(),
+});
+
+/// 记录父子关系（fork/spawn 把子进程加入管理器后调用）
+pub fn record_child(parent: ProcId, child: ProcId) {
+    WAIT_STATE.lock().parent.insert(child, parent);
+}
+
+/// `parent` 是否有可等待的存活子进程，`target` 为 `None` 表示任意子进程
+pub fn has_live_child(parent: ProcId, target: Option<ProcId>) -> bool {
+    let state = WAIT_STATE.lock();
+    match target {
+        Some(child) => state.parent.get(&child) == Some(&parent),
+        None => state.parent.values().any(|&p| p == parent),
+    }
+}
+
+/// 设置 `pid` 是否阻塞在 wait 上；阻塞的进程不会被调度，直到有子进程退出
+pub fn set_wait_blocked(pid: ProcId, blocked: bool) {
+    let mut state = WAIT_STATE.lock();
+    if blocked {
+        state.waiting.insert(pid);
+    } else {
+        state.waiting.remove(&pid);
+    }
+}
+
+/// `pid` 是否阻塞在 wait 上
+pub fn is_wait_blocked(pid: ProcId) -> bool {
+    WAIT_STATE.lock().waiting.contains(&pid)
+}
+
+/// 让当前进程退出，并唤醒阻塞在 wait 上的父进程
+pub fn exit_current(exit_code: isize) {
+    // 进程可能留下不以换行结尾的输出
+    crate::console::flush();
+    let manager = PROCESSOR.get_mut();
+    if let Some(pid) = manager.current().map(|p| p.pid) {
+        let mut state = WAIT_STATE.lock();
+        if let Some(parent) = state.parent.remove(&pid) {
+            state.waiting.remove(&parent);
+        }
+        // 子进程由 PManager 托付给 initproc，这里不再跟踪
+        state.parent.retain(|_, p| *p != pid);
+        state.waiting.remove(&pid);
+    }
+    manager.make_current_exited(exit_code);
+}
+
+/// 当前存在的进程数（在 `ProcManager` 插入/删除进程时维护）
+static PROC_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// RLIMIT_NPROC：允许同时存在的进程数上限
+///
+/// 本内核没有多用户，简化为全局限制，默认不限制。
+static NPROC_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 当前进程总数
+pub fn proc_count() -> usize {
+    PROC_COUNT.load(Ordering::Relaxed)
+}
+
+/// 读取 RLIMIT_NPROC
+pub fn nproc_limit() -> usize {
+    NPROC_LIMIT.load(Ordering::Relaxed)
+}
+
+/// 设置 RLIMIT_NPROC
+pub fn set_nproc_limit(limit: usize) {
+    NPROC_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// 是否还能再创建一个进程（fork/spawn 前检查）
+pub fn can_create_proc() -> bool {
+    proc_count() < nproc_limit()
+}
+
 /// 进程管理器（FIFO 调度）
 pub struct ProcManager {
     /// 所有进程实体的映射表
@@ -60,7 +152,9 @@ impl Manage<Process, ProcId> for ProcManager {
     /// 插入新进程
     #[inline]
     fn insert(&mut self, id: ProcId, task: Process) {
-        self.tasks.insert(id, task);
+        if self.tasks.insert(id, task).is_none() {
+            PROC_COUNT.fetch_add(1, Ordering::Relaxed);
+        }
     }
     /// 根据 PID 获取进程
     #[inline]
@@ -70,7 +164,9 @@ impl Manage<Process, ProcId> for ProcManager {
     /// 删除进程
     #[inline]
     fn delete(&mut self, id: ProcId) {
-        self.tasks.remove(&id);
+        if self.tasks.remove(&id).is_some() {
+            PROC_COUNT.fetch_sub(1, Ordering::Relaxed);
+        }
     }
 }
 
@@ -90,6 +186,10 @@ impl Schedule<ProcId> for ProcManager {
         let mut min_index = 0;
 
         for (index, &pid) in self.ready_queue.iter().enumerate() {
+            // 阻塞在 wait 上的进程不参与调度；若全部阻塞则退化为选第一个，让它重新检查
+            if is_wait_blocked(pid) {
+                continue;
+            }
             if let Some(process) = self.tasks.get(&pid) {
                 if process.stride < min_stride {
                     min_stride = process.stride;
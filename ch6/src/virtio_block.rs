@@ -41,33 +41,35 @@ use alloc::{
 };
 use core::{alloc::Layout, ptr::NonNull};
 use spin::{Lazy, Mutex};
+use tg_console::log;
 use tg_easy_fs::BlockDevice;
 use tg_kernel_vm::page_table::{MmuMeta, VAddr, VmFlags};
-use virtio_drivers::{Hal, MmioTransport, VirtIOBlk, VirtIOHeader};
+use virtio_drivers::{Hal, MmioTransport, Transport, VirtIOBlk, VirtIOHeader};
 
 /// VirtIO 块设备的 MMIO 基地址（QEMU virt 平台）
 const VIRTIO0: usize = 0x10001000;
+/// 设备特性位 VIRTIO_BLK_F_RO：设备只读
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
 
 /// 全局块设备实例（延迟初始化）
 ///
 /// 通过 MMIO 地址创建 VirtIO 块设备驱动实例。
 /// 被 easy-fs 文件系统用于读写磁盘块。
 pub static BLOCK_DEVICE: Lazy<Arc<dyn BlockDevice>> = Lazy::new(|| {
-    Arc::new(unsafe {
-        VirtIOBlock(Mutex::new(
-            VirtIOBlk::new(
-                MmioTransport::new(NonNull::new(VIRTIO0 as *mut VirtIOHeader).unwrap())
-                    .expect("Error when creating MmioTransport"),
-            )
-            .expect("Error when creating VirtIOBlk"),
-        ))
-    })
+    let mut transport = unsafe { MmioTransport::new(NonNull::new(VIRTIO0 as *mut VirtIOHeader).unwrap()) }
+        .expect("Error when creating MmioTransport");
+    // virtio-drivers 0.1.0 不协商也不保存 VIRTIO_BLK_F_RO，交给驱动之前先从设备特性中读出
+    let read_only = transport.read_device_features() & VIRTIO_BLK_F_RO != 0;
+    Arc::new(VirtIOBlock(
+        Mutex::new(VirtIOBlk::new(transport).expect("Error when creating VirtIOBlk")),
+        read_only,
+    ))
 });
 
-/// VirtIO 块设备封装
+/// VirtIO 块设备封装，第二个字段记录设备是否只读
 ///
 /// 使用 Mutex 保护内部的 VirtIOBlk，确保线程安全访问。
-struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>);
+struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>, bool);
 
 // Safety: VirtIOBlock 内部使用 Mutex 保护，确保线程安全访问
 unsafe impl Send for VirtIOBlock {}
@@ -86,10 +88,22 @@ impl BlockDevice for VirtIOBlock {
     }
     /// 写入一个磁盘块（512 字节）
     fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert!(
+            self.try_write_block(block_id, buf),
+            "Error when writing VirtIOBlk"
+        );
+    }
+    /// 写入一个磁盘块，设备报错时记录日志并返回 `false`（块缓存写回脏块走这里）
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> bool {
         self.0
             .lock()
             .write_block(block_id, buf)
-            .expect("Error when writing VirtIOBlk");
+            .map_err(|err| log::error!("VirtIOBlk: failed to write block {block_id}: {err:?}"))
+            .is_ok()
+    }
+    /// 设备协商时报告了 VIRTIO_BLK_F_RO（QEMU 以 `readonly=on` 挂载镜像）时为只读
+    fn is_read_only(&self) -> bool {
+        self.1
     }
 }
 
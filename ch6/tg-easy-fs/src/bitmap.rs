@@ -1,5 +1,6 @@
 use super::{get_block_cache, BlockDevice, BLOCK_SZ};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 // 教程说明：
 // Bitmap 用于管理 inode/data 资源位图。1 表示已占用，0 表示空闲。
@@ -35,7 +36,7 @@ impl Bitmap {
         // 从前往后扫描，定位第一个还没全满的位图块。
         for block_id in 0..self.blocks {
             let pos = get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
-                .lock()
+                .write()
                 .modify(0, |bitmap_block: &mut BitmapBlock| {
                     if let Some((bits64_pos, inner_pos)) = bitmap_block
                         .iter()
@@ -62,13 +63,36 @@ impl Bitmap {
     pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
         let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
         get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
-            .lock()
+            .write()
             .modify(0, |bitmap_block: &mut BitmapBlock| {
                 assert!(bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0);
                 // 清除该 bit，表示资源回收。
                 bitmap_block[bits64_pos] -= 1u64 << inner_pos;
             });
     }
+    /// Export the first `bits` bits as a compact byte array
+    ///
+    /// 第 i 位位于 `bytes[i / 8]` 的第 `i % 8` 位（低位在前），与磁盘上的位序一致；
+    /// 最后一个字节中超出 `bits` 的位清零。
+    pub fn export(&self, block_device: &Arc<dyn BlockDevice>, bits: usize) -> Vec<u8> {
+        let bits = bits.min(self.maximum());
+        let mut bytes = Vec::with_capacity(bits.div_ceil(8));
+        for block_id in 0..bits.div_ceil(BLOCK_BITS) {
+            get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                .read()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    for bits64 in bitmap_block.iter() {
+                        bytes.extend_from_slice(&bits64.to_le_bytes());
+                    }
+                });
+        }
+        bytes.truncate(bits.div_ceil(8));
+        let tail_bits = bits % 8;
+        if tail_bits != 0 && let Some(last) = bytes.last_mut() {
+            *last &= (1u8 << tail_bits) - 1;
+        }
+        bytes
+    }
     /// Get the max number of allocatable blocks
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
@@ -1,6 +1,7 @@
 use super::{BlockDevice, BLOCK_SZ};
 use alloc::{collections::VecDeque, sync::Arc};
-use spin::{Lazy, Mutex};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Lazy, Mutex, RwLock};
 
 /// Cached block inside memory
 pub struct BlockCache {
@@ -12,6 +13,8 @@ pub struct BlockCache {
     block_device: Arc<dyn BlockDevice>,
     /// whether the block is dirty
     modified: bool,
+    /// whether the last write-back of this dirty block failed
+    write_failed: bool,
 }
 
 impl BlockCache {
@@ -24,6 +27,7 @@ impl BlockCache {
             block_id,
             block_device,
             modified: false,
+            write_failed: false,
         }
     }
     /// Get the address of an offset inside the cached block data
@@ -74,65 +78,129 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
-    pub fn sync(&mut self) {
+    /// Write the block back if it is dirty, returning whether it is now clean on the device
+    ///
+    /// 写回失败时块保持为脏并标记 `write_failed`，下次 sync 会重试。
+    pub fn sync(&mut self) -> bool {
         if self.modified {
             // 写回策略：脏块才回写，减少无效 I/O。
-            self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache);
+            let ok = self.block_device.try_write_block(self.block_id, &self.cache);
+            self.modified = !ok;
+            self.write_failed = !ok;
         }
+        !self.modified
+    }
+
+    /// Whether the block is dirty and its last write-back failed
+    pub fn write_failed(&self) -> bool {
+        self.write_failed
     }
 }
 
 impl Drop for BlockCache {
     fn drop(&mut self) {
-        self.sync()
+        // 淘汰前已确认写回成功，这里只兜底缓存管理器之外的释放路径
+        self.sync();
     }
 }
-/// Use a block cache of 16 blocks
+/// Default capacity of the block cache, in blocks
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// Number of lookups served from the cache
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+/// Number of lookups that had to read the block from the device
+static CACHE_MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// 缓存块的键 `(块号, 设备地址)`：同时打开多个设备（如叠加设备与其基础镜像）时互不混淆
+type CacheKey = (usize, usize);
+
+/// LRU cache of disk blocks with a fixed capacity
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    /// 按最近使用时间排列，队首最久未用，命中时移到队尾
+    queue: VecDeque<(CacheKey, Arc<RwLock<BlockCache>>)>,
+    /// 最多缓存的块数
+    capacity: usize,
 }
 
 impl BlockCacheManager {
+    /// Create a cache holding at most `BLOCK_CACHE_SIZE` blocks
     pub fn new() -> Self {
+        Self::with_capacity(BLOCK_CACHE_SIZE)
+    }
+
+    /// Create a cache holding at most `capacity` blocks
+    ///
+    /// # Panics
+    ///
+    /// 如果 `capacity` 为 0 则会 panic。
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "block cache capacity must be positive");
         Self {
-            queue: VecDeque::new(),
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
         }
     }
 
+    /// Maximum number of cached blocks
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity, evicting the least recently used blocks that no longer fit
+    ///
+    /// 正在被使用或写回失败的块不能淘汰，缓存会暂时超出新容量，之后载入新块时继续淘汰。
+    ///
+    /// # Panics
+    ///
+    /// 如果 `capacity` 为 0 则会 panic。
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "block cache capacity must be positive");
+        self.capacity = capacity;
+        while self.queue.len() > capacity && self.evict_one() {}
+    }
+
+    /// Evict the least recently used block that only the cache holds, writing it back first
+    ///
+    /// 写回失败的脏块不能淘汰，否则数据随之丢失；找不到可淘汰的块时返回 `false`。
+    fn evict_one(&mut self) -> bool {
+        let victim = self
+            .queue
+            .iter()
+            .position(|pair| Arc::strong_count(&pair.1) == 1 && pair.1.write().sync());
+        if let Some(idx) = victim {
+            self.queue.remove(idx);
+        }
+        victim.is_some()
+    }
+
+    /// Get the cached block, loading it from the device on a miss
     pub fn get_block_cache(
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
-    ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            // 命中缓存
-            Arc::clone(&pair.1)
-        } else {
-            // 未命中：必要时替换一个“仅被缓存管理器持有”的块（strong_count == 1）
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+    ) -> Arc<RwLock<BlockCache>> {
+        let key = (block_id, Arc::as_ptr(&block_device) as *const () as usize);
+        if let Some(idx) = self.queue.iter().position(|pair| pair.0 == key) {
+            // 命中缓存：移到队尾，成为最近使用的块
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            let pair = self.queue.remove(idx).unwrap();
+            let block_cache = Arc::clone(&pair.1);
+            self.queue.push_back(pair);
+            return block_cache;
+        }
+        // 未命中：缓存已满时从最久未用的一端淘汰
+        while self.queue.len() >= self.capacity {
+            if !self.evict_one() {
+                panic!("Run out of BlockCache!");
             }
-            // 载入新块并插入队尾（近似 FIFO）
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
         }
+        CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+        let block_cache = Arc::new(RwLock::new(BlockCache::new(
+            block_id,
+            Arc::clone(&block_device),
+        )));
+        self.queue.push_back((key, Arc::clone(&block_cache)));
+        block_cache
     }
 }
 
@@ -141,18 +209,69 @@ pub static BLOCK_CACHE_MANAGER: Lazy<Mutex<BlockCacheManager>> =
     Lazy::new(|| Mutex::new(BlockCacheManager::new()));
 
 /// Get the block cache corresponding to the given block id and block device
+///
+/// 单块缓存用读写锁保护：`read` 只需读锁，多个读者可以同时访问同一块；
+/// `modify` 与回刷脏块（`sync`）需要写锁。
 pub fn get_block_cache(
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
-) -> Arc<Mutex<BlockCache>> {
+) -> Arc<RwLock<BlockCache>> {
     BLOCK_CACHE_MANAGER
         .lock()
         .get_block_cache(block_id, block_device)
 }
+
+/// Change the capacity of the global block cache (16 blocks by default)
+///
+/// 缩小时立即淘汰最久未用且可以淘汰的块，见 `BlockCacheManager::set_capacity`。
+pub fn block_cache_set_capacity(capacity: usize) {
+    BLOCK_CACHE_MANAGER.lock().set_capacity(capacity);
+}
+
+/// Most blocks a single readahead may bring in, so that it cannot push the whole cache out
+pub fn readahead_max_blocks() -> usize {
+    (BLOCK_CACHE_MANAGER.lock().capacity() / 2).max(1)
+}
+
+/// Number of block cache hits since boot
+pub fn block_cache_hits() -> usize {
+    CACHE_HITS.load(Ordering::Relaxed)
+}
+
+/// Number of block cache misses since boot
+pub fn block_cache_misses() -> usize {
+    CACHE_MISSES.load(Ordering::Relaxed)
+}
+
+/// Number of cached blocks that are dirty and whose last write-back failed
+pub fn block_cache_write_failures() -> usize {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    manager.queue.iter().filter(|(_, cache)| cache.read().write_failed()).count()
+}
+
+/// Sync the cached blocks of `block_device` whose block id satisfies `wanted`
+///
+/// 其余脏块留在缓存中，被淘汰时才写回；返回值与 `block_cache_sync_all` 相同。
+pub fn block_cache_sync(block_device: &Arc<dyn BlockDevice>, wanted: impl Fn(usize) -> bool) -> bool {
+    let device = Arc::as_ptr(block_device) as *const () as usize;
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    let mut ok = true;
+    for ((block_id, key_device), cache) in manager.queue.iter() {
+        if *key_device == device && wanted(*block_id) {
+            ok &= cache.write().sync();
+        }
+    }
+    ok
+}
+
 /// Sync all block cache to block device
-pub fn block_cache_sync_all() {
+///
+/// 返回是否所有脏块都已写回；失败的块保持为脏，留待下次重试。
+pub fn block_cache_sync_all() -> bool {
     let manager = BLOCK_CACHE_MANAGER.lock();
+    let mut ok = true;
     for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+        ok &= cache.write().sync();
     }
+    ok
 }
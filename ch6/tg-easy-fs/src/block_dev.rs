@@ -1,8 +1,8 @@
 use core::any::Any;
-///
-/// 教程说明：
-/// 这是 EasyFS 与具体硬件/驱动之间的最小抽象边界。
-/// 文件系统只依赖“按块读写”，不关心块设备底层是 virtio、内存盘还是其他介质。
+
+// 教程说明：
+// 这是 EasyFS 与具体硬件/驱动之间的最小抽象边界。
+// 文件系统只依赖“按块读写”，不关心块设备底层是 virtio、内存盘还是其他介质。
 
 /// Trait for block devices
 /// which reads and writes data in the unit of blocks
@@ -11,4 +11,19 @@ pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     ///Write data from buffer to block
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    ///Write data from buffer to block, returning `false` on a device error instead of panicking
+    ///
+    /// 块缓存写回脏块时调用；默认实现认为写入总能成功。
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> bool {
+        self.write_block(block_id, buf);
+        true
+    }
+    ///Flush data buffered by the device itself (no-op for write-through devices)
+    fn flush(&self) {}
+    ///Whether the device refuses writes
+    ///
+    /// `EasyFileSystem::open` 在只读设备上以只读方式挂载；默认认为设备可写。
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
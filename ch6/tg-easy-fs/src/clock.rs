@@ -0,0 +1,19 @@
+//! 文件时间戳使用的时钟
+//!
+//! easy-fs 既在宿主机的 build.rs 中运行，也在内核中运行，自身无法读取时间，
+//! 由使用者通过 `set_clock` 提供。未设置时钟时时间戳一律为 0。
+
+use spin::Once;
+
+/// 使用者提供的时钟，返回纳秒
+static CLOCK: Once<fn() -> u64> = Once::new();
+
+/// Set the clock used for inode timestamps, in nanoseconds; only the first call takes effect
+pub fn set_clock(now: fn() -> u64) {
+    CLOCK.call_once(|| now);
+}
+
+/// Current time in nanoseconds, 0 if no clock has been set
+pub(crate) fn now() -> u64 {
+    CLOCK.get().map_or(0, |now| now())
+}
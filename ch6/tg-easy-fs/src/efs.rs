@@ -1,9 +1,12 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
+    block_cache_sync, block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
     SuperBlock,
 };
 use crate::BLOCK_SZ;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 ///An easy file system on block
 pub struct EasyFileSystem {
@@ -15,6 +18,16 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    /// 数据区块数；位图最后一块中超出这个数目的位不对应真实的块
+    data_area_blocks: u32,
+    /// 每个 inode 当前被多少个文件句柄打开（仅在内存中维护）
+    open_count: BTreeMap<u32, usize>,
+    /// 已没有目录项、但仍被打开的 inode，最后一次关闭时回收
+    orphans: BTreeSet<u32>,
+    /// 目录名缓存：目录的 inode 编号 -> (名字 -> inode 编号)，首次在该目录中查找时整体读入
+    pub(crate) dir_cache: BTreeMap<u32, BTreeMap<String, u32>>,
+    /// 只读挂载：所有修改文件系统的操作都失败，不产生任何脏块
+    read_only: bool,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
@@ -45,11 +58,16 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
+            open_count: BTreeMap::new(),
+            orphans: BTreeSet::new(),
+            dir_cache: BTreeMap::new(),
+            read_only: false,
         };
         // 第二步：清盘（教学实现中直接全盘置零，简单直观）
         for i in 0..total_blocks {
             get_block_cache(i as usize, Arc::clone(&block_device))
-                .lock()
+                .write()
                 .modify(0, |data_block: &mut DataBlock| {
                     for byte in data_block.iter_mut() {
                         *byte = 0;
@@ -57,7 +75,7 @@ impl EasyFileSystem {
                 });
         }
         // 第三步：写入 SuperBlock
-        get_block_cache(0, Arc::clone(&block_device)).lock().modify(
+        get_block_cache(0, Arc::clone(&block_device)).write().modify(
             0,
             |super_block: &mut SuperBlock| {
                 super_block.initialize(
@@ -70,23 +88,28 @@ impl EasyFileSystem {
             },
         );
         // 第四步：创建根目录 inode（固定为 inode 0）
-        assert_eq!(efs.alloc_inode(), 0);
+        assert_eq!(efs.alloc_inode(), Some(0));
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
-            .lock()
+            .write()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
                 disk_inode.initialize(DiskInodeType::Directory);
+                // 根目录没有父目录项，链接数固定为 1
+                disk_inode.nlink = 1;
             });
         block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
     /// Open a block device as a filesystem
+    ///
+    /// 设备报告只读（`BlockDevice::is_read_only`）时以只读方式挂载，见 [`Self::is_read_only`]。
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        let read_only = block_device.is_read_only();
         // 打开时先读 SuperBlock，恢复布局信息。
         get_block_cache(0, Arc::clone(&block_device))
-            .lock()
+            .read()
             .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
+                assert!(super_block.is_valid(), "Error loading EFS: bad magic, rebuild the image with this version of easy-fs");
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
@@ -98,10 +121,32 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                    open_count: BTreeMap::new(),
+                    orphans: BTreeSet::new(),
+                    dir_cache: BTreeMap::new(),
+                    read_only,
                 };
                 Arc::new(Mutex::new(efs))
             })
     }
+    /// Whether the filesystem is mounted read-only
+    ///
+    /// 只读挂载时创建、链接、删除、改名、写入、截断和修改属主都会失败，
+    /// 目录项操作返回 `LinkError::ReadOnly`。
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    /// Export the data bitmap, one bit per data block (1 = allocated)
+    ///
+    /// 第 i 位对应数据区的第 i 个块，可用于可视化磁盘占用与碎片。
+    pub fn export_block_map(&self) -> Vec<u8> {
+        let data_area_blocks = get_block_cache(0, Arc::clone(&self.block_device))
+            .read()
+            .read(0, |super_block: &SuperBlock| super_block.data_area_blocks);
+        self.data_bitmap
+            .export(&self.block_device, data_area_blocks as usize)
+    }
     /// Get the root inode of the filesystem
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
         let block_device = Arc::clone(&efs.lock().block_device);
@@ -124,19 +169,27 @@ impl EasyFileSystem {
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
-    /// Allocate a new inode
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    /// Allocate a new inode, `None` when all inodes are in use
+    ///
+    /// inode 区按位图的全部位数划分，位图中的每一位都对应一个真实的 inode，不需要像数据块那样检查越界。
+    pub fn alloc_inode(&mut self) -> Option<u32> {
+        self.inode_bitmap.alloc(&self.block_device).map(|pos| pos as u32)
     }
 
-    /// Allocate a data block
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// Allocate a data block, `None` when the data area is full
+    pub fn alloc_data(&mut self) -> Option<u32> {
+        let pos = self.data_bitmap.alloc(&self.block_device)?;
+        if pos >= self.data_area_blocks as usize {
+            // 数据区已满，位图只是分配到了末尾多出来的位：撤销这次分配
+            self.data_bitmap.dealloc(&self.block_device, pos);
+            return None;
+        }
+        Some(pos as u32 + self.data_area_start_block)
     }
     /// Deallocate a data block
     pub fn dealloc_data(&mut self, block_id: u32) {
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
-            .lock()
+            .write()
             .modify(0, |data_block: &mut DataBlock| {
                 data_block.iter_mut().for_each(|p| {
                     *p = 0;
@@ -152,19 +205,88 @@ impl EasyFileSystem {
     pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
         let inode_size = core::mem::size_of::<DiskInode>();
         let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
-        let inode_id = (block_id - self.inode_area_start_block) * inodes_per_block
-            + (block_offset / inode_size) as u32;
-        inode_id
+        (block_id - self.inode_area_start_block) * inodes_per_block
+            + (block_offset / inode_size) as u32
     }
 
     /// Deallocate an inode
     pub fn dealloc_inode(&mut self, inode_id: u32) {
         let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
-            .lock()
+            .write()
             .modify(block_offset, |disk_inode: &mut DiskInode| {
                 disk_inode.initialize(DiskInodeType::File);
             });
+        // inode 编号会被重新分配，不能让新 inode 沿用旧目录的缓存
+        self.dir_cache.remove(&inode_id);
         self.inode_bitmap.dealloc(&self.block_device, inode_id as usize);
     }
+
+    /// Release all data blocks of an inode, then the inode itself
+    pub fn reclaim_inode(&mut self, inode_id: u32) {
+        let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+        let block_device = Arc::clone(&self.block_device);
+        let data_blocks_dealloc = get_block_cache(block_id as usize, Arc::clone(&block_device))
+            .write()
+            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                let size = disk_inode.size;
+                let blocks = disk_inode.clear_size(&block_device);
+                assert!(blocks.len() <= DiskInode::total_blocks(size) as usize);
+                blocks
+            });
+        for data_block in data_blocks_dealloc.into_iter() {
+            self.dealloc_data(data_block);
+        }
+        self.dealloc_inode(inode_id);
+    }
+
+    /// Record one more open file handle on an inode
+    pub fn open_inode(&mut self, inode_id: u32) {
+        *self.open_count.entry(inode_id).or_insert(0) += 1;
+    }
+
+    /// Drop one open file handle on an inode.
+    ///
+    /// 最后一个句柄关闭时，若该 inode 已被 unlink（孤儿），在此真正回收。
+    pub fn close_inode(&mut self, inode_id: u32) {
+        let Some(count) = self.open_count.get_mut(&inode_id) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.open_count.remove(&inode_id);
+            if self.orphans.remove(&inode_id) {
+                self.reclaim_inode(inode_id);
+                self.sync_blocks(&BTreeSet::new());
+            }
+        }
+    }
+
+    /// Write back the dirty cached metadata blocks (super block, bitmaps and inode area)
+    /// together with the data blocks listed in `blocks`
+    ///
+    /// 返回是否这些块都已写回。
+    pub(crate) fn sync_blocks(&self, blocks: &BTreeSet<u32>) -> bool {
+        let data_area_start = self.data_area_start_block as usize;
+        block_cache_sync(&self.block_device, |block_id| {
+            block_id < data_area_start || blocks.contains(&(block_id as u32))
+        })
+    }
+
+    /// Write back every dirty cached block of this filesystem, e.g. before shutdown
+    ///
+    /// 返回是否所有脏块都已写回；失败的块仍留在缓存中，下次同步时重试。
+    pub fn sync_all(&self) -> bool {
+        block_cache_sync(&self.block_device, |_| true)
+    }
+
+    /// Reclaim an inode whose last link was removed, or defer it until
+    /// the last open file handle is closed
+    pub fn release_unlinked(&mut self, inode_id: u32) {
+        if self.open_count.contains_key(&inode_id) {
+            self.orphans.insert(inode_id);
+        } else {
+            self.reclaim_inode(inode_id);
+        }
+    }
 }
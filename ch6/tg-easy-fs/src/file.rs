@@ -1,6 +1,6 @@
 use core::cell::Cell;
 
-use crate::Inode;
+use crate::{Inode, InodeStat};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -106,8 +106,21 @@ impl OpenFlags {
     }
 }
 
+/// Where [`FileHandle::seek`] counts the new offset from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// From the start of the file (`SEEK_SET`)
+    Start,
+    /// From the current offset (`SEEK_CUR`)
+    Current,
+    /// From the end of the file (`SEEK_END`)
+    End,
+}
+
 /// Cached file metadata in memory
-#[derive(Clone)]
+///
+/// 每个持有 inode 的句柄都计入该 inode 的打开计数（克隆也算一次），
+/// 句柄释放时计数减一，unlink 后的文件由最后一个句柄负责回收。
 pub struct FileHandle {
     /// FileSystem Inode
     pub inode: Option<Arc<Inode>>,
@@ -122,6 +135,7 @@ pub struct FileHandle {
 impl FileHandle {
     /// 创建一个新的文件句柄。
     pub fn new(read: bool, write: bool, inode: Arc<Inode>) -> Self {
+        inode.open();
         Self {
             inode: Some(inode),
             read,
@@ -170,15 +184,19 @@ impl FileHandle {
     }
 
     /// 将用户缓冲区数据写入文件。
+    ///
+    /// 文件系统空间不足时只写入能容纳的部分，返回实际写入的字节数。
     pub fn write(&self, buf: UserBuffer) -> isize {
         let mut total_write_size: usize = 0;
         if let Some(inode) = &self.inode {
-            // 连续写入每个分片，偏移随写入量前移。
+            // 连续写入每个分片，偏移随写入量前移；某个分片没写完说明空间已耗尽。
             for slice in buf.buffers.iter() {
                 let write_size = inode.write_at(self.offset.get(), slice);
-                assert_eq!(write_size, slice.len());
                 self.offset.set(self.offset.get() + write_size);
                 total_write_size += write_size;
+                if write_size < slice.len() {
+                    break;
+                }
             }
             total_write_size as _
         } else {
@@ -186,10 +204,48 @@ impl FileHandle {
         }
     }
 
-    /// 获取文件状态信息（inode ID 和硬链接数）。
-    pub fn get_stat_info(&self) -> Option<(u32, u32)> {
+    /// 获取文件状态信息（inode ID、硬链接数和时间戳）。
+    pub fn get_stat_info(&self) -> Option<InodeStat> {
         self.inode.as_ref().map(|inode| inode.get_stat_info())
     }
+
+    /// 移动读写偏移（lseek），返回新的绝对偏移。
+    ///
+    /// 没有 inode 的句柄（标准输入输出）与目录不能定位，结果为负或超过 `isize::MAX` 时
+    /// 同样返回 `None`，偏移保持不变。允许定位到文件末尾之后，之后的写入会在中间留下空洞。
+    pub fn seek(&self, offset: isize, from: SeekFrom) -> Option<usize> {
+        let inode = self.inode.as_ref().filter(|inode| !inode.is_dir())?;
+        let base = match from {
+            SeekFrom::Start => 0,
+            SeekFrom::Current => self.offset.get(),
+            SeekFrom::End => inode.size(),
+        };
+        let new_offset = base.checked_add_signed(offset).filter(|&pos| pos <= isize::MAX as usize)?;
+        self.offset.set(new_offset);
+        Some(new_offset)
+    }
+}
+
+impl Clone for FileHandle {
+    fn clone(&self) -> Self {
+        if let Some(inode) = &self.inode {
+            inode.open();
+        }
+        Self {
+            inode: self.inode.clone(),
+            read: self.read,
+            write: self.write,
+            offset: self.offset.clone(),
+        }
+    }
+}
+
+impl Drop for FileHandle {
+    fn drop(&mut self) {
+        if let Some(inode) = &self.inode {
+            inode.close();
+        }
+    }
 }
 
 /// 文件系统管理器 trait。
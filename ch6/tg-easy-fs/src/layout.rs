@@ -1,14 +1,18 @@
-use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use super::{get_block_cache, now, BlockDevice, BLOCK_SZ};
+use alloc::collections::BTreeSet;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
+use core::ops::Range;
 
-/// Magic number for sanity check
-const EFS_MAGIC: u32 = 0x3b800001;
-/// The max number of direct inodes
-const INODE_DIRECT_COUNT: usize = 28;
-/// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+/// Magic number for sanity check, bumped whenever the on-disk layout changes
+const EFS_MAGIC: u32 = 0x3b800005;
+/// The max number of direct inodes, chosen so that a `DiskInode` stays 128 bytes
+const INODE_DIRECT_COUNT: usize = 19;
+/// Bit in `DiskInode::mode` above the permission bits, set on directories whose entries are kept sorted by name
+const MODE_SORTED_DIR: u32 = 1 << 31;
+/// The max length of inode name, one byte of the 32-byte `DirEntry` goes to the cached inode type
+pub(crate) const NAME_LENGTH_LIMIT: usize = 26;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// The max number of indirect2 inodes
@@ -18,8 +22,9 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode indexs
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// The max size of a file in bytes: 19 direct + 128 indirect1 + 128 * 128 indirect2 blocks, about 8 MiB
+pub const MAX_FILE_SIZE: usize = INDIRECT2_BOUND * BLOCK_SZ;
 /// Super block of a filesystem
 #[repr(C)]
 pub struct SuperBlock {
@@ -68,10 +73,41 @@ impl SuperBlock {
     }
 }
 /// Type of a disk inode
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DiskInodeType {
+    /// Regular file
     File,
+    /// Directory, its data is an array of `DirEntry`
     Directory,
+    /// Named pipe, its data lives in a kernel buffer instead of on disk
+    Fifo,
+}
+
+/// `d_type` of a named pipe
+const DT_FIFO: u8 = 1;
+/// `d_type` of a directory
+const DT_DIR: u8 = 4;
+/// `d_type` of a regular file
+const DT_REG: u8 = 8;
+
+impl DiskInodeType {
+    /// The Linux `d_type` value of this type, which is also how `DirEntry` stores it
+    pub fn d_type(self) -> u8 {
+        match self {
+            Self::File => DT_REG,
+            Self::Directory => DT_DIR,
+            Self::Fifo => DT_FIFO,
+        }
+    }
+    /// Inverse of `d_type`, `None` for values easy-fs never writes
+    fn from_d_type(d_type: u8) -> Option<Self> {
+        match d_type {
+            DT_REG => Some(Self::File),
+            DT_DIR => Some(Self::Directory),
+            DT_FIFO => Some(Self::Fifo),
+            _ => None,
+        }
+    }
 }
 
 /// A indirect block
@@ -85,18 +121,45 @@ pub struct DiskInode {
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Owner user id
+    pub uid: u32,
+    /// Owner group id
+    pub gid: u32,
+    /// Permission bits (`0o777` style), plus the sorted-directory flag in the top bit
+    pub mode: u32,
     type_: DiskInodeType,
+    /// Number of directory entries naming this inode, `.` and `..` not counted;
+    /// it lives in the padding after `type_`
+    pub nlink: u16,
+    /// Creation time in nanoseconds
+    pub ctime: u64,
+    /// Last modification time in nanoseconds, updated whenever the data or the size changes
+    pub mtime: u64,
+    /// Last access time in nanoseconds; reads do not update it, as if mounted with `noatime`
+    pub atime: u64,
 }
 
+const _: () = assert!(core::mem::size_of::<DiskInode>() == 128);
+
 impl DiskInode {
     /// Initialize a disk inode, as well as all direct inodes under it
     /// indirect1 and indirect2 block are allocated only when they are needed
+    /// The inode is owned by root, with mode 0o755 for directories and 0o644 otherwise,
+    /// no directory entry names it yet, and all three timestamps are set to the current time
     pub fn initialize(&mut self, type_: DiskInodeType) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.uid = 0;
+        self.gid = 0;
+        self.mode = if type_ == DiskInodeType::Directory { 0o755 } else { 0o644 };
         self.type_ = type_;
+        self.nlink = 0;
+        let time = now();
+        self.ctime = time;
+        self.mtime = time;
+        self.atime = time;
     }
     /// Whether this inode is a directory
     pub fn is_dir(&self) -> bool {
@@ -107,6 +170,26 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether this inode is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
+    /// Type of this inode
+    pub fn inode_type(&self) -> DiskInodeType {
+        self.type_
+    }
+    /// Whether this is a directory whose entries are kept sorted by name
+    pub fn is_sorted_dir(&self) -> bool {
+        self.is_dir() && self.mode & MODE_SORTED_DIR != 0
+    }
+    /// Mark this directory as sorted; the caller must have sorted the entries already
+    pub fn set_sorted_dir(&mut self) {
+        self.mode |= MODE_SORTED_DIR;
+    }
+    /// Permission bits without the flags stored alongside them
+    pub fn permission(&self) -> u32 {
+        self.mode & !MODE_SORTED_DIR
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -135,40 +218,135 @@ impl DiskInode {
         assert!(new_size >= self.size);
         Self::total_blocks(new_size) - Self::total_blocks(self.size)
     }
-    /// Get id of block given inner id
+    /// Get id of block given inner id, 0 if the block is a hole of a sparse file
+    ///
+    /// `inner_id` must be below `MAX_FILE_SIZE / BLOCK_SZ`.
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
         let inner_id = inner_id as usize;
         if inner_id < INODE_DIRECT_COUNT {
             self.direct[inner_id]
         } else if inner_id < INDIRECT1_BOUND {
+            if self.indirect1 == 0 {
+                return 0;
+            }
             get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-                .lock()
+                .read()
                 .read(0, |indirect_block: &IndirectBlock| {
                     indirect_block[inner_id - INODE_DIRECT_COUNT]
                 })
         } else {
+            if self.indirect2 == 0 {
+                return 0;
+            }
             let last = inner_id - INDIRECT1_BOUND;
             let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-                .lock()
+                .read()
                 .read(0, |indirect2: &IndirectBlock| {
                     indirect2[last / INODE_INDIRECT1_COUNT]
                 });
+            if indirect1 == 0 {
+                return 0;
+            }
             get_block_cache(indirect1 as usize, Arc::clone(block_device))
-                .lock()
+                .read()
                 .read(0, |indirect1: &IndirectBlock| {
                     indirect1[last % INODE_INDIRECT1_COUNT]
                 })
         }
     }
+    /// Ids of the data blocks with inner ids in `inner_ids`, and of the index blocks leading to them
+    ///
+    /// Holes contribute nothing.
+    pub fn blocks_in(&self, inner_ids: Range<u32>, block_device: &Arc<dyn BlockDevice>) -> BTreeSet<u32> {
+        let mut blocks = BTreeSet::new();
+        let (start, end) = (inner_ids.start as usize, inner_ids.end as usize);
+        if start >= end {
+            return blocks;
+        }
+        if self.indirect1 != 0 && start < INDIRECT1_BOUND && end > DIRECT_BOUND {
+            blocks.insert(self.indirect1);
+        }
+        if self.indirect2 != 0 && end > INDIRECT1_BOUND {
+            blocks.insert(self.indirect2);
+            let first = start.saturating_sub(INDIRECT1_BOUND) / INODE_INDIRECT1_COUNT;
+            let last = (end - 1 - INDIRECT1_BOUND) / INODE_INDIRECT1_COUNT;
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .read()
+                .read(0, |indirect2: &IndirectBlock| {
+                    blocks.extend(indirect2[first..=last].iter().filter(|id| **id != 0));
+                });
+        }
+        blocks.extend(inner_ids.map(|id| self.get_block_id(id, block_device)).filter(|&id| id != 0));
+        blocks
+    }
+    /// Whether the last data block is a hole, i.e. the file was extended sparsely
+    ///
+    /// `increase_size` appends blocks right after the current last one, which is only
+    /// valid when that block and its index blocks are present.
+    pub fn has_tail_hole(&self, block_device: &Arc<dyn BlockDevice>) -> bool {
+        let data_blocks = self.data_blocks();
+        data_blocks > 0 && self.get_block_id(data_blocks - 1, block_device) == 0
+    }
+    /// Back data block `inner_id` with a disk block and return its id
+    ///
+    /// The data block and any missing index blocks on the way are taken from `alloc`.
+    /// Returns `None` once `alloc` runs out; index blocks mapped before that stay in
+    /// place and the data block remains a hole.
+    pub fn map_block(
+        &mut self,
+        inner_id: u32,
+        alloc: &mut dyn FnMut() -> Option<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Option<u32> {
+        let inner_id = inner_id as usize;
+        if inner_id < INODE_DIRECT_COUNT {
+            if self.direct[inner_id] == 0 {
+                self.direct[inner_id] = alloc()?;
+            }
+            return Some(self.direct[inner_id]);
+        }
+        if inner_id < INDIRECT1_BOUND {
+            if self.indirect1 == 0 {
+                self.indirect1 = alloc()?;
+            }
+            return Self::map_index_entry(self.indirect1, inner_id - INODE_DIRECT_COUNT, alloc, block_device);
+        }
+        if self.indirect2 == 0 {
+            self.indirect2 = alloc()?;
+        }
+        let last = inner_id - INDIRECT1_BOUND;
+        let indirect1 =
+            Self::map_index_entry(self.indirect2, last / INODE_INDIRECT1_COUNT, alloc, block_device)?;
+        Self::map_index_entry(indirect1, last % INODE_INDIRECT1_COUNT, alloc, block_device)
+    }
+    fn map_index_entry(
+        index_block: u32,
+        slot: usize,
+        alloc: &mut dyn FnMut() -> Option<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Option<u32> {
+        get_block_cache(index_block as usize, Arc::clone(block_device))
+            .write()
+            .modify(0, |index: &mut IndirectBlock| {
+                if index[slot] == 0 {
+                    index[slot] = alloc()?;
+                }
+                Some(index[slot])
+            })
+    }
     /// Inncrease the size of current disk inode
+    ///
+    /// `new_blocks` must hold `blocks_num_needed(new_size)` blocks, index blocks included.
     pub fn increase_size(
         &mut self,
         new_size: u32,
         new_blocks: Vec<u32>,
         block_device: &Arc<dyn BlockDevice>,
     ) {
+        assert!(new_size as usize <= MAX_FILE_SIZE);
         let mut current_blocks = self.data_blocks();
         self.size = new_size;
+        self.mtime = now();
         let mut total_blocks = self.data_blocks();
         let mut new_blocks = new_blocks.into_iter();
         // 阶段 1：填 direct 指针
@@ -188,7 +366,7 @@ impl DiskInode {
         }
         // 将剩余数据块号写入一级索引块
         get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
+            .write()
             .modify(0, |indirect1: &mut IndirectBlock| {
                 while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
                     indirect1[current_blocks as usize] = new_blocks.next().unwrap();
@@ -212,7 +390,7 @@ impl DiskInode {
         let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
         // alloc low-level indirect1
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
+            .write()
             .modify(0, |indirect2: &mut IndirectBlock| {
                 while (a0 < a1) || (a0 == a1 && b0 < b1) {
                     if b0 == 0 {
@@ -220,7 +398,7 @@ impl DiskInode {
                     }
                     // fill current
                     get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
+                        .write()
                         .modify(0, |indirect1: &mut IndirectBlock| {
                             indirect1[b0] = new_blocks.next().unwrap();
                         });
@@ -234,43 +412,126 @@ impl DiskInode {
             });
     }
 
+    /// Shrink current disk inode to `new_size` and return blocks that should be deallocated
+    ///
+    /// 释放新末尾之后的数据块，以及因此变空的索引块，并清零指向它们的表项；
+    /// `new_size` 落在块中间时，该块超出 size 的部分清零，之后再扩大文件时才能读出零。
+    pub fn decrease_size(&mut self, new_size: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        self.size = new_size;
+        self.mtime = now();
+        let data_blocks = self.data_blocks() as usize;
+        let tail = new_size as usize % BLOCK_SZ;
+        if tail != 0 {
+            let block_id = self.get_block_id(data_blocks as u32 - 1, block_device);
+            if block_id != 0 {
+                get_block_cache(block_id as usize, Arc::clone(block_device))
+                    .write()
+                    .modify(0, |data_block: &mut DataBlock| data_block[tail..].fill(0));
+            }
+        }
+        let mut v: Vec<u32> = Vec::new();
+        // direct
+        for entry in self.direct.iter_mut().skip(data_blocks).filter(|entry| **entry != 0) {
+            v.push(*entry);
+            *entry = 0;
+        }
+        // indirect1：新末尾之后的表项全部回收，整块不再需要时连同索引块一起回收
+        if self.indirect1 != 0 && data_blocks < INDIRECT1_BOUND {
+            let start = data_blocks.saturating_sub(DIRECT_BOUND);
+            Self::trim_index(self.indirect1, start, &mut v, block_device);
+            if start == 0 {
+                v.push(self.indirect1);
+                self.indirect1 = 0;
+            }
+        }
+        // indirect2：逐个处理其下的一级索引块
+        if self.indirect2 != 0 {
+            let start = data_blocks.saturating_sub(INDIRECT1_BOUND);
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .write()
+                .modify(0, |indirect2: &mut IndirectBlock| {
+                    let first = start / INODE_INDIRECT1_COUNT;
+                    for (a, entry) in indirect2.iter_mut().enumerate().skip(first) {
+                        if *entry == 0 {
+                            continue;
+                        }
+                        let start = start.saturating_sub(a * INODE_INDIRECT1_COUNT);
+                        Self::trim_index(*entry, start, &mut v, block_device);
+                        if start == 0 {
+                            v.push(*entry);
+                            *entry = 0;
+                        }
+                    }
+                });
+            if start == 0 {
+                v.push(self.indirect2);
+                self.indirect2 = 0;
+            }
+        }
+        v
+    }
+    /// Collect the non-zero entries of `index_block` from `start` on into `v` and clear them
+    fn trim_index(index_block: u32, start: usize, v: &mut Vec<u32>, block_device: &Arc<dyn BlockDevice>) {
+        if start >= INODE_INDIRECT1_COUNT {
+            return;
+        }
+        get_block_cache(index_block as usize, Arc::clone(block_device))
+            .write()
+            .modify(0, |index: &mut IndirectBlock| {
+                for entry in index[start..].iter_mut().filter(|entry| **entry != 0) {
+                    v.push(*entry);
+                    *entry = 0;
+                }
+            });
+    }
+
     /// Clear size to zero and return blocks that should be deallocated.
     /// We will clear the block contents to zero later.
+    /// Holes of a sparse file own no block and are skipped.
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
         let mut v: Vec<u32> = Vec::new();
         let mut data_blocks = self.data_blocks() as usize;
         self.size = 0;
+        self.mtime = now();
         let mut current_blocks = 0usize;
         // 回收 direct
         while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
+            if self.direct[current_blocks] != 0 {
+                v.push(self.direct[current_blocks]);
+            }
             self.direct[current_blocks] = 0;
             current_blocks += 1;
         }
         // 回收一级索引块及其指向的数据块
         if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
             data_blocks -= INODE_DIRECT_COUNT;
             current_blocks = 0;
         } else {
             return v;
         }
         // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
-                }
-            });
+        if self.indirect1 != 0 {
+            v.push(self.indirect1);
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .write()
+                .modify(0, |indirect1: &mut IndirectBlock| {
+                    while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
+                        if indirect1[current_blocks] != 0 {
+                            v.push(indirect1[current_blocks]);
+                        }
+                        //indirect1[current_blocks] = 0;
+                        current_blocks += 1;
+                    }
+                });
+        }
         self.indirect1 = 0;
         // 回收二级索引块及其下级索引/数据块
-        if data_blocks > INODE_INDIRECT1_COUNT {
+        if data_blocks > INODE_INDIRECT1_COUNT && self.indirect2 != 0 {
             v.push(self.indirect2);
             data_blocks -= INODE_INDIRECT1_COUNT;
         } else {
+            self.indirect2 = 0;
             return v;
         }
         // indirect2
@@ -278,28 +539,24 @@ impl DiskInode {
         let a1 = data_blocks / INODE_INDIRECT1_COUNT;
         let b1 = data_blocks % INODE_INDIRECT1_COUNT;
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
+            .write()
             .modify(0, |indirect2: &mut IndirectBlock| {
                 // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
+                for entry in indirect2.iter_mut().take(a1).filter(|entry| **entry != 0) {
                     v.push(*entry);
                     get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
+                        .write()
                         .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
+                            v.extend(indirect1.iter().filter(|entry| **entry != 0));
                         });
                 }
                 // last indirect1 block
-                if b1 > 0 {
+                if b1 > 0 && indirect2[a1] != 0 {
                     v.push(indirect2[a1]);
                     get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
+                        .write()
                         .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
+                            v.extend(indirect1.iter().take(b1).filter(|entry| **entry != 0));
                         });
                     //indirect2[a1] = 0;
                 }
@@ -328,15 +585,18 @@ impl DiskInode {
             // read and update read size
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .read(0, |data_block: &DataBlock| {
-                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                dst.copy_from_slice(src);
-            });
+            let block_id = self.get_block_id(start_block as u32, block_device);
+            if block_id == 0 {
+                // 稀疏文件的空洞读出全零
+                dst.fill(0);
+            } else {
+                get_block_cache(block_id as usize, Arc::clone(block_device))
+                    .read()
+                    .read(0, |data_block: &DataBlock| {
+                        let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                        dst.copy_from_slice(src);
+                    });
+            }
             read_size += block_read_size;
             // move to next block
             if end_current_block == end {
@@ -348,7 +608,7 @@ impl DiskInode {
         read_size
     }
     /// Write data into current disk inode
-    /// size must be adjusted properly beforehand
+    /// size must be adjusted properly and holes in the range mapped beforehand
     pub fn write_at(
         &mut self,
         offset: usize,
@@ -358,6 +618,7 @@ impl DiskInode {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         assert!(start <= end);
+        self.mtime = now();
         let mut start_block = start / BLOCK_SZ;
         let mut write_size = 0usize;
         loop {
@@ -370,7 +631,7 @@ impl DiskInode {
                 self.get_block_id(start_block as u32, block_device) as usize,
                 Arc::clone(block_device),
             )
-            .lock()
+            .write()
             .modify(0, |data_block: &mut DataBlock| {
                 let src = &buf[write_size..write_size + block_write_size];
                 let dst = &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
@@ -388,28 +649,38 @@ impl DiskInode {
     }
 }
 /// A directory entry
+///
+/// 目录项里缓存了所指 inode 的类型，列目录时不必再逐个读取子 inode。
+/// inode 的类型在它被回收之前不会改变，而回收前指向它的目录项都已删除，
+/// 因此链接数的增减不影响这里缓存的类型。
 #[repr(C)]
 pub struct DirEntry {
     name: [u8; NAME_LENGTH_LIMIT + 1],
+    /// Type of the inode, as a Linux `d_type` value
+    d_type: u8,
     inode_number: u32,
 }
 /// Size of a directory entry
 pub const DIRENT_SZ: usize = 32;
 
+const _: () = assert!(core::mem::size_of::<DirEntry>() == DIRENT_SZ);
+
 impl DirEntry {
     /// Create an empty directory entry
     pub fn empty() -> Self {
         Self {
             name: [0u8; NAME_LENGTH_LIMIT + 1],
+            d_type: 0,
             inode_number: 0,
         }
     }
-    /// Crate a directory entry from name and inode number
-    pub fn new(name: &str, inode_number: u32) -> Self {
+    /// Crate a directory entry from name, inode number and the type of that inode
+    pub fn new(name: &str, inode_number: u32, type_: DiskInodeType) -> Self {
         let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
         bytes[..name.len()].copy_from_slice(name.as_bytes());
         Self {
             name: bytes,
+            d_type: type_.d_type(),
             inode_number,
         }
     }
@@ -431,12 +702,18 @@ impl DirEntry {
         unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
     }
     /// Get name of the entry
-    pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+    ///
+    /// 名字缺少结尾的 0 或不是合法 UTF-8 时说明目录项已损坏，返回 `None`。
+    pub fn name(&self) -> Option<&str> {
+        let len = self.name.iter().position(|&b| b == 0)?;
+        core::str::from_utf8(&self.name[..len]).ok()
     }
     /// Get inode number of the entry
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
+    /// Get the cached type of the inode, `None` if the entry is corrupted
+    pub fn inode_type(&self) -> Option<DiskInodeType> {
+        DiskInodeType::from_d_type(self.d_type)
+    }
 }
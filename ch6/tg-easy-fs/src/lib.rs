@@ -15,18 +15,29 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod clock;
 mod efs;
 mod file;
 mod layout;
+mod overlay;
 mod pipe;
 mod vfs;
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_cache::{
+    block_cache_hits, block_cache_misses, block_cache_set_capacity, block_cache_sync_all, block_cache_write_failures,
+};
+use block_cache::{block_cache_sync, get_block_cache, readahead_max_blocks};
 pub use block_dev::BlockDevice;
+use clock::now;
+pub use clock::set_clock;
 pub use efs::EasyFileSystem;
 pub use file::*;
 use layout::*;
-pub use pipe::{make_pipe, PipeReader, PipeWriter};
-pub use vfs::Inode;
+pub use layout::{DiskInodeType, MAX_FILE_SIZE};
+pub use overlay::OverlayBlockDevice;
+pub use pipe::{
+    make_pipe, make_pipe_with_capacity, PipeReader, PipeWriter, WeakPipeReader, EAGAIN, EPIPE, MAX_PIPE_CAPACITY,
+};
+pub use vfs::{Inode, InodeStat, LinkError};
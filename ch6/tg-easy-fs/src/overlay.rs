@@ -0,0 +1,120 @@
+use super::{BlockDevice, BLOCK_SZ};
+use alloc::{collections::BTreeMap, sync::Arc};
+use spin::Mutex;
+
+// 教程说明：
+// 叠加块设备把一个只读的基础镜像和一个可写的 overlay 设备组合成一个块设备。
+// 块第一次被写入时分配一个 overlay 块并记入重定向表，此后对该块的读写都走 overlay，
+// 基础镜像始终保持不变。重定向表只保存在内存中，卸载后 overlay 层即被丢弃，
+// 相当于在基础镜像上做了一次临时快照。
+
+/// Copy-on-write block device layered on top of a read-only base image
+pub struct OverlayBlockDevice {
+    base: Arc<dyn BlockDevice>,
+    overlay: Arc<dyn BlockDevice>,
+    /// Number of blocks available on the overlay device
+    overlay_blocks: usize,
+    /// Base block id -> overlay block id, overlay blocks are handed out in order
+    redirect: Mutex<BTreeMap<usize, usize>>,
+}
+
+impl OverlayBlockDevice {
+    /// Layer `overlay` (holding `overlay_blocks` blocks) on top of `base`
+    pub fn new(base: Arc<dyn BlockDevice>, overlay: Arc<dyn BlockDevice>, overlay_blocks: usize) -> Self {
+        Self {
+            base,
+            overlay,
+            overlay_blocks,
+            redirect: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Number of blocks that have been copied to the overlay
+    pub fn copied_blocks(&self) -> usize {
+        self.redirect.lock().len()
+    }
+}
+
+impl BlockDevice for OverlayBlockDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        match self.redirect.lock().get(&block_id) {
+            Some(&overlay_id) => self.overlay.read_block(overlay_id, buf),
+            None => self.base.read_block(block_id, buf),
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert!(self.try_write_block(block_id, buf), "overlay device is full");
+    }
+
+    /// The whole block is replaced by `buf`, so there is nothing to copy from the base image
+    ///
+    /// overlay 块用完时返回 `false`，块缓存把该块留作脏块，而不是让内核 panic。
+    fn try_write_block(&self, block_id: usize, buf: &[u8]) -> bool {
+        assert_eq!(buf.len(), BLOCK_SZ);
+        let mut redirect = self.redirect.lock();
+        let overlay_id = match redirect.get(&block_id) {
+            Some(&overlay_id) => overlay_id,
+            None if redirect.len() < self.overlay_blocks => {
+                let next = redirect.len();
+                redirect.insert(block_id, next);
+                next
+            }
+            None => return false,
+        };
+        self.overlay.try_write_block(overlay_id, buf)
+    }
+
+    fn flush(&self) {
+        self.overlay.flush();
+    }
+
+    /// Writes only ever reach the overlay, so a read-only base image does not matter
+    fn is_read_only(&self) -> bool {
+        self.overlay.is_read_only()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::tests::{lock_block_cache, RamDisk};
+    use crate::EasyFileSystem;
+
+    #[test]
+    fn writes_stay_in_the_overlay() {
+        let _cache = lock_block_cache();
+        let base = RamDisk::new(4096);
+        let base_fs = EasyFileSystem::create(base.clone(), 4096, 1);
+        let root = EasyFileSystem::root_inode(&base_fs);
+        assert_eq!(root.create("f").unwrap().write_at(0, b"base"), 4);
+        assert!(base_fs.lock().sync_all());
+        let image = base.0.lock().clone();
+
+        let device = Arc::new(OverlayBlockDevice::new(base.clone(), RamDisk::new(64), 64));
+        let efs = EasyFileSystem::open(device.clone());
+        let file = EasyFileSystem::root_inode(&efs).find("f").unwrap();
+        assert_eq!(file.write_at(0, b"over"), 4);
+        assert!(efs.lock().sync_all());
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read_at(0, &mut buf), 4);
+        assert_eq!(&buf, b"over");
+        assert!(device.copied_blocks() > 0);
+        // 基础镜像一个字节都没有变，重新打开它看到的仍是原来的内容
+        assert!(*base.0.lock() == image);
+        let reopened = EasyFileSystem::root_inode(&EasyFileSystem::open(base)).find("f").unwrap();
+        assert_eq!(reopened.read_at(0, &mut buf), 4);
+        assert_eq!(&buf, b"base");
+    }
+
+    #[test]
+    fn full_overlay_reports_write_failure() {
+        let device = OverlayBlockDevice::new(RamDisk::new(8), RamDisk::new(1), 1);
+        let block = [1u8; BLOCK_SZ];
+        assert!(device.try_write_block(3, &block));
+        assert!(!device.try_write_block(5, &block));
+        // 已经重定向的块仍可重写
+        assert!(device.try_write_block(3, &block));
+        assert_eq!(device.copied_blocks(), 1);
+    }
+}
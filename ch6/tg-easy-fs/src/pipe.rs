@@ -1,12 +1,25 @@
 use crate::file::UserBuffer;
-use alloc::sync::{Arc, Weak};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+    vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::Mutex;
 
 // 教程阅读建议：
-// - 先看 `PipeRingBuffer`：理解固定大小环形缓冲区；
-// - 再看 `PipeReader::read` / `PipeWriter::write` 的返回值语义（>0 / 0 / -2）。
+// - 先看 `PipeRingBuffer`：理解容量为 2 的幂的环形缓冲区；
+// - 再看 `PipeReader::read` / `PipeWriter::write` 的返回值语义（>0 / 0 / -2 / -11 / -32）。
 
+/// `make_pipe` 创建的管道的缓冲区容量
 const RING_BUFFER_SIZE: usize = 32;
+/// `make_pipe_with_capacity` 允许的最大容量（与 Linux 默认的 pipe-max-size 相同）
+pub const MAX_PIPE_CAPACITY: usize = 1 << 20;
+
+/// 非阻塞模式下管道暂时不可读写时的返回值（同 Linux 的 `-EAGAIN`）
+pub const EAGAIN: isize = -11;
+/// 所有读端都已关闭时写管道的返回值（同 Linux 的 `-EPIPE`），内核据此投递 SIGPIPE
+pub const EPIPE: isize = -32;
 
 /// 管道环形缓冲区状态
 #[derive(Copy, Clone, PartialEq)]
@@ -20,23 +33,30 @@ enum RingBufferStatus {
 }
 
 /// 管道环形缓冲区
+///
+/// 容量是 2 的幂，下标回绕用按位与代替取模。
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Box<[u8]>,
     head: usize,
     tail: usize,
     status: RingBufferStatus,
+    /// 写端：所有写端共享一个 `Arc<PipeWriter>`，弱引用失效即写端全部关闭
     write_end: Option<Weak<PipeWriter>>,
+    /// 存活的读端（`PipeReader`）个数，由读端的 `Clone`/`Drop` 维护
+    readers: usize,
 }
 
 impl PipeRingBuffer {
-    /// 创建一个管道环形缓冲区
-    pub fn new() -> Self {
+    /// 创建一个容量为 `capacity` 的管道环形缓冲区，`capacity` 必须是 2 的幂
+    pub fn with_capacity(capacity: usize) -> Self {
+        debug_assert!(capacity.is_power_of_two());
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; capacity].into_boxed_slice(),
             head: 0,
             tail: 0,
             status: RingBufferStatus::Empty,
             write_end: None,
+            readers: 0,
         }
     }
 
@@ -49,7 +69,7 @@ impl PipeRingBuffer {
     fn write_byte(&mut self, byte: u8) {
         self.status = RingBufferStatus::Normal;
         self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
+        self.tail = (self.tail + 1) & (self.capacity() - 1);
         if self.tail == self.head {
             self.status = RingBufferStatus::Full;
         }
@@ -59,7 +79,7 @@ impl PipeRingBuffer {
     fn read_byte(&mut self) -> u8 {
         self.status = RingBufferStatus::Normal;
         let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.head = (self.head + 1) & (self.capacity() - 1);
         if self.head == self.tail {
             self.status = RingBufferStatus::Empty;
         }
@@ -74,7 +94,7 @@ impl PipeRingBuffer {
         } else if self.tail > self.head {
             self.tail - self.head
         } else {
-            self.tail + RING_BUFFER_SIZE - self.head
+            self.tail + self.capacity() - self.head
         }
     }
 
@@ -83,10 +103,15 @@ impl PipeRingBuffer {
         if self.status == RingBufferStatus::Full {
             0
         } else {
-            RING_BUFFER_SIZE - self.available_read()
+            self.capacity() - self.available_read()
         }
     }
 
+    /// 缓冲区容量
+    fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+
     /// 所有写端是否都已关闭
     fn all_write_ends_closed(&self) -> bool {
         // `Weak` 升级失败表示最后一个写端 Arc 已被释放。
@@ -95,23 +120,74 @@ impl PipeRingBuffer {
 }
 
 /// 管道读端
-#[derive(Clone)]
+///
+/// 克隆出的读端（fork、dup）共享非阻塞标志，与 Linux 中同一个打开文件描述共享 `O_NONBLOCK` 一致。
+/// 每个读端都计入缓冲区的读端数，最后一个读端释放后写管道返回 [`EPIPE`]。
 pub struct PipeReader {
     buffer: Arc<Mutex<PipeRingBuffer>>,
+    nonblocking: Arc<AtomicBool>,
+}
+
+impl Clone for PipeReader {
+    fn clone(&self) -> Self {
+        self.buffer.lock().readers += 1;
+        Self {
+            buffer: self.buffer.clone(),
+            nonblocking: self.nonblocking.clone(),
+        }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.buffer.lock().readers -= 1;
+    }
+}
+
+/// 不计入读端数的读端弱引用
+///
+/// 供内核登记命名管道的缓冲区：自身不让缓冲区存活，也不妨碍写端发现读端已全部关闭。
+pub struct WeakPipeReader {
+    buffer: Weak<Mutex<PipeRingBuffer>>,
+}
+
+impl WeakPipeReader {
+    /// 缓冲区仍被某个读端或写端引用时，创建一个新的读端（非阻塞标志独立，默认阻塞）
+    pub fn upgrade(&self) -> Option<PipeReader> {
+        self.buffer.upgrade().map(PipeReader::new)
+    }
 }
 
 /// 管道写端
 pub struct PipeWriter {
     buffer: Arc<Mutex<PipeRingBuffer>>,
+    nonblocking: AtomicBool,
 }
 
 impl PipeReader {
+    /// 创建共享 `buffer` 的读端，默认为阻塞模式
+    fn new(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
+        buffer.lock().readers += 1;
+        Self {
+            buffer,
+            nonblocking: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 创建不计入读端数的弱引用
+    pub fn downgrade(&self) -> WeakPipeReader {
+        WeakPipeReader {
+            buffer: Arc::downgrade(&self.buffer),
+        }
+    }
+
     /// 从管道读取数据到用户缓冲区。
     ///
     /// 返回值：
     /// - `> 0`: 实际读取的字节数
     /// - `0`: 写端已关闭且无数据可读（EOF）
     /// - `-2`: 当前无数据可读但写端未关闭（需等待）
+    /// - `-11`（[`EAGAIN`]）: 同上，但读端处于非阻塞模式，调用方不应等待
     pub fn read(&self, buf: UserBuffer) -> isize {
         let want_to_read = buf.len();
         let mut buf_iter = buf.into_iter();
@@ -123,7 +199,7 @@ impl PipeReader {
             if ring_buffer.all_write_ends_closed() {
                 return 0; // EOF
             }
-            return -2; // 需等待
+            return if self.is_nonblocking() { EAGAIN } else { -2 }; // 需等待
         }
         // 读取尽可能多的数据
         for _ in 0..loop_read {
@@ -142,22 +218,64 @@ impl PipeReader {
         // 缓冲区数据读完但还没满足需求，返回已读取的字节数
         already_read as _
     }
+
+    /// 当前缓冲区中可读取的字节数（供 fstat 等查询使用）
+    pub fn available_read(&self) -> usize {
+        self.buffer.lock().available_read()
+    }
+
+    /// 是否所有写端都已关闭（此时缓冲区读空即为 EOF）
+    pub fn write_ends_closed(&self) -> bool {
+        self.buffer.lock().all_write_ends_closed()
+    }
+
+    /// 设置非阻塞模式：开启后无数据可读时 `read` 返回 [`EAGAIN`] 而不是 -2
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+
+    /// 是否处于非阻塞模式
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+
+    /// 为同一个缓冲区创建新的写端
+    ///
+    /// 命名管道的写端全部关闭后又被以写方式打开时使用，此后读端按新写端判断 EOF。
+    pub fn new_writer(&self) -> Arc<PipeWriter> {
+        let write_end = Arc::new(PipeWriter::new(self.buffer.clone()));
+        self.buffer.lock().set_write_end(&write_end);
+        write_end
+    }
 }
 
 impl PipeWriter {
+    /// 创建共享 `buffer` 的写端，默认为阻塞模式
+    fn new(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
+        Self {
+            buffer,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
     /// 将用户缓冲区数据写入管道。
     ///
     /// 返回值：
     /// - `> 0`: 实际写入的字节数
     /// - `-2`: 当前无空间可写（需等待）
+    /// - `-11`（[`EAGAIN`]）: 同上，但写端处于非阻塞模式，调用方不应等待
+    /// - `-32`（[`EPIPE`]）: 所有读端都已关闭，数据不会再被读取
     pub fn write(&self, buf: UserBuffer) -> isize {
         let want_to_write = buf.len();
         let mut buf_iter = buf.into_iter();
         let mut already_write = 0usize;
         let mut ring_buffer = self.buffer.lock();
+        if ring_buffer.readers == 0 {
+            return EPIPE;
+        }
         let loop_write = ring_buffer.available_write();
         if loop_write == 0 {
-            return -2; // 缓冲区满，需等待
+            return if self.is_nonblocking() { EAGAIN } else { -2 }; // 缓冲区满，需等待
         }
         // 写入尽可能多的数据
         for _ in 0..loop_write {
@@ -174,18 +292,88 @@ impl PipeWriter {
         // 缓冲区写满但还没写完，返回已写入的字节数
         already_write as _
     }
+
+    /// 当前缓冲区中剩余可写入的字节数（供 fstat 等查询使用）
+    pub fn available_write(&self) -> usize {
+        self.buffer.lock().available_write()
+    }
+
+    /// 设置非阻塞模式：开启后缓冲区满时 `write` 返回 [`EAGAIN`] 而不是 -2
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+
+    /// 是否处于非阻塞模式
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Relaxed)
+    }
+
+    /// 是否所有读端都已关闭（此时写入返回 [`EPIPE`]）
+    pub fn read_ends_closed(&self) -> bool {
+        self.buffer.lock().readers == 0
+    }
 }
 
-/// 创建一个管道，返回读端和写端
+/// 创建一个默认容量（32 字节）的管道，返回读端和写端
 pub fn make_pipe() -> (PipeReader, Arc<PipeWriter>) {
+    make_pipe_with_capacity(RING_BUFFER_SIZE)
+}
+
+/// 创建一个缓冲区至少能容纳 `bytes` 字节的管道，返回读端和写端
+///
+/// 容量向上取整到 2 的幂，最小 1 字节，最大 [`MAX_PIPE_CAPACITY`]。
+/// 大块传输时用较大的容量可以减少写端因缓冲区满而等待的次数。
+pub fn make_pipe_with_capacity(bytes: usize) -> (PipeReader, Arc<PipeWriter>) {
+    let capacity = bytes.clamp(1, MAX_PIPE_CAPACITY).next_power_of_two();
     // 读端和写端共享同一个环形缓冲区对象。
-    let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
-    let read_end = PipeReader {
-        buffer: buffer.clone(),
-    };
-    let write_end = Arc::new(PipeWriter {
-        buffer: buffer.clone(),
-    });
+    let buffer = Arc::new(Mutex::new(PipeRingBuffer::with_capacity(capacity)));
+    let read_end = PipeReader::new(buffer.clone());
+    let write_end = Arc::new(PipeWriter::new(buffer.clone()));
     buffer.lock().set_write_end(&write_end);
     (read_end, write_end)
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// 把一段堆内存包装成 `UserBuffer`（测试中泄漏即可）
+    fn user_buffer(data: Vec<u8>) -> UserBuffer {
+        UserBuffer::new(alloc::vec![Vec::leak(data)])
+    }
+
+    #[test]
+    fn capacity_rounds_up_and_clamps() {
+        assert_eq!(make_pipe().1.available_write(), RING_BUFFER_SIZE);
+        assert_eq!(make_pipe_with_capacity(100).1.available_write(), 128);
+        assert_eq!(make_pipe_with_capacity(0).1.available_write(), 1);
+        assert_eq!(
+            make_pipe_with_capacity(usize::MAX).1.available_write(),
+            MAX_PIPE_CAPACITY
+        );
+    }
+
+    #[test]
+    fn large_pipe_round_trip() {
+        let (reader, writer) = make_pipe_with_capacity(4096);
+        let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        // 超出容量的部分写不进去，缓冲区满后返回 -2
+        assert_eq!(writer.write(user_buffer(data.clone())), 4096);
+        assert_eq!(writer.available_write(), 0);
+        assert_eq!(writer.write(user_buffer(alloc::vec![0])), -2);
+
+        let out = Vec::leak(alloc::vec![0u8; 5000]);
+        let ptr = out.as_ptr();
+        assert_eq!(reader.read(UserBuffer::new(alloc::vec![out])), 4096);
+        let out = unsafe { core::slice::from_raw_parts(ptr, 4096) };
+        assert_eq!(out, &data[..4096]);
+        assert_eq!(reader.available_read(), 0);
+
+        // 写端全部关闭后读空即为 EOF
+        drop(writer);
+        assert_eq!(reader.read(user_buffer(alloc::vec![0; 8])), 0);
+    }
+}
@@ -1,11 +1,55 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    get_block_cache, readahead_max_blocks, BlockDevice, DirEntry, DiskInode, DiskInodeType,
+    EasyFileSystem, BLOCK_SZ, DIRENT_SZ, MAX_FILE_SIZE, NAME_LENGTH_LIMIT,
 };
+use alloc::collections::{btree_map::Entry, BTreeMap, BTreeSet};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::ops::Range;
 use spin::{Mutex, MutexGuard};
+/// Why a directory entry operation (`create` / `mkdir` / `link` / `unlink` / `rmdir` / `rename` / `readdir` / `dirent_at`) failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkError {
+    /// The name does not exist
+    NotFound,
+    /// The new name already exists
+    AlreadyExists,
+    /// The target inode is a directory
+    IsDirectory,
+    /// Hard linking a directory, or linking under (`link`) or removing (`rmdir`) or renaming (`rename`) the reserved name `.` or `..`
+    NotPermitted,
+    /// The target inode is not a directory (`rmdir` / `rename` / `dirent_at`)
+    NotDirectory,
+    /// The directory still has entries other than `.` and `..` (`rmdir`)
+    NotEmpty,
+    /// The directory entries are corrupted
+    Corrupted,
+    /// No free inode or data block is left (`create` / `mkdir` / `link`)
+    NoSpace,
+    /// The new name is empty, contains `/` or exceeds `NAME_LENGTH_LIMIT` (`create` / `mkdir` / `link` / `rename`)
+    InvalidName,
+    /// The filesystem is mounted read-only
+    ReadOnly,
+    /// The target inode already has `u16::MAX` links (`link`)
+    TooManyLinks,
+}
+
+/// What `Inode::get_stat_info` reports about an inode, timestamps are in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InodeStat {
+    /// Inode number
+    pub ino: u32,
+    /// Number of hard links
+    pub nlink: u32,
+    /// Creation time
+    pub ctime: u64,
+    /// Last modification time
+    pub mtime: u64,
+    /// Last access time
+    pub atime: u64,
+}
+
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
     block_id: usize,
@@ -14,6 +58,11 @@ pub struct Inode {
     block_device: Arc<dyn BlockDevice>,
 }
 
+/// Inner ids of the blocks covering `[offset, offset + len)`
+fn block_range(offset: usize, len: usize) -> Range<u32> {
+    (offset / BLOCK_SZ) as u32..(offset + len).div_ceil(BLOCK_SZ) as u32
+}
+
 impl Inode {
     /// Create a vfs inode
     pub fn new(
@@ -33,41 +82,193 @@ impl Inode {
     /// Call a function over a disk inode to read it
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
-            .lock()
+            .read()
             .read(self.block_offset, f)
     }
 
     /// Call a function over a disk inode to modify it
     fn modify_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
-            .lock()
+            .write()
             .modify(self.block_offset, f)
     }
 
+    /// Read the `index`-th directory entry of a directory disk inode
+    ///
+    /// 读到的长度不足一个目录项或名字损坏时返回 `Err`，而不是 panic。
+    fn read_dirent(&self, disk_inode: &DiskInode, index: usize) -> Result<DirEntry, ()> {
+        let mut dirent = DirEntry::empty();
+        let len = disk_inode.read_at(index * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+        if len != DIRENT_SZ || dirent.name().is_none() {
+            return Err(());
+        }
+        Ok(dirent)
+    }
+
     /// Find inode under a disk inode by name
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        // assert it is a directory
-        assert!(disk_inode.is_dir());
+    ///
+    /// 当前 inode 不是目录或目录项损坏时返回 `Err`。
+    /// 查找走 `fs` 中的目录名缓存，只有目录第一次被查找时才读目录项。
+    fn find_inode_id(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+        fs: &mut EasyFileSystem,
+    ) -> Result<Option<u32>, ()> {
+        Ok(self.cached_entries(disk_inode, fs)?.get(name).copied())
+    }
+
+    /// The name -> inode id map of this directory from the name cache, loaded on first use
+    ///
+    /// `disk_inode` 必须是当前 inode 自身：在 `modify_disk_inode` 的闭包中也能调用，不会重复加锁。
+    fn cached_entries<'a>(
+        &self,
+        disk_inode: &DiskInode,
+        fs: &'a mut EasyFileSystem,
+    ) -> Result<&'a BTreeMap<String, u32>, ()> {
+        if !disk_inode.is_dir() {
+            return Err(());
+        }
+        let dir_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        let entries = match fs.dir_cache.entry(dir_id) {
+            Entry::Occupied(entries) => entries.into_mut(),
+            Entry::Vacant(slot) => {
+                let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+                slot.insert(
+                    (0..file_count)
+                        .map(|i| {
+                            let dirent = self.read_dirent(disk_inode, i)?;
+                            Ok((String::from(dirent.name().ok_or(())?), dirent.inode_number()))
+                        })
+                        .collect::<Result<BTreeMap<_, _>, ()>>()?,
+                )
+            }
+        };
+        Ok(entries)
+    }
+
+    /// Apply `f` to this directory's entry map in the name cache, if it has been loaded
+    ///
+    /// 修改目录项的操作都经过这里，让缓存与磁盘保持一致；尚未缓存的目录不受影响。
+    fn update_cache(&self, fs: &mut EasyFileSystem, f: impl FnOnce(&mut BTreeMap<String, u32>)) {
+        let dir_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        if let Some(entries) = fs.dir_cache.get_mut(&dir_id) {
+            f(entries);
+        }
+    }
+
+    /// Drop this directory's entry map from the name cache
+    ///
+    /// `write_at`、`truncate`、`clear` 等直接改写 inode 内容的操作绕过了目录项接口，
+    /// 无法逐项更新缓存，只能整体丢弃，下次查找时重新读目录项；对普通文件没有影响。
+    fn invalidate_cache(&self, fs: &mut EasyFileSystem) {
+        let dir_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        fs.dir_cache.remove(&dir_id);
+    }
+
+    /// Index of the first entry of a sorted directory whose name is not less than `name`
+    fn lower_bound(&self, name: &str, disk_inode: &DiskInode) -> Result<usize, ()> {
+        let (mut lo, mut hi) = (0, (disk_inode.size as usize) / DIRENT_SZ);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let dirent = self.read_dirent(disk_inode, mid)?;
+            if dirent.name().ok_or(())? < name {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Add a dirent to a directory disk inode
+    ///
+    /// 普通目录直接追加到末尾；有序目录先二分找到插入位置，
+    /// 再把其后的目录项整体后移一格，插入代价是 O(n) 次目录项搬移。
+    /// 目录项损坏（`Corrupted`）或数据块不足（`NoSpace`）时返回 `Err`，目录保持不变。
+    fn insert_dirent(
+        &self,
+        dirent: &DirEntry,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Result<(), LinkError> {
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
-            );
-            if dirent.name() == name {
-                return Some(dirent.inode_number());
+        let name = dirent.name().ok_or(LinkError::Corrupted)?;
+        let index = if disk_inode.is_sorted_dir() {
+            self.lower_bound(name, disk_inode).map_err(|_| LinkError::Corrupted)?
+        } else {
+            file_count
+        };
+        self.increase_size(((file_count + 1) * DIRENT_SZ) as u32, disk_inode, fs)
+            .map_err(|_| LinkError::NoSpace)?;
+        // 从尾部开始后移，避免覆盖尚未搬走的目录项
+        for i in (index..file_count).rev() {
+            let moved = self.read_dirent(disk_inode, i).map_err(|_| LinkError::Corrupted)?;
+            disk_inode.write_at((i + 1) * DIRENT_SZ, moved.as_bytes(), &self.block_device);
+        }
+        disk_inode.write_at(index * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+        let name = String::from(name);
+        self.update_cache(fs, |entries| {
+            entries.insert(name, dirent.inode_number());
+        });
+        Ok(())
+    }
+
+    /// Keep the entries of this directory sorted by name from now on
+    ///
+    /// Existing entries are sorted once; afterwards `create` and `link` insert new
+    /// entries in place, so `readdir` lists them by name. Returns `false` if this is not
+    /// a directory or its entries are corrupted.
+    pub fn set_sorted(&self) -> bool {
+        let fs = self.fs.lock();
+        if fs.is_read_only() {
+            return false;
+        }
+        let done = self.modify_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return false;
+            }
+            if disk_inode.is_sorted_dir() {
+                return true;
+            }
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            let Ok(mut dirents) = (0..file_count)
+                .map(|i| self.read_dirent(disk_inode, i))
+                .collect::<Result<Vec<_>, _>>()
+            else {
+                return false;
+            };
+            dirents.sort_unstable_by(|a, b| a.name().cmp(&b.name()));
+            for (i, dirent) in dirents.iter().enumerate() {
+                disk_inode.write_at(i * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
             }
+            disk_inode.set_sorted_dir();
+            true
+        });
+        self.sync_locked(&fs);
+        done
+    }
+
+    /// Find inode under current inode by a slash-separated path such as `"a/b/c"`
+    ///
+    /// 逐级在目录中查找，空的路径分量（开头、结尾或重复的 `/`）被忽略；
+    /// 路径为空、中间某一级不是目录或目录损坏时与“未找到”一样返回 `None`，由内核转换为 -1。
+    pub fn find(&self, path: &str) -> Option<Arc<Inode>> {
+        let mut names = path.split('/').filter(|name| !name.is_empty());
+        let mut inode = self.find_child(names.next()?)?;
+        for name in names {
+            inode = inode.find_child(name)?;
         }
-        None
+        Some(inode)
     }
 
-    /// Find inode under current inode by name
-    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
+    /// Find the entry `name` directly under current inode
+    fn find_child(&self, name: &str) -> Option<Arc<Inode>> {
         // 目录查找流程：目录 inode -> 遍历 dirent -> 定位子 inode 的磁盘位置。
-        let fs = self.fs.lock();
+        let mut fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
+            let inode_id = self.find_inode_id(name, disk_inode, &mut fs).ok().flatten();
+            inode_id.map(|inode_id| {
                 let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
                 Arc::new(Self::new(
                     block_id,
@@ -80,80 +281,217 @@ impl Inode {
     }
 
     /// Increase the size of a disk inode
+    ///
+    /// 数据块不足时归还本次已申请的块并返回 `Err`，inode 保持原样。
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> Result<(), ()> {
         if new_size < disk_inode.size {
-            return;
+            return Ok(());
         }
-        // 先按“新增块数”批量申请数据块，再一次性扩容 inode。
+        // 末尾已是空洞时无法逐块追加，只改 size，写入时再由 map_range 分配
+        if disk_inode.has_tail_hole(&self.block_device) {
+            disk_inode.size = new_size;
+            return Ok(());
+        }
+        // 先按“新增块数”批量申请数据块，全部申请到之后再一次性扩容 inode。
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+            let Some(block_id) = fs.alloc_data() else {
+                for block_id in v {
+                    fs.dealloc_data(block_id);
+                }
+                return Err(());
+            };
+            v.push(block_id);
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        Ok(())
+    }
+
+    /// Allocate blocks for the holes in `[offset, offset + len)` before writing to it
+    ///
+    /// 数据块不足时停在第一个分配失败的块，返回从 `offset` 起已有块可写的字节数。
+    fn map_range(
+        &self,
+        offset: usize,
+        len: usize,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> usize {
+        for inner_id in offset / BLOCK_SZ..(offset + len).div_ceil(BLOCK_SZ) {
+            // 已有块直接跳过，避免无谓地弄脏索引块缓存
+            if disk_inode.get_block_id(inner_id as u32, &self.block_device) == 0
+                && disk_inode
+                    .map_block(inner_id as u32, &mut || fs.alloc_data(), &self.block_device)
+                    .is_none()
+            {
+                return (inner_id * BLOCK_SZ).saturating_sub(offset);
+            }
+        }
+        len
+    }
+
+    /// Make `[offset, offset + len)` writable: fill the holes in it and grow the inode to cover it
+    ///
+    /// 数据块不足时只准备到能分配到的位置，返回从 `offset` 起可写的字节数（部分写）。
+    /// 无论在哪一步失败，size 都只增长到已分配的块或可写区间的末尾，
+    /// 不会出现 size 增大、却没有写入数据的区间。
+    /// 超出 `MAX_FILE_SIZE` 的部分同样不可写。
+    fn prepare_write(
+        &self,
+        offset: usize,
+        len: usize,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> usize {
+        let len = len.min(MAX_FILE_SIZE.saturating_sub(offset));
+        let end = offset + len;
+        let old_size = disk_inode.size as usize;
+        // 1) 文件已有范围内的部分只需填补空洞，不改变 size
+        let inside = end.min(old_size).saturating_sub(offset);
+        let mapped = self.map_range(offset, inside, disk_inode, fs);
+        if mapped < inside || inside == len {
+            return mapped;
+        }
+        // 2) 扩容：先整体申请，块不足时按块推进到无块可分为止
+        if self.increase_size(end as u32, disk_inode, fs).is_err() {
+            loop {
+                let size = disk_inode.size as usize;
+                let next = (size + 1).next_multiple_of(BLOCK_SZ).min(end);
+                if self.increase_size(next as u32, disk_inode, fs).is_err() || next == end {
+                    break;
+                }
+            }
+        }
+        // 3) 末尾是空洞时 increase_size 只改了 size，写入区间的块还要在这里分配
+        let grow_start = offset.max(old_size);
+        let grown = (disk_inode.size as usize).saturating_sub(grow_start);
+        let mapped = self.map_range(grow_start, grown, disk_inode, fs);
+        if mapped < grown {
+            // 空洞的尾部没有块，把 size 收回到实际可写的末尾
+            disk_inode.size = old_size.max(grow_start + mapped) as u32;
+        }
+        (grow_start + mapped).saturating_sub(offset)
     }
 
     /// Create inode under current inode by name.
     /// Attention: use find previously to ensure the new file not existing.
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+    pub fn create(&self, name: &str) -> Result<Arc<Inode>, LinkError> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    /// Create a named pipe (FIFO) under current inode by name.
+    /// Attention: use find previously to ensure the name not existing.
+    pub fn create_fifo(&self, name: &str) -> Result<Arc<Inode>, LinkError> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+
+    /// Create a subdirectory under current inode by name, holding `.` and `..` entries.
+    /// Attention: use find previously to ensure the name not existing.
+    pub fn mkdir(&self, name: &str) -> Result<Arc<Inode>, LinkError> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    /// Whether `name` fits in a single directory entry
+    fn valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains('/') && name.len() <= NAME_LENGTH_LIMIT
+    }
+
+    /// Allocate an inode of the given type and add a dirent for it
+    ///
+    /// 名字为空、含 `/` 或超过 `NAME_LENGTH_LIMIT` 时返回 `InvalidName`：目录项只保存单级名字，
+    /// 多级路径由 `find` 逐级解析。inode 或数据块用尽时返回 `NoSpace`，磁盘上不留下半成品。
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Result<Arc<Inode>, LinkError> {
+        if !Self::valid_name(name) {
+            return Err(LinkError::InvalidName);
+        }
         let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return Err(LinkError::ReadOnly);
+        }
         // 1) 分配新 inode
-        let new_inode_id = fs.alloc_inode();
-        // 2) 初始化 inode 元数据
+        let new_inode_id = fs.alloc_inode().ok_or(LinkError::NoSpace)?;
+        // 2) 初始化 inode 元数据；目录预先放入指向自身的 `.` 和指向父目录的 `..`
         let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
-            });
-        // 3) 在当前目录追加 dirent 项
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+        let parent_inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        let new_inode = Self::new(
+            new_inode_block_id,
+            new_inode_block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        );
+        let initialized = new_inode.modify_disk_inode(|disk_inode| {
+            disk_inode.initialize(type_);
+            disk_inode.nlink = 1;
+            if type_ == DiskInodeType::Directory {
+                for dirent in [
+                    DirEntry::new(".", new_inode_id, DiskInodeType::Directory),
+                    DirEntry::new("..", parent_inode_id, DiskInodeType::Directory),
+                ] {
+                    new_inode.insert_dirent(&dirent, disk_inode, &mut fs)?;
+                }
+            }
+            Ok(())
         });
+        // 3) 在当前目录加入 dirent 项（有序目录插入到按名排序的位置）
+        let dirent = DirEntry::new(name, new_inode_id, type_);
+        if let Err(err) = initialized
+            .and_then(|()| self.modify_disk_inode(|root_inode| self.insert_dirent(&dirent, root_inode, &mut fs)))
+        {
+            // 数据块不足或目录项损坏，新 inode 没有被任何目录引用，连同已分配的数据块一起归还
+            fs.reclaim_inode(new_inode_id);
+            return Err(err);
+        }
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
+        self.sync_locked(&fs);
+        new_inode.sync_locked(&fs);
         // 4) 返回新文件的 Inode 句柄
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Ok(Arc::new(new_inode))
         // release efs lock automatically by compiler
     }
 
-    /// List inodes by id under current inode
-    pub fn readdir(&self) -> Vec<String> {
+    /// List the entries under current inode, each paired with the type of the inode it names
+    ///
+    /// 目录项只存名字和 inode 号，类型要读出子 inode 才知道，用于区分文件与子目录。
+    /// 目录项损坏时返回 `Corrupted`。
+    pub fn readdir(&self) -> Result<Vec<(String, DiskInodeType)>, LinkError> {
         let _fs = self.fs.lock();
+        // 类型直接取目录项中缓存的值，不读子 inode
         self.read_disk_inode(|disk_inode| {
             let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
+            (0..file_count)
+                .map(|i| {
+                    let dirent = self.read_dirent(disk_inode, i)?;
+                    Ok((String::from(dirent.name().ok_or(())?), dirent.inode_type().ok_or(())?))
+                })
+                .collect::<Result<_, ()>>()
+                .map_err(|()| LinkError::Corrupted)
+        })
+    }
+
+    /// The `index`-th directory entry as `(name, inode id, inode type)`
+    ///
+    /// 供 getdents 按位置续读目录：`index` 越过末尾时返回 `Ok(None)`，
+    /// 当前 inode 不是目录时返回 `NotDirectory`，目录项损坏时返回 `Corrupted`。类型来自目录项，不读子 inode。
+    pub fn dirent_at(&self, index: usize) -> Result<Option<(String, u32, DiskInodeType)>, LinkError> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(LinkError::NotDirectory);
+            }
+            if index >= (disk_inode.size as usize) / DIRENT_SZ {
+                return Ok(None);
             }
-            v
+            let dirent = self.read_dirent(disk_inode, index).map_err(|()| LinkError::Corrupted)?;
+            let (Some(name), Some(type_)) = (dirent.name(), dirent.inode_type()) else {
+                return Err(LinkError::Corrupted);
+            };
+            Ok(Some((String::from(name), dirent.inode_number(), type_)))
         })
     }
 
@@ -163,144 +501,625 @@ impl Inode {
         self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
     }
 
+    /// Load the data blocks covering `[offset, offset + len)` into the block cache
+    ///
+    /// 只填充块缓存，不拷贝数据；区间截断到文件末尾，空洞不读盘，
+    /// 一次最多载入缓存容量一半的块，以免把缓存中的其他块全部挤出。
+    /// 返回实际载入（或已在缓存中）的块数。
+    pub fn readahead(&self, offset: usize, len: usize) -> usize {
+        let max_blocks = readahead_max_blocks();
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            let end = offset.saturating_add(len).min(disk_inode.size as usize);
+            if offset >= end {
+                return 0;
+            }
+            let mut loaded = 0;
+            for inner_id in offset / BLOCK_SZ..end.div_ceil(BLOCK_SZ) {
+                if loaded == max_blocks {
+                    break;
+                }
+                let block_id = disk_inode.get_block_id(inner_id as u32, &self.block_device);
+                if block_id != 0 {
+                    get_block_cache(block_id as usize, Arc::clone(&self.block_device));
+                    loaded += 1;
+                }
+            }
+            loaded
+        })
+    }
+
     /// Write data to current inode
+    ///
+    /// 数据块不足时只写入能容纳的前一部分，返回实际写入的字节数（可能为 0）；
+    /// 只读挂载时什么也不写，返回 0。
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+        if fs.is_read_only() {
+            return 0;
+        }
+        self.invalidate_cache(&mut fs);
+        let (size, blocks) = self.modify_disk_inode(|disk_inode| {
+            let len = self.prepare_write(offset, buf.len(), disk_inode, &mut fs);
+            let size = disk_inode.write_at(offset, &buf[..len], &self.block_device);
+            (size, disk_inode.blocks_in(block_range(offset, size), &self.block_device))
         });
-        block_cache_sync_all();
+        // 只写回这次写到的块，其他文件的脏块留在缓存里
+        fs.sync_blocks(&blocks);
         size
     }
 
-    /// Clear the data in current inode
+    /// Copy `len` bytes at `src_offset` of this inode to `dst` at `dst_offset`
+    ///
+    /// Data moves through the block cache one block-sized chunk at a time, without
+    /// a caller-side buffer. The copy stops at the end of the source file, and
+    /// overlapping ranges within the same inode are handled like `memmove`.
+    /// When the filesystem runs out of blocks only a prefix is copied.
+    /// Returns the number of bytes copied, 0 on a read-only filesystem.
+    pub fn copy_range(&self, src_offset: usize, dst: &Inode, dst_offset: usize, len: usize) -> usize {
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return 0;
+        }
+        let src_size = self.read_disk_inode(|disk_inode| disk_inode.size as usize);
+        let len = len.min(src_size.saturating_sub(src_offset));
+        if len == 0 {
+            return 0;
+        }
+        dst.invalidate_cache(&mut fs);
+        let len = dst.modify_disk_inode(|disk_inode| dst.prepare_write(dst_offset, len, disk_inode, &mut fs));
+        if len == 0 {
+            return 0;
+        }
+        let same_inode = self.block_id == dst.block_id && self.block_offset == dst.block_offset;
+        // 同一文件内目标区间在源区间之后且重叠时从尾部往前搬，避免覆盖尚未复制的数据
+        let backward = same_inode && dst_offset > src_offset && dst_offset < src_offset + len;
+        let mut chunk = [0u8; BLOCK_SZ];
+        let mut remaining = len;
+        while remaining > 0 {
+            // 每段不跨越目标块边界，使写入尽量整块落到块缓存上
+            let (from, to, size) = if backward {
+                let size = remaining.min((dst_offset + remaining - 1) % BLOCK_SZ + 1);
+                let done = remaining - size;
+                (src_offset + done, dst_offset + done, size)
+            } else {
+                let done = len - remaining;
+                let size = remaining.min(BLOCK_SZ - (dst_offset + done) % BLOCK_SZ);
+                (src_offset + done, dst_offset + done, size)
+            };
+            // 读写分两次访问 inode，两个 inode 可能位于同一个块缓存中
+            self.read_disk_inode(|disk_inode| {
+                disk_inode.read_at(from, &mut chunk[..size], &self.block_device)
+            });
+            dst.modify_disk_inode(|disk_inode| {
+                disk_inode.write_at(to, &chunk[..size], &dst.block_device)
+            });
+            remaining -= size;
+        }
+        let blocks =
+            dst.read_disk_inode(|disk_inode| disk_inode.blocks_in(block_range(dst_offset, len), &dst.block_device));
+        fs.sync_blocks(&blocks);
+        len
+    }
+
+    /// Set the size of current inode to `new_size`
+    ///
+    /// Growing is sparse: only the size changes, reads of the new range return
+    /// zeros and blocks are allocated when that range is first written.
+    /// Shrinking frees the data blocks past the new end; when the new end falls
+    /// in the middle of a block, the rest of that block is zeroed.
+    /// Returns `false` if `new_size` exceeds `MAX_FILE_SIZE` or the filesystem is read-only.
+    pub fn truncate(&self, new_size: usize) -> bool {
+        if new_size > MAX_FILE_SIZE {
+            return false;
+        }
+        let new_size = new_size as u32;
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return false;
+        }
+        self.invalidate_cache(&mut fs);
+        let tail = self.modify_disk_inode(|disk_inode| {
+            if new_size >= disk_inode.size {
+                disk_inode.size = new_size;
+                return BTreeSet::new();
+            }
+            for data_block in disk_inode.decrease_size(new_size, &self.block_device) {
+                fs.dealloc_data(data_block);
+            }
+            // 末尾块被部分清零，指向它的索引块也可能被改写
+            let data_blocks = disk_inode.data_blocks();
+            disk_inode.blocks_in(data_blocks.saturating_sub(1)..data_blocks, &self.block_device)
+        });
+        fs.sync_blocks(&tail);
+        true
+    }
+
+    /// Clear the data in current inode (no-op on a read-only filesystem)
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return;
+        }
+        self.invalidate_cache(&mut fs);
         self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            assert!(data_blocks_dealloc.len() <= DiskInode::total_blocks(size) as usize);
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
         });
-        block_cache_sync_all();
+        fs.sync_blocks(&BTreeSet::new());
     }
 
     /// Create a hard link (add a new directory entry pointing to an existing inode)
-    pub fn link(&self, name: &str, target_inode: Arc<Inode>) -> Result<(), ()> {
+    ///
+    /// Directories cannot be hard linked, otherwise a directory could become its own
+    /// ancestor; `.` and `..` are reserved for `mkdir`. Both fail with `NotPermitted`.
+    /// The target's link count goes up by one, see [`DiskInode::nlink`].
+    pub fn link(&self, name: &str, target_inode: Arc<Inode>) -> Result<(), LinkError> {
+        if matches!(name, "." | "..") {
+            return Err(LinkError::NotPermitted);
+        }
+        if !Self::valid_name(name) {
+            return Err(LinkError::InvalidName);
+        }
         let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return Err(LinkError::ReadOnly);
+        }
+        let (target_type, target_nlink) =
+            target_inode.read_disk_inode(|disk_inode| (disk_inode.inode_type(), disk_inode.nlink));
+        if target_type == DiskInodeType::Directory {
+            return Err(LinkError::NotPermitted);
+        }
+        if target_nlink == u16::MAX {
+            return Err(LinkError::TooManyLinks);
+        }
         // Check if the name already exists
-        if self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode)).is_some() {
-            return Err(());
+        let existing = self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode, &mut fs));
+        if existing.map_err(|_| LinkError::Corrupted)?.is_some() {
+            return Err(LinkError::AlreadyExists);
         }
         // Get the target inode's ID
         let target_inode_id = fs.get_inode_id(target_inode.block_id as u32, target_inode.block_offset);
         // Add directory entry
-        self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            let dirent = DirEntry::new(name, target_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
-        block_cache_sync_all();
+        let dirent = DirEntry::new(name, target_inode_id, target_type);
+        self.modify_disk_inode(|root_inode| self.insert_dirent(&dirent, root_inode, &mut fs))?;
+        target_inode.modify_disk_inode(|disk_inode| disk_inode.nlink += 1);
+        self.sync_locked(&fs);
         Ok(())
     }
 
     /// Remove a hard link (remove a directory entry)
-    pub fn unlink(&self, name: &str) -> Result<(), ()> {
+    ///
+    /// The target's link count goes down by one; the inode is reclaimed once no directory
+    /// entry names it any more, wherever the other links were.
+    pub fn unlink(&self, name: &str) -> Result<(), LinkError> {
         let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return Err(LinkError::ReadOnly);
+        }
         // Find the inode
-        let inode_id = self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode)
-        });
-        if inode_id.is_none() {
-            return Err(());
+        let Some(inode_id) = self
+            .read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode, &mut fs))
+            .map_err(|_| LinkError::Corrupted)?
+        else {
+            return Err(LinkError::NotFound);
+        };
+        // Directories are not removed through unlink
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let target = get_block_cache(block_id as usize, Arc::clone(&self.block_device));
+        if target.read().read(block_offset, |disk_inode: &DiskInode| disk_inode.is_dir()) {
+            return Err(LinkError::IsDirectory);
         }
-        let inode_id = inode_id.unwrap();
 
         // Remove the directory entry
-        self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut found_index = None;
+        self.modify_disk_inode(|root_inode| self.remove_dirent(root_inode, name, &mut fs));
+
+        // Check if this was the last link to the inode
+        let nlink = target.write().modify(block_offset, |disk_inode: &mut DiskInode| {
+            disk_inode.nlink = disk_inode.nlink.saturating_sub(1);
+            disk_inode.nlink
+        });
+        if nlink == 0 {
+            // This was the last link: reclaim now, or after the last close
+            // if some file handle still has it open
+            fs.release_unlinked(inode_id);
+        }
+
+        self.sync_locked(&fs);
+        Ok(())
+    }
+
+    /// Remove the empty subdirectory `name`
+    ///
+    /// 只含 `.` 和 `..` 的目录才算空，否则返回 `NotEmpty`；删除后回收它的数据块和 inode
+    /// （仍被打开时推迟到最后一次关闭）。目录不能硬链接，链接数总是 1；子目录的 `..`
+    /// 不计入父目录的链接数，因此父目录无需额外调整。`.` 和 `..` 不能删除，返回 `NotPermitted`。
+    pub fn rmdir(&self, name: &str) -> Result<(), LinkError> {
+        if matches!(name, "." | "..") {
+            return Err(LinkError::NotPermitted);
+        }
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return Err(LinkError::ReadOnly);
+        }
+        let Some(inode_id) = self
+            .read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode, &mut fs))
+            .map_err(|_| LinkError::Corrupted)?
+        else {
+            return Err(LinkError::NotFound);
+        };
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let dir = Inode::new(block_id, block_offset, self.fs.clone(), self.block_device.clone());
+        dir.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return Err(LinkError::NotDirectory);
+            }
+            for i in 0..(disk_inode.size as usize) / DIRENT_SZ {
+                let dirent = dir.read_dirent(disk_inode, i).map_err(|_| LinkError::Corrupted)?;
+                if !matches!(dirent.name(), Some(".") | Some("..")) {
+                    return Err(LinkError::NotEmpty);
+                }
+            }
+            Ok(())
+        })?;
+        self.modify_disk_inode(|root_inode| self.remove_dirent(root_inode, name, &mut fs));
+        fs.release_unlinked(inode_id);
+        self.sync_locked(&fs);
+        Ok(())
+    }
 
-            // Find the entry to remove
+    /// Rename the entry `old_name` of this directory to `new_name`
+    ///
+    /// 整个过程持有 `fs` 锁，只改写目录项，目录大小和 inode 的链接数都不变，因此不会出现两个名字都在
+    /// 或都不在的中间状态。有序目录中条目按新名字挪到对应位置。新旧名字相同视为成功。
+    /// 失败时：`old_name` 不存在为 `NotFound`，`new_name` 已存在为 `AlreadyExists`，
+    /// `new_name` 不是合法的单级名字为 `InvalidName`，`.` 或 `..` 作为新旧名字之一为 `NotPermitted`，
+    /// 当前 inode 不是目录为 `NotDirectory`，目录项损坏为 `Corrupted`，只读挂载为 `ReadOnly`。
+    pub fn rename(&self, old_name: &str, new_name: &str) -> Result<(), LinkError> {
+        if [old_name, new_name].iter().any(|name| matches!(*name, "." | "..")) {
+            return Err(LinkError::NotPermitted);
+        }
+        if !Self::valid_name(new_name) {
+            return Err(LinkError::InvalidName);
+        }
+        let mut fs = self.fs.lock();
+        if fs.is_read_only() {
+            return Err(LinkError::ReadOnly);
+        }
+        self.modify_disk_inode(|dir_inode| {
+            if !dir_inode.is_dir() {
+                return Err(LinkError::NotDirectory);
+            }
+            let corrupted = |()| LinkError::Corrupted;
+            let inode_id = self
+                .find_inode_id(old_name, dir_inode, &mut fs)
+                .map_err(corrupted)?
+                .ok_or(LinkError::NotFound)?;
+            if old_name == new_name {
+                return Ok(());
+            }
+            if self.find_inode_id(new_name, dir_inode, &mut fs).map_err(corrupted)?.is_some() {
+                return Err(LinkError::AlreadyExists);
+            }
+            let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+            let mut old_entry = None;
             for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                root_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
-                if dirent.name() == name {
-                    found_index = Some(i);
+                let dirent = self.read_dirent(dir_inode, i).map_err(corrupted)?;
+                if dirent.name() == Some(old_name) {
+                    old_entry = Some((i, dirent.inode_type().ok_or(LinkError::Corrupted)?));
                     break;
                 }
             }
-
-            if let Some(index) = found_index {
-                // Shift all entries after the removed one forward
-                for i in index..file_count - 1 {
-                    let mut dirent = DirEntry::empty();
-                    root_inode.read_at((i + 1) * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
-                    root_inode.write_at(i * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            // 名字缓存中有而目录项中找不到，同样视为目录损坏
+            let (old_index, type_) = old_entry.ok_or(LinkError::Corrupted)?;
+            // 新位置：有序目录按新名字二分（结果把旧条目也算在内），否则原地改写
+            let new_index = if dir_inode.is_sorted_dir() {
+                let index = self.lower_bound(new_name, dir_inode).map_err(corrupted)?;
+                if index > old_index { index - 1 } else { index }
+            } else {
+                old_index
+            };
+            // 旧位置与新位置之间的条目朝旧位置方向挪一格
+            if new_index > old_index {
+                for i in old_index..new_index {
+                    let moved = self.read_dirent(dir_inode, i + 1).map_err(corrupted)?;
+                    dir_inode.write_at(i * DIRENT_SZ, moved.as_bytes(), &self.block_device);
+                }
+            } else {
+                for i in (new_index..old_index).rev() {
+                    let moved = self.read_dirent(dir_inode, i).map_err(corrupted)?;
+                    dir_inode.write_at((i + 1) * DIRENT_SZ, moved.as_bytes(), &self.block_device);
                 }
-                // Reduce size
-                let new_size = (file_count - 1) * DIRENT_SZ;
-                // Note: We don't actually deallocate blocks here for simplicity
-                root_inode.size = new_size as u32;
             }
-        });
-
-        // Check if this was the last link to the inode
-        let nlink = self.count_links(inode_id);
-        if nlink == 0 {
-            // This was the last link, deallocate the inode's data
-            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
-                .lock()
-                .modify(block_offset, |disk_inode: &mut DiskInode| {
-                    let size = disk_inode.size;
-                    let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-                    assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-                    for data_block in data_blocks_dealloc.into_iter() {
-                        fs.dealloc_data(data_block);
-                    }
-                });
-            // Deallocate the inode itself
-            fs.dealloc_inode(inode_id);
-        }
-
-        block_cache_sync_all();
+            let dirent = DirEntry::new(new_name, inode_id, type_);
+            dir_inode.write_at(new_index * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            self.update_cache(&mut fs, |entries| {
+                entries.remove(old_name);
+                entries.insert(String::from(new_name), inode_id);
+            });
+            Ok(())
+        })?;
+        self.sync_locked(&fs);
         Ok(())
     }
 
-    /// Count the number of hard links to an inode
-    fn count_links(&self, target_inode_id: u32) -> u32 {
-        let mut count = 0;
-        self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            for i in 0..file_count {
+    /// Remove the entry `name` from a directory disk inode, keeping the others in order
+    fn remove_dirent(&self, dir_inode: &mut DiskInode, name: &str, fs: &mut EasyFileSystem) {
+        let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+        let mut found_index = None;
+
+        // Find the entry to remove
+        for i in 0..file_count {
+            let mut dirent = DirEntry::empty();
+            dir_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+            if dirent.name() == Some(name) {
+                found_index = Some(i);
+                break;
+            }
+        }
+
+        if let Some(index) = found_index {
+            // Shift all entries after the removed one forward
+            for i in index..file_count - 1 {
                 let mut dirent = DirEntry::empty();
-                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
-                if dirent.inode_number() == target_inode_id {
-                    count += 1;
-                }
+                dir_inode.read_at((i + 1) * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+                dir_inode.write_at(i * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
             }
+            // Reduce size
+            let new_size = (file_count - 1) * DIRENT_SZ;
+            // Note: We don't actually deallocate blocks here for simplicity
+            dir_inode.size = new_size as u32;
+            self.update_cache(fs, |entries| {
+                entries.remove(name);
+            });
+        }
+    }
+
+    /// Whether the filesystem holding this inode is mounted read-only
+    pub fn is_read_only(&self) -> bool {
+        self.fs.lock().is_read_only()
+    }
+
+    /// Record that a file handle has opened this inode
+    pub fn open(&self) {
+        let mut fs = self.fs.lock();
+        let inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        fs.open_inode(inode_id);
+    }
+
+    /// Record that a file handle on this inode has been closed
+    pub fn close(&self) {
+        let mut fs = self.fs.lock();
+        let inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        fs.close_inode(inode_id);
+    }
+
+    /// Get the owner `(uid, gid)` and permission bits of this inode
+    pub fn owner_and_mode(&self) -> (u32, u32, u32) {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| (disk_inode.uid, disk_inode.gid, disk_inode.permission()))
+    }
+
+    /// Write back the dirty cached blocks of this inode, like `fsync`
+    ///
+    /// Its data and index blocks are written together with the filesystem metadata
+    /// (super block, bitmaps and inode area); dirty blocks of other files stay in the
+    /// cache until they are evicted or `EasyFileSystem::sync_all` runs.
+    /// Returns whether all of them reached the device.
+    pub fn sync(&self) -> bool {
+        let fs = self.fs.lock();
+        self.sync_locked(&fs)
+    }
+
+    /// `sync` for callers already holding the `fs` lock
+    fn sync_locked(&self, fs: &EasyFileSystem) -> bool {
+        let blocks =
+            self.read_disk_inode(|disk_inode| disk_inode.blocks_in(0..disk_inode.data_blocks(), &self.block_device));
+        fs.sync_blocks(&blocks)
+    }
+
+    /// Change the owner of this inode
+    ///
+    /// 只读挂载时不做修改，返回 `false`。
+    pub fn chown(&self, uid: u32, gid: u32) -> bool {
+        let fs = self.fs.lock();
+        if fs.is_read_only() {
+            return false;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
         });
-        count
+        fs.sync_blocks(&BTreeSet::new());
+        true
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
     }
 
-    /// Get inode ID and link count for this inode
-    pub fn get_stat_info(&self) -> (u32, u32) {
+    /// Get the inode ID of this inode
+    pub fn inode_id(&self) -> u32 {
+        self.fs.lock().get_inode_id(self.block_id as u32, self.block_offset)
+    }
+
+    /// Whether this inode is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    /// Whether this inode is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
+
+    /// Get inode ID, link count and timestamps for this inode
+    pub fn get_stat_info(&self) -> InodeStat {
         let fs = self.fs.lock();
         let inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
-        drop(fs);
-        // Count links by searching the root directory
-        let root_inode = EasyFileSystem::root_inode(&self.fs);
-        let nlink = root_inode.count_links(inode_id);
-        (inode_id, nlink)
+        let (nlink, ctime, mtime, atime) = self.read_disk_inode(|disk_inode| {
+            (disk_inode.nlink as u32, disk_inode.ctime, disk_inode.mtime, disk_inode.atime)
+        });
+        InodeStat { ino: inode_id, nlink, ctime, mtime, atime }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::block_cache_misses;
+    use alloc::format;
+    use alloc::vec;
+    use std::sync::{Mutex as StdMutex, MutexGuard as StdMutexGuard, PoisonError};
+
+    /// 内存中的块设备
+    pub(crate) struct RamDisk(pub(crate) Mutex<Vec<[u8; BLOCK_SZ]>>);
+
+    impl RamDisk {
+        /// 全零的 `blocks` 块内存盘
+        pub(crate) fn new(blocks: usize) -> Arc<Self> {
+            Arc::new(Self(Mutex::new(vec![[0; BLOCK_SZ]; blocks])))
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock()[block_id]);
+        }
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock()[block_id].copy_from_slice(buf);
+        }
+    }
+
+    /// 块缓存及其命中/未命中计数是全局的，持有这把锁的测试独占它们
+    static BLOCK_CACHE: StdMutex<()> = StdMutex::new(());
+
+    /// 独占块缓存，直到返回的锁被释放
+    pub(crate) fn lock_block_cache() -> StdMutexGuard<'static, ()> {
+        BLOCK_CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 在 `blocks` 块的内存盘上新建文件系统，返回根目录和块缓存的独占锁
+    fn new_fs(blocks: usize) -> (StdMutexGuard<'static, ()>, Inode) {
+        let guard = lock_block_cache();
+        let root = EasyFileSystem::root_inode(&EasyFileSystem::create(RamDisk::new(blocks), blocks as u32, 1));
+        (guard, root)
+    }
+
+    #[test]
+    fn many_files_in_one_directory() {
+        let (_cache, root) = new_fs(4096);
+        let ids: Vec<u32> = (0..500)
+            .map(|i| root.create(&format!("file{i}")).unwrap().inode_id())
+            .collect();
+        assert_eq!(root.readdir().unwrap().len(), 500);
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(root.find(&format!("file{i}")).unwrap().inode_id(), *id);
+        }
+        // 目录项占 32 块，比块缓存大，逐项扫描必然未命中；名字已缓存时查找只读根目录 inode 所在的块
+        let misses = block_cache_misses();
+        for i in [0, 250, 499] {
+            assert_eq!(root.find(&format!("file{i}")).unwrap().inode_id(), ids[i]);
+        }
+        assert_eq!(block_cache_misses(), misses);
+        for i in (0..500).step_by(2) {
+            root.unlink(&format!("file{i}")).unwrap();
+        }
+        assert_eq!(root.readdir().unwrap().len(), 250);
+        assert!(root.find("file0").is_none());
+        assert_eq!(root.find("file499").unwrap().inode_id(), ids[499]);
+    }
+
+    #[test]
+    fn two_mib_file_round_trip() {
+        const LEN: usize = 2 << 20;
+        // 数据区约 7000 块，放不下两个 2 MiB 文件（各需 4096 个数据块加索引块）
+        let (_cache, root) = new_fs(8192);
+        let file = root.create("big").unwrap();
+        let data: Vec<u8> = (0..LEN).map(|i| (i * 7 + i / BLOCK_SZ) as u8).collect();
+        for _ in 0..2 {
+            assert_eq!(file.write_at(0, &data), LEN);
+            assert_eq!(file.size(), LEN);
+            let mut out = vec![0u8; LEN];
+            assert_eq!(file.read_at(0, &mut out), LEN);
+            assert!(out == data);
+            // clear 必须归还全部数据块和索引块，第二轮才能再写下完整的文件
+            file.clear();
+            assert_eq!(file.size(), 0);
+        }
+    }
+
+    #[test]
+    fn raw_directory_writes_invalidate_the_name_cache() {
+        let (_cache, root) = new_fs(4096);
+        let id = root.create("a").unwrap().inode_id();
+        // 查找一次，目录名缓存被载入
+        assert!(root.find("a").is_some());
+        root.clear();
+        assert!(root.find("a").is_none());
+        let dirent = DirEntry::new("b", id, DiskInodeType::File);
+        assert_eq!(root.write_at(0, dirent.as_bytes()), DIRENT_SZ);
+        assert_eq!(root.find("b").unwrap().inode_id(), id);
+        assert!(root.truncate(0));
+        assert!(root.find("b").is_none());
+    }
+
+    #[test]
+    fn link_count_spans_directories() {
+        let (_cache, root) = new_fs(4096);
+        let file = root.create("f").unwrap();
+        assert_eq!(file.write_at(0, b"shared"), 6);
+        let dir = root.mkdir("d").unwrap();
+        dir.link("g", file.clone()).unwrap();
+        assert_eq!(file.get_stat_info().nlink, 2);
+        // 另一个目录中仍有链接，inode 不能被回收
+        root.unlink("f").unwrap();
+        let linked = dir.find("g").unwrap();
+        assert_eq!(linked.inode_id(), file.inode_id());
+        assert_eq!(linked.get_stat_info().nlink, 1);
+        let other = root.create("other").unwrap();
+        assert_ne!(other.inode_id(), file.inode_id());
+        assert_eq!(other.write_at(0, b"other!"), 6);
+        let mut buf = [0u8; 6];
+        assert_eq!(linked.read_at(0, &mut buf), 6);
+        assert_eq!(&buf, b"shared");
+        // 最后一个链接删除后 inode 才被回收
+        dir.unlink("g").unwrap();
+        assert_eq!(root.create("again").unwrap().inode_id(), file.inode_id());
+    }
+
+    #[test]
+    fn rmdir_rejects_dot_entries() {
+        let (_cache, root) = new_fs(4096);
+        let dir = root.mkdir("d").unwrap();
+        assert_eq!(dir.rmdir("."), Err(LinkError::NotPermitted));
+        assert_eq!(dir.rmdir(".."), Err(LinkError::NotPermitted));
+        assert!(dir.find(".").is_some() && dir.find("..").is_some());
+        assert_eq!(root.rmdir("d"), Ok(()));
+    }
+
+    #[test]
+    fn rename_rejects_dot_entries() {
+        let (_cache, root) = new_fs(4096);
+        let dir = root.mkdir("d").unwrap();
+        dir.create("f").unwrap();
+        for (old, new) in [(".", "x"), ("..", "x"), ("f", "."), ("f", ".."), (".", ".")] {
+            assert_eq!(dir.rename(old, new), Err(LinkError::NotPermitted));
+        }
+        assert_eq!(dir.rename("missing", "x"), Err(LinkError::NotFound));
+        assert!(dir.find("f").is_some());
+        assert_eq!(dir.find(".").unwrap().inode_id(), dir.inode_id());
+        assert_eq!(dir.rename("f", "g"), Ok(()));
+        assert!(dir.find("f").is_none() && dir.find("g").is_some());
     }
 }
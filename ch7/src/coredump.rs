@@ -0,0 +1,131 @@
+//! 核心转储模块
+//!
+//! 进程因异常（缺页导致的 SIGSEGV、非法指令等）被内核结束时，把寄存器和全部用户页写入
+//! 根目录下的 `core.<pid>`，供事后分析。与 Linux 一样由 `RLIMIT_CORE` 控制：
+//! 限制为 0（默认）时不生成，否则文件大小不超过该限制，超出的页被丢弃。
+//! 进程通过 `prctl(PR_SET_DUMPABLE, 0)` 把自己标记为不可转储后，无论限制多大都不生成。
+//!
+//! 文件格式（小端）：
+//!
+//! ```text
+//! "TGCORE\0\0"                 8 字节魔数
+//! pid: u64
+//! regs: [u64; 32]              pc、x1 ~ x31
+//! 重复若干次：
+//!   vaddr: u64, len: u64       一段连续映射的用户页
+//!   data: [u8; len]
+//! ```
+//!
+//! 教程阅读建议：先看 `for_each_user_page` 如何按 Sv39 格式遍历页表，
+//! 再看 `dump` 如何把相邻的页合并成一段，边遍历边写入文件。
+
+use crate::{fs::FS, process::Process, Sv39};
+use alloc::{format, sync::Arc};
+use tg_console::log;
+use tg_easy_fs::{FSManager, Inode, OpenFlags};
+use tg_kernel_vm::page_table::MmuMeta;
+
+/// 文件开头的魔数
+const MAGIC: &[u8; 8] = b"TGCORE\0\0";
+/// 页大小
+const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+/// 若进程可转储且 `RLIMIT_CORE` 不为 0，把它的寄存器与用户内存写入 `core.<pid>`
+pub fn dump(process: &Process) {
+    let limit = process.core_limit;
+    if limit == 0 || !process.dumpable {
+        return;
+    }
+    let pid = process.pid.get_usize();
+    let name = format!("core.{pid}");
+    let file = FS.open(&name, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    let Some(inode) = file.and_then(|f| f.inode.clone()) else {
+        log::warn!("failed to create {name}");
+        return;
+    };
+    // 边生成边写入文件，不在内核堆中拼出整个转储
+    let mut out = CoreFile { inode, offset: 0 };
+    let ctx = &process.context.context;
+    let mut complete = out.append(MAGIC) && out.append(&(pid as u64).to_le_bytes());
+    for i in 0..32 {
+        // 0 号寄存器恒为 0，这个位置放 pc
+        let reg = if i == 0 { ctx.pc() } else { ctx.x(i) };
+        complete = complete && out.append(&(reg as u64).to_le_bytes());
+    }
+    if complete {
+        // 相邻的页合并成一段：(起始虚拟地址, 段头在文件中的偏移)，每写入一页回填段头中的长度
+        let mut segment: Option<(usize, usize)> = None;
+        let mut next_vaddr = 0;
+        for_each_user_page(process, &mut |vaddr, page| {
+            if out.offset + PAGE_SIZE + 16 > limit {
+                return false;
+            }
+            if segment.is_none() || vaddr != next_vaddr {
+                segment = Some((vaddr, out.offset));
+                if !(out.append(&(vaddr as u64).to_le_bytes()) && out.append(&0u64.to_le_bytes())) {
+                    return false;
+                }
+            }
+            let (start, header) = segment.unwrap();
+            if !out.append(page) {
+                return false;
+            }
+            next_vaddr = vaddr + PAGE_SIZE;
+            let len = (next_vaddr - start) as u64;
+            out.inode.write_at(header + 8, &len.to_le_bytes());
+            true
+        });
+    }
+    log::info!("process {pid}: core dumped to {name} ({} bytes)", out.offset);
+}
+
+/// 正在写入的转储文件
+struct CoreFile {
+    inode: Arc<Inode>,
+    /// 下一次追加的位置，即已写入的字节数
+    offset: usize,
+}
+
+impl CoreFile {
+    /// 在末尾追加 `bytes`，写不完整（磁盘已满）时返回 `false`
+    fn append(&mut self, bytes: &[u8]) -> bool {
+        let written = self.inode.write_at(self.offset, bytes);
+        self.offset += written;
+        written == bytes.len()
+    }
+}
+
+/// 按虚拟地址顺序访问进程的每个用户页（带 U 位的 4 KiB 叶子页），`f` 返回 `false` 时停止
+///
+/// `tg-kernel-vm` 没有遍历页表的接口，这里直接按 Sv39 格式读页表；
+/// 内核恒等映射了全部物理内存，页表页和数据页的物理地址可以直接访问。
+fn for_each_user_page(process: &Process, f: &mut dyn FnMut(usize, &[u8]) -> bool) {
+    const PTE_V: usize = 1 << 0;
+    const PTE_RWX: usize = 0b111 << 1;
+    const PTE_U: usize = 1 << 4;
+    fn walk(table: usize, level: usize, vpn: usize, f: &mut dyn FnMut(usize, &[u8]) -> bool) -> bool {
+        // 根页表只看低半部分，高半部分是内核与异界传送门
+        let entries = if level == 2 { 256 } else { 512 };
+        for index in 0..entries {
+            let pte = unsafe { *(table as *const usize).add(index) };
+            if pte & PTE_V == 0 {
+                continue;
+            }
+            let vpn = (vpn << 9) | index;
+            let base = ((pte >> 10) & ((1 << 44) - 1)) << Sv39::PAGE_BITS;
+            let keep_going = if pte & PTE_RWX == 0 {
+                level == 0 || walk(base, level - 1, vpn, f)
+            } else if level == 0 && pte & PTE_U != 0 {
+                let page = unsafe { core::slice::from_raw_parts(base as *const u8, PAGE_SIZE) };
+                f(vpn << Sv39::PAGE_BITS, page)
+            } else {
+                true
+            };
+            if !keep_going {
+                return false;
+            }
+        }
+        true
+    }
+    walk(process.address_space.root_ppn().val() << Sv39::PAGE_BITS, 2, 0, f);
+}
@@ -20,17 +20,34 @@
 //! - 最后看 `FS` 与 `read_all`：区分“程序加载路径”和“运行时 I/O 路径”。
 
 use crate::virtio_block::BLOCK_DEVICE;
-use alloc::{string::String, sync::Arc, vec::Vec};
-use spin::Lazy;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::cell::Cell;
+use spin::{Lazy, Mutex};
 use tg_easy_fs::{
-    EasyFileSystem, FSManager, FileHandle, Inode, OpenFlags, PipeReader, PipeWriter, UserBuffer,
+    block_cache_sync_all, make_pipe, EasyFileSystem, FSManager, FileHandle, Inode, LinkError,
+    OpenFlags, PipeReader, PipeWriter, SeekFrom, UserBuffer, WeakPipeReader,
 };
+use tg_syscall::StatMode;
+use tg_task_manage::ProcId;
 
 /// 全局文件系统实例（与第六章相同）
 pub static FS: Lazy<FileSystem> = Lazy::new(|| FileSystem {
     root: EasyFileSystem::root_inode(&EasyFileSystem::open(BLOCK_DEVICE.clone())),
 });
 
+/// 把块缓存中的脏块写回磁盘并冲刷块设备
+///
+/// 关机前调用，避免尚未落盘的修改丢失。
+pub fn sync_all() {
+    block_cache_sync_all();
+    BLOCK_DEVICE.flush();
+}
+
 /// 文件系统管理器
 pub struct FileSystem {
     /// 根目录 inode
@@ -40,24 +57,7 @@ pub struct FileSystem {
 impl FSManager for FileSystem {
     /// 打开文件
     fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>> {
-        let (readable, writable) = flags.read_write();
-        if flags.contains(OpenFlags::CREATE) {
-            if let Some(inode) = self.find(path) {
-                inode.clear();
-                Some(Arc::new(FileHandle::new(readable, writable, inode)))
-            } else {
-                self.root
-                    .create(path)
-                    .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
-            }
-        } else {
-            self.find(path).map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(FileHandle::new(readable, writable, inode))
-            })
-        }
+        self.open_errno(path, flags).ok()
     }
 
     /// 查找文件
@@ -67,7 +67,8 @@ impl FSManager for FileSystem {
 
     /// 列出目录内容
     fn readdir(&self, _path: &str) -> Option<alloc::vec::Vec<String>> {
-        Some(self.root.readdir())
+        let entries = self.root.readdir().ok()?;
+        Some(entries.into_iter().map(|(name, _)| name).collect())
     }
 
     /// 创建硬链接（未实现）
@@ -81,6 +82,108 @@ impl FSManager for FileSystem {
     }
 }
 
+impl FileSystem {
+    /// 与 [`FSManager::open`] 相同，失败时返回负的错误码
+    ///
+    /// inode 或数据块用尽导致创建失败时返回 ENOSPC；文件系统只读挂载时以写方式打开、
+    /// 截断或创建文件返回 EROFS；其余失败仍返回 -1。
+    pub fn open_errno(&self, path: &str, flags: OpenFlags) -> Result<Arc<FileHandle>, isize> {
+        let (readable, writable) = flags.read_write();
+        if (writable || flags.contains(OpenFlags::TRUNC)) && self.root.is_read_only() {
+            return Err(EROFS);
+        }
+        if let Some(inode) = self.find(path) {
+            // 文件已存在：CREATE 不影响已有内容，只有 TRUNC 才清空
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.truncate(0);
+            }
+            Ok(Arc::new(FileHandle::new(readable, writable, inode)))
+        } else if flags.contains(OpenFlags::CREATE) {
+            // 文件不存在且带 CREATE：创建新文件
+            self.root
+                .create(path)
+                .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
+                .map_err(create_errno)
+        } else {
+            Err(-1)
+        }
+    }
+
+    /// 在根目录下创建命名管道，失败时返回负的错误码
+    ///
+    /// 同名文件已存在时返回 -1，inode 或数据块用尽时返回 ENOSPC。
+    pub fn create_fifo(&self, path: &str) -> Result<Arc<Inode>, isize> {
+        if self.find(path).is_some() {
+            return Err(-1);
+        }
+        self.root.create_fifo(path).map_err(create_errno)
+    }
+}
+
+/// 磁盘空间不足（inode 或数据块用尽）
+const ENOSPC: isize = -28;
+/// 文件系统只读挂载
+const EROFS: isize = -30;
+
+/// 把 easy-fs 创建文件的错误转换为负的错误码，空间不足和只读以外的原因沿用 -1
+fn create_errno(err: LinkError) -> isize {
+    match err {
+        LinkError::NoSpace => ENOSPC,
+        LinkError::ReadOnly => EROFS,
+        _ => -1,
+    }
+}
+
+/// 命名管道在内核中的共享缓冲区
+///
+/// 数据不落盘：同一 FIFO inode 的所有读端共享一个缓冲区，写端共享同一个 `PipeWriter`，
+/// 最后一个写端关闭后读端读到 EOF，最后一个读端关闭后写端收到 EPIPE。
+/// 内核只持有弱引用，读写端全部关闭后缓冲区（连同未读数据）被释放，下次打开时重新创建。
+struct Fifo {
+    reader: WeakPipeReader,
+    writer: Weak<PipeWriter>,
+    /// 曾经打开过的读端数（只增不减，用于 open 时等待对端）
+    readers_opened: usize,
+    /// 曾经打开过的写端数
+    writers_opened: usize,
+}
+
+/// 已打开过的命名管道，按 inode 编号索引
+static FIFOS: Mutex<BTreeMap<u32, Fifo>> = Mutex::new(BTreeMap::new());
+
+/// 打开命名管道的读端或写端
+pub fn open_fifo(inode_id: u32, read: bool) -> Fd {
+    let mut fifos = FIFOS.lock();
+    let fifo = fifos.entry(inode_id).or_insert_with(|| {
+        let (reader, _) = make_pipe();
+        Fifo { reader: reader.downgrade(), writer: Weak::new(), readers_opened: 0, writers_opened: 0 }
+    });
+    let reader = fifo.reader.upgrade().unwrap_or_else(|| {
+        let (reader, _) = make_pipe();
+        fifo.reader = reader.downgrade();
+        reader
+    });
+    if read {
+        fifo.readers_opened += 1;
+        Fd::PipeRead(reader)
+    } else {
+        fifo.writers_opened += 1;
+        let writer = fifo.writer.upgrade().unwrap_or_else(|| {
+            let writer = reader.new_writer();
+            fifo.writer = Arc::downgrade(&writer);
+            writer
+        });
+        Fd::PipeWrite(writer)
+    }
+}
+
+/// 命名管道的对端是否已经被打开过（读端等写端，写端等读端）
+pub fn fifo_peer_opened(inode_id: u32, read: bool) -> bool {
+    FIFOS.lock().get(&inode_id).is_some_and(|fifo| {
+        if read { fifo.writers_opened > 0 } else { fifo.readers_opened > 0 }
+    })
+}
+
 /// 读取文件全部内容到 Vec<u8>
 pub fn read_all(fd: Arc<FileHandle>) -> Vec<u8> {
     let mut offset = 0usize;
@@ -111,6 +214,10 @@ pub fn read_all(fd: Arc<FileHandle>) -> Vec<u8> {
 /// fd_table[3] = Fd::File(FileHandle)             // 普通文件（open 分配）
 /// fd_table[4] = Fd::PipeRead(PipeReader)         // 管道读端（pipe 分配）
 /// fd_table[5] = Fd::PipeWrite(PipeWriter)        // 管道写端（pipe 分配）
+/// fd_table[6] = Fd::Mem(MemFile)                 // 匿名内存文件（memfd_create 分配）
+/// fd_table[7] = Fd::Null / Fd::Zero             // /dev/null、/dev/zero（open 分配）
+/// fd_table[8] = Fd::Pid(PidFd)                   // 进程描述符（pidfd_open 分配）
+/// fd_table[9] = Fd::Tty(Tty::Console)            // 控制终端（open /dev/tty 分配）
 /// ```
 #[derive(Clone)]
 pub enum Fd {
@@ -120,6 +227,16 @@ pub enum Fd {
     PipeRead(PipeReader),
     /// 管道写端（只写）
     PipeWrite(Arc<PipeWriter>),
+    /// 匿名内存文件（memfd_create 创建，可读可写）
+    Mem(MemFile),
+    /// `/dev/null`：读立即返回 0（EOF），写丢弃全部数据
+    Null,
+    /// `/dev/zero`：读填充全零，写丢弃全部数据
+    Zero,
+    /// 进程描述符：目标进程退出后可读，读出 4 字节退出码
+    Pid(PidFd),
+    /// 终端设备（`/dev/tty` 打开的控制终端），可读可写
+    Tty(Tty),
     /// 空描述符（用于 stdin/stdout/stderr）
     Empty {
         /// 是否可读
@@ -130,12 +247,24 @@ pub enum Fd {
 }
 
 impl Fd {
+    /// 识别 `/dev` 下的设备文件，这些路径不经过 easy-fs
+    pub fn open_device(path: &str) -> Option<Fd> {
+        match path {
+            "/dev/null" => Some(Fd::Null),
+            "/dev/zero" => Some(Fd::Zero),
+            // 调用者需确认进程有控制终端，并换成它的终端
+            "/dev/tty" => Some(Fd::Tty(Tty::Console)),
+            _ => None,
+        }
+    }
+
     /// 判断是否可读
     pub fn readable(&self) -> bool {
         match self {
             Fd::File(f) => f.readable(),
             Fd::PipeRead(_) => true,
             Fd::PipeWrite(_) => false,
+            Fd::Mem(_) | Fd::Null | Fd::Zero | Fd::Pid(_) | Fd::Tty(_) => true,
             Fd::Empty { read, .. } => *read,
         }
     }
@@ -144,8 +273,9 @@ impl Fd {
     pub fn writable(&self) -> bool {
         match self {
             Fd::File(f) => f.writable(),
-            Fd::PipeRead(_) => false,
+            Fd::PipeRead(_) | Fd::Pid(_) => false,
             Fd::PipeWrite(_) => true,
+            Fd::Mem(_) | Fd::Null | Fd::Zero | Fd::Tty(_) => true,
             Fd::Empty { write, .. } => *write,
         }
     }
@@ -155,6 +285,15 @@ impl Fd {
         match self {
             Fd::File(f) => f.read(buf),
             Fd::PipeRead(p) => p.read(buf),
+            Fd::Mem(m) => m.read(buf),
+            Fd::Null => 0,
+            Fd::Zero => {
+                let mut buf = buf;
+                buf.buffers.iter_mut().for_each(|slice| slice.fill(0));
+                buf.len() as isize
+            }
+            Fd::Pid(p) => p.read(buf),
+            Fd::Tty(t) => t.read(buf),
             _ => -1,
         }
     }
@@ -164,7 +303,354 @@ impl Fd {
         match self {
             Fd::File(f) => f.write(buf),
             Fd::PipeWrite(p) => p.write(buf),
+            Fd::Mem(m) => m.write(buf),
+            Fd::Null | Fd::Zero => buf.len() as isize,
+            Fd::Tty(t) => t.write(buf),
+            _ => -1,
+        }
+    }
+
+    /// 移动读写偏移（lseek），返回新的绝对偏移
+    ///
+    /// 只有普通文件与内存文件可以定位；管道、设备与标准输入输出返回 `None`。
+    pub fn seek(&self, offset: isize, from: SeekFrom) -> Option<usize> {
+        match self {
+            Fd::File(f) => f.seek(offset, from),
+            Fd::Mem(m) => {
+                let base = match from {
+                    SeekFrom::Start => 0,
+                    SeekFrom::Current => m.offset.get(),
+                    SeekFrom::End => m.len(),
+                };
+                let new_offset = base.checked_add_signed(offset).filter(|&pos| pos <= isize::MAX as usize)?;
+                m.offset.set(new_offset);
+                Some(new_offset)
+            }
+            _ => None,
+        }
+    }
+
+    /// 当前读写偏移（只有普通文件与内存文件有偏移，用于检查 RLIMIT_FSIZE）
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Fd::File(f) => Some(f.offset.get()),
+            Fd::Mem(m) => Some(m.offset.get()),
+            _ => None,
+        }
+    }
+
+    /// 获取 fd 的状态信息
+    ///
+    /// - 普通文件：inode 编号与硬链接数
+    /// - 管道：FIFO 类型，size 为读端可读字节数或写端剩余可写空间
+    /// - 空描述符：暂不支持，返回 `None`
+    pub fn stat(&self) -> Option<Stat> {
+        let mut stat = Stat::new();
+        match self {
+            Fd::File(f) => {
+                let info = f.get_stat_info()?;
+                stat.ino = info.ino as u64;
+                stat.mode = StatMode::FILE;
+                stat.nlink = info.nlink;
+            }
+            Fd::PipeRead(p) => {
+                stat.mode = S_IFIFO;
+                stat.nlink = 1;
+                stat.size = p.available_read() as u64;
+            }
+            Fd::PipeWrite(p) => {
+                stat.mode = S_IFIFO;
+                stat.nlink = 1;
+                stat.size = p.available_write() as u64;
+            }
+            Fd::Mem(m) => {
+                stat.mode = StatMode::FILE;
+                stat.nlink = 1;
+                stat.size = m.len() as u64;
+            }
+            Fd::Null | Fd::Zero | Fd::Pid(_) | Fd::Tty(_) | Fd::Empty { .. } => return None,
+        }
+        Some(stat)
+    }
+
+    /// 设置非阻塞模式，只有管道两端支持，其他描述符返回 `false`
+    ///
+    /// 标志保存在管道端对象里，dup/fork 得到的副本与原 fd 共享。
+    pub fn set_nonblocking(&self, nonblocking: bool) -> bool {
+        match self {
+            Fd::PipeRead(p) => p.set_nonblocking(nonblocking),
+            Fd::PipeWrite(p) => p.set_nonblocking(nonblocking),
+            _ => return false,
+        }
+        true
+    }
+
+    /// 是否处于非阻塞模式
+    pub fn is_nonblocking(&self) -> bool {
+        match self {
+            Fd::PipeRead(p) => p.is_nonblocking(),
+            Fd::PipeWrite(p) => p.is_nonblocking(),
+            _ => false,
+        }
+    }
+}
+
+/// 可被 poll 查询就绪状态的内核对象
+///
+/// 所有 `Fd` 变体都通过它报告就绪，poll 不必按 fd 类型分别判断。
+pub trait Pollable {
+    /// 读操作是否不会阻塞（有数据可读或已到 EOF）
+    fn poll_readable(&self) -> bool;
+    /// 写操作是否不会阻塞
+    fn poll_writable(&self) -> bool;
+    /// 登记一个等待该对象就绪的进程
+    ///
+    /// 本章没有睡眠队列：poll 未就绪时返回 `RESTART_SYSCALL`，进程下次被调度时重新检查，
+    /// 因此现有变体无需记录。引入真正等待队列的对象应在这里保存 `pid`，就绪时唤醒它。
+    fn register_waiter(&self, _pid: ProcId) {}
+}
+
+impl Pollable for Fd {
+    fn poll_readable(&self) -> bool {
+        match self {
+            Fd::PipeRead(p) => p.available_read() > 0 || p.write_ends_closed(),
+            Fd::Pid(p) => p.exit_code().is_some(),
+            // 标准输入的读取本身会等待控制台输入，视为总是就绪
+            fd => fd.readable(),
+        }
+    }
+
+    fn poll_writable(&self) -> bool {
+        match self {
+            // 读端全部关闭时写入立即返回 EPIPE，同样不会阻塞
+            Fd::PipeWrite(p) => p.available_write() > 0 || p.read_ends_closed(),
+            fd => fd.writable(),
+        }
+    }
+}
+
+/// 终端设备
+///
+/// 本内核只有控制台一个终端。进程的控制终端记录在 `Process::ctty` 中（默认为控制台，fork 继承），
+/// open("/dev/tty") 返回它的 fd，读写与 stdin/stdout 等价。
+/// 还没有前台进程组（tcsetpgrp）：所有进程组都视为前台，读终端不会收到 SIGTTIN。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tty {
+    /// SBI 控制台
+    Console,
+}
+
+impl Tty {
+    /// 从终端逐字节读取，填满 `buf` 后返回（与 stdin 相同，等待控制台输入）
+    fn read(&self, buf: UserBuffer) -> isize {
+        let len = buf.len();
+        for byte in buf {
+            unsafe { *byte = crate::console::getchar() };
+        }
+        len as isize
+    }
+
+    /// 把 `buf` 原样输出到终端
+    fn write(&self, buf: UserBuffer) -> isize {
+        let len = buf.len();
+        for slice in buf.buffers.iter() {
+            print!("{}", unsafe { core::str::from_utf8_unchecked(slice) });
+        }
+        len as isize
+    }
+}
+
+/// 进程描述符（pidfd_open 创建）
+///
+/// 与目标进程共享一个退出状态槽，进程退出时由 `notify_pid_exit` 填入退出码，
+/// poll 据此报告可读。读取不会回收进程，父进程仍需 wait。
+#[derive(Clone)]
+pub struct PidFd {
+    /// 目标进程
+    pub pid: ProcId,
+    /// 退出码，目标进程仍在运行时为 `None`
+    exit: Arc<ExitSlot>,
+}
+
+/// pidfd 共享的退出状态槽
+type ExitSlot = Mutex<Option<i32>>;
+
+/// 进程 -> 打开的 pidfd 的退出状态槽；所有 pidfd 都关闭后槽随之失效
+static PIDFD_WATCHERS: Mutex<BTreeMap<ProcId, Vec<Weak<ExitSlot>>>> =
+    Mutex::new(BTreeMap::new());
+
+impl PidFd {
+    /// 为运行中的进程 `pid` 创建 pidfd，并登记到退出通知表
+    pub fn new(pid: ProcId) -> Self {
+        let exit = Arc::new(Mutex::new(None));
+        let mut watchers = PIDFD_WATCHERS.lock();
+        let slots = watchers.entry(pid).or_default();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.push(Arc::downgrade(&exit));
+        Self { pid, exit }
+    }
+
+    /// 目标进程的退出码，仍在运行时返回 `None`
+    pub fn exit_code(&self) -> Option<i32> {
+        *self.exit.lock()
+    }
+
+    /// 读出 4 字节小端退出码；进程仍在运行或缓冲区不足 4 字节时返回 -1（EAGAIN/EINVAL）
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let Some(code) = self.exit_code() else {
+            return -1;
+        };
+        let bytes = code.to_le_bytes();
+        match buf.buffers.first_mut() {
+            Some(slice) if slice.len() >= bytes.len() => {
+                slice[..bytes.len()].copy_from_slice(&bytes);
+                bytes.len() as isize
+            }
             _ => -1,
         }
     }
 }
+
+/// 进程 `pid` 退出：把退出码交给它所有仍打开的 pidfd
+pub fn notify_pid_exit(pid: ProcId, exit_code: i32) {
+    let Some(slots) = PIDFD_WATCHERS.lock().remove(&pid) else {
+        return;
+    };
+    for exit in slots.iter().filter_map(Weak::upgrade) {
+        *exit.lock() = Some(exit_code);
+    }
+}
+
+/// 匿名内存文件（memfd_create 创建）
+///
+/// 数据保存在内核堆上的可增长缓冲区中，不落盘、不出现在目录树里。
+/// 克隆（fork 继承）得到的副本共享同一份数据，但各自维护读写偏移，
+/// 与 `FileHandle` 的语义保持一致。最后一个引用释放时数据随之回收。
+#[derive(Clone)]
+pub struct MemFile {
+    /// 文件内容
+    data: Arc<Mutex<Vec<u8>>>,
+    /// 当前读写偏移
+    offset: Cell<usize>,
+}
+
+impl MemFile {
+    /// 创建一个空的内存文件
+    pub fn new() -> Self {
+        Self {
+            data: Arc::new(Mutex::new(Vec::new())),
+            offset: Cell::new(0),
+        }
+    }
+
+    /// 当前文件长度
+    pub fn len(&self) -> usize {
+        self.data.lock().len()
+    }
+
+    /// 从当前偏移读取，返回读取的字节数
+    pub fn read(&self, mut buf: UserBuffer) -> isize {
+        let data = self.data.lock();
+        let mut offset = self.offset.get();
+        let mut total = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            if offset >= data.len() {
+                break;
+            }
+            let len = slice.len().min(data.len() - offset);
+            slice[..len].copy_from_slice(&data[offset..offset + len]);
+            offset += len;
+            total += len;
+        }
+        self.offset.set(offset);
+        total as isize
+    }
+
+    /// 从当前偏移写入，空间不足时自动扩展（空洞补零），返回写入的字节数
+    pub fn write(&self, buf: UserBuffer) -> isize {
+        let mut data = self.data.lock();
+        let mut offset = self.offset.get();
+        let mut total = 0usize;
+        for slice in buf.buffers.iter() {
+            let end = offset + slice.len();
+            if end > data.len() {
+                data.resize(end, 0);
+            }
+            data[offset..end].copy_from_slice(slice);
+            offset = end;
+            total += slice.len();
+        }
+        self.offset.set(offset);
+        total as isize
+    }
+
+    /// 把文件大小设为 `len`（ftruncate）：缩小时丢弃末尾的数据，扩大时补零
+    ///
+    /// 扩大所需的内存分配不到时返回 `false`，文件保持原样。
+    pub fn truncate(&self, len: usize) -> bool {
+        let mut data = self.data.lock();
+        let grow = len.saturating_sub(data.len());
+        if data.try_reserve_exact(grow).is_err() {
+            return false;
+        }
+        data.resize(len, 0);
+        true
+    }
+
+    /// 从 `offset` 处读取到 `buf`，不移动读写偏移，返回读取的字节数（mmap 建立映射时使用）
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.lock();
+        let Some(rest) = data.get(offset..) else { return 0 };
+        let len = buf.len().min(rest.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        len
+    }
+
+    /// 把 `buf` 写到 `offset` 处，不移动读写偏移，返回写入的字节数（共享映射写回时使用）
+    ///
+    /// 只写文件范围之内的部分：映射按页对齐，不能因为写回最后一页而把文件变长。
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut data = self.data.lock();
+        let Some(rest) = data.get_mut(offset..) else { return 0 };
+        let len = buf.len().min(rest.len());
+        rest[..len].copy_from_slice(&buf[..len]);
+        len
+    }
+}
+
+/// 管道的文件类型（Linux 的 `S_IFIFO`），`tg_syscall::StatMode` 没有定义这一位
+const S_IFIFO: StatMode = unsafe { StatMode::from_bits_unchecked(0o010000) };
+
+/// fstat 写回用户空间的文件状态
+///
+/// 前缀字段（dev/ino/mode/nlink）与 `tg_syscall::Stat` 的布局一致，
+/// `size` 占用原先保留的填充区，旧的用户程序读取时不受影响。
+#[repr(C)]
+pub struct Stat {
+    /// 设备号
+    pub dev: u64,
+    /// inode 编号
+    pub ino: u64,
+    /// 文件类型
+    pub mode: StatMode,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 文件大小（管道为当前缓冲字节数的近似值）
+    pub size: u64,
+    /// 保留填充
+    pad: [u64; 6],
+}
+
+impl Stat {
+    /// 创建一个全零的 Stat
+    pub fn new() -> Self {
+        Self {
+            dev: 0,
+            ino: 0,
+            mode: StatMode::NULL,
+            nlink: 0,
+            size: 0,
+            pad: [0; 6],
+        }
+    }
+}
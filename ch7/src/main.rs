@@ -46,12 +46,19 @@
 // 在非 RISC-V 架构上允许未使用的代码
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code, unused_imports))]
 
+/// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
+/// 核心转储模块：异常终止的进程写出 core.<pid>
+mod coredump;
 /// 文件系统模块：easy-fs 封装 + 统一的 Fd 枚举
 mod fs;
 /// 进程模块：Process 结构体（含 fd_table 和 signal）
 mod process;
 /// 处理器模块：PROCESSOR 全局管理器
 mod processor;
+/// 信号栈帧模块：SA_SIGINFO 处理函数的 siginfo 与 ucontext
+mod sigframe;
 /// VirtIO 块设备驱动
 mod virtio_block;
 
@@ -65,7 +72,7 @@ use crate::{
     fs::{read_all, FS},
     impls::{Sv39Manager, SyscallContext},
     process::Process,
-    processor::ProcManager,
+    processor::{exit_current, set_pgid, ExitReason, ProcManager},
 };
 use alloc::alloc::alloc;
 use core::{alloc::Layout, cell::UnsafeCell, mem::MaybeUninit};
@@ -84,7 +91,7 @@ use tg_kernel_vm::{
     AddressSpace,
 };
 use tg_sbi;
-use tg_signal::SignalResult;
+use tg_signal::{SignalNo, SignalResult};
 use tg_syscall::Caller;
 use tg_task_manage::{PManager, ProcId};
 use xmas_elf::ElfFile;
@@ -160,6 +167,11 @@ impl KernelSpace {
 /// 内核地址空间全局实例
 static KERNEL_SPACE: KernelSpace = KernelSpace::new();
 
+/// 系统调用需要阻塞时返回该值：主循环回退 sepc 并让出 CPU，下次被调度时重新执行这次 ecall
+///
+/// 取值与 Linux 内核内部的 ERESTARTSYS 相同，不会返回给用户态。
+const RESTART_SYSCALL: isize = -512;
+
 /// VirtIO MMIO 设备地址范围
 pub const MMIO: &[(usize, usize)] = &[(0x1000_1000, 0x00_1000)];
 
@@ -209,14 +221,21 @@ extern "C" fn rust_main() -> ! {
     tg_syscall::init_scheduling(&SyscallContext);
     tg_syscall::init_clock(&SyscallContext);
     tg_syscall::init_signal(&SyscallContext);   // 本章新增：初始化信号系统调用
+    tg_syscall::init_memory(&SyscallContext);
     // 步骤 8：从文件系统加载初始进程 initproc
-    let initproc = read_all(FS.open("initproc", OpenFlags::RDONLY).unwrap());
-    if let Some(process) = Process::from_elf(ElfFile::new(initproc.as_slice()).unwrap()) {
-        PROCESSOR.get_mut().set_manager(ProcManager::new());
-        PROCESSOR
-            .get_mut()
-            .add(process.pid, process, ProcId::from_usize(usize::MAX));
-    }
+    let Some(fd) = FS.open("initproc", OpenFlags::RDONLY) else {
+        no_initproc("not found")
+    };
+    let initproc = read_all(fd);
+    let Some(process) = ElfFile::new(initproc.as_slice()).ok().and_then(Process::from_elf) else {
+        no_initproc("not a valid RISC-V executable")
+    };
+    PROCESSOR.get_mut().set_manager(ProcManager::new());
+    // initproc 自成一个进程组
+    set_pgid(process.pid, process.pid);
+    PROCESSOR
+        .get_mut()
+        .add(process.pid, process, ProcId::from_usize(usize::MAX));
 
     // ─── 主调度循环 ───
     loop {
@@ -230,42 +249,92 @@ extern "C" fn rust_main() -> ! {
                 // ─── 系统调用（ecall 指令触发） ───
                 scause::Trap::Exception(scause::Exception::UserEnvCall) => {
                     use tg_syscall::{SyscallId as Id, SyscallResult as Ret};
+                    // seccomp：不在允许位图中的调用不分发，直接结束进程
+                    let raw_id = task.context.context.a(7);
+                    if !task.syscall_allowed(raw_id) {
+                        log::warn!("process {}: syscall {raw_id} denied by seccomp", task.pid.get_usize());
+                        exit_current(-2, ExitReason::Fault);
+                        continue;
+                    }
+                    // sp 之上的栈空间已被程序占用（如作为缓冲区传给系统调用），但可能还没有访问过，
+                    // 先把它们映射出来，系统调用翻译用户地址时才不会失败
+                    let sp = *task.context.context.sp_mut();
+                    task.grow_stack(sp);
                     let ctx = &mut task.context.context;
                     ctx.move_next();
                     let id: Id = ctx.a(7).into();
                     let args = [ctx.a(0), ctx.a(1), ctx.a(2), ctx.a(3), ctx.a(4), ctx.a(5)];
-                    let syscall_ret = tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args);
+                    let syscall_ret = match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
+                        // tg-syscall 未覆盖的调用交给内核扩展分发
+                        Ret::Unsupported(_) => match impls::handle_extra(ctx.a(7), args) {
+                            Some(ret) => Ret::Done(ret),
+                            None => Ret::Unsupported(id),
+                        },
+                        ret => ret,
+                    };
+                    // 需要阻塞的系统调用：先回退 sepc 再处理信号，信号处理函数返回后会重新执行 ecall
+                    // 其余系统调用在处理信号之前写入返回值，处理函数返回后被中断的上下文中 a0 即为返回值
+                    let restart = matches!(syscall_ret, Ret::Done(RESTART_SYSCALL));
+                    if restart {
+                        *ctx.pc_mut() -= 4;
+                    } else if let Ret::Done(ret) = syscall_ret {
+                        *ctx.a_mut(0) = ret as _;
+                    }
 
                     // ─── 本章新增：信号处理 ───
                     // 在系统调用返回用户态之前，检查并处理待处理信号。
                     // 注意：这只是一个简化的实现位置。理想情况下，
                     // 信号应该在所有 trap 处理完毕、返回用户态之前统一检查。
-                    match task.signal.handle_signals(ctx) {
+                    // 进入用户处理函数时按 sa_mask 临时扩充掩码
+                    match task.deliver_signals() {
                         // 收到终止信号（如 SIGKILL），进程应该退出
-                        SignalResult::ProcessKilled(exit_code) => unsafe {
-                            (*processor).make_current_exited(exit_code as _)
-                        },
+                        SignalResult::ProcessKilled(exit_code) => {
+                            exit_current(exit_code as _, ExitReason::Signal)
+                        }
                         // 未被终止，继续处理系统调用返回值
                         _ => match syscall_ret {
                             Ret::Done(ret) => match id {
-                                Id::EXIT => unsafe { (*processor).make_current_exited(ret) },
-                                _ => {
-                                    let ctx = &mut task.context.context;
-                                    *ctx.a_mut(0) = ret as _;
-                                    unsafe { (*processor).make_current_suspend() };
-                                }
+                                Id::EXIT => exit_current(ret, ExitReason::Exit),
+                                _ => unsafe { (*processor).make_current_suspend() },
                             },
                             Ret::Unsupported(_) => {
                                 log::info!("id = {id:?}");
-                                unsafe { (*processor).make_current_exited(-2) };
+                                exit_current(-2, ExitReason::Fault);
                             }
                         },
                     }
                 }
+                // ─── 缺页：写的是写时复制页则复制出私有页，紧邻栈底时向下增长栈，否则投递 SIGSEGV ───
+                scause::Trap::Exception(
+                    cause @ (scause::Exception::LoadPageFault | scause::Exception::StorePageFault),
+                ) => {
+                    let addr = stval::read();
+                    let cow = matches!(cause, scause::Exception::StorePageFault)
+                        && process::resolve_cow(&task.address_space, addr, 1);
+                    if cow || task.grow_stack(addr) {
+                        unsafe { (*processor).make_current_suspend() };
+                    } else {
+                        log::error!("page fault at {addr:#x}, pc = {:#x}", task.context.context.pc());
+                        task.signal.add_signal(SignalNo::SIGSEGV);
+                        match task.deliver_signals() {
+                            SignalResult::Handled => unsafe { (*processor).make_current_suspend() },
+                            SignalResult::ProcessKilled(exit_code) => {
+                                coredump::dump(task);
+                                exit_current(exit_code as _, ExitReason::Signal)
+                            }
+                            // SIGSEGV 被屏蔽或忽略时无法越过出错的指令，只能结束进程
+                            _ => {
+                                coredump::dump(task);
+                                exit_current(-3, ExitReason::Fault)
+                            }
+                        }
+                    }
+                }
                 // ─── 其他异常/中断：杀死进程 ───
                 e => {
                     log::error!("unsupported trap: {e:?}");
-                    unsafe { (*processor).make_current_exited(-3) };
+                    coredump::dump(task);
+                    exit_current(-3, ExitReason::Fault);
                 }
             }
         } else {
@@ -274,13 +343,32 @@ extern "C" fn rust_main() -> ! {
         }
     }
 
-    tg_sbi::shutdown(false)
+    shutdown(false)
+}
+
+/// 关机：先同步文件系统，再通过 SBI 关机
+///
+/// 除 panic 外的关机路径都应经过这里。
+fn shutdown(failure: bool) -> ! {
+    fs::sync_all();
+    console::flush();
+    tg_sbi::shutdown(failure)
+}
+
+/// initproc 缺失或无法加载：打印原因和镜像中可用的程序后关机，而不是 panic
+fn no_initproc(reason: &str) -> ! {
+    log::error!("cannot load initproc: {reason}");
+    println!("available apps:");
+    FS.readdir("").into_iter().flatten().for_each(|app| println!("{app}"));
+    shutdown(true)
 }
 
 /// Rust panic 处理函数
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
+    // panic 时可能正持有块缓存的锁，不做同步，直接关机
     tg_sbi::shutdown(true)
 }
 
@@ -356,21 +444,30 @@ fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
 mod impls {
     use crate::{
         build_flags,
-        fs::{read_all, Fd, FS},
-        process::Process as ProcStruct,
-        processor::ProcManager,
-        Sv39, PROCESSOR,
+        fs::{fifo_peer_opened, open_fifo, read_all, Fd, MemFile, PidFd, Pollable, Stat, FS},
+        process::{resolve_cow, unshare_page, MemMapping, Process as ProcStruct, WriteTranslate},
+        processor::{
+            continue_process, forget_pgid, group_exists, pgid_of, set_pgid, stop_process, take_stopped, wait_group,
+            ProcManager,
+        },
+        sigframe::{self, SigSource, SI_USER},
+        Sv39, PROCESSOR, RESTART_SYSCALL,
+    };
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        string::String,
+        sync::Arc,
+        vec::Vec,
     };
-    use alloc::{alloc::alloc_zeroed, string::String, vec::Vec};
     use core::{alloc::Layout, ptr::NonNull};
     use spin::Mutex;
     use tg_console::log;
-    use tg_easy_fs::{make_pipe, FSManager, OpenFlags, UserBuffer};
+    use tg_easy_fs::{make_pipe, FSManager, OpenFlags, SeekFrom, UserBuffer, EPIPE};
     use tg_kernel_vm::{
         page_table::{MmuMeta, Pte, VAddr, VmFlags, PPN, VPN},
-        PageManager,
+        AddressSpace, PageManager,
     };
-    use tg_signal::SignalNo;
+    use tg_signal::{SignalAction, SignalNo};
     use tg_syscall::*;
     use tg_task_manage::{PManager, ProcId};
     use xmas_elf::ElfFile;
@@ -383,7 +480,9 @@ mod impls {
 
     impl Sv39Manager {
         /// 自定义标志位：标记此页面由内核分配
-        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
+        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(Self::OWNED_BIT) };
+        /// OWNED 标志在页表项中的位（释放页表时直接按位判断）
+        pub(crate) const OWNED_BIT: usize = 1 << 8;
 
         /// 分配对齐的物理页面（已清零）
         #[inline]
@@ -396,6 +495,17 @@ mod impls {
             }
             .cast()
         }
+
+        /// 归还 `count` 个从 `ptr` 开始的物理页面（与 `page_alloc` 的布局一致）
+        #[inline]
+        fn page_dealloc(ptr: *mut u8, count: usize) {
+            unsafe {
+                dealloc(
+                    ptr,
+                    Layout::from_size_align_unchecked(count << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
@@ -428,11 +538,61 @@ mod impls {
             *flags |= Self::OWNED;
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        /// 释放 `pte` 指向的 `len` 个物理页面，返回释放的页数
+        ///
+        /// 只回收带 OWNED 标志、由本地址空间分配的页；外部映射的页（恒等映射、异界传送门）不归它所有。
+        /// fork 共享的页与 `drop_root` 一样，由最后一个映射它的地址空间释放。
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            if let Some(last) = unshare_page(pte.ppn().val()) {
+                if !last {
+                    return 0;
+                }
+            } else if !self.check_owned(pte) {
+                return 0;
+            }
+            Self::page_dealloc(self.p_to_v::<u8>(pte.ppn()).as_ptr(), len);
+            len
         }
+
+        /// 释放整棵页表：自有的叶子页面、全部中间页表，最后是根页表本身
+        ///
+        /// 用户地址只落在根页表的低半部分；高半部分的异界传送门项是从内核地址空间复制来的，
+        /// 指向的子树属于内核，不能释放。叶子页按 4 KiB 逐页归还。
         fn drop_root(&mut self) {
-            todo!()
+            const PTE_V: usize = 1 << 0;
+            const PTE_RWX: usize = 0b111 << 1;
+            fn free_table(table: *mut usize, level: usize, entries: usize) {
+                for index in 0..entries {
+                    let pte = unsafe { *table.add(index) };
+                    if pte & PTE_V == 0 {
+                        continue;
+                    }
+                    let ppn = (pte >> 10) & ((1 << 44) - 1);
+                    let page = (ppn << Sv39::PAGE_BITS) as *mut u8;
+                    if pte & PTE_RWX == 0 {
+                        if level > 0 {
+                            free_table(page.cast(), level - 1, 512);
+                        }
+                    } else if let Some(last) = unshare_page(ppn) {
+                        // fork 共享的页：最后一个映射它的地址空间负责释放，不论它是否为原主
+                        if last {
+                            Sv39Manager::page_dealloc(page, 1);
+                        }
+                    } else if pte & Sv39Manager::OWNED_BIT != 0 {
+                        // level 级的叶子覆盖 512^level 个页面
+                        Sv39Manager::page_dealloc(page, 1 << (9 * level));
+                    }
+                }
+                Sv39Manager::page_dealloc(table.cast(), 1);
+            }
+            free_table(self.0.as_ptr().cast(), 2, 256);
+        }
+    }
+
+    impl Drop for Sv39Manager {
+        /// 地址空间被释放（进程退出、exec 换掉旧地址空间）时回收它的页表和页面
+        fn drop(&mut self) {
+            self.drop_root();
         }
     }
 
@@ -444,7 +604,7 @@ mod impls {
     impl tg_console::Console for Console {
         #[inline]
         fn put_char(&self, c: u8) {
-            tg_sbi::console_putchar(c);
+            crate::console::put_char(c);
         }
     }
 
@@ -457,6 +617,12 @@ mod impls {
     const READABLE: VmFlags<Sv39> = build_flags("RV");
     /// 可写权限标志
     const WRITEABLE: VmFlags<Sv39> = build_flags("W_V");
+    /// 路径的最大长度（含结尾的 `\0`），与 Linux 的 `PATH_MAX` 相同
+    const PATH_MAX: usize = 4096;
+    /// 单个 argv/envp 字符串的最大长度，与 Linux 的 `MAX_ARG_STRLEN` 相同
+    const MAX_ARG_STRLEN: usize = 32 << 12;
+    /// 参数非法
+    const EINVAL: isize = -22;
 
     /// IO 系统调用实现
     ///
@@ -469,8 +635,8 @@ mod impls {
         fn write(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
             if let Some(ptr) = current.address_space.translate(VAddr::new(buf), READABLE) {
-                if fd == STDOUT || fd == STDDEBUG {
-                    // 标准输出：直接打印到控制台
+                if current.is_console(fd, true) {
+                    // 标准输出（及 dup 出的副本）：直接打印到控制台
                     print!("{}", unsafe {
                         core::str::from_utf8_unchecked(core::slice::from_raw_parts(
                             ptr.as_ptr(),
@@ -478,13 +644,30 @@ mod impls {
                         ))
                     });
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     // 普通文件或管道：通过 Fd 统一接口写入
                     let file = file.lock();
                     if file.writable() {
+                        // RLIMIT_FSIZE：超出部分被截断并投递 SIGXFSZ，一个字节都写不了时返回 -1
+                        let mut count = count;
+                        if let Some(offset) = file.offset() {
+                            let room = current.fsize_limit.saturating_sub(offset);
+                            if count > room {
+                                current.signal.add_signal(SignalNo::SIGXFSZ);
+                                if room == 0 {
+                                    return -1;
+                                }
+                                count = room;
+                            }
+                        }
                         let mut v: Vec<&'static mut [u8]> = Vec::new();
                         unsafe { v.push(core::slice::from_raw_parts_mut(ptr.as_ptr(), count)) };
-                        file.write(UserBuffer::new(v)) as _
+                        let ret = file.write(UserBuffer::new(v));
+                        // 管道读端已全部关闭：与 Linux 一样投递 SIGPIPE，并返回 EPIPE
+                        if ret == EPIPE {
+                            current.signal.add_signal(SignalNo::SIGPIPE);
+                        }
+                        ret
                     } else {
                         log::error!("file not writable");
                         -1
@@ -502,18 +685,31 @@ mod impls {
         /// read 系统调用：从文件/管道/标准输入读取
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
+            // 先确认 fd 可读，再替用户缓冲区解除写时复制：无效 fd 不应触发按用户长度的页表遍历
+            match current.fd_table.get(fd) {
+                Some(Some(file)) if file.lock().readable() => {}
+                Some(Some(_)) => {
+                    log::error!("file not readable");
+                    return -1;
+                }
+                _ => {
+                    log::error!("unsupported fd: {fd}");
+                    return -1;
+                }
+            }
+            resolve_cow(&current.address_space, buf, count);
             if let Some(ptr) = current.address_space.translate(VAddr::new(buf), WRITEABLE) {
-                if fd == STDIN {
-                    // 标准输入：通过 SBI 逐字符读取
+                if current.is_console(fd, false) {
+                    // 标准输入（及 dup 出的副本）：通过 SBI 逐字符读取
                     let mut ptr = ptr.as_ptr();
                     for _ in 0..count {
                         unsafe {
-                            *ptr = tg_sbi::console_getchar() as u8;
+                            *ptr = crate::console::getchar();
                             ptr = ptr.add(1);
                         }
                     }
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     // 普通文件或管道：通过 Fd 统一接口读取
                     let file = file.lock();
                     if file.readable() {
@@ -537,32 +733,54 @@ mod impls {
         /// open 系统调用：打开文件（与第六章相同，但 fd_table 中存 Fd::File）
         fn open(&self, _caller: Caller, path: usize, flags: usize) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
-            if let Some(ptr) = current.address_space.translate(VAddr::new(path), READABLE) {
-                // 从用户空间逐字符读取文件路径
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
-                        }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
-                    }
+            // 上次因等待命名管道对端而阻塞的 open：对端出现后返回已分配的 fd
+            if let Some((inode_id, read, fd)) = current.pending_fifo_open {
+                if !fifo_peer_opened(inode_id, read) {
+                    return RESTART_SYSCALL;
                 }
-
-                if let Some(file_handle) =
-                    FS.open(string.as_str(), OpenFlags::from_bits(flags as u32).unwrap())
-                {
+                current.pending_fifo_open = None;
+                return fd as isize;
+            }
+            if let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) {
+                // /dev 下的设备文件不经过 easy-fs
+                if let Some(mut device) = Fd::open_device(&string) {
+                    // /dev/tty 指向进程自己的控制终端，没有控制终端时失败（ENXIO）
+                    if let Fd::Tty(tty) = &mut device {
+                        let Some(ctty) = current.ctty else {
+                            return -6;
+                        };
+                        *tty = ctty;
+                    }
                     let new_fd = current.fd_table.len();
+                    current.fd_table.push(Some(Arc::new(Mutex::new(device))));
+                    new_fd as isize
+                } else {
+                    let flags = OpenFlags::from_bits(flags as u32).unwrap();
+                    let file_handle = match FS.open_errno(string.as_str(), flags) {
+                        Ok(file_handle) => file_handle,
+                        Err(errno) => return errno,
+                    };
+                    let new_fd = current.fd_table.len();
+                    // 命名管道：打开内核中共享的管道缓冲区，对端还没打开过时阻塞等待
+                    if let Some(inode) = file_handle.inode.as_ref().filter(|inode| inode.is_fifo()) {
+                        let read = file_handle.readable();
+                        if read == file_handle.writable() {
+                            log::error!("open: FIFO must be opened read-only or write-only");
+                            return -1;
+                        }
+                        let inode_id = inode.inode_id();
+                        current.fd_table.push(Some(Arc::new(Mutex::new(open_fifo(inode_id, read)))));
+                        if !fifo_peer_opened(inode_id, read) {
+                            current.pending_fifo_open = Some((inode_id, read, new_fd));
+                            return RESTART_SYSCALL;
+                        }
+                        return new_fd as isize;
+                    }
                     // 将 FileHandle 包装为 Fd::File 存入 fd_table
                     current
                         .fd_table
-                        .push(Some(Mutex::new(Fd::File((*file_handle).clone()))));
+                        .push(Some(Arc::new(Mutex::new(Fd::File((*file_handle).clone())))));
                     new_fd as isize
-                } else {
-                    -1
                 }
             } else {
                 log::error!("ptr not writeable");
@@ -578,6 +796,7 @@ mod impls {
                 return -1;
             }
             current.fd_table[fd].take();
+            current.cloexec.remove(&fd);
             0
         }
 
@@ -600,7 +819,7 @@ mod impls {
             // 将 read_fd 写入用户空间的 pipe[0]
             if let Some(mut ptr) = current
                 .address_space
-                .translate::<usize>(VAddr::new(pipe), WRITEABLE)
+                .translate_for_write::<usize>(VAddr::new(pipe), WRITEABLE)
             {
                 unsafe { *ptr.as_mut() = read_fd };
             } else {
@@ -609,7 +828,7 @@ mod impls {
             // 将 write_fd 写入用户空间的 pipe[1]
             if let Some(mut ptr) = current
                 .address_space
-                .translate::<usize>(VAddr::new(pipe + core::mem::size_of::<usize>()), WRITEABLE)
+                .translate_for_write::<usize>(VAddr::new(pipe + core::mem::size_of::<usize>()), WRITEABLE)
             {
                 unsafe { *ptr.as_mut() = write_fd };
             } else {
@@ -618,12 +837,35 @@ mod impls {
             // 将读端和写端加入 fd_table
             current
                 .fd_table
-                .push(Some(Mutex::new(Fd::PipeRead(read_end))));
+                .push(Some(Arc::new(Mutex::new(Fd::PipeRead(read_end)))));
             current
                 .fd_table
-                .push(Some(Mutex::new(Fd::PipeWrite(write_end))));
+                .push(Some(Arc::new(Mutex::new(Fd::PipeWrite(write_end)))));
             0
         }
+
+        /// fstat 系统调用：获取文件状态
+        ///
+        /// 管道返回 FIFO 类型，`size` 近似为当前缓冲的数据量，便于调试管道。
+        fn fstat(&self, _caller: Caller, fd: usize, st: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let stat = match current.fd_table.get(fd) {
+                Some(Some(file)) => match file.lock().stat() {
+                    Some(stat) => stat,
+                    None => return -1,
+                },
+                _ => return -1,
+            };
+            if let Some(mut ptr) = current
+                .address_space
+                .translate_for_write::<Stat>(VAddr::new(st), WRITEABLE)
+            {
+                unsafe { *ptr.as_mut() = stat };
+                0
+            } else {
+                -1
+            }
+        }
     }
 
     /// 进程管理系统调用实现（与第六章基本相同）
@@ -642,6 +884,8 @@ mod impls {
             let pid = child_proc.pid;
             let context = &mut child_proc.context.context;
             *context.a_mut(0) = 0 as _;
+            // 子进程继承父进程的进程组
+            set_pgid(pid, pgid_of(parent_pid).unwrap_or(parent_pid));
             unsafe {
                 (*processor).add(pid, child_proc, parent_pid);
             }
@@ -651,6 +895,9 @@ mod impls {
         /// exec 系统调用：从文件系统加载新程序
         fn exec(&self, _caller: Caller, path: usize, count: usize) -> isize {
             const READABLE: VmFlags<Sv39> = build_flags("RV");
+            if count >= PATH_MAX {
+                return -1;
+            }
             let current = PROCESSOR.get_mut().current().unwrap();
             current
                 .address_space
@@ -663,36 +910,34 @@ mod impls {
                     || {
                         log::error!("unknown app, select one in the list: ");
                         FS.readdir("")
-                            .unwrap()
                             .into_iter()
+                            .flatten()
                             .for_each(|app| println!("{app}"));
                         println!();
                         -1
                     },
                     |fd| {
-                        current.exec(ElfFile::new(&read_all(fd)).unwrap());
-                        0
+                        // ELF 解析或加载失败时返回 -1，当前进程不受影响
+                        let elf_data = read_all(fd);
+                        match ElfFile::new(&elf_data) {
+                            Ok(elf) => if current.exec(elf) { 0 } else { -1 },
+                            Err(_) => -1,
+                        }
                     },
                 )
         }
 
-        /// wait 系统调用
+        /// wait 系统调用：回收已退出的子进程，status 写入原始退出码
+        ///
+        /// `pid` 的含义见 `wait_child`；被停止的子进程不报告，需要时使用 wait4。
         fn wait(&self, _caller: Caller, pid: isize, exit_code_ptr: usize) -> isize {
-            let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
-            let current = unsafe { (*processor).current().unwrap() };
-            const WRITABLE: VmFlags<Sv39> = build_flags("W_V");
-            if let Some((dead_pid, exit_code)) =
-                unsafe { (*processor).wait(ProcId::from_usize(pid as usize)) }
-            {
-                if let Some(mut ptr) = current
-                    .address_space
-                    .translate::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
-                {
-                    unsafe { *ptr.as_mut() = exit_code as i32 };
+            match self.wait_child(pid, false) {
+                Some((child, ChildState::Exited(code))) => {
+                    write_status(exit_code_ptr, code);
+                    child
                 }
-                return dead_pid.get_usize() as isize;
-            } else {
-                return -1;
+                Some((child, _)) => child,
+                None => -1,
             }
         }
 
@@ -733,7 +978,7 @@ mod impls {
                         .current()
                         .unwrap()
                         .address_space
-                        .translate(VAddr::new(tp), WRITABLE)
+                        .translate_for_write(VAddr::new(tp), WRITABLE)
                     {
                         let time = riscv::register::time::read() * 10000 / 125;
                         *unsafe { ptr.as_mut() } = TimeSpec {
@@ -760,15 +1005,32 @@ mod impls {
     /// - `sigreturn`：从信号处理函数返回
     impl Signal for SyscallContext {
         /// kill 系统调用：向指定 PID 的进程发送信号
+        ///
+        /// SIGSTOP 和 SIGCONT 的作业控制语义由内核直接处理，不进入进程的待处理信号集：
+        /// SIGSTOP 停止目标进程，SIGCONT 让它继续；SIGKILL 也会先让停止的进程继续，以便它被终止。
         fn kill(&self, _caller: Caller, pid: isize, signum: u8) -> isize {
-            if let Some(target_task) = PROCESSOR
-                .get_mut()
-                .get_task(ProcId::from_usize(pid as usize))
-            {
+            let sender = PROCESSOR.get_mut().current().unwrap().pid.get_usize();
+            let pid = ProcId::from_usize(pid as usize);
+            if let Some(target_task) = PROCESSOR.get_mut().get_task(pid) {
                 if let Ok(signal_no) = SignalNo::try_from(signum) {
-                    if signal_no != SignalNo::ERR {
-                        target_task.signal.add_signal(signal_no);
-                        return 0;
+                    match signal_no {
+                        SignalNo::ERR => {}
+                        SignalNo::SIGSTOP => {
+                            stop_process(pid);
+                            return 0;
+                        }
+                        SignalNo::SIGCONT => {
+                            continue_process(pid);
+                            return 0;
+                        }
+                        _ => {
+                            if signal_no == SignalNo::SIGKILL {
+                                continue_process(pid);
+                            }
+                            target_task.signal_sources[signum as usize] = SigSource { pid: sender, code: SI_USER };
+                            target_task.signal.add_signal(signal_no);
+                            return 0;
+                        }
                     }
                 }
             }
@@ -779,6 +1041,8 @@ mod impls {
         ///
         /// - old_action != 0 时：将当前信号处理函数写入 old_action 指向的地址
         /// - action != 0 时：从 action 指向的地址读取新的信号处理函数并设置
+        ///
+        /// 这里的结构体没有 flags 字段，设置的处理函数不带 `SA_SIGINFO`（需要时使用 `rt_sigaction`）。
         fn sigaction(
             &self,
             _caller: Caller,
@@ -794,11 +1058,15 @@ mod impls {
                 if signal_no == SignalNo::ERR {
                     return -1;
                 }
+                // SIGKILL 和 SIGSTOP 不可捕获
+                if action != 0 && matches!(signal_no, SignalNo::SIGKILL | SignalNo::SIGSTOP) {
+                    return -1;
+                }
                 // 如果需要返回旧的处理函数
                 if old_action as usize != 0 {
                     if let Some(mut ptr) = current
                         .address_space
-                        .translate(VAddr::new(old_action), WRITEABLE)
+                        .translate_for_write(VAddr::new(old_action), WRITEABLE)
                     {
                         if let Some(signal_action) = current.signal.get_action_ref(signal_no) {
                             *unsafe { ptr.as_mut() } = signal_action;
@@ -821,6 +1089,7 @@ mod impls {
                         {
                             return -1;
                         }
+                        current.siginfo_actions &= !(1 << signum);
                     } else {
                         return -1;
                     }
@@ -838,16 +1107,886 @@ mod impls {
 
         /// sigreturn 系统调用：从信号处理函数返回
         ///
-        /// 恢复进程被信号中断前的上下文（LocalContext）
+        /// 恢复进程被信号中断前的上下文（LocalContext）；`SA_SIGINFO` 处理函数则从用户栈上的
+        /// ucontext 恢复，处理函数对其中寄存器的修改随之生效。
+        /// 返回恢复后的 a0，使主循环写回返回值时不破坏被中断的上下文。
         fn sigreturn(&self, _caller: Caller) -> isize {
             let current = PROCESSOR.get_mut().current().unwrap();
             if current.signal.sig_return(&mut current.context.context) {
-                0
+                sigframe::restore_frame(current);
+                current.leave_signal_handler();
+                current.context.context.a(0) as isize
             } else {
                 -1
             }
         }
     }
+
+    /// 内存不足
+    const ENOMEM: isize = -12;
+    /// 共享映射：修改对映射同一文件的其他进程可见，并写回文件
+    const MAP_SHARED: i32 = 0x01;
+    /// 匿名映射：不对应任何文件，内容全为 0
+    const MAP_ANONYMOUS: i32 = 0x20;
+
+    /// 内存管理系统调用实现
+    ///
+    /// 支持匿名映射与 memfd（`memfd_create` 创建的 fd）的映射，普通文件不能映射。
+    impl Memory for SyscallContext {
+        /// mmap 系统调用：映射内存区域，成功返回 0
+        ///
+        /// 映射 memfd 时把文件内容复制到新分配的页；`MAP_SHARED` 的映射在 munmap、exec
+        /// 或进程退出时把修改过的页写回 memfd，私有映射的修改不影响文件。
+        fn mmap(
+            &self,
+            _caller: Caller,
+            addr: usize,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: usize,
+        ) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+            // 检查地址是否页对齐，prot 只能有 bit 0-2 且不能为 0
+            if addr & (PAGE_SIZE - 1) != 0 || prot & !0x7 != 0 || prot == 0 {
+                return EINVAL;
+            }
+            if len == 0 {
+                return 0;
+            }
+            let Some(end) = len.checked_add(PAGE_SIZE - 1).and_then(|len| addr.checked_add(len & !(PAGE_SIZE - 1))) else {
+                return ENOMEM;
+            };
+
+            let current = PROCESSOR.get_mut().current().unwrap();
+            // 非匿名映射：只支持 memfd，与 Linux 一致要求 fd 可读，可写的共享映射还要求 fd 可写
+            let file = if flags & MAP_ANONYMOUS == 0 {
+                if offset & (PAGE_SIZE - 1) != 0 {
+                    return EINVAL;
+                }
+                let Some(Some(fd)) = usize::try_from(fd).ok().and_then(|fd| current.fd_table.get(fd)) else {
+                    return -1;
+                };
+                let fd = fd.lock();
+                let Fd::Mem(file) = &*fd else {
+                    return -1;
+                };
+                if !fd.readable() || (flags & MAP_SHARED != 0 && prot & 0x2 != 0 && !fd.writable()) {
+                    return -13; // EACCES
+                }
+                Some(file.clone())
+            } else {
+                None
+            };
+
+            // 先登记区间：与已映射的页或用户栈保留区重叠时不建立任何映射
+            if !current.add_mmap_area(addr..end) {
+                return -1;
+            }
+
+            // 构建权限标志：U（用户态）+ prot
+            let mut flags_str = *b"U___V";
+            if prot & 0x1 != 0 { flags_str[3] = b'R'; } // 可读
+            if prot & 0x2 != 0 { flags_str[2] = b'W'; } // 可写
+            if prot & 0x4 != 0 { flags_str[1] = b'X'; } // 可执行
+            let vm_flags = build_flags(unsafe { core::str::from_utf8_unchecked(&flags_str) });
+            current.address_space.map(
+                VAddr::<Sv39>::new(addr).floor()..VAddr::<Sv39>::new(end).ceil(),
+                &[],
+                0,
+                vm_flags,
+            );
+
+            if let Some(file) = file {
+                for page_addr in (addr..end).step_by(PAGE_SIZE) {
+                    let page = current
+                        .address_space
+                        .translate::<u8>(VAddr::new(page_addr), build_flags("__V"))
+                        .unwrap();
+                    let page = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), PAGE_SIZE) };
+                    file.read_at(offset + (page_addr - addr), page);
+                }
+                if flags & MAP_SHARED != 0 {
+                    current.mem_maps.push(MemMapping {
+                        start: addr,
+                        len: end - addr,
+                        file,
+                        offset,
+                    });
+                }
+            }
+            0
+        }
+
+        /// munmap 系统调用：取消内存映射，成功返回 0
+        ///
+        /// 范围必须完全落在 mmap 建立的区间内；共享 memfd 映射中修改过的页先写回 memfd。
+        fn munmap(&self, _caller: Caller, addr: usize, len: usize) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+            if addr & (PAGE_SIZE - 1) != 0 {
+                return EINVAL;
+            }
+            if len == 0 {
+                return 0;
+            }
+            let Some(end) = len.checked_add(PAGE_SIZE - 1).and_then(|len| addr.checked_add(len & !(PAGE_SIZE - 1))) else {
+                return EINVAL;
+            };
+
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if !current.remove_mmap_area(addr, end) {
+                return -1;
+            }
+
+            // 先把共享映射的修改写回 memfd，完全被取消的映射不再记录
+            current.sync_mem_maps(addr, end);
+            current.mem_maps.retain(|m| m.start < addr || m.start + m.len > end);
+
+            current
+                .address_space
+                .unmap(VAddr::<Sv39>::new(addr).floor()..VAddr::<Sv39>::new(end).ceil());
+            0
+        }
+    }
+
+    // ─── 扩展系统调用（tg-syscall 未提供的调用） ───
+
+    /// 扩展系统调用号（与 Linux RISC-V 保持一致）
+    mod extra_id {
+        pub const PRLIMIT64: usize = 261;
+        pub const MKNODAT: usize = 33;
+        pub const DUP: usize = 23;
+        pub const LSEEK: usize = 62;
+        pub const FTRUNCATE: usize = 46;
+        pub const DUP3: usize = 24;
+        pub const PIDFD_OPEN: usize = 434;
+        pub const PPOLL: usize = 73;
+        pub const FCNTL: usize = 25;
+        // rt_sigaction(signum, act, oldact)：134 已被 tg-syscall 的 sigaction 占用，使用自定义编号
+        pub const RT_SIGACTION: usize = 1134;
+        pub const MEMFD_CREATE: usize = 279;
+        pub const PRCTL: usize = 167;
+        pub const SETPGID: usize = 154;
+        pub const GETPGID: usize = 155;
+        // 本内核自定义：订阅进程退出事件
+        pub const SUBSCRIBE_PROC_EVENTS: usize = 2001;
+        // 本内核自定义：seccomp_set_allowed(bitmap, words)，语义比 Linux 的 seccomp(277) 简单得多
+        pub const SECCOMP_SET_ALLOWED: usize = 2002;
+        // execve(path, argv, envp)：221 已被 tg-syscall 的 exec(path, len) 占用，使用自定义编号
+        pub const EXECVE: usize = 1221;
+        // RISC-V Linux 没有 dup2（libc 用 dup3 实现），本内核以自定义编号提供
+        pub const DUP2: usize = 2020;
+        // wait4(pid, wstatus, options)：260 已被 tg-syscall 的 wait(pid, exit_code) 占用，使用自定义编号
+        pub const WAIT4: usize = 1260;
+    }
+
+    /// 分发扩展系统调用，未知的调用号返回 `None`
+    pub fn handle_extra(id: usize, args: [usize; 6]) -> Option<isize> {
+        let ctx = SyscallContext;
+        let ret = match id {
+            extra_id::PRLIMIT64 => ctx.prlimit64(args[0], args[1], args[2], args[3]),
+            extra_id::MEMFD_CREATE => ctx.memfd_create(args[0], args[1]),
+            extra_id::PRCTL => ctx.prctl(args[0], args[1]),
+            extra_id::SETPGID => ctx.setpgid(args[0], args[1]),
+            extra_id::GETPGID => ctx.getpgid(args[0]),
+            extra_id::SUBSCRIBE_PROC_EVENTS => ctx.subscribe_proc_events(),
+            extra_id::SECCOMP_SET_ALLOWED => ctx.seccomp_set_allowed(args[0], args[1]),
+            extra_id::MKNODAT => ctx.mknodat(args[0] as i32, args[1], args[2], args[3]),
+            extra_id::DUP => ctx.dup(args[0]),
+            extra_id::LSEEK => ctx.lseek(args[0], args[1] as isize, args[2]),
+            extra_id::FTRUNCATE => ctx.ftruncate(args[0], args[1] as isize),
+            extra_id::DUP2 => ctx.dup2(args[0], args[1]),
+            extra_id::DUP3 => ctx.dup3(args[0], args[1], args[2]),
+            extra_id::PIDFD_OPEN => ctx.pidfd_open(args[0], args[1]),
+            extra_id::PPOLL => ctx.ppoll(args[0], args[1], args[2]),
+            extra_id::FCNTL => ctx.fcntl(args[0], args[1], args[2]),
+            extra_id::RT_SIGACTION => ctx.rt_sigaction(args[0], args[1], args[2]),
+            extra_id::EXECVE => ctx.execve(args[0], args[1], args[2]),
+            extra_id::WAIT4 => ctx.wait4(args[0] as isize, args[1], args[2]),
+            _ => return None,
+        };
+        Some(ret)
+    }
+
+    /// 从用户空间读取以 `\0` 结尾的字符串，最多读 `max` 字节
+    ///
+    /// 字符串可以跨页，每到新的一页重新翻译地址。地址未映射，或读满 `max` 字节仍没有 `\0`
+    /// （ENAMETOOLONG）时返回 `None`，调用方返回 -1。
+    fn read_user_cstr(space: &AddressSpace<Sv39, Sv39Manager>, ptr: usize, max: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut raw_ptr: *const u8 = core::ptr::null();
+        for addr in ptr..ptr.checked_add(max)? {
+            if addr == ptr || addr & ((1 << Sv39::PAGE_BITS) - 1) == 0 {
+                raw_ptr = space.translate::<u8>(VAddr::new(addr), READABLE)?.as_ptr();
+            }
+            let ch = unsafe { *raw_ptr };
+            if ch == 0 {
+                return Some(string);
+            }
+            string.push(ch as char);
+            raw_ptr = unsafe { raw_ptr.add(1) };
+        }
+        None
+    }
+
+    /// 从用户空间读取以空指针结尾的字符串指针数组（argv/envp），`addr` 为 0 视为空数组
+    fn read_user_cstr_array(current: &ProcStruct, addr: usize) -> Option<Vec<String>> {
+        let mut strings = Vec::new();
+        if addr == 0 {
+            return Some(strings);
+        }
+        for i in 0.. {
+            let item = VAddr::new(addr + i * core::mem::size_of::<usize>());
+            let ptr = current.address_space.translate::<usize>(item, READABLE)?;
+            let str_addr = unsafe { *ptr.as_ref() };
+            if str_addr == 0 {
+                break;
+            }
+            strings.push(read_user_cstr(&current.address_space, str_addr, MAX_ARG_STRLEN)?);
+        }
+        Some(strings)
+    }
+
+    /// 读入要执行的文件，`#!` 开头的脚本换成它的解释器
+    ///
+    /// 与 Linux 相同，脚本首行 `#!interp [arg]` 把参数改写为 `[interp, arg, 脚本路径, 原 argv[1..]]`，
+    /// 解释器本身也可以是脚本，最多嵌套 `MAX_INTERP_DEPTH` 层。easy-fs 只有根目录，
+    /// 解释器路径只取最后一个分量（`#!/bin/sh` 即根目录下的 `sh`）。
+    /// 返回最终要加载的文件内容和改写后的参数。
+    fn load_executable(mut path: String, mut argv: Vec<String>) -> Option<(Vec<u8>, Vec<String>)> {
+        for _ in 0..=MAX_INTERP_DEPTH {
+            let data = read_all(FS.open(&path, OpenFlags::RDONLY)?);
+            let Some(line) = data.strip_prefix(b"#!") else {
+                return Some((data, argv));
+            };
+            let line = &line[..line.iter().position(|&b| b == b'\n').unwrap_or(line.len())];
+            let line = core::str::from_utf8(line).ok()?.trim();
+            let (interp, arg) = match line.split_once([' ', '\t']) {
+                Some((interp, arg)) => (interp, arg.trim()),
+                None => (line, ""),
+            };
+            let interp = interp.rsplit('/').next().filter(|name| !name.is_empty())?;
+            let mut new_argv = Vec::with_capacity(argv.len() + 2);
+            new_argv.push(String::from(interp));
+            if !arg.is_empty() {
+                new_argv.push(String::from(arg));
+            }
+            new_argv.push(path);
+            new_argv.extend(argv.into_iter().skip(1));
+            path = String::from(interp);
+            argv = new_argv;
+        }
+        log::warn!("execve: too many levels of #! interpreters");
+        None
+    }
+
+    /// `#!` 解释器的最大嵌套层数（与 Linux 的 `BINPRM_MAX_RECURSION` 相同）
+    const MAX_INTERP_DEPTH: usize = 4;
+
+    /// 以当前工作目录解析相对路径（与 Linux 保持一致）
+    const AT_FDCWD: i32 = -100;
+    /// mknod 模式中的文件类型位
+    const S_IFMT: usize = 0o170000;
+    /// 文件类型：命名管道
+    const S_IFIFO: usize = 0o010000;
+    /// wait4 选项：被停止的子进程也让 wait4 返回
+    const WUNTRACED: usize = 2;
+
+    /// `wait_child` 找到的子进程状态
+    enum ChildState {
+        /// 已退出并被回收，附带退出码
+        Exited(i32),
+        /// 被 SIGSTOP 停止（只在 `untraced` 时报告）
+        Stopped,
+        /// 有匹配的子进程，但都还在运行
+        Running,
+    }
+
+    /// 把 wait 的 status 写到当前进程的用户地址 `ptr`，地址不可写时忽略
+    fn write_status(ptr: usize, status: i32) {
+        let current = PROCESSOR.get_mut().current().unwrap();
+        if let Some(mut ptr) = current.address_space.translate_for_write::<i32>(VAddr::new(ptr), build_flags("W_V")) {
+            unsafe { *ptr.as_mut() = status };
+        }
+    }
+
+    /// 用户态的 `struct pollfd`
+    #[repr(C)]
+    struct PollFd {
+        fd: i32,
+        events: i16,
+        revents: i16,
+    }
+
+    /// poll 事件：可读
+    const POLLIN: i16 = 0x1;
+    /// poll 事件：可写
+    const POLLOUT: i16 = 0x4;
+    /// poll 事件：fd 无效
+    const POLLNVAL: i16 = 0x20;
+
+    /// seccomp 位图最多的 `u64` 个数，可覆盖 0 ~ 4095 号系统调用
+    const SECCOMP_MAX_WORDS: usize = 64;
+
+    /// 资源编号：单个文件大小上限（与 Linux 保持一致）
+    const RLIMIT_FSIZE: usize = 1;
+    /// 资源编号：用户栈大小上限
+    const RLIMIT_STACK: usize = 3;
+    /// 资源编号：核心转储文件大小上限，0 表示不生成
+    const RLIMIT_CORE: usize = 4;
+
+    /// prctl 选项：读取可转储标志
+    const PR_GET_DUMPABLE: usize = 3;
+    /// prctl 选项：设置可转储标志
+    const PR_SET_DUMPABLE: usize = 4;
+
+    /// 用户态的 `struct rlimit`
+    #[repr(C)]
+    struct RLimit {
+        /// 软限制
+        cur: u64,
+        /// 硬限制
+        max: u64,
+    }
+
+    impl SyscallContext {
+        /// prlimit64 系统调用：读取/设置资源限制
+        ///
+        /// 目前只支持当前进程（`pid` 为 0 或自身）的 `RLIMIT_FSIZE`、`RLIMIT_STACK` 与 `RLIMIT_CORE`。
+        /// `new_limit`/`old_limit` 为 0 表示不设置/不读取。
+        fn prlimit64(&self, pid: usize, resource: usize, new_limit: usize, old_limit: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if pid != 0 && pid != current.pid.get_usize() {
+                return -1;
+            }
+            let current_limit = match resource {
+                RLIMIT_FSIZE => current.fsize_limit,
+                RLIMIT_STACK => current.stack_limit,
+                RLIMIT_CORE => current.core_limit,
+                _ => {
+                    log::warn!("prlimit64: unsupported resource {resource}");
+                    return -1;
+                }
+            };
+            if old_limit != 0 {
+                let limit = current_limit as u64;
+                match current.address_space.translate_for_write::<RLimit>(VAddr::new(old_limit), WRITEABLE) {
+                    Some(mut ptr) => unsafe { *ptr.as_mut() = RLimit { cur: limit, max: limit } },
+                    None => return -1,
+                }
+            }
+            if new_limit != 0 {
+                match current.address_space.translate::<RLimit>(VAddr::new(new_limit), READABLE) {
+                    Some(ptr) => {
+                        // 软限制不能超过硬限制
+                        let limit = unsafe { ptr.as_ref() };
+                        let limit = limit.cur.min(limit.max) as usize;
+                        match resource {
+                            RLIMIT_FSIZE => current.fsize_limit = limit,
+                            RLIMIT_STACK => current.stack_limit = limit,
+                            _ => current.core_limit = limit,
+                        }
+                    }
+                    None => return -1,
+                }
+            }
+            0
+        }
+
+        /// prctl 系统调用：目前只支持 `PR_GET_DUMPABLE` 与 `PR_SET_DUMPABLE`
+        ///
+        /// 设置时 `arg` 只能是 0（不可转储）或 1（可转储），其他值与未知选项返回 EINVAL。
+        fn prctl(&self, option: usize, arg: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            match option {
+                PR_GET_DUMPABLE => current.dumpable as isize,
+                PR_SET_DUMPABLE if arg <= 1 => {
+                    current.dumpable = arg == 1;
+                    0
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// setpgid 系统调用：把进程 `pid` 移入进程组 `pgid`
+        ///
+        /// `pid` 为 0 表示当前进程，`pgid` 为 0 表示以 `pid` 自身为组号新建进程组。
+        /// 只能修改自己或自己的子进程；加入的若不是新组，该组必须已经存在，否则返回 -1。
+        fn setpgid(&self, pid: usize, pgid: usize) -> isize {
+            let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
+            let me = unsafe { (*processor).current().unwrap().pid };
+            let target = if pid == 0 { me } else { ProcId::from_usize(pid) };
+            let is_self_or_child = target == me
+                || unsafe { (*processor).get_task(target) }.is_some_and(|task| task.parent == me);
+            if !is_self_or_child {
+                return -1;
+            }
+            let pgid = if pgid == 0 { target } else { ProcId::from_usize(pgid) };
+            if pgid != target && !group_exists(pgid) {
+                return -1;
+            }
+            set_pgid(target, pgid);
+            0
+        }
+
+        /// getpgid 系统调用：查询进程所在的进程组，`pid` 为 0 表示当前进程
+        fn getpgid(&self, pid: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let target = if pid == 0 { current.pid } else { ProcId::from_usize(pid) };
+            pgid_of(target).map_or(-1, |pgid| pgid.get_usize() as isize)
+        }
+
+        /// ppoll 系统调用：等待一组 fd 中任意一个就绪，返回就绪的 fd 个数
+        ///
+        /// 就绪状态统一通过 `Pollable` 查询。`timeout` 为 0 表示无限等待，
+        /// 指向 `{0, 0}` 时只检查一次；超时返回 0。信号掩码参数暂不支持，忽略。
+        /// 没有 fd 就绪时返回 `RESTART_SYSCALL`，进程下次被调度时重新检查。
+        fn ppoll(&self, fds: usize, nfds: usize, timeout: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let mut ready = 0;
+            for i in 0..nfds {
+                let addr = fds + i * core::mem::size_of::<PollFd>();
+                let Some(mut ptr) = current.address_space.translate_for_write::<PollFd>(VAddr::new(addr), WRITEABLE)
+                else {
+                    current.poll_deadline = None;
+                    return -1;
+                };
+                let pollfd = unsafe { ptr.as_mut() };
+                pollfd.revents = 0;
+                // 负数 fd 按约定被忽略
+                let Ok(fd) = usize::try_from(pollfd.fd) else { continue };
+                match current.fd_table.get(fd) {
+                    Some(Some(file)) => {
+                        let file = file.lock();
+                        if pollfd.events & POLLIN != 0 && file.poll_readable() {
+                            pollfd.revents |= POLLIN;
+                        }
+                        if pollfd.events & POLLOUT != 0 && file.poll_writable() {
+                            pollfd.revents |= POLLOUT;
+                        }
+                        if pollfd.revents == 0 {
+                            file.register_waiter(current.pid);
+                        }
+                    }
+                    _ => pollfd.revents = POLLNVAL,
+                }
+                if pollfd.revents != 0 {
+                    ready += 1;
+                }
+            }
+            if ready > 0 {
+                current.poll_deadline = None;
+                return ready;
+            }
+            let now = riscv::register::time::read();
+            let deadline = match current.poll_deadline {
+                Some(deadline) => deadline,
+                None if timeout == 0 => usize::MAX,
+                None => match current.address_space.translate::<TimeSpec>(VAddr::new(timeout), READABLE) {
+                    // time 寄存器频率为 12.5 MHz
+                    Some(ptr) => {
+                        let ts = unsafe { ptr.as_ref() };
+                        now.saturating_add(ts.tv_sec * 12_500_000 + ts.tv_nsec / 80)
+                    }
+                    None => return -1,
+                },
+            };
+            if now >= deadline {
+                current.poll_deadline = None;
+                return 0;
+            }
+            current.poll_deadline = Some(deadline);
+            RESTART_SYSCALL
+        }
+
+        /// subscribe_proc_events：订阅进程退出事件，返回只读的管道 fd
+        ///
+        /// 此后任何进程退出时，内核向该管道写入一条 16 字节的记录
+        /// `{pid: u64, exit_code: i32, reason: u32}`（reason 见 `ExitReason`）。
+        /// 管道写满时新事件被丢弃；关闭所有读端即取消订阅。
+        fn subscribe_proc_events(&self) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let (read_end, write_end) = make_pipe();
+            crate::processor::subscribe_proc_events(write_end);
+            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::PipeRead(read_end)))));
+            (current.fd_table.len() - 1) as isize
+        }
+
+        /// seccomp_set_allowed：设置允许的系统调用号位图，之后调用其他系统调用的进程被结束
+        ///
+        /// `bitmap` 指向 `words` 个 8 字节对齐的 `u64`，第 `id` 位（`bitmap[id / 64]` 的第 `id % 64` 位）
+        /// 为 1 表示允许系统调用 `id`，超出位图的调用号一律拒绝。已设置过位图时与旧位图按位与，
+        /// 只能收紧不能放宽。位图随 fork/exec 保留。地址未对齐、`words` 超过 `SECCOMP_MAX_WORDS`
+        /// 返回 EINVAL，位图不可读返回 -1。
+        fn seccomp_set_allowed(&self, bitmap: usize, words: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if !bitmap.is_multiple_of(core::mem::size_of::<u64>()) || words > SECCOMP_MAX_WORDS {
+                return EINVAL;
+            }
+            let mut allowed = Vec::with_capacity(words);
+            for i in 0..words {
+                let addr = VAddr::new(bitmap + i * core::mem::size_of::<u64>());
+                match current.address_space.translate::<u64>(addr, READABLE) {
+                    Some(ptr) => allowed.push(unsafe { *ptr.as_ref() }),
+                    None => return -1,
+                }
+            }
+            current.restrict_syscalls(allowed);
+            0
+        }
+
+        /// memfd_create 系统调用：创建匿名内存文件
+        ///
+        /// 返回的 fd 可读可写，数据只存在于内存中，随最后一个引用关闭而释放。
+        /// `name` 仅打印到日志便于调试；`flags`（MFD_CLOEXEC 等）暂不支持，直接忽略。
+        fn memfd_create(&self, name: usize, _flags: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(string) = read_user_cstr(&current.address_space, name, PATH_MAX) else {
+                log::error!("bad path");
+                return -1;
+            };
+            let new_fd = current.fd_table.len();
+            log::debug!("memfd_create: {string} -> fd {new_fd}");
+            current
+                .fd_table
+                .push(Some(Arc::new(Mutex::new(Fd::Mem(MemFile::new())))));
+            new_fd as isize
+        }
+
+        /// execve 系统调用：执行 `path` 指向的程序，并传递 argv 与 envp
+        ///
+        /// 新程序的用户栈按 `argc, argv[], NULL, envp[], NULL` 排列，a0/a1/a2 分别为
+        /// argc、argv、envp。参数放不进用户栈或 ELF 非法时返回 -1，当前进程不受影响。
+        /// 以 `#!` 开头的脚本交给首行指定的解释器执行（见 `load_executable`）。
+        fn execve(&self, path: usize, argv: usize, envp: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(path) = read_user_cstr(&current.address_space, path, PATH_MAX) else {
+                return -1;
+            };
+            let (Some(argv), Some(envp)) =
+                (read_user_cstr_array(current, argv), read_user_cstr_array(current, envp))
+            else {
+                return -1;
+            };
+            let Some((elf_data, argv)) = load_executable(path, argv) else {
+                return -1;
+            };
+            // 与 Linux 相同，参数最多占用栈上限的四分之一
+            if ProcStruct::args_stack_size(&argv, &envp) > current.stack_limit / 4 {
+                return -1;
+            }
+            match ElfFile::new(&elf_data) {
+                Ok(elf) => if current.exec(elf) { current.push_args(&argv, &envp) as isize } else { -1 },
+                Err(_) => -1,
+            }
+        }
+
+        /// mknodat 系统调用：创建特殊文件，目前只支持命名管道（mkfifo）
+        ///
+        /// easy-fs 只有根目录，`dirfd` 只支持 `AT_FDCWD`；权限位和 `dev` 被忽略。
+        fn mknodat(&self, dirfd: i32, path: usize, mode: usize, _dev: usize) -> isize {
+            if dirfd != AT_FDCWD || mode & S_IFMT != S_IFIFO {
+                return -1;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) else {
+                log::error!("bad path");
+                return -1;
+            };
+            match FS.create_fifo(&string) {
+                Ok(_) => 0,
+                Err(errno) => errno,
+            }
+        }
+
+        /// wait 与 wait4 的公共部分：找出一个状态变化的子进程，返回它的 PID 与状态
+        ///
+        /// `pid` 的含义与 Linux 的 waitpid 相同：大于 0 等待指定子进程，-1 等待任意子进程，
+        /// 0 等待与调用者同一进程组的任意子进程，小于 -1 等待进程组 `-pid` 中的任意子进程。
+        /// 已退出的子进程在这里被回收；`untraced` 时被停止且这次停止尚未报告过的子进程也返回。
+        /// 匹配的子进程都还在运行时 PID 为 -2（调用者稍后重试），没有匹配的子进程时返回 `None`。
+        fn wait_child(&self, pid: isize, untraced: bool) -> Option<(isize, ChildState)> {
+            let processor: *mut PManager<ProcStruct, ProcManager> = PROCESSOR.get_mut() as *mut _;
+            let current = unsafe { (*processor).current().unwrap() };
+            // 按进程组等待时要匹配的组
+            let group = match pid {
+                0 => Some(pgid_of(current.pid).unwrap_or(current.pid)),
+                ..=-2 => Some(ProcId::from_usize(pid.unsigned_abs())),
+                _ => None,
+            };
+            if untraced {
+                let me = current.pid;
+                let stopped = take_stopped(|child| {
+                    let matched = match group {
+                        Some(pgid) => pgid_of(child) == Some(pgid),
+                        None => pid == -1 || child.get_usize() == pid as usize,
+                    };
+                    matched && unsafe { (*processor).get_task(child) }.is_some_and(|task| task.parent == me)
+                });
+                if let Some(child) = stopped {
+                    return Some((child.get_usize() as isize, ChildState::Stopped));
+                }
+            }
+            let (dead_pid, exit_code) = match group {
+                Some(pgid) => wait_group(unsafe { &mut *processor }, pgid),
+                None => unsafe { (*processor).wait(ProcId::from_usize(pid as usize)) },
+            }?;
+            let dead_pid = dead_pid.get_usize() as isize;
+            if dead_pid < 0 {
+                return Some((dead_pid, ChildState::Running));
+            }
+            forget_pgid(ProcId::from_usize(dead_pid as usize));
+            Some((dead_pid, ChildState::Exited(exit_code as i32)))
+        }
+
+        /// wait4 系统调用：与 wait 相同，但带 `options`，status 总是按 Linux 的方式编码
+        ///
+        /// 退出为 `(code & 0xff) << 8`（`WIFEXITED`），停止为 `(SIGSTOP << 8) | 0x7f`（`WIFSTOPPED`）。
+        /// `options` 只支持 `WUNTRACED`，含其他位时返回 -1（EINVAL）；子进程都还在运行时与 wait 一样返回 -2。
+        fn wait4(&self, pid: isize, status_ptr: usize, options: usize) -> isize {
+            if options & !WUNTRACED != 0 {
+                return -1;
+            }
+            match self.wait_child(pid, options & WUNTRACED != 0) {
+                Some((child, ChildState::Exited(code))) => {
+                    write_status(status_ptr, (code & 0xff) << 8);
+                    child
+                }
+                Some((child, ChildState::Stopped)) => {
+                    write_status(status_ptr, ((SignalNo::SIGSTOP as i32) << 8) | 0x7f);
+                    child
+                }
+                Some((child, ChildState::Running)) => child,
+                None => -1,
+            }
+        }
+
+        /// pidfd_open 系统调用：为进程 `pid` 创建进程描述符
+        ///
+        /// 目标进程退出后该 fd 变为可读，配合 ppoll 即可带超时地等待子进程；read 得到 4 字节退出码。
+        /// `flags` 必须为 0；进程不存在（包括已退出、等待回收的进程）时返回 -3（ESRCH）。
+        fn pidfd_open(&self, pid: usize, flags: usize) -> isize {
+            const ESRCH: isize = -3;
+            if flags != 0 {
+                return EINVAL;
+            }
+            let pid = ProcId::from_usize(pid);
+            let processor = PROCESSOR.get_mut();
+            if processor.get_task(pid).is_none() {
+                return ESRCH;
+            }
+            let current = processor.current().unwrap();
+            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::Pid(PidFd::new(pid))))));
+            (current.fd_table.len() - 1) as isize
+        }
+
+        /// lseek 系统调用：按 `whence` 移动 `fd` 的读写偏移，返回新的绝对偏移
+        ///
+        /// `whence` 为 SEEK_SET(0)/SEEK_CUR(1)/SEEK_END(2)，SEEK_END 以文件当前大小为基准。
+        /// 管道、标准输入输出等不能定位的 fd、非法的 `whence` 与结果为负的偏移都返回 -1。
+        fn lseek(&self, fd: usize, offset: isize, whence: usize) -> isize {
+            let from = match whence {
+                0 => SeekFrom::Start,
+                1 => SeekFrom::Current,
+                2 => SeekFrom::End,
+                _ => return -1,
+            };
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            file.lock().seek(offset, from).map_or(-1, |pos| pos as isize)
+        }
+
+        /// ftruncate 系统调用：把 `fd` 对应的文件大小设为 `length`，成功返回 0
+        ///
+        /// 支持普通文件与 memfd，fd 必须可写；memfd 扩大时分配不到内存返回 -12（ENOMEM），
+        /// 管道、设备等其他 fd 返回 -22（EINVAL）。
+        fn ftruncate(&self, fd: usize, length: isize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let Ok(length) = usize::try_from(length) else {
+                return EINVAL;
+            };
+            let file = file.lock();
+            if !file.writable() {
+                return -1;
+            }
+            match &*file {
+                Fd::File(f) => match &f.inode {
+                    Some(inode) if inode.truncate(length) => 0,
+                    _ => -1,
+                },
+                Fd::Mem(m) => if m.truncate(length) { 0 } else { ENOMEM },
+                _ => EINVAL,
+            }
+        }
+
+        /// dup 系统调用：复制 `fd`，返回编号最小的空闲 fd
+        ///
+        /// 新 fd 与 `fd` 共享同一个句柄：控制台的副本仍读写控制台，管道副本共享同一个缓冲区，
+        /// 普通文件的偏移也是共享的，通过任一 fd 读写或 lseek 都会移动另一个 fd 的偏移。
+        /// 新 fd 不带 close-on-exec。
+        fn dup(&self, fd: usize) -> isize {
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.clone();
+            let new_fd = current.install_fd(file);
+            current.cloexec.remove(&new_fd);
+            new_fd as isize
+        }
+
+        /// dup2 系统调用：把 `oldfd` 复制到 `newfd`，`newfd` 已打开时先将其关闭
+        ///
+        /// `oldfd == newfd` 时不做任何事，`oldfd` 有效则返回 `newfd`。
+        /// 其余情况与 `flags` 为 0 的 dup3 相同。
+        fn dup2(&self, oldfd: usize, newfd: usize) -> isize {
+            if oldfd == newfd {
+                let current = PROCESSOR.get_mut().current().unwrap();
+                return match current.fd_table.get(oldfd) {
+                    Some(Some(_)) => newfd as isize,
+                    _ => -1,
+                };
+            }
+            self.dup3(oldfd, newfd, 0)
+        }
+
+        /// dup3 系统调用：把 `oldfd` 复制到 `newfd`，`newfd` 已打开时先将其关闭
+        ///
+        /// 与 dup2 不同，`oldfd == newfd` 返回 -22（EINVAL）；`flags` 只接受 `O_CLOEXEC`，
+        /// 设置后 `newfd` 在 exec 成功时被关闭。`newfd` 与 `oldfd` 共享同一个句柄和文件偏移。
+        fn dup3(&self, oldfd: usize, newfd: usize, flags: usize) -> isize {
+            const O_CLOEXEC: usize = 0o2000000;
+            /// `newfd` 的上限，防止用户用一个很大的 fd 把 fd_table 撑大
+            const NOFILE: usize = 1024;
+            if oldfd == newfd || flags & !O_CLOEXEC != 0 {
+                return EINVAL;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(oldfd) else {
+                return -1;
+            };
+            if newfd >= NOFILE {
+                return -1;
+            }
+            let file = file.clone();
+            if newfd >= current.fd_table.len() {
+                current.fd_table.resize_with(newfd + 1, || None);
+            }
+            current.fd_table[newfd] = Some(file);
+            if flags & O_CLOEXEC != 0 {
+                current.cloexec.insert(newfd);
+            } else {
+                current.cloexec.remove(&newfd);
+            }
+            newfd as isize
+        }
+
+        /// fcntl 系统调用：只支持 F_GETFL / F_SETFL，用于查询与切换 `O_NONBLOCK`
+        ///
+        /// F_GETFL 返回访问模式（O_RDONLY/O_WRONLY/O_RDWR）与 O_NONBLOCK；F_SETFL 只看 O_NONBLOCK，
+        /// 目前只有管道支持非阻塞，对其他描述符置位返回 -22（EINVAL）。
+        fn fcntl(&self, fd: usize, cmd: usize, arg: usize) -> isize {
+            const F_GETFL: usize = 3;
+            const F_SETFL: usize = 4;
+            const O_NONBLOCK: usize = 0o4000;
+            let current = PROCESSOR.get_mut().current().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            match cmd {
+                F_GETFL => {
+                    let mode = match (file.readable(), file.writable()) {
+                        (true, true) => 2,
+                        (false, true) => 1,
+                        _ => 0,
+                    };
+                    let nonblock = if file.is_nonblocking() { O_NONBLOCK } else { 0 };
+                    (mode | nonblock) as isize
+                }
+                F_SETFL => {
+                    let nonblocking = arg & O_NONBLOCK != 0;
+                    if !file.set_nonblocking(nonblocking) && nonblocking {
+                        return EINVAL;
+                    }
+                    0
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// rt_sigaction 系统调用：与 sigaction 相同，但结构体按 Linux 布局带有 `sa_flags`
+        ///
+        /// flags 只识别 `SA_SIGINFO`，其余位被忽略。设置了 `SA_SIGINFO` 的处理函数以
+        /// `(signo, siginfo*, ucontext*)` 调用，栈帧布局见 `sigframe` 模块。
+        fn rt_sigaction(&self, signum: usize, action: usize, old_action: usize) -> isize {
+            /// sa_flags：处理函数接收 siginfo 与 ucontext
+            const SA_SIGINFO: usize = 4;
+            let Some(signal_no) = u8::try_from(signum).ok().and_then(|signum| SignalNo::try_from(signum).ok())
+            else {
+                return EINVAL;
+            };
+            if signum > tg_signal::MAX_SIG || signal_no == SignalNo::ERR {
+                return EINVAL;
+            }
+            // SIGKILL 和 SIGSTOP 不可捕获
+            if action != 0 && matches!(signal_no, SignalNo::SIGKILL | SignalNo::SIGSTOP) {
+                return EINVAL;
+            }
+            let current = PROCESSOR.get_mut().current().unwrap();
+            if old_action != 0 {
+                let Some(old) = current.signal.get_action_ref(signal_no) else {
+                    return -1;
+                };
+                let Some(mut ptr) = current
+                    .address_space
+                    .translate_for_write::<RtSigAction>(VAddr::new(old_action), WRITEABLE)
+                else {
+                    return -1;
+                };
+                let flags = if current.siginfo_actions & (1 << signum) != 0 { SA_SIGINFO } else { 0 };
+                *unsafe { ptr.as_mut() } = RtSigAction { handler: old.handler, flags, mask: old.mask };
+            }
+            if action != 0 {
+                let Some(ptr) = current
+                    .address_space
+                    .translate::<RtSigAction>(VAddr::new(action), READABLE)
+                else {
+                    return -1;
+                };
+                let new = unsafe { *ptr.as_ptr() };
+                if !current.signal.set_action(signal_no, &SignalAction { handler: new.handler, mask: new.mask }) {
+                    return -1;
+                }
+                if new.flags & SA_SIGINFO != 0 {
+                    current.siginfo_actions |= 1 << signum;
+                } else {
+                    current.siginfo_actions &= !(1 << signum);
+                }
+            }
+            0
+        }
+    }
+
+    /// rt_sigaction 的参数结构体（Linux RISC-V 布局，没有 sa_restorer）
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct RtSigAction {
+        /// 处理函数地址
+        handler: usize,
+        /// sa_flags
+        flags: usize,
+        /// 处理期间额外屏蔽的信号
+        mask: usize,
+    }
 }
 
 /// 非 RISC-V64 架构的占位实现
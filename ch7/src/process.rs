@@ -14,16 +14,29 @@
 //! - 再看 `fork`：关注“地址空间/文件描述符/信号配置”分别如何继承；
 //! - 最后看 `exec`：理解“替换程序但保留进程身份”的资源边界。
 
-use crate::{build_flags, fs::Fd, map_portal, parse_flags, Sv39, Sv39Manager};
-use alloc::{alloc::alloc_zeroed, boxed::Box, vec::Vec};
-use core::alloc::Layout;
+use crate::{
+    build_flags,
+    fs::{Fd, MemFile, Tty},
+    map_portal, parse_flags,
+    sigframe::{self, SigSource},
+    Sv39, Sv39Manager,
+};
+use alloc::{
+    alloc::alloc,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{alloc::Layout, mem::size_of, ops::Range, ptr::NonNull};
 use spin::Mutex;
 use tg_kernel_context::{foreign::ForeignContext, LocalContext};
 use tg_kernel_vm::{
-    page_table::{MmuMeta, VAddr, PPN, VPN},
+    page_table::{MmuMeta, VAddr, VmFlags, PPN, VPN},
     AddressSpace,
 };
-use tg_signal::Signal;
+use tg_signal::{Signal, SignalNo, SignalResult, MAX_SIG};
 use tg_signal_impl::SignalImpl;
 use tg_task_manage::ProcId;
 use xmas_elf::{
@@ -31,6 +44,11 @@ use xmas_elf::{
     program, ElfFile,
 };
 
+/// 用户栈顶
+const USER_STACK_TOP: usize = 1 << 38;
+/// RLIMIT_STACK 的默认值（与 Linux 相同，8 MiB）
+const DEFAULT_STACK_LIMIT: usize = 8 << 20;
+
 /// 进程结构体
 ///
 /// 与第六章相比新增了 `signal` 字段，`fd_table` 改为存储 `Fd` 枚举。
@@ -42,7 +60,9 @@ pub struct Process {
     /// 进程的独立地址空间
     pub address_space: AddressSpace<Sv39, Sv39Manager>,
     /// 统一文件描述符表（本章使用 Fd 枚举替代 FileHandle）
-    pub fd_table: Vec<Option<Mutex<Fd>>>,
+    pub fd_table: Vec<Option<Arc<Mutex<Fd>>>>,
+    /// 设置了 close-on-exec 的 fd（dup3 的 `O_CLOEXEC`），exec 成功后被关闭
+    pub cloexec: BTreeSet<usize>,
     /// 信号处理器（**本章新增**）
     ///
     /// 使用 `Box<dyn Signal>` trait 对象，支持多态和 fork 时的继承。
@@ -56,49 +76,340 @@ pub struct Process {
     pub heap_bottom: usize,
     /// 当前程序 break 位置
     pub program_brk: usize,
+    /// RLIMIT_FSIZE：写文件时允许达到的最大文件偏移（fork/exec 后保留）
+    pub fsize_limit: usize,
+    /// RLIMIT_STACK：用户栈自动增长的上限（fork/exec 后保留）
+    pub stack_limit: usize,
+    /// RLIMIT_CORE：核心转储文件的大小上限，0 表示不生成（fork/exec 后保留）
+    pub core_limit: usize,
+    /// PR_SET_DUMPABLE：为 `false` 时致命信号结束进程也不生成核心转储（fork 继承，exec 后恢复为 `true`）
+    ///
+    /// Linux 在 setuid 等凭据变化时会自动清除该标志；本章还没有用户身份，只能通过 prctl 修改。
+    pub dumpable: bool,
+    /// seccomp 允许的系统调用号位图（第 `id` 位为 1 表示允许），`None` 表示不过滤（fork/exec 后保留）
+    pub seccomp: Option<Vec<u64>>,
+    /// 已映射用户栈的最低地址，栈向下增长时随之下移（新程序尚未访问栈时等于栈顶）
+    pub stack_bottom: usize,
+    /// mmap 建立的区间（页对齐），munmap 只能取消这些区间内的映射（fork 继承，exec 后清空）
+    pub mmap_areas: Vec<Range<usize>>,
+    /// memfd 的共享映射（fork 继承，exec 后清空）
+    pub mem_maps: Vec<MemMapping>,
+    /// 正在执行用户态信号处理函数时，进入前的信号掩码
+    pub saved_sigmask: Option<usize>,
+    /// 以 `SA_SIGINFO` 注册处理函数的信号位图（第 `signum` 位），fork/exec 后保留，与处理函数一致
+    pub siginfo_actions: usize,
+    /// 各信号的来源（kill 的发送者），投递时填入 siginfo 后复位为内核来源
+    pub signal_sources: [SigSource; MAX_SIG + 1],
+    /// 正在执行的 `SA_SIGINFO` 处理函数的 ucontext 地址，sigreturn 时从中恢复上下文
+    pub signal_frame: Option<usize>,
+    /// 阻塞在命名管道 open 上时记录 `(inode 编号, 是否读端, 已分配的 fd)`
+    pub pending_fifo_open: Option<(u32, bool, usize)>,
+    /// 阻塞在 ppoll 上时的超时时刻（`time` 寄存器计数，无限等待为 `usize::MAX`）
+    pub poll_deadline: Option<usize>,
+    /// 创建该进程的父进程（initproc 为 `usize::MAX`），用于 wait 查找已停止的子进程
+    pub parent: ProcId,
+    /// 控制终端，open("/dev/tty") 打开的就是它；`None` 表示没有控制终端（fork/exec 后保留）
+    pub ctty: Option<Tty>,
+}
+
+/// 页表项标志位：有效
+const PTE_V: usize = 1 << 0;
+/// 页表项标志位：R/W/X 任一置位即为叶子页表项
+const PTE_RWX: usize = 0b111 << 1;
+/// 页表项标志位：可写
+const PTE_W: usize = 1 << 2;
+/// 页表项标志位：用户可访问
+const PTE_U: usize = 1 << 4;
+/// 页表项标志位：已被访问（由硬件置位）
+const PTE_A: usize = 1 << 6;
+/// 页表项标志位：已被写过（由硬件置位）
+const PTE_D: usize = 1 << 7;
+/// 页表项标志位：写时复制（RSW 的第二位，第一位是 `Sv39Manager::OWNED_BIT`）
+///
+/// fork 后父子共享的可写页清除 W 位并置上该位，对它的写入由 [`resolve_cow`] 处理。
+const PTE_COW: usize = 1 << 9;
+/// 复制页表项时保留的标志位：V R W X U
+const PTE_PERM: usize = 0x1f;
+
+/// fork 时父子共享的物理页（PPN）-> 映射它的地址空间个数
+///
+/// 地址空间释放时逐页减少计数（见 [`unshare_page`]），降到 0 时才真正释放该页；
+/// 写时复制页被复制走一份时计数也减一。
+static SHARED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// 释放地址空间时归还一个页的共享引用
+///
+/// 该页不是共享页时返回 `None`，由调用者按 OWNED 标志处理；
+/// 否则返回 `Some(是否为最后一个引用)`，最后一个引用者负责释放该页。
+pub fn unshare_page(ppn: usize) -> Option<bool> {
+    let mut shared = SHARED_PAGES.lock();
+    let count = shared.get_mut(&ppn)?;
+    *count -= 1;
+    if *count == 0 {
+        shared.remove(&ppn);
+        Some(true)
+    } else {
+        Some(false)
+    }
+}
+
+/// 页表项中的物理页号
+fn pte_ppn(pte: usize) -> usize {
+    (pte >> 10) & ((1 << 44) - 1)
+}
+
+/// fork：让 `dst` 与 `src` 共享全部用户页，不复制任何物理页
+///
+/// 只沿有效（V 位置位）的页表项向下递归，整段未映射的区域不会被访问；
+/// `dst` 只为共享的页建立所需的下级页表，稀疏地址空间的子进程页表页数与父进程相当。
+/// 可写页在 `src` 和 `dst` 中都改为写时复制；没有 U 位的页（异界传送门）不共享，由调用者重新映射。
+/// 修改了 `src` 的页表项，调用者需要刷新 TLB。
+fn share_user_pages(src: &AddressSpace<Sv39, Sv39Manager>, dst: &mut AddressSpace<Sv39, Sv39Manager>) {
+    fn walk(
+        table: usize,
+        level: usize,
+        vpn: usize,
+        dst: &mut AddressSpace<Sv39, Sv39Manager>,
+        shared: &mut BTreeMap<usize, usize>,
+    ) {
+        for index in 0..512 {
+            let pte = unsafe { &mut *(table as *mut usize).add(index) };
+            if *pte & PTE_V == 0 {
+                continue;
+            }
+            let vpn = (vpn << 9) | index;
+            let ppn = pte_ppn(*pte);
+            if *pte & PTE_RWX == 0 {
+                if level > 0 {
+                    walk(ppn << Sv39::PAGE_BITS, level - 1, vpn, dst, shared);
+                }
+            } else if level == 0 && *pte & PTE_U != 0 {
+                if *pte & (PTE_W | PTE_COW) != 0 {
+                    *pte = (*pte & !PTE_W) | PTE_COW;
+                }
+                let flags = unsafe { VmFlags::from_raw(*pte & (PTE_PERM | PTE_COW)) };
+                // 以外部页映射，子进程的地址空间不会把它当作自己分配的页
+                dst.map_extern(VPN::new(vpn)..VPN::new(vpn + 1), PPN::new(ppn), flags);
+                *shared.entry(ppn).or_insert(1) += 1;
+            }
+        }
+    }
+    let mut shared = SHARED_PAGES.lock();
+    walk(src.root_ppn().val() << Sv39::PAGE_BITS, 2, 0, dst, &mut shared);
+}
+
+/// 找到虚拟页号 `vpn` 对应的 4 KiB 用户页的页表项
+fn user_leaf_pte(space: &AddressSpace<Sv39, Sv39Manager>, vpn: usize) -> Option<*mut usize> {
+    let mut table = space.root_ppn().val() << Sv39::PAGE_BITS;
+    for level in (0..3).rev() {
+        let pte = unsafe { (table as *mut usize).add((vpn >> (9 * level)) & 0x1ff) };
+        let value = unsafe { *pte };
+        if value & PTE_V == 0 {
+            return None;
+        }
+        if value & PTE_RWX != 0 {
+            return (level == 0 && value & PTE_U != 0).then_some(pte);
+        }
+        table = pte_ppn(value) << Sv39::PAGE_BITS;
+    }
+    None
+}
+
+/// 把 `[addr, addr + len)` 中的写时复制页换成本地址空间私有的可写页
+///
+/// 用户写写时复制页引发 StorePageFault 时由 trap 处理调用；内核代用户写内存之前也要先调用它
+/// （见 [`WriteTranslate`]），否则翻译会因缺少 W 位失败。仍有其他地址空间映射的页复制一份，
+/// 只剩自己映射的页直接收回所有权，不必复制。
+/// 返回是否有页被转为可写；分配不到物理页时停在该页，它仍然不可写。
+/// `len` 往往直接来自系统调用参数，遇到第一个未映射的页就停止，遍历不会越过从 `addr` 起连续映射的部分。
+pub fn resolve_cow(space: &AddressSpace<Sv39, Sv39Manager>, addr: usize, len: usize) -> bool {
+    const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+    let first = addr >> Sv39::PAGE_BITS;
+    let last = addr.saturating_add(len.max(1) - 1) >> Sv39::PAGE_BITS;
+    let mut resolved = false;
+    for vpn in first..=last {
+        let Some(pte) = user_leaf_pte(space, vpn) else {
+            break;
+        };
+        let pte = unsafe { &mut *pte };
+        if *pte & PTE_COW == 0 {
+            continue;
+        }
+        let ppn = pte_ppn(*pte);
+        let mut shared = SHARED_PAGES.lock();
+        let new_ppn = match shared.get_mut(&ppn) {
+            Some(count) if *count > 1 => {
+                let page = unsafe { alloc(Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE)) };
+                if page.is_null() {
+                    break;
+                }
+                unsafe { core::ptr::copy_nonoverlapping((ppn << Sv39::PAGE_BITS) as *const u8, page, PAGE_SIZE) };
+                *count -= 1;
+                page as usize >> Sv39::PAGE_BITS
+            }
+            _ => {
+                shared.remove(&ppn);
+                ppn
+            }
+        };
+        // 新页归本地址空间所有；马上就要被写入，直接置上 A、D 位
+        *pte = (new_ppn << 10) | (*pte & PTE_PERM) | PTE_W | Sv39Manager::OWNED_BIT | PTE_A | PTE_D;
+        resolved = true;
+    }
+    if resolved {
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+    resolved
+}
+
+/// 内核代用户写内存时使用的地址翻译
+pub trait WriteTranslate {
+    /// 与 `translate` 相同，但先把 `T` 占用的写时复制页换成私有页（见 [`resolve_cow`]）
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>>;
+}
+
+impl WriteTranslate for AddressSpace<Sv39, Sv39Manager> {
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>> {
+        resolve_cow(self, addr.val(), core::mem::size_of::<T>());
+        self.translate(addr, flags)
+    }
+}
+
+/// memfd 的一段共享映射
+///
+/// 映射建立时把 memfd 的内容复制到新分配的页，之后用户对这些页的修改由硬件记在 PTE 的 D 位上，
+/// munmap、exec 或进程退出时只把 D 位为 1 的页写回 memfd（见 [`Process::sync_mem_maps`]）。
+#[derive(Clone)]
+pub struct MemMapping {
+    /// 起始虚拟地址（页对齐）
+    pub start: usize,
+    /// 映射长度（页对齐）
+    pub len: usize,
+    /// 被映射的 memfd（与 fd 共享同一份数据）
+    pub file: MemFile,
+    /// `start` 对应的 memfd 偏移（页对齐）
+    pub offset: usize,
 }
 
 impl Process {
     /// exec：用新程序替换当前进程（保留 PID、fd_table 和 signal）
-    pub fn exec(&mut self, elf: ElfFile) {
-        let proc = Process::from_elf(elf).unwrap();
+    ///
+    /// 新地址空间在 `from_elf` 中完整建好之后才替换当前进程的状态；
+    /// ELF 非法时返回 `false`，当前进程保持原样，可以继续运行。
+    pub fn exec(&mut self, elf: ElfFile) -> bool {
+        let Some(proc) = Process::from_elf(elf) else {
+            return false;
+        };
+        // 旧地址空间中 memfd 共享映射的修改先写回，随后映射随旧地址空间一起消失
+        self.sync_mem_maps(0, usize::MAX);
+        self.mmap_areas.clear();
+        self.mem_maps.clear();
         self.address_space = proc.address_space;
         self.context = proc.context;
         self.heap_bottom = proc.heap_bottom;
         self.program_brk = proc.program_brk;
+        self.stack_bottom = proc.stack_bottom;
+        self.dumpable = true;
+        for fd in core::mem::take(&mut self.cloexec) {
+            if let Some(slot) = self.fd_table.get_mut(fd) {
+                *slot = None;
+            }
+        }
+        true
+    }
+
+    /// 压入 argv/envp 需要的用户栈字节数（含 8 字节对齐的余量）
+    pub fn args_stack_size(argv: &[String], envp: &[String]) -> usize {
+        let strings: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+        strings + 7 + (argv.len() + envp.len() + 3) * size_of::<usize>()
+    }
+
+    /// 按 `argc, argv[], NULL, envp[], NULL` 的标准布局把参数压入新程序的用户栈
+    ///
+    /// 字符串放在栈顶，指针数组紧贴在其下方并 8 字节对齐，sp 指向 argc；
+    /// 同时把 argv、envp 数组地址放入 a1、a2，返回 argc（由系统调用返回值写入 a0）。
+    /// 参数所在的栈页在写入时按需映射，调用前需用 `args_stack_size` 确认不超过栈的上限。
+    pub fn push_args(&mut self, argv: &[String], envp: &[String]) -> usize {
+        let top = *self.context.context.sp_mut();
+        let mut sp = top;
+        let mut string_addrs = Vec::new();
+        for string in argv.iter().chain(envp) {
+            sp -= string.len() + 1;
+            string_addrs.push(sp);
+        }
+        sp &= !(size_of::<usize>() - 1);
+        let mut words = Vec::with_capacity(argv.len() + envp.len() + 3);
+        words.push(argv.len());
+        words.extend_from_slice(&string_addrs[..argv.len()]);
+        words.push(0);
+        words.extend_from_slice(&string_addrs[argv.len()..]);
+        words.push(0);
+        sp -= words.len() * size_of::<usize>();
+        // 先在内核中拼出 [sp, top) 的完整内容，再逐页写入（栈页不一定物理连续）
+        let mut image = vec![0u8; top - sp];
+        for (string, &addr) in argv.iter().chain(envp).zip(&string_addrs) {
+            image[addr - sp..][..string.len()].copy_from_slice(string.as_bytes());
+        }
+        for (i, word) in words.iter().enumerate() {
+            image[i * size_of::<usize>()..][..size_of::<usize>()].copy_from_slice(&word.to_ne_bytes());
+        }
+        sigframe::copy_to_user(self, sp, &image);
+        let ctx = &mut self.context.context;
+        *ctx.sp_mut() = sp;
+        *ctx.a_mut(1) = sp + size_of::<usize>();
+        *ctx.a_mut(2) = sp + (argv.len() + 2) * size_of::<usize>();
+        argv.len()
     }
 
     /// fork：复制当前进程创建子进程
     ///
     /// 子进程继承：
-    /// - 地址空间（深拷贝）
+    /// - 地址空间（写时复制共享，见 [`resolve_cow`]）
     /// - 文件描述符表（深拷贝，子进程继承所有已打开的文件/管道）
     /// - 信号配置（通过 `signal.from_fork()` 继承）
     pub fn fork(&mut self) -> Option<Process> {
         let pid = ProcId::new();
-        // 复制地址空间
-        let parent_addr_space = &self.address_space;
+        // 共享地址空间，可写页改为写时复制
         let mut address_space: AddressSpace<Sv39, Sv39Manager> = AddressSpace::new();
-        parent_addr_space.cloneself(&mut address_space);
+        share_user_pages(&self.address_space, &mut address_space);
+        // 父进程的可写页刚被改为只读，清掉 TLB 中仍带 W 位的旧表项
+        unsafe { riscv::asm::sfence_vma_all() };
         map_portal(&address_space);
         // 复制上下文
         let context = self.context.context.clone();
         let satp = (8 << 60) | address_space.root_ppn().val();
         let foreign_ctx = ForeignContext { context, satp };
         // 复制文件描述符表（子进程继承父进程所有 fd）
-        let new_fd_table: Vec<Option<Mutex<Fd>>> = self
+        let new_fd_table: Vec<Option<Arc<Mutex<Fd>>>> = self
             .fd_table
             .iter()
-            .map(|fd| fd.as_ref().map(|f| Mutex::new(f.lock().clone())))
+            .map(|fd| fd.as_ref().map(|f| Arc::new(Mutex::new(f.lock().clone()))))
             .collect();
         Some(Self {
             pid,
             context: foreign_ctx,
             address_space,
             fd_table: new_fd_table,
+            cloexec: self.cloexec.clone(),
             signal: self.signal.from_fork(), // 子进程继承父进程的信号配置
             heap_bottom: self.heap_bottom,
             program_brk: self.program_brk,
+            fsize_limit: self.fsize_limit,
+            stack_limit: self.stack_limit,
+            core_limit: self.core_limit,
+            dumpable: self.dumpable,
+            seccomp: self.seccomp.clone(),
+            stack_bottom: self.stack_bottom,
+            mmap_areas: self.mmap_areas.clone(),
+            mem_maps: self.mem_maps.clone(),
+            saved_sigmask: None,
+            siginfo_actions: self.siginfo_actions,
+            signal_sources: [SigSource::KERNEL; MAX_SIG + 1],
+            signal_frame: None,
+            pending_fifo_open: None,
+            poll_deadline: None,
+            parent: self.pid,
+            ctty: self.ctty,
         })
     }
 
@@ -117,6 +428,9 @@ impl Process {
             }
             _ => None?,
         };
+        if !check_load_segments(&elf) {
+            return None;
+        }
 
         const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
         const PAGE_MASK: usize = PAGE_SIZE - 1;
@@ -160,40 +474,234 @@ impl Process {
         // 堆底从 ELF 加载的最高地址的下一页开始
         let heap_bottom = VAddr::<Sv39>::new(max_end_va).ceil().base().val();
 
-        // 映射用户栈（2 页 = 8 KiB）
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
-            VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
-            build_flags("U_WRV"),
-        );
+        // 用户栈只保留 USER_STACK_TOP 以下的虚拟地址，物理页在第一次访问时由缺页处理分配（见 `grow_stack`）
         map_portal(&address_space);
 
         let mut context = LocalContext::user(entry);
         let satp = (8 << 60) | address_space.root_ppn().val();
-        *context.sp_mut() = 1 << 38;
+        *context.sp_mut() = USER_STACK_TOP;
         Some(Self {
             pid: ProcId::new(),
             context: ForeignContext { context, satp },
             address_space,
             // fd_table 使用 Fd::Empty 表示标准 I/O
             fd_table: vec![
-                Some(Mutex::new(Fd::Empty { read: true, write: false })),   // fd 0: stdin
-                Some(Mutex::new(Fd::Empty { read: false, write: true })),   // fd 1: stdout
-                Some(Mutex::new(Fd::Empty { read: false, write: true })),   // fd 2: stderr
+                Some(Arc::new(Mutex::new(Fd::Empty { read: true, write: false }))),   // fd 0: stdin
+                Some(Arc::new(Mutex::new(Fd::Empty { read: false, write: true }))),   // fd 1: stdout
+                Some(Arc::new(Mutex::new(Fd::Empty { read: false, write: true }))),   // fd 2: stderr
             ],
+            cloexec: BTreeSet::new(),
             // 初始化空的信号处理器
             signal: Box::new(SignalImpl::new()),
             heap_bottom,
             program_brk: heap_bottom,
+            fsize_limit: usize::MAX,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            core_limit: 0,
+            dumpable: true,
+            seccomp: None,
+            stack_bottom: USER_STACK_TOP,
+            mmap_areas: Vec::new(),
+            mem_maps: Vec::new(),
+            saved_sigmask: None,
+            siginfo_actions: 0,
+            signal_sources: [SigSource::KERNEL; MAX_SIG + 1],
+            signal_frame: None,
+            pending_fifo_open: None,
+            poll_deadline: None,
+            parent: ProcId::from_usize(usize::MAX),
+            ctty: Some(Tty::Console),
         })
     }
 
+    /// 处理待处理信号，真正转入用户处理函数时调用 `enter_signal_handler`
+    ///
+    /// tg-signal 转入用户处理函数和 SIGCONT 解除暂停都返回 `Handled`。只有前者把 pc 改为处理函数、
+    /// 把信号编号写入 a0，并留在“正在处理信号”的状态，据此区分，解除暂停时不改动掩码。
+    pub fn deliver_signals(&mut self) -> SignalResult {
+        let interrupted = self.context.context.clone();
+        let result = self.signal.handle_signals(&mut self.context.context);
+        if matches!(result, SignalResult::Handled) && self.signal.is_handling_signal() {
+            let signum = self.context.context.a(0);
+            self.enter_signal_handler(signum, &interrupted);
+        }
+        result
+    }
+
+    /// 进入用户态信号处理函数：处理期间屏蔽 `旧掩码 | sa_mask | {signum}`
+    ///
+    /// 旧掩码保存在 `saved_sigmask` 中，由 sigreturn 调用 `leave_signal_handler` 恢复。
+    /// 以 `SA_SIGINFO` 注册的处理函数还会在用户栈上得到 siginfo 与 ucontext（见 `sigframe`），
+    /// `interrupted` 是投递信号前的上下文。
+    fn enter_signal_handler(&mut self, signum: usize, interrupted: &LocalContext) {
+        let action = u8::try_from(signum)
+            .ok()
+            .and_then(|signum| SignalNo::try_from(signum).ok())
+            .and_then(|signal_no| self.signal.get_action_ref(signal_no));
+        let Some(action) = action else { return };
+        let old_mask = self.signal.update_mask(0);
+        self.signal.update_mask(old_mask | action.mask | (1 << signum));
+        self.saved_sigmask = Some(old_mask);
+        if self.siginfo_actions & (1 << signum) != 0 {
+            sigframe::push_frame(self, signum, interrupted, old_mask);
+        }
+    }
+
+    /// 从信号处理函数返回：恢复进入处理函数前的掩码
+    pub fn leave_signal_handler(&mut self) {
+        if let Some(mask) = self.saved_sigmask.take() {
+            self.signal.update_mask(mask);
+        }
+    }
+
+    /// 进程退出时的统一清理钩子（由 `ProcManager::delete` 调用）
+    ///
+    /// 按顺序释放进程持有的资源：
+    /// 1. 关闭所有 fd：文件句柄的打开计数、管道端点、memfd 的数据引用随之释放；
+    /// 2. memfd 的共享映射写回修改过的页后解除；共享内存段、文件映射、flock、epoll/timerfd 登记：
+    ///    本章尚未实现，新增这些子系统时在此处解除；
+    /// 3. 地址空间、信号状态随 `Process` 一起释放。
+    pub fn on_exit(&mut self) {
+        self.fd_table.clear();
+        self.sync_mem_maps(0, usize::MAX);
+        self.mem_maps.clear();
+    }
+
+    /// 分配文件描述符：优先复用编号最小的空闲槽位，没有空闲槽位时追加到末尾
+    pub fn alloc_fd(&mut self, file: Fd) -> usize {
+        self.install_fd(Arc::new(Mutex::new(file)))
+    }
+
+    /// 把一个已有的句柄装入编号最小的空闲槽位，dup 出的 fd 与原 fd 共享同一个句柄
+    pub fn install_fd(&mut self, file: Arc<Mutex<Fd>>) -> usize {
+        let file = Some(file);
+        if let Some(fd) = self.fd_table.iter().position(Option::is_none) {
+            self.fd_table[fd] = file;
+            fd
+        } else {
+            self.fd_table.push(file);
+            self.fd_table.len() - 1
+        }
+    }
+
+    /// `fd` 是否指向控制台（标准输入输出及 dup 出的副本）且允许按 `write` 指定的方向访问
+    ///
+    /// stdin 不可写、stdout 不可读：方向与 `write` 不符时返回 `false`，由调用者按普通句柄的读写权限报错。
+    pub fn is_console(&self, fd: usize, write: bool) -> bool {
+        let Some(Some(file)) = self.fd_table.get(fd) else {
+            return false;
+        };
+        match *file.lock() {
+            Fd::Empty { read, write: writable } => if write { writable } else { read },
+            _ => false,
+        }
+    }
+
+    /// 系统调用 `id` 是否被 seccomp 位图允许，未设置过滤时全部允许
+    pub fn syscall_allowed(&self, id: usize) -> bool {
+        self.seccomp
+            .as_ref()
+            .is_none_or(|bitmap| bitmap.get(id / 64).is_some_and(|word| word >> (id % 64) & 1 != 0))
+    }
+
+    /// 收紧 seccomp 位图：与已有位图按位与，只能去掉允许的调用，不能重新放开
+    pub fn restrict_syscalls(&mut self, bitmap: Vec<u64>) {
+        self.seccomp = Some(match self.seccomp.take() {
+            // 超出旧位图长度的调用本来就不允许，结果截到两者中较短的长度
+            Some(old) => bitmap.iter().zip(&old).map(|(new, old)| new & old).collect(),
+            None => bitmap,
+        });
+    }
+
+    /// 用户栈按需分配：映射从缺页地址 `addr` 所在页到当前栈底之间的栈页，返回是否成功
+    ///
+    /// 栈区是 `USER_STACK_TOP` 以下 RLIMIT_STACK 大小的一段保留地址，`from_elf` 不为它分配物理页。
+    /// 只有 `addr` 落在保留区内、当前栈底之下且不与堆重叠时才映射（一次分配的大栈帧可能跳过几页，
+    /// 中间的页一并映射），其余缺页是非法访问，交给调用者按 SIGSEGV 处理。
+    pub fn grow_stack(&mut self, addr: usize) -> bool {
+        if addr >= self.stack_bottom
+            || addr < USER_STACK_TOP.saturating_sub(self.stack_limit)
+            || addr < self.program_brk
+        {
+            return false;
+        }
+        let vpn = VAddr::<Sv39>::new(addr).floor();
+        self.address_space
+            .map(vpn..VAddr::new(self.stack_bottom).floor(), &[], 0, build_flags("U_WRV"));
+        self.stack_bottom = vpn.base().val();
+        true
+    }
+
+    /// 登记一段 mmap 区间，区间中有已映射的页或与用户栈保留区重叠时不做修改并返回 `false`
+    pub fn add_mmap_area(&mut self, range: Range<usize>) -> bool {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        if range.end > USER_STACK_TOP.saturating_sub(self.stack_limit)
+            || range.clone().step_by(PAGE_SIZE).any(|addr| {
+                user_leaf_pte(&self.address_space, addr >> Sv39::PAGE_BITS).is_some()
+            })
+        {
+            return false;
+        }
+        self.mmap_areas.push(range);
+        true
+    }
+
+    /// 从 mmap 区间中去掉 `[start, end)`，被截断的区间一分为二
+    ///
+    /// 范围内有不属于 mmap 区间的地址（ELF 段、堆、栈或未映射的空洞）时不做修改并返回 `false`。
+    pub fn remove_mmap_area(&mut self, start: usize, end: usize) -> bool {
+        // 区间互不重叠，与 `[start, end)` 的交集长度之和等于它的长度才算完全覆盖
+        let covered: usize = self
+            .mmap_areas
+            .iter()
+            .map(|area| area.end.min(end).saturating_sub(area.start.max(start)))
+            .sum();
+        if covered != end - start {
+            return false;
+        }
+        let mut areas = Vec::new();
+        for area in self.mmap_areas.drain(..) {
+            if area.end <= start || area.start >= end {
+                areas.push(area);
+                continue;
+            }
+            if area.start < start {
+                areas.push(area.start..start);
+            }
+            if area.end > end {
+                areas.push(end..area.end);
+            }
+        }
+        self.mmap_areas = areas;
+        true
+    }
+
+    /// 把 `[start, end)` 中属于 memfd 共享映射、被修改过（D 位为 1）的页写回 memfd
+    ///
+    /// 写回后清除 D 位，下次只有再被修改过的页才会写回。
+    pub fn sync_mem_maps(&self, start: usize, end: usize) {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        for map in &self.mem_maps {
+            let from = start.max(map.start);
+            let to = end.min(map.start + map.len);
+            for addr in (from..to).step_by(PAGE_SIZE) {
+                let Some(pte) = user_leaf_pte(&self.address_space, addr >> Sv39::PAGE_BITS) else {
+                    continue;
+                };
+                let pte = unsafe { &mut *pte };
+                if *pte & PTE_D == 0 {
+                    continue;
+                }
+                *pte &= !PTE_D;
+                let page = (pte_ppn(*pte) << Sv39::PAGE_BITS) as *const u8;
+                map.file.write_at(map.offset + (addr - map.start), unsafe {
+                    core::slice::from_raw_parts(page, PAGE_SIZE)
+                });
+            }
+        }
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+
     /// 修改程序 break 位置（实现 sbrk）
     pub fn change_program_brk(&mut self, size: isize) -> Option<usize> {
         let old_brk = self.program_brk;
@@ -221,3 +729,23 @@ impl Process {
         Some(old_brk)
     }
 }
+
+/// 校验 ELF 的所有 LOAD 段（在分配任何页面之前调用）
+///
+/// 检查文件内偏移不越界、文件大小不超过内存大小、页内偏移一致、段落在用户地址空间内。
+/// 全部通过才返回 `true`，这样 `from_elf` 失败时不会留下映射了一半的地址空间。
+fn check_load_segments(elf: &ElfFile) -> bool {
+    const PAGE_MASK: usize = (1 << Sv39::PAGE_BITS) - 1;
+    elf.program_iter()
+        .filter(|program| matches!(program.get_type(), Ok(program::Type::Load)))
+        .all(|program| {
+            let off_file = program.offset() as usize;
+            let len_file = program.file_size() as usize;
+            let off_mem = program.virtual_addr() as usize;
+            let len_mem = program.mem_size() as usize;
+            off_file & PAGE_MASK == off_mem & PAGE_MASK
+                && len_file <= len_mem
+                && off_file.checked_add(len_file).is_some_and(|end| end <= elf.input.len())
+                && off_mem.checked_add(len_mem).is_some_and(|end| end <= 1 << 38)
+        })
+}
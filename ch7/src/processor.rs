@@ -1,7 +1,8 @@
 //! 处理器管理模块
 //!
 //! 与第六章完全相同：PROCESSOR 全局管理器 + ProcManager 进程管理器。
-//! 调度算法仍为简单的 FIFO/RR。
+//! 调度算法仍为简单的 FIFO/RR，另外支持 SIGSTOP/SIGCONT 停止与继续进程，
+//! 并记录进程组供 wait 按组回收子进程。
 //!
 //! 教程阅读建议：
 //!
@@ -9,8 +10,15 @@
 //! - 建议结合 `ch7/src/main.rs` 一起看：本文件只负责“谁可运行”，不负责“为何被杀死/阻塞”。
 
 use crate::process::Process;
-use alloc::collections::{BTreeMap, VecDeque};
-use core::cell::UnsafeCell;
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{cell::UnsafeCell, mem::size_of};
+use spin::Mutex;
+use tg_console::log;
+use tg_easy_fs::{PipeWriter, UserBuffer};
 use tg_task_manage::{Manage, PManager, ProcId, Schedule};
 
 /// 处理器全局管理器
@@ -38,12 +46,85 @@ impl Processor {
 /// 全局处理器管理器实例
 pub static PROCESSOR: Processor = Processor::new();
 
+/// 被 SIGSTOP 停止的进程 -> 这次停止是否已经通过 wait4(WUNTRACED) 报告给父进程
+///
+/// 发送信号的一方拿不到 `ProcManager`，只在这里登记；
+/// `ProcManager` 调度时据此把停止的进程移出就绪队列，继续后再放回。
+static STOPPED: Mutex<BTreeMap<ProcId, bool>> = Mutex::new(BTreeMap::new());
+
+/// 停止进程：之后不再被调度，但仍保留在进程表中，不会被回收
+pub fn stop_process(pid: ProcId) {
+    STOPPED.lock().entry(pid).or_insert(false);
+}
+
+/// 继续被停止的进程，返回它之前是否处于停止态
+pub fn continue_process(pid: ProcId) -> bool {
+    STOPPED.lock().remove(&pid).is_some()
+}
+
+/// 取出一个满足 `is_target` 且尚未报告过的已停止进程，并标记为已报告
+///
+/// 每次停止只报告一次；进程继续后再次停止会重新报告。
+pub fn take_stopped(is_target: impl Fn(ProcId) -> bool) -> Option<ProcId> {
+    let mut stopped = STOPPED.lock();
+    let (&pid, reported) = stopped.iter_mut().find(|(pid, reported)| !**reported && is_target(**pid))?;
+    *reported = true;
+    Some(pid)
+}
+
+/// 尚未被回收的进程 -> 所在进程组
+///
+/// 进程退出后 `Process` 就被删除了，但在父进程 wait 回收之前仍要能按组匹配它，
+/// 因此进程组单独登记在这里：创建进程时加入，setpgid 时修改，被回收时移除。
+static PGIDS: Mutex<BTreeMap<ProcId, ProcId>> = Mutex::new(BTreeMap::new());
+
+/// 把进程 `pid` 加入进程组 `pgid`（新建进程或 setpgid）
+pub fn set_pgid(pid: ProcId, pgid: ProcId) {
+    PGIDS.lock().insert(pid, pgid);
+}
+
+/// 查询进程所在的进程组，进程不存在或已被回收时返回 `None`
+pub fn pgid_of(pid: ProcId) -> Option<ProcId> {
+    PGIDS.lock().get(&pid).copied()
+}
+
+/// 进程组 `pgid` 是否还有未被回收的成员
+pub fn group_exists(pgid: ProcId) -> bool {
+    PGIDS.lock().values().any(|&group| group == pgid)
+}
+
+/// 进程被 wait 回收后移除它的进程组记录
+pub fn forget_pgid(pid: ProcId) {
+    PGIDS.lock().remove(&pid);
+}
+
+/// 回收进程组 `pgid` 中任意一个已退出的子进程，返回值含义与 `PManager::wait` 相同
+///
+/// `PManager::wait` 只能指定单个 PID 或任意子进程，这里逐个尝试组内成员：
+/// 不是当前进程子进程的成员返回 `None`，直接跳过；有成员已退出就回收它，
+/// 否则只要组内还有运行中的子进程就返回 dead_pid 为 -2 的结果，让调用者稍后重试。
+pub fn wait_group(manager: &mut PManager<Process, ProcManager>, pgid: ProcId) -> Option<(ProcId, isize)> {
+    let members: Vec<ProcId> =
+        PGIDS.lock().iter().filter(|(_, group)| **group == pgid).map(|(&pid, _)| pid).collect();
+    let mut running = None;
+    for child in members {
+        match manager.wait(child) {
+            Some((dead_pid, exit_code)) if dead_pid.get_usize() as isize >= 0 => return Some((dead_pid, exit_code)),
+            Some(pending) => running = Some(pending),
+            None => {}
+        }
+    }
+    running
+}
+
 /// 进程管理器（FIFO 调度）
 pub struct ProcManager {
     /// 所有进程实体的映射表
     tasks: BTreeMap<ProcId, Process>,
     /// 就绪队列
     ready_queue: VecDeque<ProcId>,
+    /// 已停止、被移出就绪队列的进程，继续后回到就绪队列
+    parked: BTreeSet<ProcId>,
 }
 
 impl ProcManager {
@@ -52,6 +133,7 @@ impl ProcManager {
         Self {
             tasks: BTreeMap::new(),
             ready_queue: VecDeque::new(),
+            parked: BTreeSet::new(),
         }
     }
 }
@@ -67,10 +149,14 @@ impl Manage<Process, ProcId> for ProcManager {
     fn get_mut(&mut self, id: ProcId) -> Option<&mut Process> {
         self.tasks.get_mut(&id)
     }
-    /// 删除进程
+    /// 删除进程（进程退出），先执行它的退出清理钩子
     #[inline]
     fn delete(&mut self, id: ProcId) {
-        self.tasks.remove(&id);
+        if let Some(mut process) = self.tasks.remove(&id) {
+            process.on_exit();
+        }
+        self.parked.remove(&id);
+        STOPPED.lock().remove(&id);
     }
 }
 
@@ -79,8 +165,84 @@ impl Schedule<ProcId> for ProcManager {
     fn add(&mut self, id: ProcId) {
         self.ready_queue.push_back(id);
     }
-    /// 从就绪队列头部取出
+    /// 从就绪队列头部取出，跳过并移走已停止的进程
     fn fetch(&mut self) -> Option<ProcId> {
-        self.ready_queue.pop_front()
+        let stopped = STOPPED.lock();
+        // 已继续的进程排到就绪队列尾部
+        let resumed: Vec<ProcId> = self.parked.iter().filter(|id| !stopped.contains_key(id)).copied().collect();
+        for id in resumed {
+            self.parked.remove(&id);
+            self.ready_queue.push_back(id);
+        }
+        while let Some(id) = self.ready_queue.pop_front() {
+            if !stopped.contains_key(&id) {
+                return Some(id);
+            }
+            self.parked.insert(id);
+        }
+        if !self.parked.is_empty() {
+            log::warn!("all {} remaining processes are stopped", self.parked.len());
+        }
+        None
+    }
+}
+
+/// 进程退出原因（进程事件中的 `reason` 字段）
+#[derive(Clone, Copy)]
+#[repr(u32)]
+pub enum ExitReason {
+    /// 调用 exit 正常退出
+    Exit = 0,
+    /// 被信号终止
+    Signal = 1,
+    /// 触发异常或调用了不支持的系统调用，被内核结束
+    Fault = 2,
+}
+
+/// 写入进程事件管道的记录
+#[repr(C)]
+struct ProcEvent {
+    pid: u64,
+    exit_code: i32,
+    reason: u32,
+}
+
+/// 进程事件订阅者：内核持有写端，订阅进程持有读端
+static PROC_EVENT_SUBSCRIBERS: Mutex<Vec<Arc<PipeWriter>>> = Mutex::new(Vec::new());
+
+/// 登记一个进程事件管道
+pub fn subscribe_proc_events(write_end: Arc<PipeWriter>) {
+    PROC_EVENT_SUBSCRIBERS.lock().push(write_end);
+}
+
+/// 让当前进程退出，并向所有订阅者发布退出事件
+pub fn exit_current(exit_code: isize, reason: ExitReason) {
+    // 进程可能留下不以换行结尾的输出
+    crate::console::flush();
+    let manager = PROCESSOR.get_mut();
+    if let Some(pid) = manager.current().map(|p| p.pid) {
+        crate::fs::notify_pid_exit(pid, exit_code as i32);
+        let mut event = ProcEvent {
+            pid: pid.get_usize() as u64,
+            exit_code: exit_code as i32,
+            reason: reason as u32,
+        };
+        // 读端全部关闭的订阅顺便移除
+        PROC_EVENT_SUBSCRIBERS.lock().retain(|write_end| {
+            if write_end.read_ends_closed() {
+                return false;
+            }
+            // 只写入完整记录，避免读端拿到半条事件
+            if write_end.available_write() < size_of::<ProcEvent>() {
+                log::warn!("proc event of pid {} dropped: pipe full", event.pid);
+                return true;
+            }
+            let bytes = unsafe {
+                core::slice::from_raw_parts_mut(&mut event as *mut ProcEvent as *mut u8, size_of::<ProcEvent>())
+            };
+            write_end.write(UserBuffer::new(vec![bytes]));
+            true
+        });
     }
+    manager.make_current_exited(exit_code);
 }
@@ -0,0 +1,174 @@
+//! 信号栈帧模块（`SA_SIGINFO`）
+//!
+//! 通过 `rt_sigaction` 带 `SA_SIGINFO` 注册的处理函数以 `(signo, siginfo*, ucontext*)` 调用。
+//! 投递信号时在被中断时的用户栈指针下方构造：
+//!
+//! ```text
+//! 高地址  被中断时的 sp
+//!         UContext   flags、link、sigmask、gregs[32]（pc、x1 ~ x31）
+//!         SigInfo    128 字节，前几个字段与 Linux 的 siginfo_t 相同
+//! 低地址  处理函数的 sp（16 字节对齐），也是 a1
+//! ```
+//!
+//! a0 = signo，a1 = `SigInfo` 地址，a2 = `UContext` 地址。sigreturn 时从 `UContext` 恢复
+//! 全部寄存器与信号掩码，处理函数修改 `gregs` 即可改变返回后的执行位置。
+//! `SigInfo` 的前缀按 Linux 布局排列；`UContext` 是简化布局，与 Linux 的 ucontext_t 不同。
+//!
+//! 教程阅读建议：先看 `push_frame` 如何在用户栈上布局，再看 `restore_frame` 如何恢复。
+
+use crate::{
+    build_flags,
+    process::{Process, WriteTranslate},
+    Sv39,
+};
+use core::mem::size_of;
+use tg_console::log;
+use tg_kernel_context::LocalContext;
+use tg_kernel_vm::page_table::{MmuMeta, VAddr};
+
+/// si_code：由 kill 发送
+pub const SI_USER: i32 = 0;
+/// si_code：由内核产生（缺页、SIGPIPE、SIGXFSZ 等）
+pub const SI_KERNEL: i32 = 0x80;
+/// 页大小
+const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+/// 信号的来源，投递时填入 `SigInfo`
+#[derive(Clone, Copy)]
+pub struct SigSource {
+    /// 发送者 pid，内核产生的信号为 0
+    pub pid: usize,
+    /// si_code
+    pub code: i32,
+}
+
+impl SigSource {
+    /// 内核产生的信号（未经 kill 记录来源时的默认值）
+    pub const KERNEL: Self = Self { pid: 0, code: SI_KERNEL };
+}
+
+/// `siginfo_t`：signo、errno、code 与 kill 的发送者，总长 128 字节
+#[repr(C)]
+struct SigInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    _pad: i32,
+    pid: i32,
+    uid: u32,
+    _rest: [u64; 13],
+}
+
+/// 简化的 `ucontext`
+#[repr(C)]
+struct UContext {
+    flags: usize,
+    link: usize,
+    /// 处理函数返回后恢复的信号掩码
+    sigmask: usize,
+    /// pc、x1 ~ x31
+    gregs: [usize; 32],
+}
+
+/// 在用户栈上为信号 `signum` 构造 `SigInfo` 与 `UContext`，并设置处理函数的 a1、a2、sp
+///
+/// `interrupted` 是进入处理函数前的上下文，`old_mask` 是处理函数返回后要恢复的掩码。
+/// 栈空间不可写（且无法自动增长）时返回 `false`，此时 a1、a2 为 0。
+pub fn push_frame(process: &mut Process, signum: usize, interrupted: &LocalContext, old_mask: usize) -> bool {
+    let source = core::mem::replace(&mut process.signal_sources[signum], SigSource::KERNEL);
+    let info = SigInfo {
+        signo: signum as i32,
+        errno: 0,
+        code: source.code,
+        _pad: 0,
+        pid: source.pid as i32,
+        uid: 0,
+        _rest: [0; 13],
+    };
+    let mut uc = UContext { flags: 0, link: 0, sigmask: old_mask, gregs: [0; 32] };
+    uc.gregs[0] = interrupted.pc();
+    for i in 1..32 {
+        uc.gregs[i] = interrupted.x(i);
+    }
+    let pushed = interrupted
+        .x(2)
+        .checked_sub(size_of::<UContext>() + size_of::<SigInfo>())
+        .map(|frame| frame & !15)
+        .filter(|&frame| {
+            copy_to_user(process, frame + size_of::<SigInfo>(), as_bytes(&uc))
+                && copy_to_user(process, frame, as_bytes(&info))
+        });
+    let ctx = &mut process.context.context;
+    let Some(frame) = pushed else {
+        log::warn!("process {}: no room for signal frame", process.pid.get_usize());
+        *ctx.a_mut(1) = 0;
+        *ctx.a_mut(2) = 0;
+        return false;
+    };
+    *ctx.a_mut(1) = frame;
+    *ctx.a_mut(2) = frame + size_of::<SigInfo>();
+    *ctx.sp_mut() = frame;
+    process.signal_frame = Some(frame + size_of::<SigInfo>());
+    true
+}
+
+/// sigreturn：若当前处理函数带有 `UContext`，用其中的寄存器与掩码覆盖刚恢复的上下文
+///
+/// 必须在 `sig_return` 之后、`leave_signal_handler` 之前调用。
+pub fn restore_frame(process: &mut Process) {
+    let Some(addr) = process.signal_frame.take() else { return };
+    let mut uc = UContext { flags: 0, link: 0, sigmask: 0, gregs: [0; 32] };
+    let bytes = unsafe { core::slice::from_raw_parts_mut(&mut uc as *mut UContext as *mut u8, size_of::<UContext>()) };
+    if !copy_from_user(process, addr, bytes) {
+        log::warn!("process {}: signal frame at {addr:#x} unreadable", process.pid.get_usize());
+        return;
+    }
+    let ctx = &mut process.context.context;
+    *ctx.pc_mut() = uc.gregs[0];
+    for i in 1..32 {
+        *ctx.x_mut(i) = uc.gregs[i];
+    }
+    process.saved_sigmask = Some(uc.sigmask);
+}
+
+/// 把结构体视为字节序列
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 把 `bytes` 写到用户地址 `addr`，逐页翻译
+///
+/// 从高地址向低地址写：落在栈底下方、尚未分配的栈页先通过 `grow_stack` 映射。
+pub fn copy_to_user(process: &mut Process, addr: usize, bytes: &[u8]) -> bool {
+    let mut end = addr + bytes.len();
+    while end > addr {
+        let start = ((end - 1) & !(PAGE_SIZE - 1)).max(addr);
+        let mut ptr = process.address_space.translate_for_write::<u8>(VAddr::new(start), build_flags("W_V"));
+        if ptr.is_none() && process.grow_stack(start) {
+            ptr = process.address_space.translate::<u8>(VAddr::new(start), build_flags("W_V"));
+        }
+        let Some(ptr) = ptr else {
+            return false;
+        };
+        let chunk = &bytes[start - addr..end - addr];
+        unsafe { core::ptr::copy_nonoverlapping(chunk.as_ptr(), ptr.as_ptr(), chunk.len()) };
+        end = start;
+    }
+    true
+}
+
+/// 从用户地址 `addr` 读出 `buf.len()` 字节，逐页翻译
+fn copy_from_user(process: &Process, addr: usize, buf: &mut [u8]) -> bool {
+    let mut start = addr;
+    let end = addr + buf.len();
+    while start < end {
+        let next = ((start & !(PAGE_SIZE - 1)) + PAGE_SIZE).min(end);
+        let Some(ptr) = process.address_space.translate::<u8>(VAddr::new(start), build_flags("RV")) else {
+            return false;
+        };
+        let chunk = &mut buf[start - addr..next - addr];
+        unsafe { core::ptr::copy_nonoverlapping(ptr.as_ptr(), chunk.as_mut_ptr(), chunk.len()) };
+        start = next;
+    }
+    true
+}
@@ -16,26 +16,27 @@ use core::{alloc::Layout, ptr::NonNull};
 use spin::{Lazy, Mutex};
 use tg_easy_fs::BlockDevice;
 use tg_kernel_vm::page_table::{MmuMeta, VAddr, VmFlags};
-use virtio_drivers::{Hal, MmioTransport, VirtIOBlk, VirtIOHeader};
+use virtio_drivers::{Hal, MmioTransport, Transport, VirtIOBlk, VirtIOHeader};
 
 /// VirtIO MMIO 基地址
 const VIRTIO0: usize = 0x10001000;
+/// 设备特性位 VIRTIO_BLK_F_RO：设备只读
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
 
 /// 全局块设备实例
 pub static BLOCK_DEVICE: Lazy<Arc<dyn BlockDevice>> = Lazy::new(|| {
-    Arc::new(unsafe {
-        VirtIOBlock(Mutex::new(
-            VirtIOBlk::new(
-                MmioTransport::new(NonNull::new(VIRTIO0 as *mut VirtIOHeader).unwrap())
-                    .expect("Error when creating MmioTransport"),
-            )
-            .expect("Error when creating VirtIOBlk"),
-        ))
-    })
+    let mut transport = unsafe { MmioTransport::new(NonNull::new(VIRTIO0 as *mut VirtIOHeader).unwrap()) }
+        .expect("Error when creating MmioTransport");
+    // virtio-drivers 0.1.0 不协商也不保存 VIRTIO_BLK_F_RO，交给驱动之前先从设备特性中读出
+    let read_only = transport.read_device_features() & VIRTIO_BLK_F_RO != 0;
+    Arc::new(VirtIOBlock(
+        Mutex::new(VirtIOBlk::new(transport).expect("Error when creating VirtIOBlk")),
+        read_only,
+    ))
 });
 
-/// VirtIO 块设备封装
-struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>);
+/// VirtIO 块设备封装，第二个字段记录设备是否只读
+struct VirtIOBlock(Mutex<VirtIOBlk<VirtioHal, MmioTransport>>, bool);
 
 // Safety: 内部使用 Mutex 保护
 unsafe impl Send for VirtIOBlock {}
@@ -57,6 +58,10 @@ impl BlockDevice for VirtIOBlock {
             .write_block(block_id, buf)
             .expect("Error when writing VirtIOBlk");
     }
+    /// 设备协商时报告了 VIRTIO_BLK_F_RO（QEMU 以 `readonly=on` 挂载镜像）时为只读
+    fn is_read_only(&self) -> bool {
+        self.1
+    }
 }
 
 /// VirtIO HAL 实现（DMA 内存管理和地址转换）
@@ -138,7 +138,8 @@ fn build_user_app(tg_user_root: &PathBuf, name: &str, base_address: u64) {
     }
 }
 
-struct BlockFile(std::sync::Mutex<std::fs::File>);
+/// 用宿主机上的镜像文件模拟块设备，第二个字段记录文件是否以只读方式打开
+struct BlockFile(std::sync::Mutex<std::fs::File>, bool);
 
 impl BlockDevice for BlockFile {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
@@ -156,6 +157,10 @@ impl BlockDevice for BlockFile {
             .expect("Error when seeking!");
         assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
     }
+
+    fn is_read_only(&self) -> bool {
+        self.1
+    }
 }
 
 fn easy_fs_pack(
@@ -170,6 +175,7 @@ fn easy_fs_pack(
     fs::create_dir_all(fs_target)?;
     let fs_file = fs_target.join("fs.img");
     println!("cargo:rerun-if-changed={}", fs_file.display());
+    // 打包要写入镜像，文件以读写方式打开，设备报告为可写
     let block_file = Arc::new(BlockFile(std::sync::Mutex::new({
         let f = OpenOptions::new()
             .read(true)
@@ -178,7 +184,7 @@ fn easy_fs_pack(
             .open(fs_file)?;
         f.set_len(64 * 2048 * BLOCK_SZ as u64).unwrap();
         f
-    })));
+    }), false));
 
     let efs = EasyFileSystem::create(block_file, 64 * 2048, 1);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
@@ -188,8 +194,16 @@ fn easy_fs_pack(
         let mut all_data: Vec<u8> = Vec::new();
         host_file.read_to_end(&mut all_data).unwrap();
         let inode = root_inode.create(case.as_str()).unwrap();
-        inode.write_at(0, all_data.as_slice());
+        assert!(
+            all_data.len() <= tg_easy_fs::MAX_FILE_SIZE,
+            "{case} exceeds the easy-fs file size limit of {} bytes",
+            tg_easy_fs::MAX_FILE_SIZE
+        );
+        let written = inode.write_at(0, all_data.as_slice());
+        assert_eq!(written, all_data.len(), "fs.img is too small for {case}");
     }
+    // 逐个文件的写入只同步了各自的块，收尾时把剩下的脏块全部写回
+    assert!(efs.lock().sync_all(), "failed to write back fs.img");
 
     Ok(())
 }
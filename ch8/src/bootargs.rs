@@ -0,0 +1,139 @@
+//! 启动参数模块
+//!
+//! QEMU 以 `-append "..."` 传入的命令行写在设备树 `/chosen` 节点的 `bootargs` 属性中，
+//! 进入内核时 `a1` 寄存器指向设备树。本模块从设备树取出这个字符串并解析成 [`BootArgs`]。
+//!
+//! 参数以空白分隔，形如 `key=value`：
+//!
+//! - `log=<level>`：日志级别，优先于编译期的 `LOG` 环境变量
+//! - `sched=fifo|rr`：`fifo` 只在系统调用处切换线程，`rr` 额外用时钟中断按时间片抢占
+//! - `mem=<size>`：内核使用的物理内存大小，支持 `K`/`M`/`G` 后缀
+//! - `root=<dev>`：根文件系统所在设备，目前只有 `/dev/vda`
+//!
+//! [`BootArgs::parse`] 是纯函数，不依赖内核环境。
+
+use tg_console::log;
+
+/// 调度策略
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sched {
+    /// 线程只在系统调用处让出处理器（默认）
+    Fifo,
+    /// 时间片轮转，时钟中断到来时抢占当前线程
+    Rr,
+}
+
+/// 解析后的启动参数，未给出的项为默认值
+#[derive(Clone, Copy, Debug)]
+pub struct BootArgs<'a> {
+    /// 日志级别
+    pub log: Option<&'a str>,
+    /// 调度策略
+    pub sched: Sched,
+    /// 物理内存大小（字节）
+    pub mem: Option<usize>,
+    /// 根设备
+    pub root: Option<&'a str>,
+}
+
+impl<'a> BootArgs<'a> {
+    /// 解析命令行，无法识别的参数打印警告后跳过
+    pub fn parse(cmdline: &'a str) -> Self {
+        let mut args = Self { log: None, sched: Sched::Fifo, mem: None, root: None };
+        for param in cmdline.split_ascii_whitespace() {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match (key, value) {
+                (_, "") => log::warn!("bootargs: missing value in `{param}`"),
+                ("log", level) => args.log = Some(level),
+                ("sched", "fifo") => args.sched = Sched::Fifo,
+                ("sched", "rr") => args.sched = Sched::Rr,
+                ("mem", size) => match parse_size(size) {
+                    Some(size) => args.mem = Some(size),
+                    None => log::warn!("bootargs: bad size `{param}`"),
+                },
+                ("root", dev) => args.root = Some(dev),
+                _ => log::warn!("bootargs: unknown `{param}`"),
+            }
+        }
+        args
+    }
+}
+
+/// 解析带 `K`/`M`/`G` 后缀（大小写均可）的字节数
+fn parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 10),
+        b'm' | b'M' => (&s[..s.len() - 1], 20),
+        b'g' | b'G' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+/// 从设备树读出 `/chosen/bootargs`，设备树无效或没有该属性时返回 `None`
+///
+/// # Safety
+///
+/// 必须在开启分页前调用：设备树位于内核地址空间之外，返回的字符串也只在此之前有效。
+pub unsafe fn from_dtb(dtb: usize) -> Option<&'static str> {
+    const FDT_MAGIC: u32 = 0xd00d_feed;
+    const FDT_BEGIN_NODE: u32 = 1;
+    const FDT_END_NODE: u32 = 2;
+    const FDT_PROP: u32 = 3;
+    const FDT_NOP: u32 = 4;
+    // 只接受 RAM 中 4 字节对齐的地址，避免引导代码没有传递设备树时访问到非法地址
+    if !(0x8000_0000..0x1_0000_0000).contains(&dtb) || !dtb.is_multiple_of(4) {
+        return None;
+    }
+    let be32 = |addr: usize| u32::from_be(unsafe { (addr as *const u32).read() });
+    let bytes = |addr: usize, len: usize| unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+    let cstr = |addr: usize| {
+        let mut len = 0;
+        while unsafe { *((addr + len) as *const u8) } != 0 {
+            len += 1;
+        }
+        bytes(addr, len)
+    };
+    if be32(dtb) != FDT_MAGIC {
+        return None;
+    }
+    let strings = dtb + be32(dtb + 12) as usize;
+    let mut pos = dtb + be32(dtb + 8) as usize;
+    // 根节点深度为 1，`/chosen` 深度为 2
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(pos);
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(pos);
+                pos = (pos + name.len() + 1).next_multiple_of(4);
+                depth += 1;
+                if depth == 2 {
+                    in_chosen = name == b"chosen";
+                }
+            }
+            FDT_END_NODE => {
+                if depth == 2 {
+                    in_chosen = false;
+                }
+                depth = depth.checked_sub(1)?;
+            }
+            FDT_PROP => {
+                let len = be32(pos) as usize;
+                let name = cstr(strings + be32(pos + 4) as usize);
+                let value = pos + 8;
+                pos = (value + len).next_multiple_of(4);
+                if in_chosen && depth == 2 && name == b"bootargs" {
+                    let value = bytes(value, len);
+                    let value = value.strip_suffix(b"\0").unwrap_or(value);
+                    return core::str::from_utf8(value).ok();
+                }
+            }
+            FDT_NOP => {}
+            // FDT_END 或损坏的设备树
+            _ => return None,
+        }
+    }
+}
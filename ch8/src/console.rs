@@ -0,0 +1,23 @@
+//! 控制台输出缓冲
+//!
+//! 行缓冲本身在 `tg-console-buffer` 中实现（ch2~ch8 共用），这里只提供本章的逐字节输出函数。
+//! 缓冲中可能留有不以换行结尾的内容（例如 shell 提示符），因此读控制台输入、进程退出、
+//! 关机与 panic 前都要调用 [`flush`]。
+
+/// 把一个字节写入缓冲，遇到换行或缓冲满时刷新
+pub fn put_char(c: u8) {
+    tg_console_buffer::put_char(c, tg_sbi::console_putchar);
+}
+
+/// 立即输出缓冲中的全部内容
+pub fn flush() {
+    tg_console_buffer::flush(tg_sbi::console_putchar);
+}
+
+/// 先刷新输出，再从控制台读一个字符
+///
+/// 交互程序通常先打印不带换行的提示再读输入，不刷新的话提示不会显示。
+pub fn getchar() -> u8 {
+    flush();
+    tg_sbi::console_getchar() as u8
+}
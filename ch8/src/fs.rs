@@ -16,16 +16,27 @@
 
 use crate::virtio_block::BLOCK_DEVICE;
 use alloc::{string::String, sync::Arc, vec::Vec};
-use spin::Lazy;
+use core::cell::Cell;
+use spin::{Lazy, Mutex};
 use tg_easy_fs::{
-    EasyFileSystem, FSManager, FileHandle, Inode, OpenFlags, PipeReader, PipeWriter, UserBuffer,
+    block_cache_sync_all, EasyFileSystem, FSManager, FileHandle, Inode, LinkError, OpenFlags,
+    PipeReader, PipeWriter, SeekFrom, UserBuffer,
 };
+use tg_syscall::StatMode;
 
 /// 全局文件系统实例（延迟初始化）
 pub static FS: Lazy<FileSystem> = Lazy::new(|| FileSystem {
     root: EasyFileSystem::root_inode(&EasyFileSystem::open(BLOCK_DEVICE.clone())),
 });
 
+/// 把块缓存中的脏块写回磁盘并冲刷块设备
+///
+/// 关机前调用，避免尚未落盘的修改丢失。
+pub fn sync_all() {
+    block_cache_sync_all();
+    BLOCK_DEVICE.flush();
+}
+
 /// easy-fs 文件系统封装
 pub struct FileSystem {
     /// 根 Inode
@@ -35,24 +46,7 @@ pub struct FileSystem {
 impl FSManager for FileSystem {
     /// 打开文件
     fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>> {
-        let (readable, writable) = flags.read_write();
-        if flags.contains(OpenFlags::CREATE) {
-            if let Some(inode) = self.find(path) {
-                inode.clear();
-                Some(Arc::new(FileHandle::new(readable, writable, inode)))
-            } else {
-                self.root
-                    .create(path)
-                    .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
-            }
-        } else {
-            self.find(path).map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(FileHandle::new(readable, writable, inode))
-            })
-        }
+        self.open_errno(path, flags).ok()
     }
 
     /// 查找文件
@@ -62,13 +56,56 @@ impl FSManager for FileSystem {
 
     /// 列出目录
     fn readdir(&self, _path: &str) -> Option<alloc::vec::Vec<String>> {
-        Some(self.root.readdir())
+        let entries = self.root.readdir().ok()?;
+        Some(entries.into_iter().map(|(name, _)| name).collect())
     }
 
     fn link(&self, _src: &str, _dst: &str) -> isize { unimplemented!() }
     fn unlink(&self, _path: &str) -> isize { unimplemented!() }
 }
 
+impl FileSystem {
+    /// 与 [`FSManager::open`] 相同，失败时返回负的错误码
+    ///
+    /// inode 或数据块用尽导致创建失败时返回 ENOSPC；文件系统只读挂载时以写方式打开、
+    /// 截断或创建文件返回 EROFS；其余失败仍返回 -1。
+    pub fn open_errno(&self, path: &str, flags: OpenFlags) -> Result<Arc<FileHandle>, isize> {
+        let (readable, writable) = flags.read_write();
+        if (writable || flags.contains(OpenFlags::TRUNC)) && self.root.is_read_only() {
+            return Err(EROFS);
+        }
+        if let Some(inode) = self.find(path) {
+            // 文件已存在：CREATE 不影响已有内容，只有 TRUNC 才清空
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.truncate(0);
+            }
+            Ok(Arc::new(FileHandle::new(readable, writable, inode)))
+        } else if flags.contains(OpenFlags::CREATE) {
+            // 文件不存在且带 CREATE：创建新文件
+            self.root
+                .create(path)
+                .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
+                .map_err(create_errno)
+        } else {
+            Err(-1)
+        }
+    }
+}
+
+/// 磁盘空间不足（inode 或数据块用尽）
+const ENOSPC: isize = -28;
+/// 文件系统只读挂载
+const EROFS: isize = -30;
+
+/// 把 easy-fs 创建文件的错误转换为负的错误码，空间不足和只读以外的原因沿用 -1
+fn create_errno(err: LinkError) -> isize {
+    match err {
+        LinkError::NoSpace => ENOSPC,
+        LinkError::ReadOnly => EROFS,
+        _ => -1,
+    }
+}
+
 /// 读取文件全部内容到 Vec<u8>
 pub fn read_all(fd: Arc<FileHandle>) -> Vec<u8> {
     let mut offset = 0usize;
@@ -97,6 +134,12 @@ pub enum Fd {
     PipeRead(PipeReader),
     /// 管道写端（只写）
     PipeWrite(Arc<PipeWriter>),
+    /// 匿名内存文件（memfd_create 创建，可读可写）
+    Mem(MemFile),
+    /// `/dev/null`：读立即返回 0（EOF），写丢弃全部数据
+    Null,
+    /// `/dev/zero`：读填充全零，写丢弃全部数据
+    Zero,
     /// 空描述符（用于 stdin/stdout/stderr）
     Empty {
         /// 是否可读
@@ -107,12 +150,55 @@ pub enum Fd {
 }
 
 impl Fd {
+    /// 识别 `/dev` 下的设备文件（不经过 easy-fs）
+    pub fn open_device(path: &str) -> Option<Fd> {
+        match path {
+            "/dev/null" => Some(Fd::Null),
+            "/dev/zero" => Some(Fd::Zero),
+            _ => None,
+        }
+    }
+
+    /// 移动读写偏移（lseek），返回新的绝对偏移
+    ///
+    /// 只有普通文件与内存文件可以定位；管道、设备与标准输入输出返回 `None`。
+    pub fn seek(&self, offset: isize, from: SeekFrom) -> Option<usize> {
+        match self {
+            Fd::File(f) => f.seek(offset, from),
+            Fd::Mem(m) => {
+                let base = match from {
+                    SeekFrom::Start => 0,
+                    SeekFrom::Current => m.offset.get(),
+                    SeekFrom::End => m.len(),
+                };
+                let new_offset = base.checked_add_signed(offset).filter(|&pos| pos <= isize::MAX as usize)?;
+                m.offset.set(new_offset);
+                Some(new_offset)
+            }
+            _ => None,
+        }
+    }
+
+    /// 描述符种类的简短名字（供自省接口列出 fd）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fd::File(_) => "file",
+            Fd::PipeRead(_) => "pipe:r",
+            Fd::PipeWrite(_) => "pipe:w",
+            Fd::Mem(_) => "memfd",
+            Fd::Null => "/dev/null",
+            Fd::Zero => "/dev/zero",
+            Fd::Empty { .. } => "console",
+        }
+    }
+
     /// 该描述符是否可读
     pub fn readable(&self) -> bool {
         match self {
             Fd::File(f) => f.readable(),
             Fd::PipeRead(_) => true,
             Fd::PipeWrite(_) => false,
+            Fd::Mem(_) | Fd::Null | Fd::Zero => true,
             Fd::Empty { read, .. } => *read,
         }
     }
@@ -123,6 +209,7 @@ impl Fd {
             Fd::File(f) => f.writable(),
             Fd::PipeRead(_) => false,
             Fd::PipeWrite(_) => true,
+            Fd::Mem(_) | Fd::Null | Fd::Zero => true,
             Fd::Empty { write, .. } => *write,
         }
     }
@@ -132,6 +219,13 @@ impl Fd {
         match self {
             Fd::File(f) => f.read(buf),
             Fd::PipeRead(p) => p.read(buf),
+            Fd::Mem(m) => m.read(buf),
+            Fd::Null => 0,
+            Fd::Zero => {
+                let mut buf = buf;
+                buf.buffers.iter_mut().for_each(|slice| slice.fill(0));
+                buf.len() as isize
+            }
             _ => -1,
         }
     }
@@ -141,7 +235,186 @@ impl Fd {
         match self {
             Fd::File(f) => f.write(buf),
             Fd::PipeWrite(p) => p.write(buf),
+            Fd::Mem(m) => m.write(buf),
+            Fd::Null | Fd::Zero => buf.len() as isize,
             _ => -1,
         }
     }
+
+    /// 描述符状态（管道的 size 为当前缓冲字节数的近似值）
+    pub fn stat(&self) -> Option<Stat> {
+        let mut stat = Stat::new();
+        match self {
+            Fd::File(f) => {
+                let info = f.get_stat_info()?;
+                stat.ino = info.ino as u64;
+                stat.mode = StatMode::FILE;
+                stat.nlink = info.nlink;
+            }
+            Fd::PipeRead(p) => {
+                stat.mode = S_IFIFO;
+                stat.nlink = 1;
+                stat.size = p.available_read() as u64;
+            }
+            Fd::PipeWrite(p) => {
+                stat.mode = S_IFIFO;
+                stat.nlink = 1;
+                stat.size = p.available_write() as u64;
+            }
+            Fd::Mem(m) => {
+                stat.mode = StatMode::FILE;
+                stat.nlink = 1;
+                stat.size = m.len() as u64;
+            }
+            Fd::Null | Fd::Zero | Fd::Empty { .. } => return None,
+        }
+        Some(stat)
+    }
+
+    /// 设置非阻塞模式，只有管道两端支持，其他描述符返回 `false`
+    ///
+    /// 标志保存在管道端对象里，dup/fork 得到的副本与原 fd 共享。
+    pub fn set_nonblocking(&self, nonblocking: bool) -> bool {
+        match self {
+            Fd::PipeRead(p) => p.set_nonblocking(nonblocking),
+            Fd::PipeWrite(p) => p.set_nonblocking(nonblocking),
+            _ => return false,
+        }
+        true
+    }
+
+    /// 是否处于非阻塞模式
+    pub fn is_nonblocking(&self) -> bool {
+        match self {
+            Fd::PipeRead(p) => p.is_nonblocking(),
+            Fd::PipeWrite(p) => p.is_nonblocking(),
+            _ => false,
+        }
+    }
+}
+
+/// 匿名内存文件（memfd_create 创建）
+///
+/// 数据保存在内核堆上的可增长缓冲区中，不落盘、不出现在目录树里。
+/// 克隆（fork 继承）得到的副本共享同一份数据，但各自维护读写偏移，
+/// 与 `FileHandle` 的语义保持一致。最后一个引用释放时数据随之回收。
+#[derive(Clone)]
+pub struct MemFile {
+    /// 文件内容
+    data: Arc<Mutex<Vec<u8>>>,
+    /// 当前读写偏移
+    offset: Cell<usize>,
+}
+
+impl MemFile {
+    /// 创建一个空的内存文件
+    pub fn new() -> Self {
+        Self {
+            data: Arc::new(Mutex::new(Vec::new())),
+            offset: Cell::new(0),
+        }
+    }
+
+    /// 当前文件长度
+    pub fn len(&self) -> usize {
+        self.data.lock().len()
+    }
+
+    /// 从当前偏移读取，返回读取的字节数
+    pub fn read(&self, mut buf: UserBuffer) -> isize {
+        let data = self.data.lock();
+        let mut offset = self.offset.get();
+        let mut total = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            if offset >= data.len() {
+                break;
+            }
+            let len = slice.len().min(data.len() - offset);
+            slice[..len].copy_from_slice(&data[offset..offset + len]);
+            offset += len;
+            total += len;
+        }
+        self.offset.set(offset);
+        total as isize
+    }
+
+    /// 从当前偏移写入，空间不足时自动扩展（空洞补零），返回写入的字节数
+    pub fn write(&self, buf: UserBuffer) -> isize {
+        let mut data = self.data.lock();
+        let mut offset = self.offset.get();
+        let mut total = 0usize;
+        for slice in buf.buffers.iter() {
+            let end = offset + slice.len();
+            if end > data.len() {
+                data.resize(end, 0);
+            }
+            data[offset..end].copy_from_slice(slice);
+            offset = end;
+            total += slice.len();
+        }
+        self.offset.set(offset);
+        total as isize
+    }
+
+    /// 把文件大小设为 `len`（ftruncate）：缩小时丢弃末尾的数据，扩大时补零
+    ///
+    /// 扩大所需的内存分配不到时返回 `false`，文件保持原样。
+    pub fn truncate(&self, len: usize) -> bool {
+        let mut data = self.data.lock();
+        let grow = len.saturating_sub(data.len());
+        if data.try_reserve_exact(grow).is_err() {
+            return false;
+        }
+        data.resize(len, 0);
+        true
+    }
+
+    /// 从 `offset` 处读取到 `buf`，不移动读写偏移，返回读取的字节数（mmap 建立映射时使用）
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.lock();
+        let Some(rest) = data.get(offset..) else { return 0 };
+        let len = buf.len().min(rest.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        len
+    }
+
+    /// 把 `buf` 写到 `offset` 处，不移动读写偏移，返回写入的字节数（共享映射写回时使用）
+    ///
+    /// 只写文件范围之内的部分：映射按页对齐，不能因为写回最后一页而把文件变长。
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut data = self.data.lock();
+        let Some(rest) = data.get_mut(offset..) else { return 0 };
+        let len = buf.len().min(rest.len());
+        rest[..len].copy_from_slice(&buf[..len]);
+        len
+    }
+}
+
+/// 管道的文件类型（Linux 的 `S_IFIFO`），`tg_syscall::StatMode` 没有定义这一位
+const S_IFIFO: StatMode = unsafe { StatMode::from_bits_unchecked(0o010000) };
+
+/// fstat 写回用户空间的文件状态（与第七章相同）
+///
+/// 前缀布局与 `tg_syscall::Stat` 一致，`size` 占用原保留填充区。
+#[repr(C)]
+pub struct Stat {
+    /// 设备号
+    pub dev: u64,
+    /// inode 编号
+    pub ino: u64,
+    /// 文件类型
+    pub mode: StatMode,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 文件大小
+    pub size: u64,
+    /// 保留填充
+    pad: [u64; 6],
+}
+
+impl Stat {
+    /// 创建全零的 Stat
+    pub fn new() -> Self {
+        Self { dev: 0, ino: 0, mode: StatMode::NULL, nlink: 0, size: 0, pad: [0; 6] }
+    }
 }
@@ -31,7 +31,7 @@
 //! | 管理器 | PManager | PThreadManager（进程 + 线程双层管理） |
 //! | 同步 | 无 | Mutex / Semaphore / Condvar |
 //! | task-manage feature | `proc` | `thread` |
-//! | 新增依赖 | — | tg-sync |
+//! | 同步原语实现 | — | `sync` 模块（接口与 tg-sync 一致） |
 //!
 //! 教程阅读建议：
 //!
@@ -46,12 +46,19 @@
 #![cfg_attr(target_arch = "riscv64", deny(warnings, missing_docs))]
 #![cfg_attr(not(target_arch = "riscv64"), allow(dead_code, unused_imports))]
 
+/// 控制台输出缓冲：行缓冲，遇到换行或缓冲满时批量输出
+mod console;
+
+/// 启动参数模块：从设备树读取并解析内核命令行
+mod bootargs;
 /// 文件系统模块：easy-fs 封装 + 统一 Fd 枚举
 mod fs;
 /// 进程与线程模块：Process（资源容器）和 Thread（执行单元）
 mod process;
 /// 处理器模块：PROCESSOR 全局管理器（PThreadManager）
 mod processor;
+/// 同步原语模块：MutexBlocking / Semaphore / Condvar
+mod sync;
 /// VirtIO 块设备驱动
 mod virtio_block;
 
@@ -62,10 +69,14 @@ extern crate tg_console;
 extern crate alloc;
 
 use crate::{
+    bootargs::{BootArgs, Sched},
     fs::{read_all, FS},
     impls::{Sv39Manager, SyscallContext},
     process::{Process, Thread},
-    processor::{ProcManager, ProcessorInner, ThreadManager},
+    processor::{
+        exit_current_thread, exit_thread_group, expire_timed_waits, next_wait_deadline, reap_waited_thread,
+        take_expired_alarms, ProcManager, ProcessorInner, ThreadManager,
+    },
 };
 use alloc::alloc::alloc;
 use core::{alloc::Layout, cell::UnsafeCell, mem::MaybeUninit};
@@ -84,7 +95,7 @@ use tg_kernel_vm::{
     AddressSpace,
 };
 use tg_sbi;
-use tg_signal::SignalResult;
+use tg_signal::{SignalNo, SignalResult};
 use tg_syscall::Caller;
 use tg_task_manage::ProcId;
 use xmas_elf::ElfFile;
@@ -126,10 +137,16 @@ unsafe extern "C" fn _start() -> ! {
     )
 }
 
-/// 物理内存容量 = 48 MiB
+/// 物理内存容量 = 48 MiB（可被启动参数 `mem=` 覆盖）
 const MEMORY: usize = 48 << 20;
 /// 异界传送门所在虚页
 const PROTAL_TRANSIT: VPN<Sv39> = VPN::MAX;
+/// semaphore_down / mutex_lock / condvar_wait 的内部返回值：资源不可用，当前线程需要阻塞
+///
+/// 只在内核内部使用，不会出现在用户态：主循环见到它就阻塞线程并把返回值写为 0，
+/// 唤醒者把资源转交给该线程后再让它就绪，被唤醒时系统调用已经完成。
+/// 与 -1 区分开，id 非法等真正的错误才能原样返回给用户态，而不是把线程永远阻塞。
+const BLOCKED: isize = isize::MIN;
 
 /// 内核地址空间的全局存储
 struct KernelSpace {
@@ -167,20 +184,39 @@ pub const MMIO: &[(usize, usize)] = &[(0x1000_1000, 0x00_1000)];
 /// - 使用 `PThreadManager`（双层管理器）替代 `PManager`
 /// - 初始化时同时创建 Process 和 Thread
 /// - 主循环中新增**线程阻塞**处理（SEMAPHORE_DOWN/MUTEX_LOCK/CONDVAR_WAIT）
-extern "C" fn rust_main() -> ! {
+///
+/// `dtb` 是引导时 `a1` 中的设备树地址，启动参数从中读取。
+extern "C" fn rust_main(_hartid: usize, dtb: usize) -> ! {
     let layout = tg_linker::KernelLayout::locate();
     // 步骤 1：BSS 清零
     unsafe { layout.zero_bss() };
-    // 步骤 2：控制台和日志
+    // 步骤 2：控制台、启动参数和日志（设备树只在开启分页前可访问）
     tg_console::init_console(&Console);
     tg_console::set_log_level(option_env!("LOG"));
+    let cmdline = unsafe { bootargs::from_dtb(dtb) }.unwrap_or("");
+    let args = BootArgs::parse(cmdline);
+    if args.log.is_some() {
+        tg_console::set_log_level(args.log);
+    }
     tg_console::test_log();
+    log::info!("bootargs: {cmdline:?}");
+    let memory = match args.mem {
+        Some(mem) if mem > layout.len() => mem & !((1 << Sv39::PAGE_BITS) - 1),
+        Some(mem) => {
+            log::warn!("mem={mem} is smaller than the kernel image, using {MEMORY}");
+            MEMORY
+        }
+        None => MEMORY,
+    };
+    if args.root.is_some_and(|dev| dev != "/dev/vda") {
+        log::warn!("root={} is not supported, using /dev/vda", args.root.unwrap());
+    }
     // 步骤 3：堆分配器
     tg_kernel_alloc::init(layout.start() as _);
     unsafe {
         tg_kernel_alloc::transfer(core::slice::from_raw_parts_mut(
             layout.end() as _,
-            MEMORY - layout.len(),
+            memory - layout.len(),
         ))
     };
     // 步骤 4：异界传送门
@@ -189,7 +225,7 @@ extern "C" fn rust_main() -> ! {
     let portal_ptr = unsafe { alloc(portal_layout) };
     assert!(portal_layout.size() < 1 << Sv39::PAGE_BITS);
     // 步骤 5：内核地址空间
-    kernel_space(layout, MEMORY, portal_ptr as _);
+    kernel_space(layout, memory, portal_ptr as _);
     // 步骤 6：异界传送门初始化
     let portal = unsafe { MultislotPortal::init_transit(PROTAL_TRANSIT.base().val(), 1) };
     // 步骤 7：系统调用初始化
@@ -200,24 +236,79 @@ extern "C" fn rust_main() -> ! {
     tg_syscall::init_signal(&SyscallContext);
     tg_syscall::init_thread(&SyscallContext);       // 本章新增：线程系统调用
     tg_syscall::init_sync_mutex(&SyscallContext);   // 本章新增：同步原语系统调用
+    tg_syscall::init_memory(&SyscallContext);
     // 步骤 8：加载 initproc（返回 Process + Thread）
-    let initproc = read_all(FS.open("initproc", OpenFlags::RDONLY).unwrap());
-    if let Some((process, thread)) = Process::from_elf(ElfFile::new(initproc.as_slice()).unwrap()) {
-        // 初始化双层管理器：ProcManager（进程）+ ThreadManager（线程）
-        PROCESSOR.get_mut().set_proc_manager(ProcManager::new());
-        PROCESSOR.get_mut().set_manager(ThreadManager::new());
-        let (pid, tid) = (process.pid, thread.tid);
-        PROCESSOR
-            .get_mut()
-            .add_proc(pid, process, ProcId::from_usize(usize::MAX));
-        PROCESSOR.get_mut().add(tid, thread, pid);
-    }
+    let Some(fd) = FS.open("initproc", OpenFlags::RDONLY) else {
+        no_initproc("not found")
+    };
+    let initproc = read_all(fd);
+    let Some((process, thread)) = ElfFile::new(initproc.as_slice()).ok().and_then(Process::from_elf) else {
+        no_initproc("not a valid RISC-V executable")
+    };
+    // 初始化双层管理器：ProcManager（进程）+ ThreadManager（线程）
+    PROCESSOR.get_mut().set_proc_manager(ProcManager::new());
+    PROCESSOR.get_mut().set_manager(ThreadManager::new());
+    let (pid, tid) = (process.pid, thread.tid);
+    PROCESSOR
+        .get_mut()
+        .add_proc(pid, process, ProcId::from_usize(usize::MAX));
+    PROCESSOR.get_mut().add(tid, thread, pid);
+
+    // 时间片轮转与限时等待都需要时钟中断；FIFO 下只在有限时等待时设置定时器。
+    // 此后 args 中的字符串已不可访问，只保留调度策略
+    let sched = args.sched;
+    tg_sbi::set_timer(u64::MAX);
+    unsafe { sie::set_stimer() };
+
+    // 本轮时间片的结束时刻，FIFO 下没有时间片；时钟中断早于它到来说明只是限时等待到期
+    let mut slice_end = u64::MAX;
+    // 上一轮被限时等待的时钟中断打断的线程，本轮不经过就绪队列直接继续运行
+    let mut resume = false;
 
     // ─── 主调度循环 ───
     loop {
         let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
-        if let Some(task) = unsafe { (*processor).find_next() } {
+        // 到期的 alarm 投递 SIGALRM，进程下次从系统调用或时钟中断返回用户态时处理
+        for pid in take_expired_alarms(time::read64()) {
+            if let Some(proc) = unsafe { (*processor).get_proc(pid) } {
+                proc.signal.add_signal(SignalNo::SIGALRM);
+            }
+        }
+        // 到期的限时等待：把线程移出等待队列，系统调用返回超时
+        unsafe { expire_timed_waits(&mut *processor, time::read64()) };
+        let resumed = core::mem::take(&mut resume);
+        let next = if resumed {
+            unsafe { (*processor).current() }
+        } else {
+            unsafe { (*processor).find_next() }
+        };
+        if let Some(task) = next {
+            // 线程组已被致命信号杀死：组内其余线程不再运行，直接退出
+            if let Some(exit_code) = unsafe { (*processor).get_current_proc() }.and_then(|proc| proc.group_exit) {
+                unsafe { exit_current_thread(&mut *processor, exit_code) };
+                continue;
+            }
+            unsafe { reap_waited_thread(&mut *processor) };
+            let deadline = next_wait_deadline();
+            if sched == Sched::Rr {
+                // 继续运行的线程沿用原来的时间片
+                if !resumed {
+                    slice_end = time::read64() + 12500;
+                }
+                tg_sbi::set_timer(deadline.map_or(slice_end, |deadline| deadline.min(slice_end)));
+            } else if let Some(deadline) = deadline {
+                tg_sbi::set_timer(deadline);
+            }
+            let start = time::read64();
             unsafe { task.context.execute(portal, ()) };
+            // 每轮只在陷入内核时读一次 time，既用于累计运行时间，也作为本轮 clock_gettime 的缓存
+            let now = time::read64();
+            PROCESSOR.set_now(now);
+            // 累计当前进程的用户态运行时间
+            if let Some(current_proc) = unsafe { (*processor).get_current_proc() } {
+                current_proc.utime += (now - start) as usize;
+                current_proc.check_cpu_limit();
+            }
 
             match scause::read().cause() {
                 // ─── 系统调用 ───
@@ -227,28 +318,51 @@ extern "C" fn rust_main() -> ! {
                     ctx.move_next();
                     let id: Id = ctx.a(7).into();
                     let args = [ctx.a(0), ctx.a(1), ctx.a(2), ctx.a(3), ctx.a(4), ctx.a(5)];
-                    let syscall_ret = tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args);
+                    let syscall_ret = match tg_syscall::handle(Caller { entity: 0, flow: 0 }, id, args) {
+                        // tg-syscall 未覆盖的调用交给内核扩展分发
+                        Ret::Unsupported(_) => match impls::handle_extra(ctx.a(7), args) {
+                            Some(ret) => Ret::Done(ret),
+                            None => Ret::Unsupported(id),
+                        },
+                        ret => ret,
+                    };
 
                     // ─── 信号处理 ───
                     let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
-                    match current_proc.signal.handle_signals(ctx) {
+                    current_proc.take_thread_signals(task);
+                    let ctx = &mut task.context.context;
+                    // 进入用户处理函数时按 sa_mask 临时扩充掩码
+                    match current_proc.deliver_signals(ctx) {
                         SignalResult::ProcessKilled(exit_code) => unsafe {
-                            (*processor).make_current_exited(exit_code as _)
+                            exit_thread_group(&mut *processor, exit_code as _)
                         },
                         _ => match syscall_ret {
                             Ret::Done(ret) => match id {
-                                Id::EXIT => unsafe { (*processor).make_current_exited(ret) },
+                                Id::EXIT => unsafe { exit_current_thread(&mut *processor, ret) },
                                 // ─── 本章新增：同步原语阻塞处理 ───
-                                // 当 semaphore_down / mutex_lock / condvar_wait 返回 -1 时，
-                                // 表示资源不可用，将当前线程标记为阻塞态
-                                Id::SEMAPHORE_DOWN | Id::MUTEX_LOCK | Id::CONDVAR_WAIT => {
+                                // 当 semaphore_down / mutex_lock / condvar_wait 返回 BLOCKED 时，
+                                // 表示资源不可用，将当前线程标记为阻塞态；
+                                // waittid 返回 BLOCKED 表示目标线程未退出，唤醒者会把 a0 改写为退出码；
+                                // 读写锁的 lock 与 mutex_lock 相同，限时等待超时时由调度循环把 a0 改写为 -2
+                                Id::SEMAPHORE_DOWN
+                                | Id::MUTEX_LOCK
+                                | Id::CONDVAR_WAIT
+                                | Id::WAITID
+                                | Id(
+                                    impls::extra_id::RWLOCK_READ_LOCK
+                                    | impls::extra_id::RWLOCK_WRITE_LOCK
+                                    | impls::extra_id::SEMAPHORE_DOWN_TIMEOUT
+                                    | impls::extra_id::CONDVAR_WAIT_TIMEOUT,
+                                ) => {
                                     let ctx = &mut task.context.context;
-                                    *ctx.a_mut(0) = ret as _;
-                                    if ret == -1 {
-                                        // 阻塞：从就绪队列移除，等待资源释放后唤醒
+                                    if ret == BLOCKED {
+                                        // 阻塞：从就绪队列移除，等待资源释放后唤醒。
+                                        // 被唤醒时资源已经转交给该线程，因此系统调用返回 0
+                                        *ctx.a_mut(0) = 0;
                                         unsafe { (*processor).make_current_blocked() };
                                     } else {
-                                        // 成功获取：正常挂起（时间片轮转）
+                                        // 成功获取（0）或参数错误（-1）：直接返回，正常挂起（时间片轮转）
+                                        *ctx.a_mut(0) = ret as _;
                                         unsafe { (*processor).make_current_suspend() };
                                     }
                                 }
@@ -260,29 +374,81 @@ extern "C" fn rust_main() -> ! {
                             },
                             Ret::Unsupported(_) => {
                                 log::info!("id = {id:?}");
-                                unsafe { (*processor).make_current_exited(-2) };
+                                unsafe { exit_current_thread(&mut *processor, -2) };
                             }
                         },
                     }
                 }
+                // ─── 时间片用完（sched=rr），或限时等待到期 ───
+                scause::Trap::Interrupt(scause::Interrupt::SupervisorTimer) => {
+                    tg_sbi::set_timer(u64::MAX);
+                    // 纯计算的进程可能一直不进行系统调用，RLIMIT_CPU 等信号要在这里处理
+                    let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+                    current_proc.take_thread_signals(task);
+                    let ctx = &mut task.context.context;
+                    match current_proc.deliver_signals(ctx) {
+                        SignalResult::ProcessKilled(exit_code) => unsafe {
+                            exit_thread_group(&mut *processor, exit_code as _)
+                        },
+                        // 时间片未用完（FIFO 下总是如此）：只是限时等待到期，下一轮开头唤醒到期的线程后
+                        // 当前线程继续运行，不被轮转到就绪队列末尾
+                        _ if now < slice_end => resume = true,
+                        _ => unsafe { (*processor).make_current_suspend() },
+                    }
+                }
+                // ─── 写 fork 后写时复制的页：复制出私有页，重新执行出错的指令 ───
+                scause::Trap::Exception(scause::Exception::StorePageFault) => {
+                    let addr = stval::read();
+                    let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+                    if process::resolve_cow(&current_proc.address_space, addr, 1) {
+                        unsafe { (*processor).make_current_suspend() };
+                    } else {
+                        log::error!("page fault at {addr:#x}, pc = {:#x}", task.context.context.pc());
+                        unsafe { exit_current_thread(&mut *processor, -3) };
+                    }
+                }
                 e => {
                     log::error!("unsupported trap: {e:?}");
-                    unsafe { (*processor).make_current_exited(-3) };
+                    unsafe { exit_current_thread(&mut *processor, -3) };
                 }
             }
+        } else if let Some(deadline) = next_wait_deadline() {
+            // 所有线程都在阻塞，但有限时等待：空转到最早的到期时刻
+            while time::read64() < deadline {
+                core::hint::spin_loop();
+            }
         } else {
             println!("no task");
             break;
         }
     }
 
-    tg_sbi::shutdown(false)
+    shutdown(false)
+}
+
+/// 关机：先同步文件系统，再通过 SBI 关机
+///
+/// 除 panic 外的关机路径都应经过这里。
+fn shutdown(failure: bool) -> ! {
+    fs::sync_all();
+    console::flush();
+    tg_sbi::shutdown(failure)
+}
+
+/// initproc 缺失或无法加载：打印原因和镜像中可用的程序后关机，而不是 panic
+fn no_initproc(reason: &str) -> ! {
+    log::error!("cannot load initproc: {reason}");
+    println!("available apps:");
+    FS.readdir("").into_iter().flatten().for_each(|app| println!("{app}"));
+    shutdown(true)
 }
 
 /// panic 处理
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     println!("{info}");
+    console::flush();
+    // panic 时可能正持有块缓存的锁，不做同步，直接关机
     tg_sbi::shutdown(true)
 }
 
@@ -348,22 +514,32 @@ fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
 mod impls {
     use crate::{
         build_flags,
-        fs::{read_all, Fd, FS},
-        processor::ProcessorInner,
-        Sv39, Thread, PROCESSOR,
+        fs::{read_all, Fd, MemFile, Stat, FS},
+        process::{resolve_cow, unshare_page, MapArea, MemMapping, WriteTranslate},
+        processor::{
+            add_tid_waiter, add_wait_deadline, cancel_wait_deadline, donate_priority, get_real_timer,
+            recompute_inherited, release_pid, release_tid, set_real_timer, take_exit_time, ProcessorInner,
+            TimedWait,
+        },
+        Sv39, Thread, BLOCKED, PROCESSOR,
     };
+    use core::fmt::Write;
     use alloc::sync::Arc;
-    use alloc::{alloc::alloc_zeroed, string::String, vec::Vec};
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        string::String,
+        vec::Vec,
+    };
     use core::{alloc::Layout, ptr::NonNull};
     use spin::Mutex;
     use tg_console::log;
-    use tg_easy_fs::{make_pipe, FSManager, OpenFlags, UserBuffer};
+    use tg_easy_fs::{make_pipe, FSManager, OpenFlags, SeekFrom, UserBuffer, EPIPE};
     use tg_kernel_vm::{
         page_table::{MmuMeta, Pte, VAddr, VmFlags, VmMeta, PPN, VPN},
-        PageManager,
+        AddressSpace, PageManager,
     };
     use tg_signal::SignalNo;
-    use tg_sync::{Condvar, Mutex as MutexTrait, MutexBlocking, Semaphore};
+    use crate::sync::{Condvar, Mutex as MutexTrait, MutexBlocking, Resource, RwLock, Semaphore, DEADLOCK};
     use tg_syscall::*;
     use tg_task_manage::{ProcId, ThreadId};
     use xmas_elf::ElfFile;
@@ -375,7 +551,9 @@ mod impls {
     pub struct Sv39Manager(NonNull<Pte<Sv39>>);
 
     impl Sv39Manager {
-        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
+        const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(Self::OWNED_BIT) };
+        /// OWNED 标志在页表项中的位（释放页表时直接按位判断）
+        pub(crate) const OWNED_BIT: usize = 1 << 8;
         #[inline]
         fn page_alloc<T>(count: usize) -> *mut T {
             unsafe {
@@ -386,6 +564,17 @@ mod impls {
             }
             .cast()
         }
+
+        /// 归还 `count` 个从 `ptr` 开始的物理页面（与 `page_alloc` 的布局一致）
+        #[inline]
+        fn page_dealloc(ptr: *mut u8, count: usize) {
+            unsafe {
+                dealloc(
+                    ptr,
+                    Layout::from_size_align_unchecked(count << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
@@ -410,8 +599,62 @@ mod impls {
             *flags |= Self::OWNED;
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize { todo!() }
-        fn drop_root(&mut self) { todo!() }
+        /// 释放 `pte` 指向的 `len` 个物理页面，返回释放的页数
+        ///
+        /// 只回收带 OWNED 标志、由本地址空间分配的页；外部映射的页（恒等映射、异界传送门）不归它所有。
+        /// fork 共享的页与 `drop_root` 一样，由最后一个映射它的地址空间释放。
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            if let Some(last) = unshare_page(pte.ppn().val()) {
+                if !last {
+                    return 0;
+                }
+            } else if !self.check_owned(pte) {
+                return 0;
+            }
+            Self::page_dealloc(self.p_to_v::<u8>(pte.ppn()).as_ptr(), len);
+            len
+        }
+
+        /// 释放整棵页表：自有的叶子页面、全部中间页表，最后是根页表本身
+        ///
+        /// 用户地址只落在根页表的低半部分；高半部分的异界传送门项是从内核地址空间复制来的，
+        /// 指向的子树属于内核，不能释放。叶子页按 4 KiB 逐页归还。
+        fn drop_root(&mut self) {
+            const PTE_V: usize = 1 << 0;
+            const PTE_RWX: usize = 0b111 << 1;
+            fn free_table(table: *mut usize, level: usize, entries: usize) {
+                for index in 0..entries {
+                    let pte = unsafe { *table.add(index) };
+                    if pte & PTE_V == 0 {
+                        continue;
+                    }
+                    let ppn = (pte >> 10) & ((1 << 44) - 1);
+                    let page = (ppn << Sv39::PAGE_BITS) as *mut u8;
+                    if pte & PTE_RWX == 0 {
+                        if level > 0 {
+                            free_table(page.cast(), level - 1, 512);
+                        }
+                    } else if let Some(last) = unshare_page(ppn) {
+                        // fork 共享的页：最后一个映射它的地址空间负责释放，不论它是否为原主
+                        if last {
+                            Sv39Manager::page_dealloc(page, 1);
+                        }
+                    } else if pte & Sv39Manager::OWNED_BIT != 0 {
+                        // level 级的叶子覆盖 512^level 个页面
+                        Sv39Manager::page_dealloc(page, 1 << (9 * level));
+                    }
+                }
+                Sv39Manager::page_dealloc(table.cast(), 1);
+            }
+            free_table(self.0.as_ptr().cast(), 2, 256);
+        }
+    }
+
+    impl Drop for Sv39Manager {
+        /// 地址空间被释放（进程退出、exec 换掉旧地址空间）时回收它的页表和页面
+        fn drop(&mut self) {
+            self.drop_root();
+        }
     }
 
     // ─── 控制台 ───
@@ -420,7 +663,7 @@ mod impls {
     pub struct Console;
     impl tg_console::Console for Console {
         #[inline]
-        fn put_char(&self, c: u8) { tg_sbi::console_putchar(c); }
+        fn put_char(&self, c: u8) { crate::console::put_char(c); }
     }
 
     // ─── 系统调用实现 ───
@@ -429,6 +672,27 @@ mod impls {
     pub struct SyscallContext;
     const READABLE: VmFlags<Sv39> = build_flags("RV");
     const WRITEABLE: VmFlags<Sv39> = build_flags("W_V");
+    /// 路径的最大长度（含结尾的 `\0`），与 Linux 的 `PATH_MAX` 相同
+    const PATH_MAX: usize = 4096;
+
+    /// 从用户空间读取以 `\0` 结尾的字符串，最多读 `max` 字节，可以跨页
+    ///
+    /// 地址未映射，或读满 `max` 字节仍没有 `\0`（ENAMETOOLONG）时返回 `None`，调用方返回 -1。
+    fn read_user_cstr(space: &AddressSpace<Sv39, Sv39Manager>, ptr: usize, max: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut raw_ptr: *const u8 = core::ptr::null();
+        for addr in ptr..ptr.checked_add(max)? {
+            // 只在起点和每个新页的开头翻译地址
+            if addr == ptr || addr & ((1 << Sv39::PAGE_BITS) - 1) == 0 {
+                raw_ptr = space.translate::<u8>(VAddr::new(addr), READABLE)?.as_ptr();
+            }
+            let ch = unsafe { *raw_ptr };
+            if ch == 0 { return Some(string); }
+            string.push(ch as char);
+            raw_ptr = unsafe { raw_ptr.add(1) };
+        }
+        None
+    }
 
     /// IO 系统调用（与第七章基本相同）
     ///
@@ -438,19 +702,24 @@ mod impls {
         fn write(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().get_current_proc().unwrap();
             if let Some(ptr) = current.address_space.translate(VAddr::new(buf), READABLE) {
-                if fd == STDOUT || fd == STDDEBUG {
+                if current.is_console(fd, true) {
                     print!("{}", unsafe {
                         core::str::from_utf8_unchecked(core::slice::from_raw_parts(
                             ptr.as_ptr(), count,
                         ))
                     });
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     let file = file.lock();
                     if file.writable() {
                         let mut v: Vec<&'static mut [u8]> = Vec::new();
                         unsafe { v.push(core::slice::from_raw_parts_mut(ptr.as_ptr(), count)) };
-                        file.write(UserBuffer::new(v)) as _
+                        let ret = file.write(UserBuffer::new(v));
+                        // 管道读端已全部关闭：与 Linux 一样投递 SIGPIPE，并返回 EPIPE
+                        if ret == EPIPE {
+                            current.signal.add_signal(SignalNo::SIGPIPE);
+                        }
+                        ret
                     } else { log::error!("file not writable"); -1 }
                 } else { log::error!("unsupported fd: {fd}"); -1 }
             } else { log::error!("ptr not readable"); -1 }
@@ -458,14 +727,27 @@ mod impls {
 
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            // 先确认 fd 可读，再替用户缓冲区解除写时复制：无效 fd 不应触发按用户长度的页表遍历
+            match current.fd_table.get(fd) {
+                Some(Some(file)) if file.lock().readable() => {}
+                Some(Some(_)) => {
+                    log::error!("file not readable");
+                    return -1;
+                }
+                _ => {
+                    log::error!("unsupported fd: {fd}");
+                    return -1;
+                }
+            }
+            resolve_cow(&current.address_space, buf, count);
             if let Some(ptr) = current.address_space.translate(VAddr::new(buf), WRITEABLE) {
-                if fd == STDIN {
+                if current.is_console(fd, false) {
                     let mut ptr = ptr.as_ptr();
                     for _ in 0..count {
-                        unsafe { *ptr = tg_sbi::console_getchar() as u8; ptr = ptr.add(1); }
+                        unsafe { *ptr = crate::console::getchar(); ptr = ptr.add(1); }
                     }
                     count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+                } else if let Some(Some(file)) = current.fd_table.get(fd) {
                     let file = file.lock();
                     if file.readable() {
                         let mut v: Vec<&'static mut [u8]> = Vec::new();
@@ -478,24 +760,21 @@ mod impls {
 
         fn open(&self, _caller: Caller, path: usize, flags: usize) -> isize {
             let current = PROCESSOR.get_mut().get_current_proc().unwrap();
-            if let Some(ptr) = current.address_space.translate(VAddr::new(path), READABLE) {
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 { break; }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
-                    }
-                }
-                if let Some(file_handle) =
-                    FS.open(string.as_str(), OpenFlags::from_bits(flags as u32).unwrap())
-                {
+            if let Some(string) = read_user_cstr(&current.address_space, path, PATH_MAX) {
+                if let Some(device) = Fd::open_device(&string) {
                     let new_fd = current.fd_table.len();
-                    current.fd_table.push(Some(Mutex::new(Fd::File((*file_handle).clone()))));
+                    current.fd_table.push(Some(Arc::new(Mutex::new(device))));
                     new_fd as isize
-                } else { -1 }
+                } else {
+                    match FS.open_errno(string.as_str(), OpenFlags::from_bits(flags as u32).unwrap()) {
+                        Ok(file_handle) => {
+                            let new_fd = current.fd_table.len();
+                            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::File((*file_handle).clone())))));
+                            new_fd as isize
+                        }
+                        Err(errno) => errno,
+                    }
+                }
             } else { log::error!("ptr not writeable"); -1 }
         }
 
@@ -514,15 +793,28 @@ mod impls {
             let read_fd = current.fd_table.len();
             let write_fd = read_fd + 1;
             if let Some(mut ptr) = current.address_space
-                .translate::<usize>(VAddr::new(pipe), WRITEABLE)
+                .translate_for_write::<usize>(VAddr::new(pipe), WRITEABLE)
             { unsafe { *ptr.as_mut() = read_fd }; } else { return -1; }
             if let Some(mut ptr) = current.address_space
-                .translate::<usize>(VAddr::new(pipe + core::mem::size_of::<usize>()), WRITEABLE)
+                .translate_for_write::<usize>(VAddr::new(pipe + core::mem::size_of::<usize>()), WRITEABLE)
             { unsafe { *ptr.as_mut() = write_fd }; } else { return -1; }
-            current.fd_table.push(Some(Mutex::new(Fd::PipeRead(read_end))));
-            current.fd_table.push(Some(Mutex::new(Fd::PipeWrite(write_end))));
+            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::PipeRead(read_end)))));
+            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::PipeWrite(write_end)))));
             0
         }
+
+        /// fstat 系统调用（管道返回 FIFO 类型及缓冲字节数）
+        fn fstat(&self, _caller: Caller, fd: usize, st: usize) -> isize {
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let stat = match current.fd_table.get(fd) {
+                Some(Some(file)) => match file.lock().stat() { Some(stat) => stat, None => return -1 },
+                _ => return -1,
+            };
+            if let Some(mut ptr) = current.address_space.translate_for_write::<Stat>(VAddr::new(st), WRITEABLE) {
+                unsafe { *ptr.as_mut() = stat };
+                0
+            } else { -1 }
+        }
     }
 
     /// 进程管理系统调用
@@ -548,6 +840,7 @@ mod impls {
         /// exec：从文件系统加载新程序
         fn exec(&self, _caller: Caller, path: usize, count: usize) -> isize {
             const READABLE: VmFlags<Sv39> = build_flags("RV");
+            if count >= PATH_MAX { return -1; }
             let current = PROCESSOR.get_mut().get_current_proc().unwrap();
             current.address_space
                 .translate(VAddr::new(path), READABLE)
@@ -558,11 +851,17 @@ mod impls {
                 .map_or_else(
                     || {
                         log::error!("unknown app, select one in the list: ");
-                        FS.readdir("").unwrap().into_iter().for_each(|app| println!("{app}"));
+                        FS.readdir("").into_iter().flatten().for_each(|app| println!("{app}"));
                         println!();
                         -1
                     },
-                    |fd| { current.exec(ElfFile::new(&read_all(fd)).unwrap()); 0 },
+                    |fd| {
+                        let elf_data = read_all(fd);
+                        match ElfFile::new(&elf_data) {
+                            Ok(elf) => if current.exec(elf) { 0 } else { -1 },
+                            Err(_) => -1,
+                        }
+                    },
                 )
         }
 
@@ -573,46 +872,74 @@ mod impls {
             if let Some((dead_pid, exit_code)) =
                 unsafe { (*processor).wait(ProcId::from_usize(pid as usize)) }
             {
+                current.cutime += take_exit_time(dead_pid);
+                // 子进程已从管理器中移除，立即归还它的 PID 和线程的 TID，下一次 fork 即可复用
+                release_pid(dead_pid);
                 if let Some(mut ptr) = current.address_space
-                    .translate::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
+                    .translate_for_write::<i32>(VAddr::new(exit_code_ptr), WRITABLE)
                 { unsafe { *ptr.as_mut() = exit_code as i32 }; }
                 return dead_pid.get_usize() as isize;
             } else { return -1; }
         }
 
+        /// getpid：返回线程组号 tgid，同一进程的所有线程结果相同
         fn getpid(&self, _caller: Caller) -> isize {
-            PROCESSOR.get_mut().get_current_proc().unwrap().pid.get_usize() as _
+            PROCESSOR.get_mut().current().unwrap().tgid.get_usize() as _
         }
     }
 
     impl Scheduling for SyscallContext {
         #[inline]
         fn sched_yield(&self, _caller: Caller) -> isize { 0 }
+
+        /// set_priority：设置当前线程的基础优先级（>= 2），返回设置的值，失败返回 -1
+        ///
+        /// 线程按有效优先级调度：继承来的优先级（见 `processor::donate_priority`）高于基础优先级时以前者为准。
+        fn set_priority(&self, _caller: Caller, prio: isize) -> isize {
+            if prio < 2 {
+                return -1;
+            }
+            PROCESSOR.get_mut().current().unwrap().priority = prio as usize;
+            prio
+        }
+    }
+
+    /// 互斥锁被释放或转交后，重新计算释放者 `tid` 与接手者 `waking_tid` 继承的优先级
+    fn update_inherited(processor: *mut ProcessorInner, tid: ThreadId, waking_tid: Option<ThreadId>) {
+        let mutexes: Vec<Arc<dyn MutexTrait>> =
+            unsafe { (*processor).get_current_proc().unwrap() }.mutex_list.iter().flatten().cloned().collect();
+        for tid in core::iter::once(tid).chain(waking_tid) {
+            unsafe { recompute_inherited(&mut *processor, tid, &mutexes) };
+        }
     }
 
     impl Clock for SyscallContext {
         #[inline]
         fn clock_gettime(&self, _caller: Caller, clock_id: ClockId, tp: usize) -> isize {
             const WRITABLE: VmFlags<Sv39> = build_flags("W_V");
-            match clock_id {
-                ClockId::CLOCK_MONOTONIC => {
-                    if let Some(mut ptr) = PROCESSOR.get_mut().get_current_proc().unwrap()
-                        .address_space.translate(VAddr::new(tp), WRITABLE)
-                    {
-                        let time = riscv::register::time::read() * 10000 / 125;
-                        *unsafe { ptr.as_mut() } = TimeSpec {
-                            tv_sec: time / 1_000_000_000,
-                            tv_nsec: time % 1_000_000_000,
-                        };
-                        0
-                    } else { log::error!("ptr not readable"); -1 }
-                }
-                _ => -1,
-            }
+            // CLOCK_MONOTONIC 返回本轮调度缓存的时刻；CLOCK_MONOTONIC_RAW 绕过缓存直接读 CSR
+            let ticks = match clock_id {
+                ClockId::CLOCK_MONOTONIC => PROCESSOR.now(),
+                ClockId::CLOCK_MONOTONIC_RAW => riscv::register::time::read64(),
+                _ => return -1,
+            };
+            if let Some(mut ptr) = PROCESSOR.get_mut().get_current_proc().unwrap()
+                .address_space.translate_for_write(VAddr::new(tp), WRITABLE)
+            {
+                let time = ticks as usize * 10000 / 125;
+                *unsafe { ptr.as_mut() } = TimeSpec {
+                    tv_sec: time / 1_000_000_000,
+                    tv_nsec: time % 1_000_000_000,
+                };
+                0
+            } else { log::error!("ptr not readable"); -1 }
         }
     }
 
     /// 信号系统调用（与第七章相同）
+    ///
+    /// 信号状态属于进程：kill 投递给整个线程组，由最先返回用户态的线程处理；
+    /// 只投递给单个线程的 tgkill 见 `SyscallContext::tgkill`。
     impl Signal for SyscallContext {
         fn kill(&self, _caller: Caller, pid: isize, signum: u8) -> isize {
             if let Some(target_task) = PROCESSOR.get_mut()
@@ -634,7 +961,7 @@ mod impls {
             if let Ok(signal_no) = SignalNo::try_from(signum) {
                 if signal_no == SignalNo::ERR { return -1; }
                 if old_action as usize != 0 {
-                    if let Some(mut ptr) = current.address_space.translate(VAddr::new(old_action), WRITEABLE) {
+                    if let Some(mut ptr) = current.address_space.translate_for_write(VAddr::new(old_action), WRITEABLE) {
                         if let Some(signal_action) = current.signal.get_action_ref(signal_no) {
                             *unsafe { ptr.as_mut() } = signal_action;
                         } else { return -1; }
@@ -658,57 +985,49 @@ mod impls {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
             let current = unsafe { (*processor).get_current_proc().unwrap() };
             let current_thread = unsafe { (*processor).current().unwrap() };
-            if current.signal.sig_return(&mut current_thread.context.context) { 0 } else { -1 }
+            if current.signal.sig_return(&mut current_thread.context.context) {
+                current.leave_signal_handler();
+                0
+            } else {
+                -1
+            }
         }
     }
 
     /// 线程系统调用（**本章新增**）
     impl tg_syscall::Thread for SyscallContext {
-        /// thread_create：在当前进程中创建新线程
-        ///
-        /// 为新线程分配独立的用户栈（从高地址向下搜索未映射的页面），
-        /// 创建新的执行上下文，入口为 entry，参数为 arg。
+        /// thread_create：在当前进程中创建新线程（默认栈大小）
         fn thread_create(&self, _caller: Caller, entry: usize, arg: usize) -> isize {
-            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
-            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
-            // 从最高用户栈位置向下搜索空闲的页表区域
-            let mut vpn = VPN::<Sv39>::new((1 << 26) - 2);
-            let addrspace = &mut current_proc.address_space;
-            loop {
-                let idx = vpn.index_in(Sv39::MAX_LEVEL);
-                if !addrspace.root()[idx].is_valid() { break; }
-                vpn = VPN::<Sv39>::new(vpn.val() - 3);
-            }
-            // 分配 2 页用户栈
-            let stack = unsafe {
-                alloc_zeroed(Layout::from_size_align_unchecked(
-                    2 << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS,
-                ))
-            };
-            addrspace.map_extern(vpn..vpn + 2, PPN::new(stack as usize >> Sv39::PAGE_BITS), build_flags("U_WRV"));
-            let satp = (8 << 60) | addrspace.root_ppn().val();
-            let mut context = tg_kernel_context::LocalContext::user(entry);
-            *context.sp_mut() = (vpn + 2).base().val();
-            *context.a_mut(0) = arg;
-            let thread = Thread::new(satp, context);
-            let tid = thread.tid;
-            unsafe { (*processor).add(tid, thread, current_proc.pid); }
-            tid.get_usize() as _
+            self.create_thread(entry, arg, THREAD_STACK_PAGES)
         }
 
-        /// gettid：获取当前线程 TID
+        /// gettid：获取当前线程 TID（与 getpid 返回的 tgid 相互独立）
         fn gettid(&self, _caller: Caller) -> isize {
             PROCESSOR.get_mut().current().unwrap().tid.get_usize() as _
         }
 
         /// waittid：等待指定线程退出
+        ///
+        /// 目标线程仍在运行时阻塞当前线程，目标退出时由 `exit_current_thread` 唤醒并写入退出码；
+        /// 多个线程等待同一个线程时全部被唤醒。目标不存在或不属于本进程时返回 -1。
+        /// 取到退出码即完成回收，目标的 TID 归还到池中，可被下一个新线程复用。
         fn waittid(&self, _caller: Caller, tid: usize) -> isize {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
-            let current_thread = unsafe { (*processor).current().unwrap() };
-            if tid == current_thread.tid.get_usize() { return -1; }
-            if let Some(exit_code) = unsafe { (*processor).waittid(ThreadId::from_usize(tid)) } {
-                exit_code
-            } else { -1 }
+            let current_tid = unsafe { (*processor).current().unwrap() }.tid;
+            if tid == current_tid.get_usize() { return -1; }
+            let tid = ThreadId::from_usize(tid);
+            match unsafe { (*processor).waittid(tid) } {
+                // -2 表示尚未退出；退出码本身也可能是 -2，以线程是否还在区分
+                Some(-2) if unsafe { (*processor).get_task(tid) }.is_some() => {
+                    add_tid_waiter(tid, current_tid);
+                    BLOCKED
+                }
+                Some(exit_code) => {
+                    release_tid(tid);
+                    exit_code
+                }
+                None => -1,
+            }
         }
     }
 
@@ -729,28 +1048,40 @@ mod impls {
                 current_proc.semaphore_list.push(Some(Arc::new(Semaphore::new(res_count))));
                 current_proc.semaphore_list.len() - 1
             };
+            current_proc.deadlock.add_resource(Resource::Semaphore(id), res_count);
             id as isize
         }
 
         /// V 操作：释放信号量，唤醒等待线程
         fn semaphore_up(&self, _caller: Caller, sem_id: usize) -> isize {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let tid = unsafe { (*processor).current().unwrap() }.tid;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
             let sem = Arc::clone(current_proc.semaphore_list[sem_id].as_ref().unwrap());
-            if let Some(tid) = sem.up() {
-                unsafe { (*processor).re_enque(tid); }
+            let waking_tid = sem.up();
+            current_proc.deadlock.release(tid, Resource::Semaphore(sem_id), waking_tid);
+            if let Some(waking_tid) = waking_tid {
+                cancel_wait_deadline(waking_tid);
+                unsafe { (*processor).re_enque(waking_tid); }
             }
             0
         }
 
-        /// P 操作：获取信号量，不可用则阻塞
+        /// P 操作：获取信号量，不可用则阻塞；启用死锁检测且请求不安全时返回 `DEADLOCK`
         fn semaphore_down(&self, _caller: Caller, sem_id: usize) -> isize {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
             let current = unsafe { (*processor).current().unwrap() };
             let tid = current.tid;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
-            let sem = Arc::clone(current_proc.semaphore_list[sem_id].as_ref().unwrap());
-            if !sem.down(tid) { -1 } else { 0 }
+            let Some(Some(sem)) = current_proc.semaphore_list.get(sem_id) else { return -1 };
+            let sem = Arc::clone(sem);
+            let resource = Resource::Semaphore(sem_id);
+            if !current_proc.deadlock.request(tid, resource) {
+                return DEADLOCK;
+            }
+            if !sem.down(tid) { return BLOCKED; }
+            current_proc.deadlock.acquire(tid, resource);
+            0
         }
 
         /// 创建互斥锁（blocking=true 为阻塞锁）
@@ -759,36 +1090,55 @@ mod impls {
                 Some(Arc::new(MutexBlocking::new()))
             } else { None };
             let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
-            if let Some(id) = current_proc.mutex_list.iter().enumerate()
+            let id = if let Some(id) = current_proc.mutex_list.iter().enumerate()
                 .find(|(_, item)| item.is_none()).map(|(id, _)| id)
             {
                 current_proc.mutex_list[id] = new_mutex;
-                id as isize
+                id
             } else {
                 current_proc.mutex_list.push(new_mutex);
-                current_proc.mutex_list.len() as isize - 1
-            }
+                current_proc.mutex_list.len() - 1
+            };
+            current_proc.deadlock.add_resource(Resource::Mutex(id), 1);
+            id as isize
         }
 
         /// 解锁，唤醒等待线程
         fn mutex_unlock(&self, _caller: Caller, mutex_id: usize) -> isize {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let tid = unsafe { (*processor).current().unwrap() }.tid;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
             let mutex = Arc::clone(current_proc.mutex_list[mutex_id].as_ref().unwrap());
-            if let Some(tid) = mutex.unlock() {
-                unsafe { (*processor).re_enque(tid); }
+            let waking_tid = mutex.unlock();
+            current_proc.deadlock.release(tid, Resource::Mutex(mutex_id), waking_tid);
+            update_inherited(processor, tid, waking_tid);
+            if let Some(waking_tid) = waking_tid {
+                unsafe { (*processor).re_enque(waking_tid); }
             }
             0
         }
 
-        /// 加锁，已被占用则阻塞
+        /// 加锁，已被占用则阻塞；启用死锁检测且请求不安全时返回 `DEADLOCK`
         fn mutex_lock(&self, _caller: Caller, mutex_id: usize) -> isize {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
             let current = unsafe { (*processor).current().unwrap() };
             let tid = current.tid;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
-            let mutex = Arc::clone(current_proc.mutex_list[mutex_id].as_ref().unwrap());
-            if !mutex.lock(tid) { -1 } else { 0 }
+            let Some(Some(mutex)) = current_proc.mutex_list.get(mutex_id) else { return -1 };
+            let mutex = Arc::clone(mutex);
+            let resource = Resource::Mutex(mutex_id);
+            if !current_proc.deadlock.request(tid, resource) {
+                return DEADLOCK;
+            }
+            if !mutex.lock(tid) {
+                // 优先级继承：持有者至少以当前线程的优先级运行，直到释放这把锁
+                if let Some(owner) = mutex.owner() {
+                    unsafe { donate_priority(&mut *processor, owner, tid) };
+                }
+                return BLOCKED;
+            }
+            current_proc.deadlock.acquire(tid, resource);
+            0
         }
 
         /// 创建条件变量
@@ -811,8 +1161,15 @@ mod impls {
             let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
             let condvar = Arc::clone(current_proc.condvar_list[condvar_id].as_ref().unwrap());
-            if let Some(tid) = condvar.signal() {
-                unsafe { (*processor).re_enque(tid); }
+            if let Some(signaled) = condvar.signal() {
+                // 已被 signal 的限时等待者不会再超时，即使还要排队等互斥锁
+                cancel_wait_deadline(signaled.tid);
+                if signaled.acquired {
+                    current_proc.deadlock.acquire(signaled.tid, Resource::Mutex(signaled.mutex_id));
+                    unsafe { (*processor).re_enque(signaled.tid); }
+                } else if let Some(owner) = current_proc.mutex_list[signaled.mutex_id].as_ref().and_then(|m| m.owner()) {
+                    unsafe { donate_priority(&mut *processor, owner, signaled.tid) };
+                }
             }
             0
         }
@@ -823,19 +1180,839 @@ mod impls {
             let current = unsafe { (*processor).current().unwrap() };
             let tid = current.tid;
             let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
-            let condvar = Arc::clone(current_proc.condvar_list[condvar_id].as_ref().unwrap());
-            let mutex = Arc::clone(current_proc.mutex_list[mutex_id].as_ref().unwrap());
-            let (flag, waking_tid) = condvar.wait_with_mutex(tid, mutex);
+            let (Some(Some(condvar)), Some(Some(mutex))) =
+                (current_proc.condvar_list.get(condvar_id), current_proc.mutex_list.get(mutex_id))
+            else { return -1 };
+            let (condvar, mutex) = (Arc::clone(condvar), Arc::clone(mutex));
+            let waking_tid = condvar.wait_with_mutex(tid, mutex, mutex_id);
+            // 释放互斥锁，被唤醒后还要重新持有它
+            let resource = Resource::Mutex(mutex_id);
+            current_proc.deadlock.release(tid, resource, waking_tid);
+            current_proc.deadlock.wait_for(tid, resource);
+            update_inherited(processor, tid, waking_tid);
             if let Some(waking_tid) = waking_tid {
                 unsafe { (*processor).re_enque(waking_tid); }
             }
-            if !flag { -1 } else { 0 }
+            // 总是阻塞，直到被 signal 唤醒并重新持有互斥锁
+            BLOCKED
         }
 
-        /// 死锁检测（TODO 练习题）
+        /// 死锁检测：`is_enable` 为 1 时启用、为 0 时关闭，只影响当前进程
+        ///
+        /// 启用后 mutex_lock / semaphore_down 在请求会使系统进入不安全状态时返回 `DEADLOCK`（-0xDEAD），
+        /// 而不是让线程阻塞（算法见 `sync::DeadlockDetector`）。其他参数返回 -1。
         fn enable_deadlock_detect(&self, _caller: Caller, is_enable: i32) -> isize {
-            tg_console::log::info!("enable_deadlock_detect: is_enable = {is_enable}, not implemented");
-            -1
+            let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
+            match is_enable {
+                0 | 1 => {
+                    current_proc.deadlock.enabled = is_enable == 1;
+                    0
+                }
+                _ => -1,
+            }
+        }
+    }
+
+    /// 内存不足
+    const ENOMEM: isize = -12;
+    /// 共享映射：修改对映射同一文件的其他进程可见，并写回文件
+    const MAP_SHARED: i32 = 0x01;
+    /// 匿名映射：不对应任何文件，内容全为 0
+    const MAP_ANONYMOUS: i32 = 0x20;
+
+    /// 内存管理系统调用实现
+    ///
+    /// 支持匿名映射与 memfd（`memfd_create` 创建的 fd）的映射，普通文件不能映射。
+    /// 映射属于进程，所有线程共享。
+    impl Memory for SyscallContext {
+        /// mmap 系统调用：映射内存区域，成功返回 0
+        ///
+        /// 映射 memfd 时把文件内容复制到新分配的页；`MAP_SHARED` 的映射在 munmap、exec
+        /// 或进程退出时把修改过的页写回 memfd，私有映射的修改不影响文件。
+        fn mmap(
+            &self,
+            _caller: Caller,
+            addr: usize,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: usize,
+        ) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+            // 检查地址是否页对齐，prot 只能有 bit 0-2 且不能为 0
+            if addr & (PAGE_SIZE - 1) != 0 || prot & !0x7 != 0 || prot == 0 {
+                return EINVAL;
+            }
+            if len == 0 {
+                return 0;
+            }
+            let Some(end) = len.checked_add(PAGE_SIZE - 1).and_then(|len| addr.checked_add(len & !(PAGE_SIZE - 1))) else {
+                return ENOMEM;
+            };
+
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            // 非匿名映射：只支持 memfd，与 Linux 一致要求 fd 可读，可写的共享映射还要求 fd 可写
+            let file = if flags & MAP_ANONYMOUS == 0 {
+                if offset & (PAGE_SIZE - 1) != 0 {
+                    return EINVAL;
+                }
+                let Some(Some(fd)) = usize::try_from(fd).ok().and_then(|fd| current.fd_table.get(fd)) else {
+                    return -1;
+                };
+                let fd = fd.lock();
+                let Fd::Mem(file) = &*fd else {
+                    return -1;
+                };
+                if !fd.readable() || (flags & MAP_SHARED != 0 && prot & 0x2 != 0 && !fd.writable()) {
+                    return -13; // EACCES
+                }
+                Some(file.clone())
+            } else {
+                None
+            };
+
+            // 构建权限标志：U（用户态）+ prot
+            let mut flags_str = *b"U___V";
+            if prot & 0x1 != 0 { flags_str[3] = b'R'; } // 可读
+            if prot & 0x2 != 0 { flags_str[2] = b'W'; } // 可写
+            if prot & 0x4 != 0 { flags_str[1] = b'X'; } // 可执行
+
+            // 先登记区间：与已映射的页重叠时不建立任何映射
+            if !current.add_mmap_area(addr..end, flags_str) {
+                return -1;
+            }
+            let vm_flags = build_flags(unsafe { core::str::from_utf8_unchecked(&flags_str) });
+            current.address_space.map(
+                VAddr::<Sv39>::new(addr).floor()..VAddr::<Sv39>::new(end).ceil(),
+                &[],
+                0,
+                vm_flags,
+            );
+
+            if let Some(file) = file {
+                for page_addr in (addr..end).step_by(PAGE_SIZE) {
+                    let page = current
+                        .address_space
+                        .translate::<u8>(VAddr::new(page_addr), build_flags("__V"))
+                        .unwrap();
+                    let page = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), PAGE_SIZE) };
+                    file.read_at(offset + (page_addr - addr), page);
+                }
+                if flags & MAP_SHARED != 0 {
+                    current.mem_maps.push(MemMapping {
+                        start: addr,
+                        len: end - addr,
+                        file,
+                        offset,
+                    });
+                }
+            }
+            0
+        }
+
+        /// munmap 系统调用：取消内存映射，成功返回 0
+        ///
+        /// 范围必须完全落在 mmap 建立的区间内；共享 memfd 映射中修改过的页先写回 memfd。
+        fn munmap(&self, _caller: Caller, addr: usize, len: usize) -> isize {
+            const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+
+            if addr & (PAGE_SIZE - 1) != 0 {
+                return EINVAL;
+            }
+            if len == 0 {
+                return 0;
+            }
+            let Some(end) = len.checked_add(PAGE_SIZE - 1).and_then(|len| addr.checked_add(len & !(PAGE_SIZE - 1))) else {
+                return EINVAL;
+            };
+
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            if !current.remove_mmap_area(addr, end) {
+                return -1;
+            }
+
+            // 先把共享映射的修改写回 memfd，完全被取消的映射不再记录
+            current.sync_mem_maps(addr, end);
+            current.mem_maps.retain(|m| m.start < addr || m.start + m.len > end);
+
+            current
+                .address_space
+                .unmap(VAddr::<Sv39>::new(addr).floor()..VAddr::<Sv39>::new(end).ceil());
+            0
+        }
+    }
+
+    // ─── 扩展系统调用（tg-syscall 未提供的调用） ───
+
+    /// 与 Linux `struct rusage` 布局一致，`timeval` 以 `[秒, 微秒]` 表示
+    #[repr(C)]
+    struct RUsage {
+        ru_utime: [usize; 2],
+        ru_stime: [usize; 2],
+        rest: [usize; 14],
+    }
+
+    /// time 寄存器频率为 12.5 MHz
+    const TICKS_PER_SEC: u64 = 12_500_000;
+
+    /// 定时器种类：真实时间，到期投递 SIGALRM
+    const ITIMER_REAL: usize = 0;
+
+    /// 用户态的 `struct itimerval`，`timeval` 以 `[秒, 微秒]` 表示
+    #[repr(C)]
+    struct ITimerVal {
+        /// 重装间隔
+        it_interval: [usize; 2],
+        /// 距下次到期的时间
+        it_value: [usize; 2],
+    }
+
+    /// `timeval` 转为 time 寄存器计数，微秒数非法时返回 `None`
+    fn timeval_to_ticks([sec, usec]: [usize; 2]) -> Option<u64> {
+        if usec >= 1_000_000 {
+            return None;
+        }
+        let usec = (sec as u64).checked_mul(1_000_000)?.checked_add(usec as u64)?;
+        // 每微秒 12.5 个计数
+        usec.checked_mul(125).map(|ticks| ticks / 10)
+    }
+
+    /// 由 `(到期时刻, 间隔)` 构造用户态的 `itimerval`，没有定时器时全为 0
+    fn itimerval(timer: Option<(u64, u64)>, now: u64) -> ITimerVal {
+        let to_timeval = |ticks: u64| {
+            let usec = ticks * 10 / 125;
+            [(usec / 1_000_000) as usize, (usec % 1_000_000) as usize]
+        };
+        let (deadline, interval) = timer.unwrap_or((now, 0));
+        ITimerVal { it_interval: to_timeval(interval), it_value: to_timeval(deadline.saturating_sub(now)) }
+    }
+
+    /// 用户态的 `struct rlimit`
+    #[repr(C)]
+    struct RLimit {
+        /// 软限制
+        cur: u64,
+        /// 硬限制
+        max: u64,
+    }
+
+    /// `timeout_ns` 纳秒后的 time 寄存器值
+    fn deadline_after(timeout_ns: usize) -> u64 {
+        let ticks = timeout_ns as u128 * TICKS_PER_SEC as u128 / 1_000_000_000;
+        riscv::register::time::read64().saturating_add(ticks.min(u64::MAX as u128) as u64)
+    }
+
+    /// thread_self_info 写给用户的线程自省信息
+    #[repr(C)]
+    struct ThreadSelfInfo {
+        /// 线程 ID
+        tid: usize,
+        /// 所属线程组（进程）ID
+        tgid: usize,
+        /// set_tid_address 登记的地址，未登记时为 0
+        clear_child_tid: usize,
+        /// 用户栈起始地址（含）
+        stack_start: usize,
+        /// 用户栈结束地址（不含），即线程创建时的栈顶
+        stack_end: usize,
+    }
+
+    /// 资源编号：CPU 时间上限（秒）
+    const RLIMIT_CPU: usize = 0;
+    /// 参数非法
+    const EINVAL: isize = -22;
+
+    /// 扩展系统调用号（与 Linux RISC-V 保持一致）
+    pub mod extra_id {
+        pub const DUP: usize = 23;
+        pub const LSEEK: usize = 62;
+        pub const FTRUNCATE: usize = 46;
+        pub const SET_TID_ADDRESS: usize = 96;
+        pub const GETITIMER: usize = 102;
+        pub const SETITIMER: usize = 103;
+        pub const TGKILL: usize = 131;
+        pub const GETRUSAGE: usize = 165;
+        pub const PRCTL: usize = 167;
+        pub const PRLIMIT64: usize = 261;
+        pub const MEMFD_CREATE: usize = 279;
+        pub const FCNTL: usize = 25;
+        // 以下为本内核自定义编号，紧随对应的 create/lock/unlock 等调用
+        pub const MUTEX_DESTROY: usize = 1013;
+        pub const SEMAPHORE_DESTROY: usize = 1023;
+        // semaphore_down_timeout(sem_id, timeout_ns)
+        pub const SEMAPHORE_DOWN_TIMEOUT: usize = 1024;
+        pub const CONDVAR_DESTROY: usize = 1033;
+        // condvar_wait_timeout(condvar_id, mutex_id, timeout_ns)
+        pub const CONDVAR_WAIT_TIMEOUT: usize = 1034;
+        // 读写锁：rwlock_create(writer_preferred)，随后四个调用的参数都是 rwlock_create 返回的 id
+        pub const RWLOCK_CREATE: usize = 1040;
+        pub const RWLOCK_READ_LOCK: usize = 1041;
+        pub const RWLOCK_READ_UNLOCK: usize = 1042;
+        pub const RWLOCK_WRITE_LOCK: usize = 1043;
+        pub const RWLOCK_WRITE_UNLOCK: usize = 1044;
+        // thread_create(entry, arg, stack_size)：栈字节数向上取整到页，0 表示默认大小
+        // 编号紧随 thread_create/gettid/waittid（1000~1002）
+        pub const THREAD_CREATE_WITH_STACK: usize = 1003;
+        // thread_self_info(buf)：把调用线程的 ThreadSelfInfo 写入 buf
+        pub const THREAD_SELF_INFO: usize = 1004;
+        // proc_read(pid, key, buf, len)：读取进程自省信息
+        pub const PROC_READ: usize = 2000;
+        // alarm(seconds)：RISC-V Linux 没有 alarm（libc 用 setitimer 实现），这里以自定义编号提供
+        pub const ALARM: usize = 2001;
+        // RISC-V Linux 没有 dup2（libc 用 dup3 实现），本内核以自定义编号提供
+        pub const DUP2: usize = 2020;
+    }
+
+    /// 分发扩展系统调用，未知的调用号返回 `None`
+    pub fn handle_extra(id: usize, args: [usize; 6]) -> Option<isize> {
+        let ctx = SyscallContext;
+        let ret = match id {
+            extra_id::DUP => ctx.dup(args[0]),
+            extra_id::LSEEK => ctx.lseek(args[0], args[1] as isize, args[2]),
+            extra_id::FTRUNCATE => ctx.ftruncate(args[0], args[1] as isize),
+            extra_id::DUP2 => ctx.dup2(args[0], args[1]),
+            extra_id::SET_TID_ADDRESS => ctx.set_tid_address(args[0]),
+            extra_id::PRCTL => ctx.prctl(args[0], args[1]),
+            extra_id::GETITIMER => ctx.getitimer(args[0], args[1]),
+            extra_id::SETITIMER => ctx.setitimer(args[0], args[1], args[2]),
+            extra_id::TGKILL => ctx.tgkill(args[0], args[1], args[2]),
+            extra_id::GETRUSAGE => ctx.getrusage(args[0] as isize, args[1]),
+            extra_id::PRLIMIT64 => ctx.prlimit64(args[0], args[1], args[2], args[3]),
+            extra_id::MEMFD_CREATE => ctx.memfd_create(args[0], args[1]),
+            extra_id::FCNTL => ctx.fcntl(args[0], args[1], args[2]),
+            extra_id::MUTEX_DESTROY => ctx.mutex_destroy(args[0]),
+            extra_id::SEMAPHORE_DESTROY => ctx.semaphore_destroy(args[0]),
+            extra_id::CONDVAR_DESTROY => ctx.condvar_destroy(args[0]),
+            extra_id::SEMAPHORE_DOWN_TIMEOUT => ctx.semaphore_down_timeout(args[0], args[1]),
+            extra_id::CONDVAR_WAIT_TIMEOUT => ctx.condvar_wait_timeout(args[0], args[1], args[2]),
+            extra_id::RWLOCK_CREATE => ctx.rwlock_create(args[0] != 0),
+            extra_id::RWLOCK_READ_LOCK => ctx.rwlock_lock(args[0], false),
+            extra_id::RWLOCK_READ_UNLOCK => ctx.rwlock_unlock(args[0], false),
+            extra_id::RWLOCK_WRITE_LOCK => ctx.rwlock_lock(args[0], true),
+            extra_id::RWLOCK_WRITE_UNLOCK => ctx.rwlock_unlock(args[0], true),
+            extra_id::THREAD_CREATE_WITH_STACK => {
+                let pages = if args[2] == 0 { THREAD_STACK_PAGES } else { args[2].div_ceil(1 << Sv39::PAGE_BITS) };
+                ctx.create_thread(args[0], args[1], pages)
+            }
+            extra_id::THREAD_SELF_INFO => ctx.thread_self_info(args[0]),
+            extra_id::PROC_READ => ctx.proc_read(args[0], args[1], args[2], args[3]),
+            extra_id::ALARM => ctx.alarm(args[0]),
+            _ => return None,
+        };
+        Some(ret)
+    }
+
+    /// 线程栈默认页数
+    const THREAD_STACK_PAGES: usize = 2;
+    /// 线程栈最大页数（栈从内核堆分配，需要物理连续）
+    const THREAD_STACK_MAX_PAGES: usize = 256;
+
+    impl SyscallContext {
+        /// 创建线程并分配 `pages` 页用户栈，返回 TID，栈过大或分配失败返回 -1
+        ///
+        /// 栈放在一个完全未使用的一级页表项（1 GiB 区域）内，从高地址向下搜索，
+        /// 因此不同线程的栈互不重叠，栈底之下的页面保持未映射，充当 guard page。
+        fn create_thread(&self, entry: usize, arg: usize, pages: usize) -> isize {
+            if pages == 0 || pages > THREAD_STACK_MAX_PAGES { return -1; }
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+            // 从最高用户栈位置向下搜索空闲的页表区域，栈顶为 vpn + 2
+            let mut vpn = VPN::<Sv39>::new((1 << 26) - 2);
+            let addrspace = &mut current_proc.address_space;
+            loop {
+                let idx = vpn.index_in(Sv39::MAX_LEVEL);
+                if !addrspace.root()[idx].is_valid() { break; }
+                vpn = VPN::<Sv39>::new(vpn.val() - 3);
+            }
+            let top = vpn + 2;
+            let bottom = VPN::<Sv39>::new(top.val() - pages);
+            // 栈必须整体落在同一个空闲区域内
+            if bottom.index_in(Sv39::MAX_LEVEL) != vpn.index_in(Sv39::MAX_LEVEL) { return -1; }
+            let stack = unsafe {
+                alloc_zeroed(Layout::from_size_align_unchecked(
+                    pages << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS,
+                ))
+            };
+            if stack.is_null() { return -1; }
+            // 标记 OWNED：栈页归本地址空间所有，线程所在进程退出时随地址空间一起回收
+            addrspace.map_extern(bottom..top, PPN::new(stack as usize >> Sv39::PAGE_BITS), build_flags("U_WRV") | Sv39Manager::OWNED);
+            let satp = (8 << 60) | addrspace.root_ppn().val();
+            current_proc.maps.push(MapArea { range: bottom..top, flags: *b"U_WRV" });
+            let mut context = tg_kernel_context::LocalContext::user(entry);
+            *context.sp_mut() = top.base().val();
+            *context.a_mut(0) = arg;
+            let mut thread = Thread::new(current_proc.pid, satp, context, bottom.base().val()..top.base().val());
+            // 新线程沿用创建者的基础优先级（继承来的优先级不传递）
+            thread.priority = unsafe { (*processor).current().unwrap() }.priority;
+            let tid = thread.tid;
+            unsafe { (*processor).add(tid, thread, current_proc.pid); }
+            tid.get_usize() as _
+        }
+
+        /// set_tid_address：登记 `tidptr`，线程退出时内核向该地址写入 0，返回调用线程的 TID
+        fn set_tid_address(&self, tidptr: usize) -> isize {
+            let thread = PROCESSOR.get_mut().current().unwrap();
+            thread.clear_child_tid = tidptr;
+            thread.tid.get_usize() as isize
+        }
+
+        /// prctl 系统调用：只支持 PR_GET_TID_ADDRESS，把登记的 clear_child_tid 地址写入 `*arg`
+        ///
+        /// 没有登记过时写入 0；其他 option 返回 -22（EINVAL）。
+        fn prctl(&self, option: usize, arg: usize) -> isize {
+            const PR_GET_TID_ADDRESS: usize = 40;
+            if option != PR_GET_TID_ADDRESS {
+                return EINVAL;
+            }
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let clear_child_tid = unsafe { (*processor).current().unwrap() }.clear_child_tid;
+            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+            let Some(mut ptr) =
+                current_proc.address_space.translate_for_write::<usize>(VAddr::new(arg), WRITEABLE)
+            else {
+                return -1;
+            };
+            *unsafe { ptr.as_mut() } = clear_child_tid;
+            0
+        }
+
+        /// thread_self_info：把调用线程的 TID、所属进程、clear_child_tid 地址与栈区间写入 `buf`
+        ///
+        /// 栈区间与创建时一致：主线程为 exec 建立的栈，其余线程为 thread_create 分配的栈。
+        fn thread_self_info(&self, buf: usize) -> isize {
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let thread = unsafe { (*processor).current().unwrap() };
+            let info = ThreadSelfInfo {
+                tid: thread.tid.get_usize(),
+                tgid: thread.tgid.get_usize(),
+                clear_child_tid: thread.clear_child_tid,
+                stack_start: thread.stack.start,
+                stack_end: thread.stack.end,
+            };
+            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+            let Some(mut ptr) =
+                current_proc.address_space.translate_for_write::<ThreadSelfInfo>(VAddr::new(buf), WRITEABLE)
+            else {
+                return -1;
+            };
+            *unsafe { ptr.as_mut() } = info;
+            0
+        }
+
+        /// proc_read：以文本形式读取进程 `pid` 的自省信息，返回写入 `buf` 的字节数
+        ///
+        /// `key` 为以 `\0` 结尾的字符串：`status`（ppid/线程数）、`maps`（已映射区间）、
+        /// `fd`（打开的描述符）、`stat`（自身及已回收子进程的用户态运行时间）。输出超过 `len` 时截断。
+        fn proc_read(&self, pid: usize, key: usize, buf: usize, len: usize) -> isize {
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let current = unsafe { (*processor).get_current_proc().unwrap() };
+            let Some(key) = read_user_cstr(&current.address_space, key, PATH_MAX) else { return -1 };
+            let pid = ProcId::from_usize(pid);
+            let Some(target) = (unsafe { (*processor).get_proc(pid) }) else { return -1 };
+            let mut text = String::new();
+            match key.as_str() {
+                "status" => {
+                    let threads = unsafe { (*processor).get_thread(pid) }.map_or(0, |tids| tids.len());
+                    let _ = writeln!(text, "pid: {}", pid.get_usize());
+                    match target.parent {
+                        Some(parent) => { let _ = writeln!(text, "ppid: {}", parent.get_usize()); }
+                        None => { let _ = writeln!(text, "ppid: none"); }
+                    }
+                    let _ = writeln!(text, "threads: {threads}");
+                }
+                "maps" => {
+                    for area in &target.maps {
+                        let flags = core::str::from_utf8(&area.flags).unwrap_or("?????");
+                        let _ = writeln!(
+                            text, "{:#x}-{:#x} {flags}",
+                            area.range.start.base().val(), area.range.end.base().val(),
+                        );
+                    }
+                }
+                "fd" => {
+                    for (fd, slot) in target.fd_table.iter().enumerate() {
+                        if let Some(file) = slot {
+                            let _ = writeln!(text, "{fd} {}", file.lock().kind());
+                        }
+                    }
+                }
+                "stat" => {
+                    let _ = writeln!(text, "utime: {}", target.utime);
+                    let _ = writeln!(text, "cutime: {}", target.cutime);
+                }
+                _ => return -1,
+            }
+            let count = text.len().min(len);
+            if count == 0 { return 0; }
+            resolve_cow(&current.address_space, buf, count);
+            let Some(ptr) = current.address_space.translate::<u8>(VAddr::new(buf), WRITEABLE) else { return -1 };
+            unsafe { core::ptr::copy_nonoverlapping(text.as_ptr(), ptr.as_ptr(), count) };
+            count as isize
+        }
+
+        /// getrusage：查询 CPU 时间，`who` 为 `RUSAGE_SELF`（0）或 `RUSAGE_CHILDREN`（-1）
+        ///
+        /// 只填写 `ru_utime`，其余字段清零；内核态时间不单独统计。
+        fn getrusage(&self, who: isize, usage: usize) -> isize {
+            const RUSAGE_SELF: isize = 0;
+            const RUSAGE_CHILDREN: isize = -1;
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let ticks = match who {
+                RUSAGE_SELF => current.utime,
+                RUSAGE_CHILDREN => current.cutime,
+                _ => return -1,
+            };
+            let Some(mut ptr) = current.address_space.translate_for_write::<RUsage>(VAddr::new(usage), WRITEABLE) else {
+                return -1;
+            };
+            // time 寄存器频率为 12.5 MHz
+            let usec = ticks * 10 / 125;
+            *unsafe { ptr.as_mut() } = RUsage {
+                ru_utime: [usec / 1_000_000, usec % 1_000_000],
+                ru_stime: [0; 2],
+                rest: [0; 14],
+            };
+            0
+        }
+
+        /// prlimit64：读取/设置当前进程（`pid` 为 0 或自身）的资源限制，目前只支持 `RLIMIT_CPU`
+        ///
+        /// `new_limit`/`old_limit` 为 0 表示不设置/不读取；`RLIM_INFINITY`（`u64::MAX`）表示不限制，
+        /// 软限制大于硬限制时返回 -1。
+        fn prlimit64(&self, pid: usize, resource: usize, new_limit: usize, old_limit: usize) -> isize {
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            if pid != 0 && pid != current.pid.get_usize() {
+                return -1;
+            }
+            if resource != RLIMIT_CPU {
+                log::warn!("prlimit64: unsupported resource {resource}");
+                return -1;
+            }
+            let new = if new_limit == 0 {
+                None
+            } else {
+                let Some(ptr) = current.address_space.translate::<RLimit>(VAddr::new(new_limit), READABLE) else {
+                    return -1;
+                };
+                let limit = unsafe { ptr.as_ref() };
+                if limit.cur > limit.max {
+                    return -1;
+                }
+                Some([limit.cur as usize, limit.max as usize])
+            };
+            if old_limit != 0 {
+                let Some(mut ptr) =
+                    current.address_space.translate_for_write::<RLimit>(VAddr::new(old_limit), WRITEABLE)
+                else {
+                    return -1;
+                };
+                let [cur, max] = current.cpu_limit;
+                *unsafe { ptr.as_mut() } = RLimit { cur: cur as u64, max: max as u64 };
+            }
+            if let Some(limit) = new {
+                current.cpu_limit = limit;
+            }
+            0
+        }
+
+        /// tgkill：只向线程组 `tgid` 中的线程 `tid` 投递信号
+        ///
+        /// 信号记在目标线程上，由它下一次返回用户态前处理。线程不存在、不属于 `tgid`
+        /// 或信号编号无效时返回 -1。
+        fn tgkill(&self, tgid: usize, tid: usize, signum: usize) -> isize {
+            if signum > tg_signal::MAX_SIG { return -1; }
+            let Ok(signal_no) = SignalNo::try_from(signum as u8) else { return -1 };
+            if signal_no == SignalNo::ERR { return -1; }
+            let Some(thread) = PROCESSOR.get_mut().get_task(ThreadId::from_usize(tid)) else { return -1 };
+            if thread.tgid.get_usize() != tgid { return -1; }
+            thread.pending_signals |= 1 << signum;
+            0
+        }
+
+        /// alarm：`seconds` 秒后向当前进程投递 SIGALRM，0 表示取消
+        ///
+        /// 与 Linux 相同，alarm 与 `ITIMER_REAL` 共用一个定时器，会清除 setitimer 设置的周期。
+        /// 返回旧定时器剩余的秒数（不足一秒按一秒算），没有旧定时器时返回 0。
+        fn alarm(&self, seconds: usize) -> isize {
+            let pid = PROCESSOR.get_mut().get_current_proc().unwrap().pid;
+            let now = riscv::register::time::read64();
+            let deadline = (seconds != 0).then(|| now.saturating_add((seconds as u64).saturating_mul(TICKS_PER_SEC)));
+            match set_real_timer(pid, deadline, 0) {
+                Some((old, _)) => old.saturating_sub(now).div_ceil(TICKS_PER_SEC) as isize,
+                None => 0,
+            }
+        }
+
+        /// setitimer：设置 `ITIMER_REAL` 定时器，`it_value` 后投递 SIGALRM，之后每隔 `it_interval` 重装
+        ///
+        /// `it_value` 为 0 表示取消。`old` 非 0 时写入旧定时器的剩余时间与间隔。
+        /// 只支持 `ITIMER_REAL`，微秒数不在 `[0, 1000000)` 内时返回 -1。
+        fn setitimer(&self, which: usize, new: usize, old: usize) -> isize {
+            if which != ITIMER_REAL {
+                return -1;
+            }
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(ptr) = current.address_space.translate::<ITimerVal>(VAddr::new(new), READABLE) else {
+                return -1;
+            };
+            let new = unsafe { ptr.as_ref() };
+            let (Some(value), Some(interval)) = (timeval_to_ticks(new.it_value), timeval_to_ticks(new.it_interval))
+            else {
+                return -1;
+            };
+            let old_timer = if old != 0 {
+                let Some(mut ptr) =
+                    current.address_space.translate_for_write::<ITimerVal>(VAddr::new(old), WRITEABLE)
+                else {
+                    return -1;
+                };
+                Some(unsafe { ptr.as_mut() })
+            } else {
+                None
+            };
+            let now = riscv::register::time::read64();
+            let deadline = (value != 0).then(|| now.saturating_add(value));
+            let previous = set_real_timer(current.pid, deadline, interval);
+            if let Some(old_timer) = old_timer {
+                *old_timer = itimerval(previous, now);
+            }
+            0
+        }
+
+        /// getitimer：读取 `ITIMER_REAL` 定时器的剩余时间与间隔，没有定时器时全为 0
+        fn getitimer(&self, which: usize, curr: usize) -> isize {
+            if which != ITIMER_REAL {
+                return -1;
+            }
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(mut ptr) =
+                current.address_space.translate_for_write::<ITimerVal>(VAddr::new(curr), WRITEABLE)
+            else {
+                return -1;
+            };
+            let now = riscv::register::time::read64();
+            *unsafe { ptr.as_mut() } = itimerval(get_real_timer(current.pid), now);
+            0
+        }
+
+        /// memfd_create：创建匿名内存文件（flags 暂被忽略）
+        fn memfd_create(&self, name: usize, _flags: usize) -> isize {
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(string) = read_user_cstr(&current.address_space, name, PATH_MAX) else { return -1 };
+            let new_fd = current.fd_table.len();
+            log::debug!("memfd_create: {string} -> fd {new_fd}");
+            current.fd_table.push(Some(Arc::new(Mutex::new(Fd::Mem(MemFile::new())))));
+            new_fd as isize
+        }
+
+        /// lseek 系统调用：按 `whence` 移动 `fd` 的读写偏移，返回新的绝对偏移
+        ///
+        /// `whence` 为 SEEK_SET(0)/SEEK_CUR(1)/SEEK_END(2)，SEEK_END 以文件当前大小为基准。
+        /// 管道、标准输入输出等不能定位的 fd、非法的 `whence` 与结果为负的偏移都返回 -1。
+        fn lseek(&self, fd: usize, offset: isize, whence: usize) -> isize {
+            let from = match whence {
+                0 => SeekFrom::Start,
+                1 => SeekFrom::Current,
+                2 => SeekFrom::End,
+                _ => return -1,
+            };
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            file.lock().seek(offset, from).map_or(-1, |pos| pos as isize)
+        }
+
+        /// ftruncate 系统调用：把 `fd` 对应的文件大小设为 `length`，成功返回 0
+        ///
+        /// 支持普通文件与 memfd，fd 必须可写；memfd 扩大时分配不到内存返回 -12（ENOMEM），
+        /// 管道、设备等其他 fd 返回 -22（EINVAL）。
+        fn ftruncate(&self, fd: usize, length: isize) -> isize {
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let Ok(length) = usize::try_from(length) else {
+                return EINVAL;
+            };
+            let file = file.lock();
+            if !file.writable() {
+                return -1;
+            }
+            match &*file {
+                Fd::File(f) => match &f.inode {
+                    Some(inode) if inode.truncate(length) => 0,
+                    _ => -1,
+                },
+                Fd::Mem(m) => if m.truncate(length) { 0 } else { ENOMEM },
+                _ => EINVAL,
+            }
+        }
+
+        /// dup 系统调用：复制 `fd`，返回编号最小的空闲 fd
+        ///
+        /// 新 fd 与 `fd` 共享同一个句柄：控制台的副本仍读写控制台，管道副本共享同一个缓冲区，
+        /// 普通文件的偏移也是共享的，通过任一 fd 读写或 lseek 都会移动另一个 fd 的偏移。
+        fn dup(&self, fd: usize) -> isize {
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.clone();
+            current.install_fd(file) as isize
+        }
+
+        /// dup2 系统调用：把 `oldfd` 复制到 `newfd`，`newfd` 已打开时先将其关闭
+        ///
+        /// `oldfd == newfd` 时不做任何事，`oldfd` 有效则返回 `newfd`。
+        fn dup2(&self, oldfd: usize, newfd: usize) -> isize {
+            /// `newfd` 的上限，防止用户用一个很大的 fd 把 fd_table 撑大
+            const NOFILE: usize = 1024;
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(Some(file)) = current.fd_table.get(oldfd) else {
+                return -1;
+            };
+            if oldfd == newfd {
+                return newfd as isize;
+            }
+            if newfd >= NOFILE {
+                return -1;
+            }
+            let file = file.clone();
+            if newfd >= current.fd_table.len() {
+                current.fd_table.resize_with(newfd + 1, || None);
+            }
+            current.fd_table[newfd] = Some(file);
+            newfd as isize
+        }
+
+        /// fcntl 系统调用：只支持 F_GETFL / F_SETFL，用于查询与切换 `O_NONBLOCK`
+        ///
+        /// F_GETFL 返回访问模式（O_RDONLY/O_WRONLY/O_RDWR）与 O_NONBLOCK；F_SETFL 只看 O_NONBLOCK，
+        /// 目前只有管道支持非阻塞，对其他描述符置位返回 -22（EINVAL）。
+        fn fcntl(&self, fd: usize, cmd: usize, arg: usize) -> isize {
+            const F_GETFL: usize = 3;
+            const F_SETFL: usize = 4;
+            const O_NONBLOCK: usize = 0o4000;
+            let current = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let Some(Some(file)) = current.fd_table.get(fd) else {
+                return -1;
+            };
+            let file = file.lock();
+            match cmd {
+                F_GETFL => {
+                    let mode = match (file.readable(), file.writable()) {
+                        (true, true) => 2,
+                        (false, true) => 1,
+                        _ => 0,
+                    };
+                    let nonblock = if file.is_nonblocking() { O_NONBLOCK } else { 0 };
+                    (mode | nonblock) as isize
+                }
+                F_SETFL => {
+                    let nonblocking = arg & O_NONBLOCK != 0;
+                    if !file.set_nonblocking(nonblocking) && nonblocking {
+                        return EINVAL;
+                    }
+                    0
+                }
+                _ => EINVAL,
+            }
+        }
+
+        /// mutex_destroy：释放互斥锁槽位供 mutex_create 复用（仍有等待者时返回 -1）
+        fn mutex_destroy(&self, mutex_id: usize) -> isize {
+            let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
+            match current_proc.mutex_list.get_mut(mutex_id) {
+                Some(slot) if slot.as_ref().is_some_and(|it| !it.has_waiters()) => {
+                    *slot = None;
+                    current_proc.deadlock.remove_resource(Resource::Mutex(mutex_id));
+                    0
+                }
+                _ => -1,
+            }
+        }
+
+        /// semaphore_destroy：释放信号量槽位（仍有等待者时返回 -1）
+        fn semaphore_destroy(&self, sem_id: usize) -> isize {
+            let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
+            match current_proc.semaphore_list.get_mut(sem_id) {
+                Some(slot) if slot.as_ref().is_some_and(|it| !it.has_waiters()) => {
+                    *slot = None;
+                    current_proc.deadlock.remove_resource(Resource::Semaphore(sem_id));
+                    0
+                }
+                _ => -1,
+            }
+        }
+
+        /// condvar_destroy：释放条件变量槽位（仍有等待者时返回 -1）
+        fn condvar_destroy(&self, condvar_id: usize) -> isize {
+            let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
+            match current_proc.condvar_list.get_mut(condvar_id) {
+                Some(slot) if slot.as_ref().is_some_and(|it| !it.has_waiters()) => { *slot = None; 0 }
+                _ => -1,
+            }
+        }
+
+        /// semaphore_down_timeout：与 semaphore_down 相同，但最多阻塞 `timeout_ns` 纳秒，超时返回 -2
+        fn semaphore_down_timeout(&self, sem_id: usize, timeout_ns: usize) -> isize {
+            let ret = self.semaphore_down(Caller { entity: 0, flow: 0 }, sem_id);
+            if ret == BLOCKED {
+                let tid = PROCESSOR.get_mut().current().unwrap().tid;
+                add_wait_deadline(tid, deadline_after(timeout_ns), TimedWait::Semaphore(sem_id));
+            }
+            ret
+        }
+
+        /// condvar_wait_timeout：与 condvar_wait 相同，但最多等待 `timeout_ns` 纳秒
+        ///
+        /// 超时返回 -2，返回前同样会重新持有互斥锁。
+        fn condvar_wait_timeout(&self, condvar_id: usize, mutex_id: usize, timeout_ns: usize) -> isize {
+            let ret = self.condvar_wait(Caller { entity: 0, flow: 0 }, condvar_id, mutex_id);
+            if ret == BLOCKED {
+                let tid = PROCESSOR.get_mut().current().unwrap().tid;
+                add_wait_deadline(tid, deadline_after(timeout_ns), TimedWait::Condvar(condvar_id, mutex_id));
+            }
+            ret
+        }
+
+        /// rwlock_create：创建读写锁，`writer_preferred` 为真时有写者排队会挡住新读者
+        fn rwlock_create(&self, writer_preferred: bool) -> isize {
+            let current_proc = PROCESSOR.get_mut().get_current_proc().unwrap();
+            let rwlock = Some(Arc::new(RwLock::new(writer_preferred)));
+            let id = if let Some(id) = current_proc.rwlock_list.iter().position(|item| item.is_none()) {
+                current_proc.rwlock_list[id] = rwlock;
+                id
+            } else {
+                current_proc.rwlock_list.push(rwlock);
+                current_proc.rwlock_list.len() - 1
+            };
+            id as isize
+        }
+
+        /// 获取读锁（`write` 为假）或写锁，需要等待时返回 `BLOCKED`
+        fn rwlock_lock(&self, rwlock_id: usize, write: bool) -> isize {
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let tid = unsafe { (*processor).current().unwrap() }.tid;
+            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+            let Some(Some(rwlock)) = current_proc.rwlock_list.get(rwlock_id) else { return -1 };
+            let acquired = if write { rwlock.write_lock(tid) } else { rwlock.read_lock(tid) };
+            if acquired { 0 } else { BLOCKED }
+        }
+
+        /// 释放读锁（`write` 为假）或写锁，把锁转交给的线程重新入队
+        fn rwlock_unlock(&self, rwlock_id: usize, write: bool) -> isize {
+            let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
+            let current_proc = unsafe { (*processor).get_current_proc().unwrap() };
+            let Some(Some(rwlock)) = current_proc.rwlock_list.get(rwlock_id) else { return -1 };
+            let woken = if write { rwlock.write_unlock() } else { rwlock.read_unlock() };
+            for tid in woken {
+                unsafe { (*processor).re_enque(tid) };
+            }
+            0
         }
     }
 }
@@ -9,6 +9,14 @@
 //!
 //! 同一进程的所有线程共享 `Process` 中的资源。
 //!
+//! ## 线程组
+//!
+//! 一个进程的全部线程构成一个线程组：线程组号 tgid 就是进程的 pid，
+//! 每个线程另有独立的 tid，由 `ProcessorInner::get_thread(pid)` 列出，第一个是主线程（组长）。
+//! `getpid` 返回 tgid，`gettid` 返回 tid；`kill` 把信号投递给整个线程组，
+//! 由最先返回用户态的线程处理，`tgkill` 只投递给指定线程。
+//! 线程被致命信号杀死时整个线程组随之退出（见 `Process::group_exit`）。
+//!
 //! ## 新增字段
 //!
 //! | 字段 | 说明 |
@@ -16,6 +24,7 @@
 //! | `semaphore_list` | 信号量列表（进程内所有线程共享） |
 //! | `mutex_list` | 互斥锁列表 |
 //! | `condvar_list` | 条件变量列表 |
+//! | `rwlock_list` | 读写锁列表 |
 //!
 //! 教程阅读建议：
 //!
@@ -24,20 +33,29 @@
 //! - 最后结合 `processor.rs` 看线程生命周期与进程资源回收的关系。
 
 use crate::{
-    build_flags, fs::Fd, map_portal, parse_flags, processor::ProcessorInner, Sv39, Sv39Manager,
-    PROCESSOR,
+    build_flags,
+    fs::{Fd, MemFile},
+    map_portal, parse_flags,
+    processor::{alloc_pid, alloc_tid, release_pid, ProcessorInner},
+    sync::{Condvar, DeadlockDetector, Mutex as MutexTrait, RwLock, Semaphore},
+    Sv39, Sv39Manager, PROCESSOR,
 };
-use alloc::{alloc::alloc_zeroed, boxed::Box, sync::Arc, vec::Vec};
-use core::alloc::Layout;
+use alloc::{
+    alloc::alloc,
+    boxed::Box,
+    collections::BTreeMap,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{alloc::Layout, ops::Range, ptr::NonNull};
 use spin::Mutex;
 use tg_kernel_context::{foreign::ForeignContext, LocalContext};
 use tg_kernel_vm::{
-    page_table::{MmuMeta, VAddr, PPN, VPN},
+    page_table::{MmuMeta, VAddr, VmFlags, PPN, VPN},
     AddressSpace,
 };
-use tg_signal::Signal;
+use tg_signal::{Signal, SignalNo, SignalResult};
 use tg_signal_impl::SignalImpl;
-use tg_sync::{Condvar, Mutex as MutexTrait, Semaphore};
 use tg_task_manage::{ProcId, ThreadId};
 use xmas_elf::{
     header::{self, HeaderPt2, Machine},
@@ -51,18 +69,70 @@ use xmas_elf::{
 pub struct Thread {
     /// 线程 ID（不可变）
     pub tid: ThreadId,
+    /// 所属线程组（即进程 pid，不可变）
+    pub tgid: ProcId,
+    /// 由 tgkill 指定给本线程、尚未处理的信号（按信号编号置位）
+    pub pending_signals: usize,
     /// 执行上下文（包含 LocalContext + satp）
     pub context: ForeignContext,
+    /// 基础优先级（set_priority 设置，值越大越先被调度）
+    pub priority: usize,
+    /// 优先级继承：持有的互斥锁上等待者的最高有效优先级，没有等待者时为 0
+    pub inherited: usize,
+    /// 用户栈区间 `[start, end)`（虚拟地址），栈从 `end` 向下增长
+    pub stack: Range<usize>,
+    /// set_tid_address 登记的地址，线程退出时向这里写入 0；0 表示未登记
+    pub clear_child_tid: usize,
 }
 
+/// 线程的默认优先级
+pub const DEFAULT_PRIORITY: usize = 16;
+
 impl Thread {
-    /// 创建新线程
-    pub fn new(satp: usize, context: LocalContext) -> Self {
+    /// 在线程组 `tgid` 中创建新线程，`stack` 为它的用户栈区间
+    pub fn new(tgid: ProcId, satp: usize, context: LocalContext, stack: Range<usize>) -> Self {
         Self {
-            tid: ThreadId::new(),
+            tid: alloc_tid(tgid),
+            tgid,
+            pending_signals: 0,
             context: ForeignContext { context, satp },
+            priority: DEFAULT_PRIORITY,
+            inherited: 0,
+            stack,
+            clear_child_tid: 0,
         }
     }
+
+    /// 调度使用的有效优先级：基础优先级与继承来的优先级中较高的一个
+    #[inline]
+    pub fn effective_priority(&self) -> usize {
+        self.priority.max(self.inherited)
+    }
+}
+
+/// 用户地址空间中的一段映射（供自省接口列出 maps）
+#[derive(Clone)]
+pub struct MapArea {
+    /// 虚页范围
+    pub range: Range<VPN<Sv39>>,
+    /// 权限字符串，格式同 `build_flags`（如 `U_WRV`）
+    pub flags: [u8; 5],
+}
+
+/// memfd 的一段共享映射
+///
+/// 映射建立时把 memfd 的内容复制到新分配的页，之后用户对这些页的修改由硬件记在 PTE 的 D 位上，
+/// munmap、exec 或进程退出时只把 D 位为 1 的页写回 memfd（见 [`Process::sync_mem_maps`]）。
+#[derive(Clone)]
+pub struct MemMapping {
+    /// 起始虚拟地址（页对齐）
+    pub start: usize,
+    /// 映射长度（页对齐）
+    pub len: usize,
+    /// 被映射的 memfd（与 fd 共享同一份数据）
+    pub file: MemFile,
+    /// `start` 对应的 memfd 偏移（页对齐）
+    pub offset: usize,
 }
 
 /// 进程（资源容器）
@@ -75,70 +145,347 @@ pub struct Process {
     /// 地址空间（所有线程共享）
     pub address_space: AddressSpace<Sv39, Sv39Manager>,
     /// 文件描述符表（所有线程共享）
-    pub fd_table: Vec<Option<Mutex<Fd>>>,
+    pub fd_table: Vec<Option<Arc<Mutex<Fd>>>>,
+    /// 父进程 ID（initproc 为 `None`；父进程先退出时不随托孤更新）
+    pub parent: Option<ProcId>,
+    /// 已映射的用户区间（ELF 段、各线程栈与 mmap 区间）
+    pub maps: Vec<MapArea>,
+    /// mmap 建立的区间（虚拟地址，页对齐），munmap 只能取消这些区间内的映射
+    pub mmap_areas: Vec<Range<usize>>,
+    /// memfd 的共享映射，修改过的页在 munmap、exec 或进程退出时写回
+    pub mem_maps: Vec<MemMapping>,
+    /// 所有线程在用户态累计运行的时间（`time` 寄存器计数）
+    pub utime: usize,
+    /// 已被 wait 回收的子进程累计的 CPU 时间
+    pub cutime: usize,
+    /// RLIMIT_CPU `[软限制, 硬限制]`（秒），`usize::MAX` 表示不限制（fork/exec 后保留）
+    pub cpu_limit: [usize; 2],
+    /// 最近一次因超出 CPU 软限制投递 SIGXCPU 时的 CPU 秒数
+    xcpu_sent: usize,
     /// 信号处理器
     pub signal: Box<dyn Signal>,
+    /// 正在执行用户态信号处理函数时，进入前的信号掩码
+    pub saved_sigmask: Option<usize>,
     /// 信号量列表（**本章新增**，所有线程共享）
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     /// 互斥锁列表（**本章新增**，所有线程共享）
     pub mutex_list: Vec<Option<Arc<dyn MutexTrait>>>,
     /// 条件变量列表（**本章新增**，所有线程共享）
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// 读写锁列表（所有线程共享）
+    pub rwlock_list: Vec<Option<Arc<RwLock>>>,
+    /// 互斥锁与信号量的分配状态，`enable_deadlock_detect` 启用后据此拒绝不安全的请求
+    pub deadlock: DeadlockDetector,
+    /// 线程组退出码：某个线程被致命信号杀死后设置，其余线程下次被调度时以同样的退出码退出
+    ///
+    /// 阻塞在同步原语或 waittid 上的线程由 `exit_thread_group` 移出等待队列，被调度时退出。
+    pub group_exit: Option<isize>,
+}
+
+/// 页表项标志位：有效
+const PTE_V: usize = 1 << 0;
+/// 页表项标志位：R/W/X 任一置位即为叶子页表项
+const PTE_RWX: usize = 0b111 << 1;
+/// 页表项标志位：可写
+const PTE_W: usize = 1 << 2;
+/// 页表项标志位：用户可访问
+const PTE_U: usize = 1 << 4;
+/// 页表项标志位：已被访问（由硬件置位）
+const PTE_A: usize = 1 << 6;
+/// 页表项标志位：已被写过（由硬件置位）
+const PTE_D: usize = 1 << 7;
+/// 页表项标志位：写时复制（RSW 的第二位，第一位是 `Sv39Manager::OWNED_BIT`）
+///
+/// fork 后父子共享的可写页清除 W 位并置上该位，对它的写入由 [`resolve_cow`] 处理。
+const PTE_COW: usize = 1 << 9;
+/// 复制页表项时保留的标志位：V R W X U
+const PTE_PERM: usize = 0x1f;
+
+/// fork 时父子共享的物理页（PPN）-> 映射它的地址空间个数
+///
+/// 地址空间释放时逐页减少计数（见 [`unshare_page`]），降到 0 时才真正释放该页；
+/// 写时复制页被复制走一份时计数也减一。
+static SHARED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// 释放地址空间时归还一个页的共享引用
+///
+/// 该页不是共享页时返回 `None`，由调用者按 OWNED 标志处理；
+/// 否则返回 `Some(是否为最后一个引用)`，最后一个引用者负责释放该页。
+pub fn unshare_page(ppn: usize) -> Option<bool> {
+    let mut shared = SHARED_PAGES.lock();
+    let count = shared.get_mut(&ppn)?;
+    *count -= 1;
+    if *count == 0 {
+        shared.remove(&ppn);
+        Some(true)
+    } else {
+        Some(false)
+    }
+}
+
+/// 页表项中的物理页号
+fn pte_ppn(pte: usize) -> usize {
+    (pte >> 10) & ((1 << 44) - 1)
+}
+
+/// fork：让 `dst` 与 `src` 共享全部用户页，不复制任何物理页
+///
+/// 只沿有效（V 位置位）的页表项向下递归，整段未映射的区域不会被访问；
+/// `dst` 只为共享的页建立所需的下级页表，稀疏地址空间的子进程页表页数与父进程相当。
+/// 可写页在 `src` 和 `dst` 中都改为写时复制；没有 U 位的页（异界传送门）不共享，由调用者重新映射。
+/// 修改了 `src` 的页表项，调用者需要刷新 TLB。
+fn share_user_pages(src: &AddressSpace<Sv39, Sv39Manager>, dst: &mut AddressSpace<Sv39, Sv39Manager>) {
+    fn walk(
+        table: usize,
+        level: usize,
+        vpn: usize,
+        dst: &mut AddressSpace<Sv39, Sv39Manager>,
+        shared: &mut BTreeMap<usize, usize>,
+    ) {
+        for index in 0..512 {
+            let pte = unsafe { &mut *(table as *mut usize).add(index) };
+            if *pte & PTE_V == 0 {
+                continue;
+            }
+            let vpn = (vpn << 9) | index;
+            let ppn = pte_ppn(*pte);
+            if *pte & PTE_RWX == 0 {
+                if level > 0 {
+                    walk(ppn << Sv39::PAGE_BITS, level - 1, vpn, dst, shared);
+                }
+            } else if level == 0 && *pte & PTE_U != 0 {
+                if *pte & (PTE_W | PTE_COW) != 0 {
+                    *pte = (*pte & !PTE_W) | PTE_COW;
+                }
+                let flags = unsafe { VmFlags::from_raw(*pte & (PTE_PERM | PTE_COW)) };
+                // 以外部页映射，子进程的地址空间不会把它当作自己分配的页
+                dst.map_extern(VPN::new(vpn)..VPN::new(vpn + 1), PPN::new(ppn), flags);
+                *shared.entry(ppn).or_insert(1) += 1;
+            }
+        }
+    }
+    let mut shared = SHARED_PAGES.lock();
+    walk(src.root_ppn().val() << Sv39::PAGE_BITS, 2, 0, dst, &mut shared);
+}
+
+/// 找到虚拟页号 `vpn` 对应的 4 KiB 用户页的页表项
+fn user_leaf_pte(space: &AddressSpace<Sv39, Sv39Manager>, vpn: usize) -> Option<*mut usize> {
+    let mut table = space.root_ppn().val() << Sv39::PAGE_BITS;
+    for level in (0..3).rev() {
+        let pte = unsafe { (table as *mut usize).add((vpn >> (9 * level)) & 0x1ff) };
+        let value = unsafe { *pte };
+        if value & PTE_V == 0 {
+            return None;
+        }
+        if value & PTE_RWX != 0 {
+            return (level == 0 && value & PTE_U != 0).then_some(pte);
+        }
+        table = pte_ppn(value) << Sv39::PAGE_BITS;
+    }
+    None
+}
+
+/// 把 `[addr, addr + len)` 中的写时复制页换成本地址空间私有的可写页
+///
+/// 用户写写时复制页引发 StorePageFault 时由 trap 处理调用；内核代用户写内存之前也要先调用它
+/// （见 [`WriteTranslate`]），否则翻译会因缺少 W 位失败。仍有其他地址空间映射的页复制一份，
+/// 只剩自己映射的页直接收回所有权，不必复制。
+/// 返回是否有页被转为可写；分配不到物理页时停在该页，它仍然不可写。
+/// `len` 往往直接来自系统调用参数，遇到第一个未映射的页就停止，遍历不会越过从 `addr` 起连续映射的部分。
+pub fn resolve_cow(space: &AddressSpace<Sv39, Sv39Manager>, addr: usize, len: usize) -> bool {
+    const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+    let first = addr >> Sv39::PAGE_BITS;
+    let last = addr.saturating_add(len.max(1) - 1) >> Sv39::PAGE_BITS;
+    let mut resolved = false;
+    for vpn in first..=last {
+        let Some(pte) = user_leaf_pte(space, vpn) else {
+            break;
+        };
+        let pte = unsafe { &mut *pte };
+        if *pte & PTE_COW == 0 {
+            continue;
+        }
+        let ppn = pte_ppn(*pte);
+        let mut shared = SHARED_PAGES.lock();
+        let new_ppn = match shared.get_mut(&ppn) {
+            Some(count) if *count > 1 => {
+                let page = unsafe { alloc(Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE)) };
+                if page.is_null() {
+                    break;
+                }
+                unsafe { core::ptr::copy_nonoverlapping((ppn << Sv39::PAGE_BITS) as *const u8, page, PAGE_SIZE) };
+                *count -= 1;
+                page as usize >> Sv39::PAGE_BITS
+            }
+            _ => {
+                shared.remove(&ppn);
+                ppn
+            }
+        };
+        // 新页归本地址空间所有；马上就要被写入，直接置上 A、D 位
+        *pte = (new_ppn << 10) | (*pte & PTE_PERM) | PTE_W | Sv39Manager::OWNED_BIT | PTE_A | PTE_D;
+        resolved = true;
+    }
+    if resolved {
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+    resolved
+}
+
+/// 内核代用户写内存时使用的地址翻译
+pub trait WriteTranslate {
+    /// 与 `translate` 相同，但先把 `T` 占用的写时复制页换成私有页（见 [`resolve_cow`]）
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>>;
+}
+
+impl WriteTranslate for AddressSpace<Sv39, Sv39Manager> {
+    fn translate_for_write<T>(&self, addr: VAddr<Sv39>, flags: VmFlags<Sv39>) -> Option<NonNull<T>> {
+        resolve_cow(self, addr.val(), core::mem::size_of::<T>());
+        self.translate(addr, flags)
+    }
 }
 
 impl Process {
     /// exec：替换当前进程的地址空间和主线程上下文
     ///
     /// 注意：只支持单线程进程执行 exec
-    pub fn exec(&mut self, elf: ElfFile) {
-        let (proc, thread) = Process::from_elf(elf).unwrap();
+    /// ELF 非法时返回 `false` 且不修改当前进程
+    pub fn exec(&mut self, elf: ElfFile) -> bool {
+        let Some((proc, thread)) = Process::from_elf(elf) else { return false };
+        // 换掉地址空间之前把共享映射的修改写回 memfd
+        self.sync_mem_maps(0, usize::MAX);
+        self.mmap_areas.clear();
+        self.mem_maps.clear();
         self.address_space = proc.address_space;
+        self.maps = proc.maps;
         let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
         unsafe {
             let pthreads = (*processor).get_thread(self.pid).unwrap();
-            (*processor).get_task(pthreads[0]).unwrap().context = thread.context;
+            let main_thread = (*processor).get_task(pthreads[0]).unwrap();
+            main_thread.context = thread.context;
+            main_thread.stack = thread.stack;
+            // 登记的地址属于旧的地址空间
+            main_thread.clear_child_tid = 0;
         }
+        // 新程序沿用当前进程的 PID 和主线程，`from_elf` 为它分配的编号用不上
+        release_pid(proc.pid);
+        true
     }
 
     /// fork：创建子进程（复制地址空间和主线程上下文）
     ///
-    /// 子进程继承父进程的地址空间（深拷贝）、文件描述符和信号配置。
+    /// 子进程继承父进程的地址空间（写时复制共享，见 [`resolve_cow`]）、文件描述符和信号配置。
     /// 同步原语列表不继承（子进程创建空的列表）。
+    /// 父进程的其他线程之后写共享页时同样会触发写时复制，它们共用父进程的页表。
     pub fn fork(&mut self) -> Option<(Self, Thread)> {
-        let pid = ProcId::new();
-        // 深拷贝地址空间
-        let parent_addr_space = &self.address_space;
+        let pid = alloc_pid();
+        // 共享地址空间，可写页改为写时复制
         let mut address_space: AddressSpace<Sv39, Sv39Manager> = AddressSpace::new();
-        parent_addr_space.cloneself(&mut address_space);
+        share_user_pages(&self.address_space, &mut address_space);
+        // 父进程的可写页刚被改为只读，清掉 TLB 中仍带 W 位的旧表项
+        unsafe { riscv::asm::sfence_vma_all() };
         map_portal(&address_space);
         // 复制主线程上下文
         let processor: *mut ProcessorInner = PROCESSOR.get_mut() as *mut ProcessorInner;
         let pthreads = unsafe { (*processor).get_thread(self.pid).unwrap() };
-        let context = unsafe {
-            (*processor).get_task(pthreads[0]).unwrap().context.context.clone()
-        };
+        let main_thread = unsafe { (*processor).get_task(pthreads[0]).unwrap() };
+        let context = main_thread.context.context.clone();
         let satp = (8 << 60) | address_space.root_ppn().val();
-        let thread = Thread::new(satp, context);
+        // 与 Linux 的 fork 一样，子进程不继承 set_tid_address 登记的地址
+        let thread = Thread::new(pid, satp, context, main_thread.stack.clone());
         // 复制文件描述符表
-        let new_fd_table: Vec<Option<Mutex<Fd>>> = self.fd_table
+        let new_fd_table: Vec<Option<Arc<Mutex<Fd>>>> = self.fd_table
             .iter()
-            .map(|fd| fd.as_ref().map(|f| Mutex::new(f.lock().clone())))
+            .map(|fd| fd.as_ref().map(|f| Arc::new(Mutex::new(f.lock().clone()))))
             .collect();
         Some((
             Self {
                 pid,
                 address_space,
                 fd_table: new_fd_table,
+                parent: Some(self.pid),
+                maps: self.maps.clone(),
+                mmap_areas: self.mmap_areas.clone(),
+                mem_maps: self.mem_maps.clone(),
+                utime: 0,
+                cutime: 0,
+                cpu_limit: self.cpu_limit,
+                xcpu_sent: 0,
                 signal: self.signal.from_fork(),
+                saved_sigmask: None,
                 // 子进程的同步原语列表初始为空
                 semaphore_list: Vec::new(),
                 mutex_list: Vec::new(),
                 condvar_list: Vec::new(),
+                rwlock_list: Vec::new(),
+                deadlock: DeadlockDetector::default(),
+                group_exit: None,
             },
             thread,
         ))
     }
 
+    /// 累加 CPU 时间后检查 RLIMIT_CPU
+    ///
+    /// 与 Linux 相同：超过软限制后每多用一秒投递一次 SIGXCPU，达到硬限制时投递 SIGKILL。
+    /// 信号在下一次返回用户态前处理（系统调用或时钟中断之后）。
+    pub fn check_cpu_limit(&mut self) {
+        // time 寄存器频率为 12.5 MHz
+        let seconds = self.utime / 12_500_000;
+        let [soft, hard] = self.cpu_limit;
+        if seconds >= hard {
+            self.signal.add_signal(SignalNo::SIGKILL);
+        } else if seconds >= soft && seconds > self.xcpu_sent {
+            self.xcpu_sent = seconds;
+            self.signal.add_signal(SignalNo::SIGXCPU);
+        }
+    }
+
+    /// 以线程上下文 `ctx` 处理待处理信号，真正转入用户处理函数时调用 `enter_signal_handler`
+    ///
+    /// tg-signal 转入用户处理函数和 SIGCONT 解除暂停都返回 `Handled`。只有前者把 pc 改为处理函数、
+    /// 把信号编号写入 a0，并留在“正在处理信号”的状态，据此区分，解除暂停时不改动掩码。
+    pub fn deliver_signals(&mut self, ctx: &mut LocalContext) -> SignalResult {
+        let result = self.signal.handle_signals(ctx);
+        if matches!(result, SignalResult::Handled) && self.signal.is_handling_signal() {
+            self.enter_signal_handler(ctx.a(0));
+        }
+        result
+    }
+
+    /// 进入用户态信号处理函数：处理期间屏蔽 `旧掩码 | sa_mask | {signum}`
+    ///
+    /// 旧掩码保存在 `saved_sigmask` 中，由 sigreturn 调用 `leave_signal_handler` 恢复。
+    fn enter_signal_handler(&mut self, signum: usize) {
+        let action = u8::try_from(signum)
+            .ok()
+            .and_then(|signum| SignalNo::try_from(signum).ok())
+            .and_then(|signal_no| self.signal.get_action_ref(signal_no));
+        let Some(action) = action else { return };
+        let old_mask = self.signal.update_mask(0);
+        self.signal.update_mask(old_mask | action.mask | (1 << signum));
+        self.saved_sigmask = Some(old_mask);
+    }
+
+    /// 把 tgkill 指定给 `thread` 的信号转入进程的待处理集合，随后由该线程的 `handle_signals` 处理
+    ///
+    /// 被屏蔽的信号留在进程的待处理集合中，解除屏蔽后可能由组内其他线程处理。
+    pub fn take_thread_signals(&mut self, thread: &mut Thread) {
+        let pending = core::mem::take(&mut thread.pending_signals);
+        for signum in (0..usize::BITS as u8).filter(|signum| pending & (1 << signum) != 0) {
+            if let Ok(signal_no) = SignalNo::try_from(signum) {
+                self.signal.add_signal(signal_no);
+            }
+        }
+    }
+
+    /// 从信号处理函数返回：恢复进入处理函数前的掩码
+    pub fn leave_signal_handler(&mut self) {
+        if let Some(mask) = self.saved_sigmask.take() {
+            self.signal.update_mask(mask);
+        }
+    }
+
     /// 从 ELF 文件创建进程和主线程
     ///
     /// 解析 ELF 段，建立地址空间，分配用户栈，创建初始上下文。
@@ -150,11 +497,15 @@ impl Process {
             { pt2.entry_point as usize }
             _ => None?,
         };
+        if !check_load_segments(&elf) {
+            return None;
+        }
 
         const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
         const PAGE_MASK: usize = PAGE_SIZE - 1;
 
         let mut address_space = AddressSpace::new();
+        let mut maps = Vec::new();
         for program in elf.program_iter() {
             if !matches!(program.get_type(), Ok(program::Type::Load)) { continue; }
             let off_file = program.offset() as usize;
@@ -166,48 +517,206 @@ impl Process {
             if program.flags().is_execute() { flags[1] = b'X'; }
             if program.flags().is_write() { flags[2] = b'W'; }
             if program.flags().is_read() { flags[3] = b'R'; }
+            let range = VAddr::new(off_mem).floor()..VAddr::new(end_mem).ceil();
             address_space.map(
-                VAddr::new(off_mem).floor()..VAddr::new(end_mem).ceil(),
+                range.clone(),
                 &elf.input[off_file..][..len_file],
                 off_mem & PAGE_MASK,
                 parse_flags(unsafe { core::str::from_utf8_unchecked(&flags) }).unwrap(),
             );
+            maps.push(MapArea { range, flags });
         }
-        // 分配 2 页用户栈
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
-            VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
-            build_flags("U_WRV"),
-        );
+        // 分配 2 页用户栈，物理页由 map 分配并标记 OWNED，随地址空间一起回收
+        let stack_range = VPN::<Sv39>::new((1 << 26) - 2)..VPN::new(1 << 26);
+        let stack_vaddr = stack_range.start.base().val()..stack_range.end.base().val();
+        address_space.map(stack_range.clone(), &[], 0, build_flags("U_WRV"));
+        maps.push(MapArea { range: stack_range, flags: *b"U_WRV" });
         map_portal(&address_space);
         let satp = (8 << 60) | address_space.root_ppn().val();
         let mut context = LocalContext::user(entry);
         *context.sp_mut() = 1 << 38;
-        let thread = Thread::new(satp, context);
+        let pid = alloc_pid();
+        let thread = Thread::new(pid, satp, context, stack_vaddr);
 
         Some((
             Self {
-                pid: ProcId::new(),
+                pid,
                 address_space,
                 fd_table: vec![
                     // stdin
-                    Some(Mutex::new(Fd::Empty { read: true, write: false })),
+                    Some(Arc::new(Mutex::new(Fd::Empty { read: true, write: false }))),
                     // stdout
-                    Some(Mutex::new(Fd::Empty { read: false, write: true })),
+                    Some(Arc::new(Mutex::new(Fd::Empty { read: false, write: true }))),
                     // stderr
-                    Some(Mutex::new(Fd::Empty { read: false, write: true })),
+                    Some(Arc::new(Mutex::new(Fd::Empty { read: false, write: true }))),
                 ],
+                parent: None,
+                maps,
+                mmap_areas: Vec::new(),
+                mem_maps: Vec::new(),
+                utime: 0,
+                cutime: 0,
+                cpu_limit: [usize::MAX; 2],
+                xcpu_sent: 0,
                 signal: Box::new(SignalImpl::new()),
+                saved_sigmask: None,
                 semaphore_list: Vec::new(),
                 mutex_list: Vec::new(),
                 condvar_list: Vec::new(),
+                rwlock_list: Vec::new(),
+                deadlock: DeadlockDetector::default(),
+                group_exit: None,
             },
             thread,
         ))
     }
+
+    /// 登记一段 mmap 区间，区间中有已映射的页或超出用户地址空间时不做修改并返回 `false`
+    pub fn add_mmap_area(&mut self, range: Range<usize>, flags: [u8; 5]) -> bool {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        if range.end > 1 << 38
+            || range.clone().step_by(PAGE_SIZE).any(|addr| {
+                user_leaf_pte(&self.address_space, addr >> Sv39::PAGE_BITS).is_some()
+            })
+        {
+            return false;
+        }
+        self.maps.push(MapArea {
+            range: VAddr::new(range.start).floor()..VAddr::new(range.end).ceil(),
+            flags,
+        });
+        self.mmap_areas.push(range);
+        true
+    }
+
+    /// 从 mmap 区间中去掉 `[start, end)`，被截断的区间一分为二，`maps` 中对应的记录同样处理
+    ///
+    /// 范围内有不属于 mmap 区间的地址（ELF 段、线程栈或未映射的空洞）时不做修改并返回 `false`。
+    pub fn remove_mmap_area(&mut self, start: usize, end: usize) -> bool {
+        // 区间互不重叠，与 `[start, end)` 的交集长度之和等于它的长度才算完全覆盖
+        let covered: usize = self
+            .mmap_areas
+            .iter()
+            .map(|area| area.end.min(end).saturating_sub(area.start.max(start)))
+            .sum();
+        if covered != end - start {
+            return false;
+        }
+        let mut areas = Vec::new();
+        for area in self.mmap_areas.drain(..) {
+            if area.end <= start || area.start >= end {
+                areas.push(area);
+                continue;
+            }
+            if area.start < start {
+                areas.push(area.start..start);
+            }
+            if area.end > end {
+                areas.push(end..area.end);
+            }
+        }
+        self.mmap_areas = areas;
+        // 被覆盖的范围只含 mmap 区间，与之相交的 `maps` 记录都是 mmap 登记的
+        let (start, end) = (VAddr::<Sv39>::new(start).floor(), VAddr::<Sv39>::new(end).ceil());
+        let mut maps = Vec::new();
+        for area in self.maps.drain(..) {
+            if area.range.end <= start || area.range.start >= end {
+                maps.push(area);
+                continue;
+            }
+            if area.range.start < start {
+                maps.push(MapArea { range: area.range.start..start, flags: area.flags });
+            }
+            if area.range.end > end {
+                maps.push(MapArea { range: end..area.range.end, flags: area.flags });
+            }
+        }
+        self.maps = maps;
+        true
+    }
+
+    /// 把 `[start, end)` 中属于 memfd 共享映射、被修改过（D 位为 1）的页写回 memfd
+    ///
+    /// 写回后清除 D 位，下次只有再被修改过的页才会写回。
+    pub fn sync_mem_maps(&self, start: usize, end: usize) {
+        const PAGE_SIZE: usize = 1 << Sv39::PAGE_BITS;
+        for map in &self.mem_maps {
+            let from = start.max(map.start);
+            let to = end.min(map.start + map.len);
+            for addr in (from..to).step_by(PAGE_SIZE) {
+                let Some(pte) = user_leaf_pte(&self.address_space, addr >> Sv39::PAGE_BITS) else {
+                    continue;
+                };
+                let pte = unsafe { &mut *pte };
+                if *pte & PTE_D == 0 {
+                    continue;
+                }
+                *pte &= !PTE_D;
+                let page = (pte_ppn(*pte) << Sv39::PAGE_BITS) as *const u8;
+                map.file.write_at(map.offset + (addr - map.start), unsafe {
+                    core::slice::from_raw_parts(page, PAGE_SIZE)
+                });
+            }
+        }
+        unsafe { riscv::asm::sfence_vma_all() };
+    }
+
+    /// 线程退出时把 set_tid_address 登记的 `u32` 清零，地址不可写时忽略
+    ///
+    /// Linux 随后还会在该地址上做 futex 唤醒；本章没有 futex，等待线程结束仍用 waittid。
+    pub fn clear_tid_word(&mut self, addr: usize) {
+        if let Some(mut ptr) = self.address_space.translate_for_write::<u32>(VAddr::new(addr), build_flags("W_V")) {
+            *unsafe { ptr.as_mut() } = 0;
+        }
+    }
+
+    /// 分配文件描述符：优先复用编号最小的空闲槽位，没有空闲槽位时追加到末尾
+    pub fn alloc_fd(&mut self, file: Fd) -> usize {
+        self.install_fd(Arc::new(Mutex::new(file)))
+    }
+
+    /// 把一个已有的句柄装入编号最小的空闲槽位，dup 出的 fd 与原 fd 共享同一个句柄
+    pub fn install_fd(&mut self, file: Arc<Mutex<Fd>>) -> usize {
+        let file = Some(file);
+        if let Some(fd) = self.fd_table.iter().position(Option::is_none) {
+            self.fd_table[fd] = file;
+            fd
+        } else {
+            self.fd_table.push(file);
+            self.fd_table.len() - 1
+        }
+    }
+
+    /// `fd` 是否指向控制台（标准输入输出及 dup 出的副本）且允许按 `write` 指定的方向访问
+    ///
+    /// stdin 不可写、stdout 不可读：方向与 `write` 不符时返回 `false`，由调用者按普通句柄的读写权限报错。
+    pub fn is_console(&self, fd: usize, write: bool) -> bool {
+        let Some(Some(file)) = self.fd_table.get(fd) else {
+            return false;
+        };
+        match *file.lock() {
+            Fd::Empty { read, write: writable } => if write { writable } else { read },
+            _ => false,
+        }
+    }
+}
+
+/// 校验 ELF 的所有 LOAD 段（在分配任何页面之前调用）
+///
+/// 检查文件内偏移不越界、文件大小不超过内存大小、页内偏移一致、段落在用户地址空间内。
+/// 全部通过才返回 `true`，这样 `from_elf` 失败时不会留下映射了一半的地址空间。
+fn check_load_segments(elf: &ElfFile) -> bool {
+    const PAGE_MASK: usize = (1 << Sv39::PAGE_BITS) - 1;
+    elf.program_iter()
+        .filter(|program| matches!(program.get_type(), Ok(program::Type::Load)))
+        .all(|program| {
+            let off_file = program.offset() as usize;
+            let len_file = program.file_size() as usize;
+            let off_mem = program.virtual_addr() as usize;
+            let len_mem = program.mem_size() as usize;
+            off_file & PAGE_MASK == off_mem & PAGE_MASK
+                && len_file <= len_mem
+                && off_file.checked_add(len_file).is_some_and(|end| end <= elf.input.len())
+                && off_mem.checked_add(len_mem).is_some_and(|end| end <= 1 << 38)
+        })
 }
@@ -18,9 +18,20 @@
 //! - 再看 `ThreadManager` 与 `ProcManager` 的 `Manage` 实现：理解两层对象如何独立维护；
 //! - 最后看 `Schedule<ThreadId>`：明确调度粒度已经从进程切换为线程。
 
-use crate::process::{Process, Thread};
-use alloc::collections::{BTreeMap, VecDeque};
-use core::cell::UnsafeCell;
+use crate::{
+    process::{Process, Thread},
+    sync::{Mutex as MutexTrait, Resource},
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
 use tg_task_manage::{Manage, PThreadManager, ProcId, Schedule, ThreadId};
 
 /// 处理器内部类型（双层管理器）
@@ -29,6 +40,8 @@ pub type ProcessorInner = PThreadManager<Process, Thread, ThreadManager, ProcMan
 /// 全局处理器包装（通过 `UnsafeCell` 允许内部可变）
 pub struct Processor {
     inner: UnsafeCell<ProcessorInner>,
+    /// 本轮调度中线程陷入内核时读到的 time CSR，`clock_gettime` 直接复用
+    now: AtomicU64,
 }
 
 unsafe impl Sync for Processor {}
@@ -36,9 +49,17 @@ unsafe impl Sync for Processor {}
 impl Processor {
     /// 创建新处理器
     pub const fn new() -> Self {
-        Self { inner: UnsafeCell::new(PThreadManager::new()) }
+        Self { inner: UnsafeCell::new(PThreadManager::new()), now: AtomicU64::new(0) }
     }
 
+    /// 记录本轮调度的时刻
+    #[inline]
+    pub fn set_now(&self, now: u64) { self.now.store(now, Ordering::Relaxed); }
+
+    /// 本轮调度的时刻（缓存值，不读 CSR）
+    #[inline]
+    pub fn now(&self) -> u64 { self.now.load(Ordering::Relaxed) }
+
     /// 获取内部可变引用
     #[inline]
     pub fn get_mut(&self) -> &mut ProcessorInner {
@@ -52,7 +73,8 @@ pub static PROCESSOR: Processor = Processor::new();
 /// 线程管理器
 ///
 /// 维护所有线程实体和就绪队列。
-/// 使用 FIFO 调度策略。
+/// 按有效优先级调度（见 `Thread::effective_priority`），同优先级按入队顺序，
+/// 所有线程都是默认优先级时就是 FIFO。
 pub struct ThreadManager {
     /// 线程实体表（TID → Thread）
     tasks: BTreeMap<ThreadId, Thread>,
@@ -82,8 +104,17 @@ impl Manage<Thread, ThreadId> for ThreadManager {
 impl Schedule<ThreadId> for ThreadManager {
     /// 加入就绪队列
     fn add(&mut self, id: ThreadId) { self.ready_queue.push_back(id); }
-    /// 取出下一个就绪线程
-    fn fetch(&mut self) -> Option<ThreadId> { self.ready_queue.pop_front() }
+    /// 取出有效优先级最高的就绪线程，同优先级取最早入队的
+    fn fetch(&mut self) -> Option<ThreadId> {
+        let mut best: Option<(usize, usize)> = None;
+        for (index, tid) in self.ready_queue.iter().enumerate() {
+            let priority = self.tasks.get(tid).map_or(0, Thread::effective_priority);
+            if best.is_none_or(|(_, best_priority)| priority > best_priority) {
+                best = Some((index, priority));
+            }
+        }
+        self.ready_queue.remove(best?.0)
+    }
 }
 
 /// 进程管理器
@@ -109,5 +140,339 @@ impl Manage<Process, ProcId> for ProcManager {
     fn get_mut(&mut self, id: ProcId) -> Option<&mut Process> { self.procs.get_mut(&id) }
     /// 删除进程实体
     #[inline]
-    fn delete(&mut self, id: ProcId) { self.procs.remove(&id); }
+    fn delete(&mut self, id: ProcId) {
+        if let Some(proc) = self.procs.remove(&id) {
+            // 地址空间随进程释放，先把共享映射的修改写回 memfd
+            proc.sync_mem_maps(0, usize::MAX);
+            EXIT_TIMES.lock().insert(id, proc.utime + proc.cutime);
+            set_real_timer(id, None, 0);
+        }
+    }
+}
+
+/// 可回收的编号池：分配时优先复用最小的已归还编号，没有时再取新编号
+struct IdPool {
+    /// 从未分配过的最小编号
+    next: usize,
+    /// 已归还、可以复用的编号
+    free: BTreeSet<usize>,
+}
+
+impl IdPool {
+    const fn new() -> Self {
+        Self { next: 0, free: BTreeSet::new() }
+    }
+
+    fn alloc(&mut self) -> usize {
+        self.free.pop_first().unwrap_or_else(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+
+    fn dealloc(&mut self, id: usize) {
+        debug_assert!(id < self.next && !self.free.contains(&id), "id {id} freed twice");
+        self.free.insert(id);
+    }
+}
+
+/// PID 池
+static PID_POOL: Mutex<IdPool> = Mutex::new(IdPool::new());
+/// TID 池
+static TID_POOL: Mutex<IdPool> = Mutex::new(IdPool::new());
+/// 尚未归还的 TID → 所属进程，进程被 wait 回收时，没有被 waittid 回收过的 TID 随之归还
+static TID_OWNERS: Mutex<BTreeMap<ThreadId, ProcId>> = Mutex::new(BTreeMap::new());
+
+/// 为新进程分配 PID（取代 `ProcId::new`，编号在进程被 wait 回收后复用）
+pub fn alloc_pid() -> ProcId {
+    ProcId::from_usize(PID_POOL.lock().alloc())
+}
+
+/// 为进程 `pid` 中的新线程分配 TID（取代 `ThreadId::new`）
+pub fn alloc_tid(pid: ProcId) -> ThreadId {
+    let tid = ThreadId::from_usize(TID_POOL.lock().alloc());
+    TID_OWNERS.lock().insert(tid, pid);
+    tid
+}
+
+/// 归还已被 waittid 回收的线程的 TID
+pub fn release_tid(tid: ThreadId) {
+    if TID_OWNERS.lock().remove(&tid).is_some() {
+        TID_POOL.lock().dealloc(tid.get_usize());
+    }
+}
+
+/// 归还已被 wait 回收的进程的 PID，以及该进程中尚未归还的全部 TID
+pub fn release_pid(pid: ProcId) {
+    let mut owners = TID_OWNERS.lock();
+    let mut tids = TID_POOL.lock();
+    owners.retain(|tid, owner| {
+        let keep = *owner != pid;
+        if !keep {
+            tids.dealloc(tid.get_usize());
+        }
+        keep
+    });
+    PID_POOL.lock().dealloc(pid.get_usize());
+}
+
+/// 已退出、尚未被 wait 回收的进程的最终 CPU 时间（含其已回收子进程）
+static EXIT_TIMES: Mutex<BTreeMap<ProcId, usize>> = Mutex::new(BTreeMap::new());
+
+/// 取出已退出进程 `pid` 的最终 CPU 时间，供父进程 wait 回收时累加
+pub fn take_exit_time(pid: ProcId) -> usize {
+    EXIT_TIMES.lock().remove(&pid).unwrap_or(0)
+}
+
+/// 真实时间定时器（alarm / setitimer 的 `ITIMER_REAL`），时刻以 `time` 寄存器计数
+///
+/// 每个进程最多一个：新的设置覆盖旧的；fork 出的子进程不继承。
+struct RealTimers {
+    /// 定时器队列：`(到期时刻, 进程)`，按到期时刻排序
+    queue: BTreeSet<(u64, ProcId)>,
+    /// 周期性定时器的重装间隔
+    interval: BTreeMap<ProcId, u64>,
+}
+
+static REAL_TIMERS: Mutex<RealTimers> =
+    Mutex::new(RealTimers { queue: BTreeSet::new(), interval: BTreeMap::new() });
+
+/// 把 `pid` 的定时器设为在 `deadline` 到期（`None` 表示取消），之后每隔 `interval` 重装（0 表示一次性）
+///
+/// 返回原来的 `(到期时刻, 间隔)`。
+pub fn set_real_timer(pid: ProcId, deadline: Option<u64>, interval: u64) -> Option<(u64, u64)> {
+    let mut timers = REAL_TIMERS.lock();
+    let old = timers.queue.iter().find(|(_, p)| *p == pid).copied();
+    if let Some(old) = old {
+        timers.queue.remove(&old);
+    }
+    let old_interval = timers.interval.remove(&pid).unwrap_or(0);
+    if let Some(deadline) = deadline {
+        timers.queue.insert((deadline, pid));
+        if interval != 0 {
+            timers.interval.insert(pid, interval);
+        }
+    }
+    old.map(|(deadline, _)| (deadline, old_interval))
+}
+
+/// `pid` 当前定时器的 `(到期时刻, 间隔)`
+pub fn get_real_timer(pid: ProcId) -> Option<(u64, u64)> {
+    let timers = REAL_TIMERS.lock();
+    let (deadline, _) = timers.queue.iter().find(|(_, p)| *p == pid).copied()?;
+    Some((deadline, timers.interval.get(&pid).copied().unwrap_or(0)))
+}
+
+/// 取出所有在 `now` 之前到期的定时器，返回对应的进程
+///
+/// 周期性定时器重新入队；若错过了多个周期，只投递一次，下次到期时刻跳到 `now` 之后。
+pub fn take_expired_alarms(now: u64) -> Vec<ProcId> {
+    let mut timers = REAL_TIMERS.lock();
+    let mut expired = Vec::new();
+    while let Some(&(deadline, pid)) = timers.queue.first() {
+        if deadline > now {
+            break;
+        }
+        timers.queue.pop_first();
+        if let Some(&interval) = timers.interval.get(&pid) {
+            let missed = (now - deadline) / interval;
+            timers.queue.insert((deadline + (missed + 1) * interval, pid));
+        }
+        expired.push(pid);
+    }
+    expired
+}
+
+/// 限时等待超时后系统调用的返回值
+pub const WAIT_TIMEOUT: isize = -2;
+
+/// 限时等待（`semaphore_down_timeout` / `condvar_wait_timeout`）的对象
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TimedWait {
+    /// 信号量 id
+    Semaphore(usize),
+    /// 条件变量 id 与等待时释放的互斥锁 id
+    Condvar(usize, usize),
+}
+
+/// 限时等待的到期队列：`(到期时刻, 线程, 等待对象)`，按到期时刻排序，时刻以 `time` 寄存器计数
+static WAIT_DEADLINES: Mutex<BTreeSet<(u64, ThreadId, TimedWait)>> = Mutex::new(BTreeSet::new());
+
+/// 登记线程 `tid` 最迟在 `deadline` 被唤醒
+pub fn add_wait_deadline(tid: ThreadId, deadline: u64, wait: TimedWait) {
+    WAIT_DEADLINES.lock().insert((deadline, tid, wait));
+}
+
+/// 线程 `tid` 在到期前被正常唤醒（semaphore_up、condvar_signal），取消它的到期时刻
+pub fn cancel_wait_deadline(tid: ThreadId) {
+    WAIT_DEADLINES.lock().retain(|&(_, waiter, _)| waiter != tid);
+}
+
+/// 最早的到期时刻，调度循环据此设置时钟中断
+pub fn next_wait_deadline() -> Option<u64> {
+    WAIT_DEADLINES.lock().first().map(|&(deadline, ..)| deadline)
+}
+
+/// 唤醒所有在 `now` 之前到期的限时等待者，它们的系统调用返回 [`WAIT_TIMEOUT`]
+///
+/// 先把线程移出信号量或条件变量的等待队列。条件变量的等待者返回前还要重新持有互斥锁，
+/// 锁被占用时转入该锁的等待队列，由持锁者 unlock 时交接，返回值仍是超时。
+pub fn expire_timed_waits(processor: &mut ProcessorInner, now: u64) {
+    let mut expired = Vec::new();
+    {
+        let mut deadlines = WAIT_DEADLINES.lock();
+        while let Some(&(deadline, tid, wait)) = deadlines.first() {
+            if deadline > now {
+                break;
+            }
+            deadlines.pop_first();
+            expired.push((tid, wait));
+        }
+    }
+    for (tid, wait) in expired {
+        // 线程可能已随线程组一起退出
+        let Some(tgid) = processor.get_task(tid).map(|thread| thread.tgid) else { continue };
+        let Some(process) = processor.get_proc(tgid) else { continue };
+        let runnable = match wait {
+            TimedWait::Semaphore(sem_id) => {
+                let Some(Some(sem)) = process.semaphore_list.get(sem_id) else { continue };
+                if !sem.cancel(tid) {
+                    continue;
+                }
+                process.deadlock.cancel_request(tid, Resource::Semaphore(sem_id));
+                true
+            }
+            TimedWait::Condvar(condvar_id, mutex_id) => {
+                let Some(Some(condvar)) = process.condvar_list.get(condvar_id) else { continue };
+                let Some(mutex) = condvar.cancel(tid) else { continue };
+                let acquired = mutex.lock(tid);
+                if acquired {
+                    process.deadlock.acquire(tid, Resource::Mutex(mutex_id));
+                } else if let Some(owner) = mutex.owner() {
+                    donate_priority(processor, owner, tid);
+                }
+                acquired
+            }
+        };
+        if let Some(thread) = processor.get_task(tid) {
+            *thread.context.context.a_mut(0) = WAIT_TIMEOUT as _;
+        }
+        if runnable {
+            processor.re_enque(tid);
+        }
+    }
+}
+
+/// 优先级继承：`waiter` 阻塞在 `owner` 持有的互斥锁上，`owner` 至少继承 `waiter` 的有效优先级
+///
+/// 只提升直接持有者；持有者自己又阻塞在别的锁上时不沿链传递。
+pub fn donate_priority(processor: &mut ProcessorInner, owner: ThreadId, waiter: ThreadId) {
+    let Some(priority) = processor.get_task(waiter).map(|thread| thread.effective_priority()) else { return };
+    if let Some(owner) = processor.get_task(owner) {
+        owner.inherited = owner.inherited.max(priority);
+    }
+}
+
+/// 重新计算 `tid` 继承的优先级：取它在 `mutexes` 中仍持有的锁上所有等待者的最高有效优先级
+///
+/// 在 `tid` 释放锁或得到转交的锁之后调用，释放最后一个被争用的锁时恢复为基础优先级。
+pub fn recompute_inherited(processor: &mut ProcessorInner, tid: ThreadId, mutexes: &[Arc<dyn MutexTrait>]) {
+    let inherited = mutexes
+        .iter()
+        .filter(|mutex| mutex.owner() == Some(tid))
+        .flat_map(|mutex| mutex.waiters())
+        .filter_map(|waiter| processor.get_task(waiter).map(|thread| thread.effective_priority()))
+        .max()
+        .unwrap_or(0);
+    if let Some(thread) = processor.get_task(tid) {
+        thread.inherited = inherited;
+    }
+}
+
+/// 阻塞在 `waittid` 上的线程：目标 TID → 等待者 TID 列表
+static TID_WAITERS: Mutex<BTreeMap<ThreadId, Vec<ThreadId>>> = Mutex::new(BTreeMap::new());
+
+/// 登记线程 `waiter` 阻塞等待线程 `tid` 退出
+pub fn add_tid_waiter(tid: ThreadId, waiter: ThreadId) {
+    TID_WAITERS.lock().entry(tid).or_default().push(waiter);
+}
+
+/// 被唤醒的 waittid 等待者 → 它要回收的线程
+///
+/// 退出记录要在目标线程退出之后、以等待者为当前线程调用 `waittid` 才能取走，
+/// 因此由第一个等待者在回到用户态之前完成回收（见 [`reap_waited_thread`]）。
+static PENDING_REAPS: Mutex<BTreeMap<ThreadId, ThreadId>> = Mutex::new(BTreeMap::new());
+
+/// 当前线程若是被唤醒的 waittid 等待者，取走目标线程的退出记录并归还其 TID
+///
+/// 调度循环在当前线程回到用户态之前调用，与 waittid 直接取到退出码时一样，回收后 TID 立即可被复用。
+pub fn reap_waited_thread(processor: &mut ProcessorInner) {
+    let Some(waiter) = processor.current().map(|thread| thread.tid) else { return };
+    if let Some(tid) = PENDING_REAPS.lock().remove(&waiter) {
+        processor.waittid(tid);
+        release_tid(tid);
+    }
+}
+
+/// 以 `exit_code` 结束当前线程，并唤醒所有阻塞在 `waittid(当前线程)` 上的线程
+///
+/// 每个等待者的返回值（a0）都直接写为退出码，因此多个线程等待同一个线程时都能拿到它；
+/// 有等待者时由第一个等待者回收退出记录并归还 TID，之后再 waittid 返回 -1；
+/// 没有等待者时退出码留在 `PThreadManager` 中，之后调用 waittid 的线程取走并归还 TID。
+/// 线程的所有退出路径（exit、致命信号、异常、线程组退出）都应经过这里。
+pub fn exit_current_thread(processor: &mut ProcessorInner, exit_code: isize) {
+    // 线程可能留下不以换行结尾的输出
+    crate::console::flush();
+    if let Some((tid, clear_child_tid)) = processor.current().map(|thread| (thread.tid, thread.clear_child_tid)) {
+        // 没来得及回收就退出的等待者，目标线程的 TID 留到进程被 wait 回收时归还
+        PENDING_REAPS.lock().remove(&tid);
+        let mut reaper = None;
+        for waiter in TID_WAITERS.lock().remove(&tid).unwrap_or_default() {
+            // 等待者可能已随进程一起退出
+            if let Some(thread) = processor.get_task(waiter) {
+                *thread.context.context.a_mut(0) = exit_code as _;
+                processor.re_enque(waiter);
+                reaper.get_or_insert(waiter);
+            }
+        }
+        if let Some(waiter) = reaper {
+            PENDING_REAPS.lock().insert(waiter, tid);
+        }
+        if let Some(process) = processor.get_current_proc() {
+            process.deadlock.remove_thread(tid);
+            if clear_child_tid != 0 {
+                process.clear_tid_word(clear_child_tid);
+            }
+        }
+    }
+    processor.make_current_exited(exit_code);
+}
+
+/// 线程被致命信号杀死：以 `exit_code` 结束当前线程所在的整个线程组
+///
+/// 设置 `group_exit` 后，把阻塞在互斥锁、信号量、条件变量、读写锁和 waittid 上的同组线程
+/// 移出等待队列并重新入队，它们被调度时由主循环以同样的退出码结束；最后结束当前线程。
+/// 同步原语随进程一起销毁，因此不必再把锁交接给这些线程。
+pub fn exit_thread_group(processor: &mut ProcessorInner, exit_code: isize) {
+    if let Some(process) = processor.get_current_proc() {
+        process.group_exit = Some(exit_code);
+        let pid = process.pid;
+        let mut blocked = BTreeSet::new();
+        blocked.extend(process.mutex_list.iter().flatten().flat_map(|mutex| mutex.take_waiters()));
+        blocked.extend(process.semaphore_list.iter().flatten().flat_map(|sem| sem.take_waiters()));
+        blocked.extend(process.condvar_list.iter().flatten().flat_map(|condvar| condvar.take_waiters()));
+        blocked.extend(process.rwlock_list.iter().flatten().flat_map(|rwlock| rwlock.take_waiters()));
+        // waittid 只能等待同一进程的线程，以组内线程为目标的等待者都属于本组
+        let members = processor.get_thread(pid).cloned().unwrap_or_default();
+        let mut tid_waiters = TID_WAITERS.lock();
+        for tid in &members {
+            blocked.extend(tid_waiters.remove(tid).unwrap_or_default());
+        }
+        drop(tid_waiters);
+        for tid in blocked {
+            cancel_wait_deadline(tid);
+            processor.re_enque(tid);
+        }
+    }
+    exit_current_thread(processor, exit_code);
 }
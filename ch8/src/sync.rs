@@ -0,0 +1,478 @@
+//! 同步原语模块
+//!
+//! 与 `tg-sync` 的接口保持一致（`Mutex` trait、`MutexBlocking`、`Semaphore`、`Condvar`），
+//! 放在内核内部实现，便于按需扩展（例如查询等待队列是否为空以支持销毁）。
+//!
+//! 所有原语都不直接调度线程：需要阻塞时返回 `false`，需要唤醒时返回对应的 `ThreadId`，
+//! 由系统调用层调用 `make_current_blocked` / `re_enque` 完成调度。
+//!
+//! `RwLock` 是本内核新增的读写锁，`tg-sync` 中没有对应类型。
+//!
+//! `DeadlockDetector` 用银行家算法的安全性检查实现 `enable_deadlock_detect`。
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex as SpinMutex;
+use tg_task_manage::ThreadId;
+
+/// 互斥锁接口
+pub trait Mutex: Sync + Send {
+    /// 加锁，锁已被占用时把 `tid` 加入等待队列并返回 `false`
+    fn lock(&self, tid: ThreadId) -> bool;
+    /// 解锁，若有等待者则把锁直接交给它并返回其 `ThreadId`
+    fn unlock(&self) -> Option<ThreadId>;
+    /// 是否有线程在等待该锁
+    fn has_waiters(&self) -> bool;
+    /// 当前持有者，用于优先级继承
+    fn owner(&self) -> Option<ThreadId>;
+    /// 等待队列中的线程
+    fn waiters(&self) -> Vec<ThreadId>;
+    /// 清空等待队列并返回其中的线程，线程组退出时用来唤醒阻塞的线程
+    fn take_waiters(&self) -> Vec<ThreadId>;
+}
+
+/// 阻塞互斥锁
+pub struct MutexBlocking {
+    inner: SpinMutex<MutexBlockingInner>,
+}
+
+struct MutexBlockingInner {
+    /// 持有者，`None` 表示未上锁
+    owner: Option<ThreadId>,
+    wait_queue: VecDeque<ThreadId>,
+}
+
+impl MutexBlocking {
+    /// 创建一个未上锁的互斥锁
+    pub fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(MutexBlockingInner { owner: None, wait_queue: VecDeque::new() }),
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self, tid: ThreadId) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.owner.is_some() {
+            inner.wait_queue.push_back(tid);
+            false
+        } else {
+            inner.owner = Some(tid);
+            true
+        }
+    }
+
+    fn unlock(&self) -> Option<ThreadId> {
+        let mut inner = self.inner.lock();
+        assert!(inner.owner.is_some());
+        // 有等待者时锁不释放，直接转交给队首线程
+        let waking = inner.wait_queue.pop_front();
+        inner.owner = waking;
+        waking
+    }
+
+    fn has_waiters(&self) -> bool {
+        !self.inner.lock().wait_queue.is_empty()
+    }
+
+    fn owner(&self) -> Option<ThreadId> {
+        self.inner.lock().owner
+    }
+
+    fn waiters(&self) -> Vec<ThreadId> {
+        self.inner.lock().wait_queue.iter().copied().collect()
+    }
+
+    fn take_waiters(&self) -> Vec<ThreadId> {
+        self.inner.lock().wait_queue.drain(..).collect()
+    }
+}
+
+/// 信号量
+pub struct Semaphore {
+    inner: SpinMutex<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    count: isize,
+    wait_queue: VecDeque<ThreadId>,
+}
+
+impl Semaphore {
+    /// 创建初始计数为 `res_count` 的信号量
+    pub fn new(res_count: usize) -> Self {
+        Self {
+            inner: SpinMutex::new(SemaphoreInner { count: res_count as isize, wait_queue: VecDeque::new() }),
+        }
+    }
+
+    /// V 操作，若有等待者则返回需要唤醒的线程
+    pub fn up(&self) -> Option<ThreadId> {
+        let mut inner = self.inner.lock();
+        inner.count += 1;
+        if inner.count <= 0 { inner.wait_queue.pop_front() } else { None }
+    }
+
+    /// P 操作，资源不足时把 `tid` 加入等待队列并返回 `false`
+    pub fn down(&self, tid: ThreadId) -> bool {
+        let mut inner = self.inner.lock();
+        inner.count -= 1;
+        if inner.count < 0 {
+            inner.wait_queue.push_back(tid);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// 限时等待超时：把 `tid` 移出等待队列并撤销它的 P 操作，不在队列中（已被唤醒）时返回 `false`
+    pub fn cancel(&self, tid: ThreadId) -> bool {
+        let mut inner = self.inner.lock();
+        let Some(index) = inner.wait_queue.iter().position(|&waiter| waiter == tid) else { return false };
+        inner.wait_queue.remove(index);
+        inner.count += 1;
+        true
+    }
+
+    /// 是否有线程在等待该信号量
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.lock().wait_queue.is_empty()
+    }
+
+    /// 线程组退出：清空等待队列并撤销其中线程的 P 操作，返回这些线程
+    pub fn take_waiters(&self) -> Vec<ThreadId> {
+        let mut inner = self.inner.lock();
+        inner.count += inner.wait_queue.len() as isize;
+        inner.wait_queue.drain(..).collect()
+    }
+}
+
+/// [`Condvar::signal`] 唤醒的等待者
+pub struct Signaled {
+    /// 等待者
+    pub tid: ThreadId,
+    /// 它等待时释放的互斥锁 id
+    pub mutex_id: usize,
+    /// 是否立即重新持有了互斥锁；为 `false` 时已转入该锁的等待队列，由持锁者 unlock 时交接
+    pub acquired: bool,
+}
+
+/// 条件变量的一个等待者
+struct Waiter {
+    tid: ThreadId,
+    /// 等待时释放、被唤醒后要重新持有的互斥锁
+    mutex: Arc<dyn Mutex>,
+    /// `mutex` 在 `mutex_list` 中的下标
+    mutex_id: usize,
+}
+
+/// 条件变量
+///
+/// 等待队列中同时记下每个等待者用的互斥锁，被 signal 唤醒的线程要先重新持有它才能返回。
+pub struct Condvar {
+    inner: SpinMutex<VecDeque<Waiter>>,
+}
+
+impl Condvar {
+    /// 创建一个没有等待者的条件变量
+    pub fn new() -> Self {
+        Self { inner: SpinMutex::new(VecDeque::new()) }
+    }
+
+    /// 唤醒一个等待者，没有等待者时返回 `None`
+    ///
+    /// 等待者的互斥锁被占用时把它转入该锁的等待队列，由持锁者 unlock 时交接。
+    pub fn signal(&self) -> Option<Signaled> {
+        let Waiter { tid, mutex, mutex_id } = self.inner.lock().pop_front()?;
+        let acquired = mutex.lock(tid);
+        Some(Signaled { tid, mutex_id, acquired })
+    }
+
+    /// 把 `tid` 加入等待队列并释放 `mutex`，调用者随后必须阻塞当前线程
+    ///
+    /// 入队和解锁在条件变量的自旋锁内完成：signal 要么看不到 `tid`，要么看到它时锁已经释放，
+    /// 不会出现“锁已释放、还没入队”的窗口。内核单核运行且陷入后不响应中断，
+    /// 从这里返回到主循环标记阻塞之间不会切换到其他线程，因此也不会丢失唤醒。
+    ///
+    /// `mutex_id` 是 `mutex` 在 `mutex_list` 中的下标，signal 时交给死锁检测。返回释放锁时需要唤醒的线程。
+    pub fn wait_with_mutex(&self, tid: ThreadId, mutex: Arc<dyn Mutex>, mutex_id: usize) -> Option<ThreadId> {
+        let mut queue = self.inner.lock();
+        let waking_tid = mutex.unlock();
+        queue.push_back(Waiter { tid, mutex, mutex_id });
+        waking_tid
+    }
+
+    /// 限时等待超时：把 `tid` 移出等待队列，返回它需要重新持有的互斥锁；不在队列中（已被 signal）时返回 `None`
+    pub fn cancel(&self, tid: ThreadId) -> Option<Arc<dyn Mutex>> {
+        let mut queue = self.inner.lock();
+        let index = queue.iter().position(|waiter| waiter.tid == tid)?;
+        queue.remove(index).map(|waiter| waiter.mutex)
+    }
+
+    /// 是否有线程在等待该条件变量
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.lock().is_empty()
+    }
+
+    /// 线程组退出：清空等待队列并返回其中的线程，它们不再重新持有互斥锁
+    pub fn take_waiters(&self) -> Vec<ThreadId> {
+        self.inner.lock().drain(..).map(|waiter| waiter.tid).collect()
+    }
+}
+
+/// 读写锁
+///
+/// 多个读者可以同时持有，写者独占。释放后按偏好决定交给谁：
+///
+/// - 读者优先（`writer_preferred = false`）：只要没有写者持有，新读者直接进入，即使有写者在排队，
+///   读者源源不断时写者可能饿死；锁空闲时先唤醒全部排队读者，没有读者才交给写者。
+/// - 写者优先（`writer_preferred = true`）：有写者排队时新读者也要排队；锁空闲时先交给最早的写者，
+///   没有写者才唤醒全部排队读者。
+///
+/// 与 `MutexBlocking::unlock` 一样，唤醒时锁已经转交给被唤醒者，由调用者把返回的线程重新入队；
+/// 写锁释放时可能一次唤醒多个读者，因此返回 `Vec`。
+pub struct RwLock {
+    inner: SpinMutex<RwLockInner>,
+}
+
+struct RwLockInner {
+    writer_preferred: bool,
+    /// 持有读锁的线程数
+    readers: usize,
+    /// 是否有写者持有
+    writer: bool,
+    /// 等待者，`true` 表示写者
+    wait_queue: VecDeque<(ThreadId, bool)>,
+}
+
+impl RwLock {
+    /// 创建一个未被持有的读写锁
+    pub fn new(writer_preferred: bool) -> Self {
+        Self {
+            inner: SpinMutex::new(RwLockInner {
+                writer_preferred,
+                readers: 0,
+                writer: false,
+                wait_queue: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// 获取读锁，需要等待时把 `tid` 加入等待队列并返回 `false`
+    pub fn read_lock(&self, tid: ThreadId) -> bool {
+        let mut inner = self.inner.lock();
+        let writer_waiting = inner.writer_preferred && inner.wait_queue.iter().any(|&(_, writer)| writer);
+        if inner.writer || writer_waiting {
+            inner.wait_queue.push_back((tid, false));
+            false
+        } else {
+            inner.readers += 1;
+            true
+        }
+    }
+
+    /// 释放读锁，返回需要重新入队的线程（最后一个读者离开时才可能唤醒）
+    pub fn read_unlock(&self) -> Vec<ThreadId> {
+        let mut inner = self.inner.lock();
+        inner.readers = inner.readers.saturating_sub(1);
+        inner.wake()
+    }
+
+    /// 获取写锁，需要等待时把 `tid` 加入等待队列并返回 `false`
+    pub fn write_lock(&self, tid: ThreadId) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.writer || inner.readers > 0 {
+            inner.wait_queue.push_back((tid, true));
+            false
+        } else {
+            inner.writer = true;
+            true
+        }
+    }
+
+    /// 释放写锁，返回需要重新入队的线程
+    pub fn write_unlock(&self) -> Vec<ThreadId> {
+        let mut inner = self.inner.lock();
+        inner.writer = false;
+        inner.wake()
+    }
+
+    /// 是否有线程在等待该锁
+    pub fn has_waiters(&self) -> bool {
+        !self.inner.lock().wait_queue.is_empty()
+    }
+
+    /// 线程组退出：清空等待队列并返回其中的线程
+    pub fn take_waiters(&self) -> Vec<ThreadId> {
+        self.inner.lock().wait_queue.drain(..).map(|(tid, _)| tid).collect()
+    }
+}
+
+impl RwLockInner {
+    /// 锁空闲时按偏好把它交给排队的写者或全部排队读者，返回被唤醒的线程
+    fn wake(&mut self) -> Vec<ThreadId> {
+        if self.writer || self.readers > 0 {
+            return Vec::new();
+        }
+        let first_writer = self.wait_queue.iter().position(|&(_, writer)| writer);
+        let has_reader = self.wait_queue.iter().any(|&(_, writer)| !writer);
+        match first_writer {
+            Some(index) if self.writer_preferred || !has_reader => {
+                self.writer = true;
+                self.wait_queue.remove(index).map(|(tid, _)| tid).into_iter().collect()
+            }
+            _ => {
+                let mut woken = Vec::new();
+                self.wait_queue.retain(|&(tid, writer)| {
+                    if !writer {
+                        woken.push(tid);
+                    }
+                    writer
+                });
+                self.readers = woken.len();
+                woken
+            }
+        }
+    }
+}
+
+/// 死锁检测跟踪的资源：互斥锁（1 个单位）或信号量（初始计数个单位），按各自列表中的 id 区分
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Resource {
+    /// `mutex_list` 中的互斥锁
+    Mutex(usize),
+    /// `semaphore_list` 中的信号量
+    Semaphore(usize),
+}
+
+/// 每个线程对各资源的数量
+type Holdings = BTreeMap<ThreadId, BTreeMap<Resource, usize>>;
+
+/// 死锁检测（银行家算法的安全性检查）
+///
+/// 无论是否启用都维护三张表：可用量 Available、各线程的已分配量 Allocation
+/// 与各线程正在请求（已阻塞或即将阻塞）的数量 Need。启用后，每次 mutex_lock / semaphore_down
+/// 先把请求计入 Need，再检查能否让所有线程依次满足需求并释放资源；不能时撤回请求，
+/// 系统调用返回 [`DEADLOCK`]。
+///
+/// 信号量若用作“事件通知”（初值 0、由其他线程 up），等待者在检查时看不到未来的 up，
+/// 同样会被判为不安全，这是该算法固有的保守性。
+#[derive(Default)]
+pub struct DeadlockDetector {
+    /// 是否启用检测
+    pub enabled: bool,
+    available: BTreeMap<Resource, usize>,
+    allocation: Holdings,
+    need: Holdings,
+}
+
+/// 请求会使系统进入不安全状态时 mutex_lock / semaphore_down 的返回值
+pub const DEADLOCK: isize = -0xDEAD;
+
+impl DeadlockDetector {
+    /// 登记新创建（或复用 id）的资源，初始可用 `count` 个单位
+    pub fn add_resource(&mut self, resource: Resource, count: usize) {
+        self.remove_resource(resource);
+        self.available.insert(resource, count);
+    }
+
+    /// 资源被销毁：从所有表中移除
+    pub fn remove_resource(&mut self, resource: Resource) {
+        self.available.remove(&resource);
+        for table in [&mut self.allocation, &mut self.need] {
+            table.values_mut().for_each(|row| {
+                row.remove(&resource);
+            });
+        }
+    }
+
+    /// 线程 `tid` 请求一个单位的 `resource`
+    ///
+    /// 启用检测且请求会导致不安全状态时返回 `false`，请求不计入 Need；
+    /// 否则请求计入 Need，随后由 [`acquire`](Self::acquire) 或释放者的交接兑现。
+    pub fn request(&mut self, tid: ThreadId, resource: Resource) -> bool {
+        self.wait_for(tid, resource);
+        if self.enabled && !self.is_safe() {
+            Self::take(&mut self.need, tid, resource);
+            return false;
+        }
+        true
+    }
+
+    /// 把请求计入 Need 而不做安全性检查，用于 condvar_wait 释放后必须重新持有的互斥锁
+    pub fn wait_for(&mut self, tid: ThreadId, resource: Resource) {
+        *self.need.entry(tid).or_default().entry(resource).or_default() += 1;
+    }
+
+    /// 撤回线程 `tid` 尚未兑现的请求（限时等待超时）
+    pub fn cancel_request(&mut self, tid: ThreadId, resource: Resource) {
+        Self::take(&mut self.need, tid, resource);
+    }
+
+    /// 线程 `tid` 立即得到了请求的资源
+    pub fn acquire(&mut self, tid: ThreadId, resource: Resource) {
+        if let Some(count) = self.available.get_mut(&resource) {
+            *count = count.saturating_sub(1);
+        }
+        self.grant(tid, resource);
+    }
+
+    /// 线程 `tid` 释放一个单位的 `resource`；`to` 为直接接手该资源的等待者
+    ///
+    /// 信号量可以由未持有它的线程 up，此时 Allocation 中没有可扣减的数量。
+    pub fn release(&mut self, tid: ThreadId, resource: Resource, to: Option<ThreadId>) {
+        Self::take(&mut self.allocation, tid, resource);
+        match to {
+            Some(waiter) => self.grant(waiter, resource),
+            None => {
+                if let Some(count) = self.available.get_mut(&resource) {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    /// 线程退出：丢弃它的记录（退出时仍持有的资源不会再被释放，不再计入可用量）
+    pub fn remove_thread(&mut self, tid: ThreadId) {
+        self.allocation.remove(&tid);
+        self.need.remove(&tid);
+    }
+
+    /// 把一个单位的 `resource` 从 `tid` 的 Need 移到 Allocation
+    fn grant(&mut self, tid: ThreadId, resource: Resource) {
+        Self::take(&mut self.need, tid, resource);
+        *self.allocation.entry(tid).or_default().entry(resource).or_default() += 1;
+    }
+
+    /// 表中 `tid` 对 `resource` 的数量减一（已为 0 时不变）
+    fn take(table: &mut Holdings, tid: ThreadId, resource: Resource) {
+        if let Some(count) = table.get_mut(&tid).and_then(|row| row.get_mut(&resource)) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// 安全性检查：反复找一个 Need 能被当前 Work 满足的线程，假设它运行结束并归还 Allocation，
+    /// 所有线程都能结束则状态安全
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut pending: Vec<ThreadId> = self.need.keys().chain(self.allocation.keys()).copied().collect();
+        pending.sort_unstable();
+        pending.dedup();
+        let satisfiable = |tid: &ThreadId, work: &BTreeMap<Resource, usize>| {
+            self.need.get(tid).is_none_or(|row| {
+                row.iter().all(|(resource, &count)| count <= work.get(resource).copied().unwrap_or(0))
+            })
+        };
+        while let Some(index) = pending.iter().position(|tid| satisfiable(tid, &work)) {
+            let tid = pending.swap_remove(index);
+            for (resource, &count) in self.allocation.get(&tid).into_iter().flatten() {
+                *work.entry(*resource).or_default() += count;
+            }
+        }
+        pending.is_empty()
+    }
+}
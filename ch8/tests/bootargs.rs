@@ -0,0 +1,51 @@
+//! 启动参数解析的主机端测试
+//!
+//! 内核本身只能在 RISC-V 上运行，这里直接引入 `bootargs` 模块，它的解析部分不依赖内核环境：
+//!
+//! ```sh
+//! cargo test --target x86_64-unknown-linux-gnu --test bootargs
+//! ```
+
+#[allow(dead_code)]
+#[path = "../src/bootargs.rs"]
+mod bootargs;
+
+use bootargs::{BootArgs, Sched};
+
+#[test]
+fn empty_cmdline_uses_defaults() {
+    let args = BootArgs::parse("");
+    assert_eq!(args.log, None);
+    assert_eq!(args.sched, Sched::Fifo);
+    assert_eq!(args.mem, None);
+    assert_eq!(args.root, None);
+}
+
+#[test]
+fn parses_every_key() {
+    let args = BootArgs::parse("log=trace  sched=rr\tmem=64M root=/dev/vda");
+    assert_eq!(args.log, Some("trace"));
+    assert_eq!(args.sched, Sched::Rr);
+    assert_eq!(args.mem, Some(64 << 20));
+    assert_eq!(args.root, Some("/dev/vda"));
+}
+
+#[test]
+fn size_suffixes() {
+    assert_eq!(BootArgs::parse("mem=4096").mem, Some(4096));
+    assert_eq!(BootArgs::parse("mem=8k").mem, Some(8 << 10));
+    assert_eq!(BootArgs::parse("mem=1G").mem, Some(1 << 30));
+    // 溢出与非法数字都被忽略
+    assert_eq!(BootArgs::parse("mem=99999999999999999999G").mem, None);
+    assert_eq!(BootArgs::parse("mem=12x").mem, None);
+    assert_eq!(BootArgs::parse("mem=M").mem, None);
+}
+
+#[test]
+fn bad_params_are_skipped() {
+    let args = BootArgs::parse("quiet sched=cfs log= foo=bar sched=rr");
+    assert_eq!(args.log, None);
+    assert_eq!(args.sched, Sched::Rr);
+    // 后出现的同名参数覆盖前面的
+    assert_eq!(BootArgs::parse("sched=rr sched=fifo").sched, Sched::Fifo);
+}